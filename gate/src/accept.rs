@@ -0,0 +1,113 @@
+use axum::http::HeaderValue;
+
+use crate::results::accepted;
+use crate::templates::{escape_html, STORE_HTML};
+use crate::update_graphs::GraphTarget;
+
+/// Media type Oxigraph is asked for when a Graph Store read is rendered
+pub const TURTLE: &str = "text/turtle";
+
+/// Largest Graph Store response rendered as a page (4 MiB); larger ones
+/// are passed on as plain-text Turtle
+pub const STORE_PAGE_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+struct Rewrite {
+    prefix: String,
+    accept: HeaderValue,
+}
+
+/// Per-route `Accept` for clients that name no media type (`ACCEPT_REWRITES`)
+#[derive(Default)]
+pub struct AcceptRewrites {
+    rewrites: Vec<Rewrite>,
+}
+
+impl AcceptRewrites {
+    /// Parse `ACCEPT_REWRITES`: comma-separated `prefix=media-type` pairs.
+    /// Prefixes match like `UPSTREAM_ROUTES`, on the path the client used.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut rewrites = Vec::new();
+        for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (prefix, media_type) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("rewrite {} must be prefix=media-type", entry))?;
+            let prefix = prefix.trim().trim_end_matches('/');
+            if !prefix.is_empty() && !prefix.starts_with('/') {
+                return Err(format!("prefix of rewrite {} must start with /", entry));
+            }
+            let media_type = media_type.trim();
+            if !media_type.contains('/') {
+                return Err(format!("rewrite {} must name a media type", entry));
+            }
+            let accept = HeaderValue::from_str(media_type).map_err(|_| format!("invalid media type in {}", entry))?;
+            if rewrites.iter().any(|r: &Rewrite| r.prefix.eq_ignore_ascii_case(prefix)) {
+                return Err(format!("prefix {} is rewritten twice", prefix));
+            }
+            rewrites.push(Rewrite {
+                prefix: prefix.to_string(),
+                accept,
+            });
+        }
+        rewrites.sort_by_key(|r| std::cmp::Reverse(r.prefix.len()));
+        Ok(Self { rewrites })
+    }
+
+    /// The `Accept` to send instead for a request to `path`, when the
+    /// client sent none or prefers `*/*`
+    pub fn rewrite(&self, path: &str, accept: Option<&str>) -> Option<&HeaderValue> {
+        if !is_generic(accept) {
+            return None;
+        }
+        self.rewrites.iter().find_map(|rewrite| {
+            let head = path.get(..rewrite.prefix.len())?;
+            let rest = &path[rewrite.prefix.len()..];
+            (head.eq_ignore_ascii_case(&rewrite.prefix) && (rest.is_empty() || rest.starts_with('/')))
+                .then_some(&rewrite.accept)
+        })
+    }
+}
+
+/// The media range a client prefers most; equally preferred ones go by
+/// their order in the header
+fn preferred(accept: &str) -> Option<String> {
+    let mut best: Option<(String, f32)> = None;
+    for (media_type, quality) in accepted(accept) {
+        if quality > 0.0 && best.as_ref().is_none_or(|(_, q)| quality > *q) {
+            best = Some((media_type, quality));
+        }
+    }
+    best.map(|(media_type, _)| media_type)
+}
+
+/// Whether a client named no media type: no `Accept`, or `*/*` first
+fn is_generic(accept: Option<&str>) -> bool {
+    match accept.map(str::trim).filter(|a| !a.is_empty()) {
+        None => true,
+        Some(accept) => preferred(accept).is_none_or(|media_type| media_type == "*/*"),
+    }
+}
+
+/// Whether a client, typically a browser, prefers an HTML page
+pub fn prefers_html(accept: Option<&str>) -> bool {
+    accept
+        .and_then(preferred)
+        .is_some_and(|media_type| media_type == "text/html" || media_type == "application/xhtml+xml")
+}
+
+/// Turtle from the Graph Store as a page. `target` is the graph read, or
+/// `None` for the whole dataset.
+pub fn store_page(target: Option<&GraphTarget>, turtle: &str) -> String {
+    let title = match target {
+        Some(GraphTarget::Named(iri)) => iri.clone(),
+        Some(GraphTarget::Default) => "Default graph".to_string(),
+        None => "Dataset".to_string(),
+    };
+    let summary = format!(
+        "{} lines of Turtle. Request <code>Accept: text/turtle</code> or another RDF format to download it.",
+        turtle.lines().count()
+    );
+    STORE_HTML
+        .replace("{title}", &escape_html(&title))
+        .replace("{summary}", &summary)
+        .replace("{turtle}", &escape_html(turtle))
+}
@@ -0,0 +1,619 @@
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::files::{
+    binding_value, get_access_rank_iri, hash_token, send_sparql_update, sparql_select, validate_uuid,
+    ACCESS_GRAPH,
+};
+use crate::login_audit::{failure_stats, FailureStats, DEFAULT_STATS_HOURS};
+use crate::maintenance::render_maintenance;
+use crate::notify::{notify, AuthEvent};
+use crate::proxy::GRAPH_IRI;
+use crate::roles::{Role, PERMISSION_ADMIN};
+use crate::sessions::Session;
+use crate::templates::{escape_html, ADMIN_HTML};
+use crate::AppState;
+
+pub const ADMIN_PATH: &str = "/gate/admin";
+
+#[derive(Deserialize)]
+pub struct RankForm {
+    rank: i32,
+}
+
+/// A policy granted to a token, as shown in the token table
+struct Grant {
+    policy: String,
+    target: String,
+    rank: Option<i32>,
+}
+
+struct TokenRow {
+    token: String,
+    created: Option<DateTime<Utc>>,
+    grants: Vec<Grant>,
+}
+
+struct UserRow {
+    user: String,
+    dn: String,
+    rank: Option<i32>,
+}
+
+pub async fn is_admin(state: &AppState, headers: &HeaderMap, jar: &CookieJar) -> bool {
+    get_access_rank_iri(state, GRAPH_IRI, headers, jar).await >= state.roles.required_rank(PERMISSION_ADMIN)
+}
+
+pub fn forbidden() -> Response {
+    (StatusCode::FORBIDDEN, "Access denied - administration requires admin access").into_response()
+}
+
+pub fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+/// A `dcterms:created` value: an `xsd:dateTime`, or the unix seconds
+/// recorded before
+pub fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(time) => Some(time.with_timezone(&Utc)),
+        Err(_) => Utc.timestamp_opt(value.parse().ok()?, 0).single(),
+    }
+}
+
+/// Issued tokens with the policies granted to each
+async fn load_tokens(state: &AppState) -> Result<Vec<TokenRow>, String> {
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX dcterms: <http://purl.org/dc/terms/>
+
+SELECT ?token ?created ?policy ?target ?level FROM <{graph}> WHERE {{
+    ?token a liqk:AccessToken .
+    OPTIONAL {{ ?token dcterms:created ?created }}
+    OPTIONAL {{
+        ?policy liqk:policy-grantee ?token ;
+            liqk:policy-target ?target .
+        OPTIONAL {{ ?policy liqk:access-level ?level }}
+    }}
+}}
+ORDER BY ?created ?token ?target"#,
+        graph = ACCESS_GRAPH,
+    );
+
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+
+    // Rows are ordered by token, so each token's grants are contiguous
+    let mut tokens: Vec<TokenRow> = Vec::new();
+    for b in &bindings {
+        let Some(token) = binding_value(b, "token") else {
+            continue;
+        };
+        if tokens.last().map(|t| t.token.as_str()) != Some(token) {
+            tokens.push(TokenRow {
+                token: token.to_string(),
+                created: binding_value(b, "created").and_then(parse_time),
+                grants: Vec::new(),
+            });
+        }
+        if let (Some(row), Some(policy), Some(target)) =
+            (tokens.last_mut(), binding_value(b, "policy"), binding_value(b, "target"))
+        {
+            row.grants.push(Grant {
+                policy: policy.to_string(),
+                target: target.to_string(),
+                rank: binding_value(b, "level").and_then(|level| state.roles.rank_of(level)),
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Directory users known to the access graph
+async fn load_users(state: &AppState) -> Result<Vec<UserRow>, String> {
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?user ?dn ?level FROM <{graph}> WHERE {{
+    ?user a liqk:DirectoryUser ;
+        liqk:ldap-dn ?dn .
+    OPTIONAL {{ ?user liqk:access-level ?level }}
+}}
+ORDER BY ?dn"#,
+        graph = ACCESS_GRAPH,
+    );
+
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+
+    Ok(bindings
+        .iter()
+        .filter_map(|b| {
+            Some(UserRow {
+                user: binding_value(b, "user")?.to_string(),
+                dn: binding_value(b, "dn")?.to_string(),
+                rank: binding_value(b, "level").and_then(|level| state.roles.rank_of(level)),
+            })
+        })
+        .collect())
+}
+
+/// Replace the access level of a policy or directory user
+async fn set_access_level(state: &AppState, subject: &Uuid, class: &str, level_iri: &str) -> Result<(), String> {
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+DELETE {{ GRAPH <{graph}> {{ ?subject liqk:access-level ?old }} }}
+INSERT {{ GRAPH <{graph}> {{ ?subject liqk:access-level <{level}> }} }}
+WHERE {{
+    GRAPH <{graph}> {{
+        BIND(<urn:uuid:{subject}> AS ?subject)
+        ?subject a {class} .
+        OPTIONAL {{ ?subject liqk:access-level ?old }}
+    }}
+}}"#,
+        graph = ACCESS_GRAPH,
+        subject = subject,
+        class = class,
+        level = level_iri,
+    );
+
+    send_sparql_update(state, "access-level", &query).await
+}
+
+/// Delete a token together with every policy granted to it
+async fn delete_token(state: &AppState, token: &Uuid) -> Result<(), String> {
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+DELETE {{
+    GRAPH <{graph}> {{
+        ?token ?p ?o .
+        ?policy ?pp ?po .
+    }}
+}}
+WHERE {{
+    GRAPH <{graph}> {{
+        BIND(<urn:uuid:{token}> AS ?token)
+        ?token a liqk:AccessToken ;
+            ?p ?o .
+        OPTIONAL {{
+            ?policy liqk:policy-grantee ?token ;
+                ?pp ?po .
+        }}
+    }}
+}}"#,
+        graph = ACCESS_GRAPH,
+        token = token,
+    );
+
+    send_sparql_update(state, "token-revoke", &query).await
+}
+
+async fn delete_user(state: &AppState, user: &Uuid) -> Result<(), String> {
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+DELETE {{ GRAPH <{graph}> {{ ?user ?p ?o }} }}
+WHERE {{
+    GRAPH <{graph}> {{
+        BIND(<urn:uuid:{user}> AS ?user)
+        ?user a liqk:DirectoryUser ;
+            ?p ?o .
+    }}
+}}"#,
+        graph = ACCESS_GRAPH,
+        user = user,
+    );
+
+    send_sparql_update(state, "user-revoke", &query).await
+}
+
+/// A POST button; `action` must be a gate-generated path
+pub fn button(action: &str, label: &str) -> String {
+    format!(
+        r#"<form method="POST" action="{}"><button type="submit">{}</button></form>"#,
+        escape_html(action),
+        escape_html(label),
+    )
+}
+
+/// A role selector that submits the chosen rank on change
+fn rank_select(action: &str, current: Option<i32>, roles: &[Role]) -> String {
+    let mut options = String::new();
+    if current.is_none() {
+        options.push_str(r#"<option value="" selected disabled>-</option>"#);
+    }
+    for role in roles {
+        options.push_str(&format!(
+            r#"<option value="{}"{}>{} ({})</option>"#,
+            role.rank,
+            if current == Some(role.rank) { " selected" } else { "" },
+            escape_html(&role.label),
+            role.rank,
+        ));
+    }
+    format!(
+        r#"<form method="POST" action="{}"><select name="rank" onchange="this.form.submit()">{}</select></form>"#,
+        escape_html(action),
+        options,
+    )
+}
+
+pub fn uuid_of(iri: &str) -> Option<Uuid> {
+    iri.strip_prefix("urn:uuid:").and_then(validate_uuid)
+}
+
+fn render_sessions(state: &AppState) -> String {
+    let sessions = state.sessions.list();
+    if sessions.is_empty() {
+        return r#"<tr><td class="empty" colspan="6">No active sessions</td></tr>"#.to_string();
+    }
+    sessions
+        .iter()
+        .map(|s| {
+            // Session IDs are bearer secrets; address them by their hash
+            let action = format!("{}/sessions/{}/revoke", ADMIN_PATH, hash_token(&s.id));
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&s.identity),
+                escape_html(s.backend),
+                s.rank,
+                format_time(s.created),
+                format_time(s.last_seen),
+                button(&action, "Revoke"),
+            )
+        })
+        .collect()
+}
+
+/// Hourly bar chart and top clients for failed logins
+fn render_failures(stats: &FailureStats) -> String {
+    let max = stats.by_hour.iter().map(|(_, count)| *count).max().unwrap_or(0).max(1);
+    let bars: String = stats
+        .by_hour
+        .iter()
+        .map(|(start, count)| {
+            let hour = Utc.timestamp_opt(*start, 0).single().map(format_time).unwrap_or_default();
+            format!(
+                "<div{} style=\"height: {}%\" title=\"{}: {}\"></div>",
+                if *count == 0 { " class=\"zero\"" } else { "" },
+                count * 100 / max,
+                hour,
+                count,
+            )
+        })
+        .collect();
+
+    let clients: String = if stats.by_client.is_empty() {
+        r#"<tr><td class="empty" colspan="3">No failed logins</td></tr>"#.to_string()
+    } else {
+        stats
+            .by_client
+            .iter()
+            .map(|(ip, count, last)| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    escape_html(ip),
+                    count,
+                    Utc.timestamp_opt(*last, 0).single().map(format_time).unwrap_or_default(),
+                )
+            })
+            .collect()
+    };
+
+    format!(
+        r#"<p class="summary">{} in the last {} hours</p>
+    <div class="chart">{}</div>
+    <h2>Top clients</h2>
+    <table>
+        <tr><th>Client</th><th>Failures</th><th>Last attempt</th></tr>
+{}
+    </table>"#,
+        stats.total, stats.hours, bars, clients,
+    )
+}
+
+fn render_tokens(tokens: &[TokenRow], roles: &[Role]) -> String {
+    if tokens.is_empty() {
+        return r#"<tr><td class="empty" colspan="4">No tokens</td></tr>"#.to_string();
+    }
+    tokens
+        .iter()
+        .map(|t| {
+            let created = t.created.map(format_time).unwrap_or_default();
+            let grants: String = t
+                .grants
+                .iter()
+                .map(|g| match uuid_of(&g.policy) {
+                    Some(policy) => format!(
+                        "<div class=\"grant\"><span>{}</span>{}</div>",
+                        escape_html(&g.target),
+                        rank_select(&format!("{}/policies/{}/rank", ADMIN_PATH, policy), g.rank, roles),
+                    ),
+                    None => format!(
+                        "<div class=\"grant\"><span>{}</span><span>{}</span></div>",
+                        escape_html(&g.target),
+                        g.rank.map(|r| r.to_string()).unwrap_or_default(),
+                    ),
+                })
+                .collect();
+            let revoke = uuid_of(&t.token)
+                .map(|token| button(&format!("{}/tokens/{}/revoke", ADMIN_PATH, token), "Revoke"))
+                .unwrap_or_default();
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&t.token),
+                created,
+                grants,
+                revoke,
+            )
+        })
+        .collect()
+}
+
+fn render_users(users: &[UserRow], roles: &[Role]) -> String {
+    if users.is_empty() {
+        return r#"<tr><td class="empty" colspan="3">No directory users</td></tr>"#.to_string();
+    }
+    users
+        .iter()
+        .map(|u| match uuid_of(&u.user) {
+            Some(user) => format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&u.dn),
+                rank_select(&format!("{}/users/{}/rank", ADMIN_PATH, user), u.rank, roles),
+                button(&format!("{}/users/{}/revoke", ADMIN_PATH, user), "Revoke"),
+            ),
+            None => format!(
+                "<tr><td>{}</td><td>{}</td><td></td></tr>",
+                escape_html(&u.dn),
+                u.rank.map(|r| r.to_string()).unwrap_or_default(),
+            ),
+        })
+        .collect()
+}
+
+/// Validate a path UUID and the caller's admin rank
+pub async fn admin_target(
+    state: &AppState,
+    addr: &SocketAddr,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+    uuid_str: &str,
+) -> Result<Uuid, (StatusCode, &'static str)> {
+    if !is_admin(state, headers, jar).await {
+        warn!(client = %addr, "Access denied - administration requires admin rank");
+        return Err((StatusCode::FORBIDDEN, "Access denied - administration requires admin access"));
+    }
+    validate_uuid(uuid_str).ok_or((StatusCode::BAD_REQUEST, "Invalid UUID format"))
+}
+
+/// Remove the session whose ID hashes to `handle`
+fn remove_session_by_handle(state: &AppState, handle: &str) -> Option<Session> {
+    let session = state.sessions.list().into_iter().find(|s| hash_token(&s.id) == handle)?;
+    state.sessions.remove(&session.id);
+    state.rank_cache.invalidate_all();
+    Some(session)
+}
+
+/// Finish an admin change: drop cached ranks and return to the admin page
+fn applied(state: &AppState, result: Result<(), String>, addr: &SocketAddr, action: &str, target: &Uuid) -> Response {
+    match result {
+        Ok(()) => {
+            state.rank_cache.invalidate_all();
+            info!(client = %addr, action = action, target = %target, "Admin change applied");
+            Redirect::to(ADMIN_PATH).into_response()
+        }
+        Err(e) => {
+            warn!(client = %addr, action = action, target = %target, error = %e, "Admin change failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to apply change").into_response()
+        }
+    }
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// GET /gate/admin - Failed logins, sessions, tokens and directory users (admin only)
+pub async fn admin_page(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    if !is_admin(&state, &headers, &jar).await {
+        warn!(client = %addr, "Access denied - administration requires admin rank");
+        return forbidden();
+    }
+
+    let (tokens, users, failures) = match tokio::try_join!(
+        load_tokens(&state),
+        load_users(&state),
+        failure_stats(&state, DEFAULT_STATS_HOURS)
+    ) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            warn!(client = %addr, error = %e, "Failed to load admin data");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load access data").into_response();
+        }
+    };
+
+    let roles = state.roles.list();
+    Html(
+        ADMIN_HTML
+            .replace("{maintenance}", &render_maintenance(&state))
+            .replace("{failures}", &render_failures(&failures))
+            .replace("{sessions}", &render_sessions(&state))
+            .replace("{tokens}", &render_tokens(&tokens, &roles))
+            .replace("{users}", &render_users(&users, &roles)),
+    )
+    .into_response()
+}
+
+/// POST /gate/admin/sessions/:handle/revoke - End a session by the hash of its ID
+pub async fn revoke_session_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(handle): Path<String>,
+) -> Response {
+    if !is_admin(&state, &headers, &jar).await {
+        warn!(client = %addr, "Access denied - administration requires admin rank");
+        return forbidden();
+    }
+
+    let Some(session) = remove_session_by_handle(&state, &handle) else {
+        return (StatusCode::NOT_FOUND, "Session not found").into_response();
+    };
+
+    info!(client = %addr, identity = %session.identity, backend = session.backend, "Session revoked");
+    Redirect::to(ADMIN_PATH).into_response()
+}
+
+/// GET /gate/admin/sessions - Live sessions as JSON (admin only). The
+/// `id` is the SHA-256 of the session ID, as accepted by the kill endpoints.
+pub async fn sessions_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    if !is_admin(&state, &headers, &jar).await {
+        warn!(client = %addr, "Access denied - administration requires admin rank");
+        return forbidden();
+    }
+
+    let sessions: Vec<_> = state
+        .sessions
+        .list()
+        .iter()
+        .map(|s| {
+            json!({
+                "id": hash_token(&s.id),
+                "identity": s.identity,
+                "backend": s.backend,
+                "rank": s.rank,
+                "created": s.created.to_rfc3339(),
+                "last_seen": s.last_seen.to_rfc3339(),
+            })
+        })
+        .collect();
+
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        json!({ "sessions": sessions }).to_string(),
+    )
+        .into_response()
+}
+
+/// DELETE /gate/admin/sessions/:handle - Kill a session by the hash of its ID
+pub async fn kill_session_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(handle): Path<String>,
+) -> Response {
+    if !is_admin(&state, &headers, &jar).await {
+        warn!(client = %addr, "Access denied - administration requires admin rank");
+        return forbidden();
+    }
+
+    match remove_session_by_handle(&state, &handle) {
+        Some(session) => {
+            info!(client = %addr, identity = %session.identity, backend = session.backend, "Session killed");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        None => (StatusCode::NOT_FOUND, "Session not found").into_response(),
+    }
+}
+
+/// POST /gate/admin/tokens/:uuid/revoke - Delete a token and its policies
+pub async fn revoke_token_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(uuid_str): Path<String>,
+) -> Response {
+    let token = match admin_target(&state, &addr, &headers, &jar, &uuid_str).await {
+        Ok(uuid) => uuid,
+        Err(e) => return e.into_response(),
+    };
+    let result = delete_token(&state, &token).await;
+    if result.is_ok() {
+        let client = addr.ip().to_string();
+        notify(&state, AuthEvent::TokenRevoked { client, token: format!("urn:uuid:{}", token) });
+    }
+    applied(&state, result, &addr, "revoke token", &token)
+}
+
+/// POST /gate/admin/policies/:uuid/rank - Change the rank a policy grants
+pub async fn policy_rank_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(uuid_str): Path<String>,
+    Form(form): Form<RankForm>,
+) -> Response {
+    let policy = match admin_target(&state, &addr, &headers, &jar, &uuid_str).await {
+        Ok(uuid) => uuid,
+        Err(e) => return e.into_response(),
+    };
+    let Some(level_iri) = state.roles.role_iri(form.rank) else {
+        return (StatusCode::BAD_REQUEST, "rank does not match a role").into_response();
+    };
+    let result = set_access_level(&state, &policy, "liqk:AccessPolicy", &level_iri).await;
+    applied(&state, result, &addr, "change policy rank", &policy)
+}
+
+/// POST /gate/admin/users/:uuid/rank - Change a directory user's rank
+pub async fn user_rank_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(uuid_str): Path<String>,
+    Form(form): Form<RankForm>,
+) -> Response {
+    let user = match admin_target(&state, &addr, &headers, &jar, &uuid_str).await {
+        Ok(uuid) => uuid,
+        Err(e) => return e.into_response(),
+    };
+    let Some(level_iri) = state.roles.role_iri(form.rank) else {
+        return (StatusCode::BAD_REQUEST, "rank does not match a role").into_response();
+    };
+    let result = set_access_level(&state, &user, "liqk:DirectoryUser", &level_iri).await;
+    applied(&state, result, &addr, "change user rank", &user)
+}
+
+/// POST /gate/admin/users/:uuid/revoke - Remove a directory user from the graph
+pub async fn revoke_user_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(uuid_str): Path<String>,
+) -> Response {
+    let user = match admin_target(&state, &addr, &headers, &jar, &uuid_str).await {
+        Ok(uuid) => uuid,
+        Err(e) => return e.into_response(),
+    };
+    let result = delete_user(&state, &user).await;
+    applied(&state, result, &addr, "revoke user", &user)
+}
@@ -0,0 +1,441 @@
+//! ZIP downloads of a directory subtree.
+//!
+//! The archive is produced while it is sent: entries are stored without
+//! compression, each file's CRC-32 is computed as it is read and written
+//! after its content in a data descriptor, and the central directory
+//! follows the last entry. Files of 4 GiB and more, and entries or a
+//! central directory starting past 4 GiB, get zip64 records, so archives
+//! are not limited in size. Nothing is staged on disk.
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use futures_util::{stream, StreamExt};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+use tokio::io::AsyncReadExt;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::browse::{parse_urn_uuid, RANK_CHECK_CONCURRENCY};
+use crate::files::{attachment, binding_value, get_access_rank, sparql_select, FILESYSTEM_GRAPH};
+use crate::AppState;
+
+/// Read buffer for the content of archived files
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Largest value of a 32-bit size or offset field; larger values go to a
+/// zip64 extra field
+const ZIP32_MAX: u64 = 0xFFFF_FFFF;
+/// Most entries the end of central directory record counts
+const ZIP32_MAX_ENTRIES: u64 = 0xFFFF;
+
+const LOCAL_HEADER_SIG: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR_SIG: u32 = 0x0807_4b50;
+const CENTRAL_HEADER_SIG: u32 = 0x0201_4b50;
+const ZIP64_END_SIG: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIG: u32 = 0x0706_4b50;
+const END_SIG: u32 = 0x0605_4b50;
+
+/// Version needed to extract: 2.0, or 4.5 for zip64
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+/// Made by: Unix (for the file modes), spec 4.5
+const VERSION_MADE_BY: u16 = (3 << 8) | VERSION_ZIP64;
+/// Sizes and CRC follow the content in a data descriptor
+const FLAG_DATA_DESCRIPTOR: u16 = 0x0008;
+/// Names are UTF-8
+const FLAG_UTF8: u16 = 0x0800;
+const ZIP64_EXTRA_TAG: u16 = 0x0001;
+const FILE_MODE: u32 = 0o100644;
+const DIR_MODE: u32 = 0o040755;
+/// MS-DOS directory attribute
+const DOS_DIRECTORY: u32 = 0x10;
+
+/// A file or directory of the archive
+pub struct ArchiveEntry {
+    /// Path inside the archive; directories end with `/`
+    pub name: String,
+    /// `liqk:storedAs` of a file, `None` for a directory
+    pub stored_as: Option<String>,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// Labels that can be a path segment of an archive entry
+fn is_entry_label(label: &str) -> bool {
+    !label.is_empty() && label != "." && label != ".." && !label.contains(['/', '\\', '\0'])
+}
+
+/// A node below the archived directory
+struct Child {
+    uuid: Uuid,
+    label: String,
+    stored_as: Option<String>,
+    written: Option<DateTime<Utc>>,
+}
+
+/// The entries of an archive of the subtree under `root`, named `prefix`
+/// in the archive, directories before their contents. Nodes the requester
+/// has less than `required` rank on are left out with everything below
+/// them; a node included in several directories is archived in each.
+pub async fn archive_entries(
+    state: &AppState,
+    root: &Uuid,
+    prefix: &str,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+    required: i32,
+) -> Result<Vec<ArchiveEntry>, String> {
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX dc: <http://purl.org/dc/terms/>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?parent ?node ?label ?storedAs ?written FROM <{graph}> WHERE {{
+    <urn:uuid:{root}> posix:includes* ?parent .
+    ?parent posix:includes ?node .
+    ?node rdfs:label ?label .
+    OPTIONAL {{ ?node liqk:storedAs ?storedAs }}
+    OPTIONAL {{ ?node dc:modified ?modified }}
+    OPTIONAL {{ ?node dc:created ?created }}
+    BIND(COALESCE(?modified, ?created) AS ?written)
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        root = root,
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+
+    let mut children: HashMap<Uuid, Vec<Child>> = HashMap::new();
+    for binding in &bindings {
+        let (Some(parent), Some(uuid), Some(label)) = (
+            binding_value(binding, "parent").and_then(parse_urn_uuid),
+            binding_value(binding, "node").and_then(parse_urn_uuid),
+            binding_value(binding, "label"),
+        ) else {
+            continue;
+        };
+        children.entry(parent).or_default().push(Child {
+            uuid,
+            label: label.to_string(),
+            stored_as: binding_value(binding, "storedAs").map(str::to_string),
+            written: binding_value(binding, "written")
+                .and_then(|w| DateTime::parse_from_rfc3339(w).ok())
+                .map(|w| w.with_timezone(&Utc)),
+        });
+    }
+
+    let nodes: HashSet<Uuid> = children.values().flatten().map(|child| child.uuid).collect();
+    let readable: HashSet<Uuid> = stream::iter(nodes)
+        .map(|node| async move { (node, get_access_rank(state, &node, headers, jar).await) })
+        .buffer_unordered(RANK_CHECK_CONCURRENCY)
+        .filter_map(|(node, rank)| async move { (rank >= required).then_some(node) })
+        .collect()
+        .await;
+
+    let mut entries = Vec::new();
+    // Directories on the way down, so a cycle in the graph ends the walk
+    let mut pending = vec![(*root, format!("{}/", prefix), vec![*root])];
+    while let Some((dir, path, ancestors)) = pending.pop() {
+        let Some(dir_children) = children.get(&dir) else {
+            continue;
+        };
+        for child in dir_children {
+            if !readable.contains(&child.uuid) || !is_entry_label(&child.label) || ancestors.contains(&child.uuid) {
+                continue;
+            }
+            let name = format!("{}{}", path, child.label);
+            match &child.stored_as {
+                Some(stored_as) => entries.push(ArchiveEntry {
+                    name,
+                    stored_as: Some(stored_as.clone()),
+                    modified: child.written,
+                }),
+                None => {
+                    let name = format!("{}/", name);
+                    entries.push(ArchiveEntry { name: name.clone(), stored_as: None, modified: child.written });
+                    let mut below = ancestors.clone();
+                    below.push(child.uuid);
+                    pending.push((child.uuid, name, below));
+                }
+            }
+        }
+    }
+    // Sorted by path, every directory comes right before its contents
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+/// MS-DOS time and date of an entry; the format starts in 1980
+fn dos_time(modified: Option<&DateTime<Utc>>) -> (u16, u16) {
+    let Some(t) = modified.filter(|t| (1980..2108).contains(&t.year())) else {
+        return (0, (1 << 5) | 1);
+    };
+    let time = (t.hour() << 11) | (t.minute() << 5) | (t.second() / 2);
+    let date = ((t.year() as u32 - 1980) << 9) | (t.month() << 5) | t.day();
+    (time as u16, date as u16)
+}
+
+/// A zip64 extra field with the values that did not fit their 32-bit
+/// fields, in the order the format requires
+fn zip64_extra(values: &[u64]) -> Vec<u8> {
+    let mut extra = Vec::with_capacity(4 + values.len() * 8);
+    extra.extend_from_slice(&ZIP64_EXTRA_TAG.to_le_bytes());
+    extra.extend_from_slice(&((values.len() * 8) as u16).to_le_bytes());
+    for value in values {
+        extra.extend_from_slice(&value.to_le_bytes());
+    }
+    extra
+}
+
+/// A 32-bit size or offset field: the value, or the zip64 marker
+fn field32(value: u64) -> u32 {
+    value.min(ZIP32_MAX) as u32
+}
+
+/// The file being written
+struct Current {
+    reader: tokio::io::Take<tokio::fs::File>,
+    crc: crc32fast::Hasher,
+    written: u64,
+    zip64: bool,
+    header: EntryHeader,
+}
+
+/// What the central directory records about an entry
+struct EntryHeader {
+    name: String,
+    time: u16,
+    date: u16,
+    offset: u64,
+    is_dir: bool,
+}
+
+/// Produces the archive chunk by chunk
+struct ZipWriter {
+    files_dir: PathBuf,
+    entries: std::vec::IntoIter<ArchiveEntry>,
+    current: Option<Current>,
+    /// Bytes produced so far
+    offset: u64,
+    central: Vec<u8>,
+    count: u64,
+    finished: bool,
+}
+
+impl ZipWriter {
+    fn new(files_dir: PathBuf, entries: Vec<ArchiveEntry>) -> Self {
+        Self {
+            files_dir,
+            entries: entries.into_iter(),
+            current: None,
+            offset: 0,
+            central: Vec::new(),
+            count: 0,
+            finished: false,
+        }
+    }
+
+    /// The next piece of the archive; `None` once it is complete
+    async fn next_chunk(&mut self) -> std::io::Result<Option<Bytes>> {
+        if let Some(current) = &mut self.current {
+            let mut buffer = vec![0u8; CHUNK_BYTES];
+            let read = current.reader.read(&mut buffer).await?;
+            if read > 0 {
+                buffer.truncate(read);
+                current.crc.update(&buffer);
+                current.written += read as u64;
+                return Ok(Some(self.emit(buffer)));
+            }
+            let current = self.current.take().expect("current entry");
+            return Ok(Some(self.finish_file(current)));
+        }
+
+        while let Some(entry) = self.entries.next() {
+            let (time, date) = dos_time(entry.modified.as_ref());
+            let header = EntryHeader { name: entry.name, time, date, offset: self.offset, is_dir: entry.stored_as.is_none() };
+            let Some(stored_as) = entry.stored_as else {
+                let local = self.local_header(&header, false);
+                self.central_header(&header, false, 0, 0);
+                return Ok(Some(self.emit(local)));
+            };
+            // A stored file that cannot be opened is left out; once its
+            // header is sent, a read error aborts the archive
+            let file = match tokio::fs::File::open(self.files_dir.join(&stored_as)).await {
+                Ok(file) => file,
+                Err(e) => {
+                    warn!(stored_as = %stored_as, entry = %header.name, error = %e, "Stored file left out of archive");
+                    continue;
+                }
+            };
+            let size = file.metadata().await?.len();
+            let zip64 = size >= ZIP32_MAX;
+            let local = self.local_header(&header, zip64);
+            self.current = Some(Current {
+                reader: file.take(size),
+                crc: crc32fast::Hasher::new(),
+                written: 0,
+                zip64,
+                header,
+            });
+            return Ok(Some(self.emit(local)));
+        }
+
+        if self.finished {
+            return Ok(None);
+        }
+        self.finished = true;
+        Ok(Some(self.end_records()))
+    }
+
+    fn emit(&mut self, chunk: Vec<u8>) -> Bytes {
+        self.offset += chunk.len() as u64;
+        Bytes::from(chunk)
+    }
+
+    fn local_header(&self, header: &EntryHeader, zip64: bool) -> Vec<u8> {
+        let flags = if header.is_dir { FLAG_UTF8 } else { FLAG_UTF8 | FLAG_DATA_DESCRIPTOR };
+        // Sizes follow in the data descriptor; a zip64 entry says so with
+        // the marker and a zeroed extra field
+        let (size, extra) = if zip64 { (ZIP32_MAX as u32, zip64_extra(&[0, 0])) } else { (0, Vec::new()) };
+        let mut out = Vec::with_capacity(30 + header.name.len() + extra.len());
+        out.extend_from_slice(&LOCAL_HEADER_SIG.to_le_bytes());
+        out.extend_from_slice(&(if zip64 { VERSION_ZIP64 } else { VERSION_DEFAULT }).to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes()); // stored
+        out.extend_from_slice(&header.time.to_le_bytes());
+        out.extend_from_slice(&header.date.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes()); // CRC-32
+        out.extend_from_slice(&size.to_le_bytes());
+        out.extend_from_slice(&size.to_le_bytes());
+        out.extend_from_slice(&(header.name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        out.extend_from_slice(header.name.as_bytes());
+        out.extend_from_slice(&extra);
+        out
+    }
+
+    /// The data descriptor closing a file, whose central directory header
+    /// is recorded for the end of the archive
+    fn finish_file(&mut self, current: Current) -> Bytes {
+        let crc = current.crc.finalize();
+        let mut out = Vec::with_capacity(24);
+        out.extend_from_slice(&DATA_DESCRIPTOR_SIG.to_le_bytes());
+        out.extend_from_slice(&crc.to_le_bytes());
+        if current.zip64 {
+            out.extend_from_slice(&current.written.to_le_bytes());
+            out.extend_from_slice(&current.written.to_le_bytes());
+        } else {
+            out.extend_from_slice(&(current.written as u32).to_le_bytes());
+            out.extend_from_slice(&(current.written as u32).to_le_bytes());
+        }
+        self.central_header(&current.header, current.zip64, crc, current.written);
+        self.emit(out)
+    }
+
+    fn central_header(&mut self, header: &EntryHeader, zip64: bool, crc: u32, size: u64) {
+        let mut values = Vec::new();
+        if zip64 {
+            values.extend([size, size]);
+        }
+        if header.offset >= ZIP32_MAX {
+            values.push(header.offset);
+        }
+        let extra = if values.is_empty() { Vec::new() } else { zip64_extra(&values) };
+        let size32 = if zip64 { ZIP32_MAX as u32 } else { size as u32 };
+        let version = if values.is_empty() { VERSION_DEFAULT } else { VERSION_ZIP64 };
+        let (flags, attributes) = if header.is_dir {
+            (FLAG_UTF8, (DIR_MODE << 16) | DOS_DIRECTORY)
+        } else {
+            (FLAG_UTF8 | FLAG_DATA_DESCRIPTOR, FILE_MODE << 16)
+        };
+
+        let out = &mut self.central;
+        out.extend_from_slice(&CENTRAL_HEADER_SIG.to_le_bytes());
+        out.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+        out.extend_from_slice(&version.to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes()); // stored
+        out.extend_from_slice(&header.time.to_le_bytes());
+        out.extend_from_slice(&header.date.to_le_bytes());
+        out.extend_from_slice(&crc.to_le_bytes());
+        out.extend_from_slice(&size32.to_le_bytes());
+        out.extend_from_slice(&size32.to_le_bytes());
+        out.extend_from_slice(&(header.name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes()); // comment
+        out.extend_from_slice(&0u16.to_le_bytes()); // disk
+        out.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        out.extend_from_slice(&attributes.to_le_bytes());
+        out.extend_from_slice(&field32(header.offset).to_le_bytes());
+        out.extend_from_slice(header.name.as_bytes());
+        out.extend_from_slice(&extra);
+        self.count += 1;
+    }
+
+    /// The central directory and the end records, with zip64 ones when a
+    /// count, size or offset does not fit the classic record
+    fn end_records(&mut self) -> Bytes {
+        let start = self.offset;
+        let size = self.central.len() as u64;
+        let mut out = std::mem::take(&mut self.central);
+        if self.count >= ZIP32_MAX_ENTRIES || start >= ZIP32_MAX || size >= ZIP32_MAX {
+            let zip64_end = start + size;
+            out.extend_from_slice(&ZIP64_END_SIG.to_le_bytes());
+            out.extend_from_slice(&44u64.to_le_bytes()); // size of the rest of the record
+            out.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+            out.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            out.extend_from_slice(&0u32.to_le_bytes()); // disk
+            out.extend_from_slice(&0u32.to_le_bytes()); // disk of the central directory
+            out.extend_from_slice(&self.count.to_le_bytes());
+            out.extend_from_slice(&self.count.to_le_bytes());
+            out.extend_from_slice(&size.to_le_bytes());
+            out.extend_from_slice(&start.to_le_bytes());
+            out.extend_from_slice(&ZIP64_LOCATOR_SIG.to_le_bytes());
+            out.extend_from_slice(&0u32.to_le_bytes()); // disk of the zip64 end record
+            out.extend_from_slice(&zip64_end.to_le_bytes());
+            out.extend_from_slice(&1u32.to_le_bytes()); // disks
+        }
+        let count = self.count.min(ZIP32_MAX_ENTRIES) as u16;
+        out.extend_from_slice(&END_SIG.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes()); // disk
+        out.extend_from_slice(&0u16.to_le_bytes()); // disk of the central directory
+        out.extend_from_slice(&count.to_le_bytes());
+        out.extend_from_slice(&count.to_le_bytes());
+        out.extend_from_slice(&field32(size).to_le_bytes());
+        out.extend_from_slice(&field32(start).to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes()); // comment
+        self.emit(out)
+    }
+}
+
+/// Stream the archive of `entries`, offered for download as `{name}.zip`
+pub fn zip_response(files_dir: &str, name: &str, entries: Vec<ArchiveEntry>) -> Response {
+    let writer = ZipWriter::new(PathBuf::from(files_dir), entries);
+    let body = stream::unfold(Some(writer), |writer| async move {
+        let mut writer = writer?;
+        match writer.next_chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), Some(writer))),
+            Ok(None) => None,
+            Err(e) => {
+                warn!(error = %e, "Archive aborted");
+                Some((Err(e), None))
+            }
+        }
+    });
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, attachment(&format!("{}.zip", name))),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
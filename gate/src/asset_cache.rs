@@ -0,0 +1,163 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use crate::etag::is_not_modified;
+
+/// File extensions cached when `ASSET_CACHE_EXTENSIONS` is unset
+pub const DEFAULT_ASSET_EXTENSIONS: &str = "js,css,svg,png,ico,woff,woff2,map";
+/// Default client `Cache-Control: max-age` for assets in seconds (1 day)
+pub const DEFAULT_ASSET_MAX_AGE_SECS: u64 = 86_400;
+
+/// How long a cached asset is served before it is revalidated upstream
+const REVALIDATE_AFTER: Duration = Duration::from_secs(60);
+/// Assets larger than this are passed through uncached (8 MiB)
+const MAX_ENTRY_BYTES: usize = 8 * 1024 * 1024;
+/// Upper bound for all cached bodies together (64 MiB)
+const MAX_TOTAL_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Clone)]
+pub struct CachedAsset {
+    pub etag: Option<String>,
+    headers: HeaderMap,
+    body: Bytes,
+    checked: Instant,
+}
+
+impl CachedAsset {
+    /// Whether the asset was checked upstream recently enough to serve as is
+    pub fn is_fresh(&self) -> bool {
+        self.checked.elapsed() < REVALIDATE_AFTER
+    }
+}
+
+/// In-memory cache of the Oxigraph UI's static assets (scripts, styles,
+/// fonts, images), so page loads over slow links do not refetch them
+/// through the proxy.
+///
+/// Entries are keyed by path and `Accept-Encoding`, since the stored body
+/// keeps the upstream `Content-Encoding`. Stale entries with an `ETag` are
+/// revalidated with `If-None-Match`. The cache sits behind the rank check,
+/// so it never serves a client who could not fetch the asset upstream.
+pub struct AssetCache {
+    extensions: Vec<String>,
+    /// Client `Cache-Control: max-age` in seconds
+    max_age_secs: u64,
+    entries: RwLock<HashMap<String, CachedAsset>>,
+}
+
+impl AssetCache {
+    /// An empty extension list disables the cache
+    pub fn new(extensions: Vec<String>, max_age_secs: u64) -> Self {
+        Self {
+            extensions: extensions.into_iter().map(|e| e.to_lowercase()).collect(),
+            max_age_secs,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Parse `ASSET_CACHE_EXTENSIONS`: extensions separated by `,`, with or
+    /// without the leading dot
+    pub fn parse_extensions(value: &str) -> Vec<String> {
+        value
+            .split(',')
+            .map(|e| e.trim().trim_start_matches('.').to_string())
+            .filter(|e| !e.is_empty())
+            .collect()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.extensions.is_empty()
+    }
+
+    /// Whether a request fetches a cacheable asset
+    pub fn is_asset(&self, method: &Method, path: &str) -> bool {
+        if !matches!(*method, Method::GET | Method::HEAD) {
+            return false;
+        }
+        let file_name = path.rsplit('/').next().unwrap_or("");
+        match file_name.rsplit_once('.') {
+            Some((_, ext)) => self.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)),
+            None => false,
+        }
+    }
+
+    /// Cache key for a request: path and query plus the accepted encodings
+    pub fn key(path_and_query: &str, headers: &HeaderMap) -> String {
+        let accept_encoding = headers
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        format!("{}\n{}", path_and_query, accept_encoding)
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedAsset> {
+        self.entries.read().unwrap().get(key).cloned()
+    }
+
+    /// Store a `200` response. Oversized bodies are not cached; when the
+    /// cache is full, the least recently checked entries are dropped.
+    pub fn insert(&self, key: String, headers: &HeaderMap, body: Bytes) {
+        if body.len() > MAX_ENTRY_BYTES {
+            return;
+        }
+        let etag = headers.get(header::ETAG).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let mut entries = self.entries.write().unwrap();
+        entries.remove(&key);
+
+        let mut total: usize = entries.values().map(|a| a.body.len()).sum();
+        while total + body.len() > MAX_TOTAL_BYTES {
+            let Some(oldest) = entries.iter().min_by_key(|(_, a)| a.checked).map(|(k, _)| k.clone()) else {
+                break;
+            };
+            if let Some(removed) = entries.remove(&oldest) {
+                total -= removed.body.len();
+            }
+        }
+
+        entries.insert(
+            key,
+            CachedAsset {
+                etag,
+                headers: headers.clone(),
+                body,
+                checked: Instant::now(),
+            },
+        );
+    }
+
+    /// Mark an entry as confirmed by upstream (a `304` to revalidation)
+    pub fn touch(&self, key: &str) {
+        if let Some(asset) = self.entries.write().unwrap().get_mut(key) {
+            asset.checked = Instant::now();
+        }
+    }
+
+    /// `Cache-Control` for asset responses. `private` keeps shared caches
+    /// from handing assets behind the gate to other users.
+    pub fn cache_control(&self) -> HeaderValue {
+        HeaderValue::from_str(&format!("private, max-age={}", self.max_age_secs))
+            .expect("Cache-Control value is ASCII")
+    }
+
+    /// Answer a request from a cached asset, with `304` when the client
+    /// already holds the same version
+    pub fn respond(&self, asset: &CachedAsset, request_headers: &HeaderMap) -> Response {
+        let not_modified = asset.etag.as_deref().is_some_and(|etag| is_not_modified(request_headers, etag));
+
+        let mut headers = asset.headers.clone();
+        headers.insert(header::CACHE_CONTROL, self.cache_control());
+        if not_modified {
+            headers.remove(header::CONTENT_LENGTH);
+            return (StatusCode::NOT_MODIFIED, headers).into_response();
+        }
+        (StatusCode::OK, headers, asset.body.clone()).into_response()
+    }
+}
@@ -1,39 +1,318 @@
+use async_trait::async_trait;
 use axum::{
-    extract::{ConnectInfo, State},
-    http::{header, HeaderMap},
+    extract::{ConnectInfo, FromRequestParts, Path, Request, State},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    middleware::Next,
     response::{Html, IntoResponse, Redirect, Response},
     Form,
 };
 use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use ring::rand::{SecureRandom, SystemRandom};
 use serde::Deserialize;
-use std::{env, net::SocketAddr, sync::Arc};
-use tracing::{info, warn};
+use std::{collections::HashMap, env, net::SocketAddr, sync::Arc, time::Duration};
+use tracing::{error, info, warn};
 
-use crate::files::{hash_token, verify_token_exists};
-use crate::templates::{LOGIN_ERROR_HTML, LOGIN_HTML};
+use crate::accept::AcceptRewrites;
+use crate::asset_cache::{AssetCache, DEFAULT_ASSET_EXTENSIONS, DEFAULT_ASSET_MAX_AGE_SECS};
+use crate::cors::{CorsConfig, DEFAULT_CORS_METHODS};
+use crate::destructive::default_protected_graphs;
+use crate::encoding::DEFAULT_COMPRESS_MIN_BYTES;
+use crate::etag::DEFAULT_QUERY_ETAG_MAX_MB;
+use crate::files::{
+    credential, fingerprint, get_access_rank, get_access_rank_iri, hash_token, validate_uuid, verify_token_exists,
+    Credential, UploadConflict, DEFAULT_UPLOAD_CONFLICT,
+};
+use crate::github::{GithubConfig, DEFAULT_GITHUB_API_URL, DEFAULT_GITHUB_URL};
+use crate::jwt::{DEFAULT_JWT_ISSUER, DEFAULT_JWT_TTL_SECS};
+use crate::home::HomePage;
+use crate::idempotency::DEFAULT_IDEMPOTENCY_KEY_TTL_SECS;
+use crate::journal::DEFAULT_JOURNAL_FILE;
+use crate::ldap::LdapConfig;
+use crate::listen::{BindAddr, DEFAULT_BIND_ADDR};
+use crate::limits::{
+    BodyLimits, DEFAULT_QUERY_BODY_LIMIT_MB, DEFAULT_UPDATE_BODY_LIMIT_MB, DEFAULT_UPLOAD_BODY_LIMIT_MB,
+};
+use crate::login_audit::record_failure;
+use crate::metrics::DEFAULT_METRICS_PERMISSION;
+use crate::notify::{
+    emergency_token_used, successful_login, NotifyConfig, DEFAULT_FAILED_LOGIN_THRESHOLD, EVENT_NAMES,
+};
+use crate::proxy::{
+    UpstreamPool, DEFAULT_UPSTREAM_CONNECT_TIMEOUT_SECS, DEFAULT_UPSTREAM_POOL_IDLE_TIMEOUT_SECS,
+    DEFAULT_UPSTREAM_TIMEOUT_SECS, DEFAULT_UPSTREAM_WRITE_TIMEOUT_SECS,
+};
+use crate::proxy_auth::{ProxyAuthConfig, DEFAULT_USER_HEADERS};
+use crate::path_cache::DEFAULT_PATH_CACHE_TTL_SECS;
+use crate::query_limits::{QueryLimits, DEFAULT_QUERY_LIMITS_EXEMPT_RANK};
+use crate::query_log::Redaction;
+use crate::query_cache::DEFAULT_QUERY_CACHE_MAX_MB;
+use crate::quotas::DEFAULT_STORAGE_QUOTA_MB;
+use crate::rank_cache::DEFAULT_RANK_CACHE_TTL_SECS;
+use crate::rate_limit::{Rate, RateLimiter};
+use crate::replicas::{Replicas, DEFAULT_REPLICA_CHECK_INTERVAL_SECS};
+use crate::results::DEFAULT_RESULT_CONVERSION_MAX_MB;
+use crate::roles::permission_iri;
+use crate::rules::Rules;
+use crate::security_headers::{
+    SecurityHeaders, DEFAULT_CONTENT_SECURITY_POLICY, DEFAULT_HSTS_MAX_AGE_SECS, DEFAULT_REFERRER_POLICY,
+};
+use crate::shadow::Shadow;
+use crate::share::DEFAULT_SHARE_MAX_TTL_SECS;
+use crate::telemetry::export_configured;
+use crate::templates::{
+    escape_html, render_page, GITHUB_LOGIN_HTML, HOME_HTML, LOGIN_ERROR_HTML, LOGIN_HTML, PASSWORD_FORM_HTML,
+};
+use crate::tls::{CertSource, TlsConfig, DEFAULT_ACME_CACHE_DIR, DEFAULT_TLS_BIND_ADDR};
+use crate::trash::DEFAULT_TRASH_RETENTION_DAYS;
+use crate::upstreams::Upstreams;
 use crate::AppState;
 
 pub const DEFAULT_OXIGRAPH_URL: &str = "http://localhost:7878";
 pub const ENV_FILE: &str = ".env";
-pub const TOKEN_COOKIE_NAME: &str = "oxigraph_gate_token";
+pub const DEFAULT_COOKIE_NAME: &str = "oxigraph_gate_token";
+/// Prefix browsers only accept on Secure, host-only cookies with `Path=/`
+const HOST_COOKIE_PREFIX: &str = "__Host-";
 /// Session cookie max age in seconds (3 months / ~90 days)
 pub const SESSION_MAX_AGE_SECS: i64 = 7_776_000;
+/// Days without activity after which a browser session must log in again
+pub const DEFAULT_SESSION_IDLE_DAYS: i64 = 14;
 
 pub const DEFAULT_FILES_DIR: &str = "../files";
+/// Shortest accepted `EMERGENCY_TOKEN` or `METRICS_TOKEN`
+const MIN_EMERGENCY_TOKEN_LEN: usize = 32;
+
+/// Attributes of the session cookie (`COOKIE_*`)
+pub struct CookieConfig {
+    pub name: String,
+    /// `Domain` attribute; host-only when unset
+    pub domain: Option<String>,
+    pub path: String,
+    pub same_site: SameSite,
+}
+
+impl CookieConfig {
+    pub fn parse_same_site(value: &str) -> Result<SameSite, String> {
+        match value.trim().to_lowercase().as_str() {
+            "strict" => Ok(SameSite::Strict),
+            "lax" => Ok(SameSite::Lax),
+            "none" => Ok(SameSite::None),
+            other => Err(format!("unknown SameSite mode {} (expected strict, lax or none)", other)),
+        }
+    }
+
+    /// Reject combinations browsers would silently drop the cookie for
+    pub fn validate(&self, secure: bool) -> Result<(), String> {
+        if self.name.starts_with(HOST_COOKIE_PREFIX) {
+            if !secure {
+                return Err(format!("{} cookies require SECURE_COOKIES=true", HOST_COOKIE_PREFIX));
+            }
+            if self.domain.is_some() {
+                return Err(format!("{} cookies cannot set COOKIE_DOMAIN", HOST_COOKIE_PREFIX));
+            }
+            if self.path != "/" {
+                return Err(format!("{} cookies must use COOKIE_PATH=/", HOST_COOKIE_PREFIX));
+            }
+        }
+        if self.same_site == SameSite::None && !secure {
+            return Err("COOKIE_SAMESITE=none requires SECURE_COOKIES=true".to_string());
+        }
+        if !self.path.starts_with('/') {
+            return Err("COOKIE_PATH must start with /".to_string());
+        }
+        Ok(())
+    }
+
+    /// Cookie path for a gate route, below `path` when the gate is
+    /// served under a sub-path
+    pub fn sub_path(&self, route: &str) -> String {
+        format!("{}{}", self.path.trim_end_matches('/'), route)
+    }
+
+    /// Expired cookie clearing the session cookie. Name, path and domain
+    /// must match; browsers ignore a `__Host-` cookie without Secure.
+    pub fn removal(&self, secure: bool) -> Cookie<'static> {
+        let mut cookie = Cookie::build(self.name.clone()).path(self.path.clone()).secure(secure);
+        if let Some(domain) = &self.domain {
+            cookie = cookie.domain(domain.clone());
+        }
+        cookie.build()
+    }
+}
 
 /// Configuration loaded from environment
 pub struct GateConfig {
     pub oxigraph_url: String,
+    /// Read replicas `/query` requests are spread over
+    pub replicas: Replicas,
+    /// Seconds between replica health checks
+    pub replica_check_interval_secs: u64,
+    /// Secondary Oxigraph queries are mirrored to
+    pub shadow: Shadow,
     /// Whether to set Secure flag on cookies (requires HTTPS)
     pub secure_cookies: bool,
+    /// Session cookie name, scope and SameSite mode
+    pub cookie: CookieConfig,
     /// Directory for file storage
     pub files_dir: String,
+    /// What uploads do when a name is taken in their target directory
+    pub upload_conflict: UploadConflict,
+    /// Days a trashed item is kept before it is purged (0 keeps it)
+    pub trash_retention_days: u64,
+    /// Bytes each identity may store, in MiB (0 for no quota)
+    pub storage_quota_mb: u64,
+    /// Break-glass token granting full access regardless of the access graph
+    pub emergency_token: Option<String>,
+    /// LDAP login backend (enabled when `LDAP_URL` is set)
+    pub ldap: Option<LdapConfig>,
+    /// GitHub OAuth login (enabled when `GITHUB_CLIENT_ID` is set)
+    pub github: Option<GithubConfig>,
+    /// Key for signing share links; random per process when unset
+    pub share_secret: Option<String>,
+    /// Upper bound for share link lifetimes in seconds
+    pub share_max_ttl_secs: i64,
+    /// Public base URL of the gate, for absolute links in manifests
+    pub public_url: Option<String>,
+    /// Lifetime of cached access ranks in seconds (0 disables the cache)
+    pub rank_cache_ttl_secs: u64,
+    /// Lifetime of cached path resolutions in seconds (0 disables the cache)
+    pub path_cache_ttl_secs: u64,
+    /// Lifetime of cached query results in seconds (0 disables the cache)
+    pub query_cache_ttl_secs: u64,
+    /// Seconds an update's `Idempotency-Key` is remembered (0 disables keys)
+    pub idempotency_key_ttl_secs: u64,
+    /// Upper bound for all cached query results together in MiB
+    pub query_cache_max_mb: usize,
+    /// Largest query result converted to XML, CSV or TSV in MiB (0 disables)
+    pub result_conversion_max_mb: usize,
+    /// Largest query result buffered only to be given an ETag in MiB (0 disables)
+    pub query_etag_max_mb: usize,
+    /// Request body limits (from `*_BODY_LIMIT_MB`)
+    pub body_limits: BodyLimits,
+    /// Shape limits on queries from low-rank requesters
+    pub query_limits: QueryLimits,
+    /// Rows per page of unbounded SELECT results (0 disables pagination)
+    pub query_page_size: u64,
+    /// Request rate limits on the proxy (from `RATE_LIMIT_*`)
+    pub rate_limiter: RateLimiter,
+    /// Inactivity timeout for browser sessions in days (0 disables)
+    pub session_idle_days: i64,
+    /// Gzip request bodies sent to Oxigraph
+    pub upstream_gzip: bool,
+    /// Seconds to wait for a connection to Oxigraph (0 waits indefinitely)
+    pub upstream_connect_timeout_secs: u64,
+    /// Seconds Oxigraph may take to answer a query (0 waits indefinitely)
+    pub upstream_timeout_secs: u64,
+    /// Seconds Oxigraph may take to answer an update or bulk load
+    pub upstream_write_timeout_secs: u64,
+    /// Connection pool of the upstream clients
+    pub upstream_pool: UpstreamPool,
+    /// Prefix → upstream routes for proxied requests (from `UPSTREAM_ROUTES`)
+    pub upstreams: Upstreams,
+    /// Per-route `Accept` for clients naming no media type (from `ACCEPT_REWRITES`)
+    pub accept_rewrites: AcceptRewrites,
+    /// Render Graph Store reads as pages for browsers
+    pub store_pages: bool,
+    /// Cross-origin policy (from `CORS_ALLOWED_*`)
+    pub cors: CorsConfig,
+    /// Compress responses for clients that accept gzip or brotli
+    pub compress_responses: bool,
+    /// Size below which responses are not compressed
+    pub compress_min_bytes: u16,
+    /// Only allow updates to graphs the requester can edit
+    pub restrict_update_graphs: bool,
+    /// Scope queries lacking the endpoint rank to the graphs the requester can read
+    pub restrict_query_graphs: bool,
+    /// Named graphs anonymous clients may query
+    pub public_graphs: Vec<String>,
+    /// Graphs whose `CLEAR`/`DROP` needs admin access or a confirmation
+    pub protected_graphs: Vec<String>,
+    /// Reverse-proxy header authentication (enabled by `TRUSTED_PROXY_IPS`)
+    pub proxy_auth: Option<ProxyAuthConfig>,
+    /// Path-based authorization rules (from `AUTH_RULES` or built in)
+    pub rules: Rules,
+    /// Plugins to mount (from `PLUGINS`); all compiled-in ones when unset
+    pub plugins: Option<Vec<String>>,
+    /// What `GET /` shows
+    pub home_page: HomePage,
+    /// PEM file with the Ed25519 JWT signing key; random per process when unset
+    pub jwt_key_file: Option<String>,
+    /// Auth event notifications (enabled by `NOTIFY_WEBHOOK_URL` or `NOTIFY_COMMAND`)
+    pub notify: Option<NotifyConfig>,
+    /// Extensions of proxied UI assets to cache (empty disables)
+    pub asset_cache_extensions: Vec<String>,
+    /// Client `Cache-Control: max-age` for cached assets in seconds
+    pub asset_cache_max_age_secs: u64,
+    /// `iss` claim of issued JWTs
+    pub jwt_issuer: String,
+    /// Lifetime of issued JWTs in seconds
+    pub jwt_ttl_secs: i64,
+    /// Export spans over OTLP (`OTEL_EXPORTER_OTLP_ENDPOINT` is set)
+    pub trace_export: bool,
+    /// Log the SPARQL the gate generates per request
+    pub query_log: bool,
+    /// Literal values masked in logged queries
+    pub query_log_redaction: Redaction,
+    /// Round trip in milliseconds above which proxied queries are logged (0 disables)
+    pub slow_query_ms: u64,
+    /// Write-ahead journal of gate-initiated updates; `None` disables it
+    pub journal_file: Option<String>,
+    /// Start in read-only maintenance mode
+    pub read_only: bool,
+    /// Token that lets a scraper read `/gate/metrics`
+    pub metrics_token: Option<String>,
+    /// Permission IRI whose rank grants access to `/gate/metrics`
+    pub metrics_permission: String,
+    /// Where plain HTTP is served: a TCP address or a Unix socket
+    pub bind_addr: BindAddr,
+    /// Permissions given to the Unix socket; left to the umask when `None`
+    pub unix_socket_mode: Option<u32>,
+    /// Built-in HTTPS, when a certificate or ACME domains are configured
+    pub tls: Option<TlsConfig>,
+    /// Headers set on gate pages and optionally proxied responses
+    pub security_headers: SecurityHeaders,
 }
 
-pub fn load_config() -> GateConfig {
+/// An authenticated principal produced by a password-based backend
+pub struct Principal {
+    /// Stable identity (e.g. an LDAP DN)
+    pub identity: String,
+    /// Access rank granted on every resource for the session
+    pub rank: i32,
+}
+
+/// A username/password login backend. Successful logins create a
+/// server-side session instead of storing a token in the cookie.
+#[async_trait]
+pub trait AuthBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Returns `Ok(None)` when the credentials are rejected
+    async fn authenticate(&self, username: &str, password: &str) -> Result<Option<Principal>, String>;
+}
+
+/// Load `.env` into the environment, without overriding variables already set
+pub fn load_env_file() {
     let _ = dotenvy::from_filename(ENV_FILE);
+}
 
+/// Read the configuration from the environment, after `load_env_file` and
+/// `decrypt_env`
+pub fn load_config() -> GateConfig {
     let oxigraph_url = env::var("OXIGRAPH_URL").unwrap_or_else(|_| DEFAULT_OXIGRAPH_URL.to_string());
+    let replicas = match env::var("OXIGRAPH_REPLICA_URLS") {
+        Ok(value) => Replicas::parse(&value).unwrap_or_else(|e| panic!("Invalid OXIGRAPH_REPLICA_URLS: {}", e)),
+        Err(_) => Replicas::default(),
+    };
+    let replica_check_interval_secs = env::var("REPLICA_CHECK_INTERVAL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&secs| secs > 0)
+        .unwrap_or(DEFAULT_REPLICA_CHECK_INTERVAL_SECS);
+    let shadow = match env::var("SHADOW_URL") {
+        Ok(value) => Shadow::parse(&value).unwrap_or_else(|e| panic!("Invalid SHADOW_URL: {}", e)),
+        Err(_) => Shadow::default(),
+    };
 
     // SECURE_COOKIES: Set to "false" only for local development without HTTPS
     // In production, this should always be true (the default)
@@ -41,36 +320,594 @@ pub fn load_config() -> GateConfig {
         .map(|v| v.to_lowercase() != "false")
         .unwrap_or(true);
 
+    let cookie_name = env::var("COOKIE_NAME")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| DEFAULT_COOKIE_NAME.to_string());
+    let host_prefix = env::var("COOKIE_HOST_PREFIX")
+        .map(|v| v.to_lowercase() == "true")
+        .unwrap_or(false);
+    let cookie = CookieConfig {
+        name: if host_prefix && !cookie_name.starts_with(HOST_COOKIE_PREFIX) {
+            format!("{}{}", HOST_COOKIE_PREFIX, cookie_name)
+        } else {
+            cookie_name
+        },
+        domain: env::var("COOKIE_DOMAIN").ok().filter(|s| !s.is_empty()),
+        path: env::var("COOKIE_PATH")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "/".to_string()),
+        same_site: match env::var("COOKIE_SAMESITE") {
+            Ok(value) => CookieConfig::parse_same_site(&value)
+                .unwrap_or_else(|e| panic!("Invalid COOKIE_SAMESITE: {}", e)),
+            Err(_) => SameSite::Strict,
+        },
+    };
+    if let Err(e) = cookie.validate(secure_cookies) {
+        panic!("Invalid cookie settings: {}", e);
+    }
+
     let files_dir = env::var("FILES_DIR").unwrap_or_else(|_| DEFAULT_FILES_DIR.to_string());
+    let upload_conflict = match env::var("UPLOAD_CONFLICT") {
+        Ok(value) => UploadConflict::parse(&value).unwrap_or_else(|e| panic!("Invalid UPLOAD_CONFLICT: {}", e)),
+        Err(_) => DEFAULT_UPLOAD_CONFLICT,
+    };
+    let trash_retention_days = env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS);
+    let storage_quota_mb = env::var("STORAGE_QUOTA_MB")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_STORAGE_QUOTA_MB);
+
+    let emergency_token = env::var("EMERGENCY_TOKEN").ok().filter(|s| !s.is_empty());
+    if emergency_token.as_ref().is_some_and(|t| t.len() < MIN_EMERGENCY_TOKEN_LEN) {
+        panic!("EMERGENCY_TOKEN must be at least {} characters", MIN_EMERGENCY_TOKEN_LEN);
+    }
+
+    let ldap = env::var("LDAP_URL").ok().map(|url| LdapConfig {
+        url,
+        user_dn_template: env::var("LDAP_USER_DN")
+            .expect("LDAP_USER_DN must be set when LDAP_URL is set"),
+        ranks: env::var("LDAP_RANKS")
+            .map(|v| LdapConfig::parse_ranks(&v))
+            .unwrap_or_default(),
+        default_rank: env::var("LDAP_DEFAULT_RANK")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0),
+    });
+
+    let github = env::var("GITHUB_CLIENT_ID").ok().filter(|s| !s.is_empty()).map(|client_id| GithubConfig {
+        client_id,
+        client_secret: env::var("GITHUB_CLIENT_SECRET")
+            .expect("GITHUB_CLIENT_SECRET must be set when GITHUB_CLIENT_ID is set"),
+        redirect_url: env::var("GITHUB_REDIRECT_URL").ok().filter(|s| !s.is_empty()),
+        users: env::var("GITHUB_USERS")
+            .map(|v| LdapConfig::parse_ranks(&v))
+            .unwrap_or_default(),
+        orgs: env::var("GITHUB_ORGS")
+            .map(|v| LdapConfig::parse_ranks(&v))
+            .unwrap_or_default(),
+        url: env::var("GITHUB_URL")
+            .map(|v| v.trim_end_matches('/').to_string())
+            .unwrap_or_else(|_| DEFAULT_GITHUB_URL.to_string()),
+        api_url: env::var("GITHUB_API_URL")
+            .map(|v| v.trim_end_matches('/').to_string())
+            .unwrap_or_else(|_| DEFAULT_GITHUB_API_URL.to_string()),
+    });
+
+    let share_secret = env::var("SHARE_SECRET").ok().filter(|s| !s.is_empty());
+    let share_max_ttl_secs = env::var("SHARE_MAX_TTL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_SHARE_MAX_TTL_SECS);
+    let public_url = env::var("PUBLIC_URL")
+        .ok()
+        .map(|v| v.trim_end_matches('/').to_string())
+        .filter(|s| !s.is_empty());
+
+    let rank_cache_ttl_secs = env::var("RANK_CACHE_TTL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_RANK_CACHE_TTL_SECS);
+
+    let path_cache_ttl_secs = env::var("PATH_CACHE_TTL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_PATH_CACHE_TTL_SECS);
+
+    let query_cache_ttl_secs = env::var("QUERY_CACHE_TTL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let idempotency_key_ttl_secs = env::var("IDEMPOTENCY_KEY_TTL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_IDEMPOTENCY_KEY_TTL_SECS);
+    let query_cache_max_mb = env::var("QUERY_CACHE_MAX_MB")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_QUERY_CACHE_MAX_MB);
+    let result_conversion_max_mb = env::var("RESULT_CONVERSION_MAX_MB")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_RESULT_CONVERSION_MAX_MB);
+    let query_etag_max_mb = env::var("QUERY_ETAG_MAX_MB")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_QUERY_ETAG_MAX_MB);
+
+    let body_limit_var = |name: &str, default: usize| {
+        env::var(name).ok().and_then(|v| v.parse().ok()).filter(|&mb| mb > 0).unwrap_or(default)
+    };
+    let body_limits = BodyLimits::from_mb(
+        body_limit_var("QUERY_BODY_LIMIT_MB", DEFAULT_QUERY_BODY_LIMIT_MB),
+        body_limit_var("UPDATE_BODY_LIMIT_MB", DEFAULT_UPDATE_BODY_LIMIT_MB),
+        body_limit_var("UPLOAD_BODY_LIMIT_MB", DEFAULT_UPLOAD_BODY_LIMIT_MB),
+    );
+
+    let rate_var = |class: &str| {
+        let var = |name: String| env::var(&name).ok().filter(|s| !s.is_empty()).map(|v| {
+            v.parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite() && *n >= 0.0)
+                .unwrap_or_else(|| panic!("Invalid {}: {}", name, v))
+        });
+        Rate::new(
+            var(format!("RATE_LIMIT_{}_RPS", class)).unwrap_or(0.0),
+            var(format!("RATE_LIMIT_{}_BURST", class)),
+        )
+    };
+    let query_limits = QueryLimits {
+        require_limit: env::var("QUERY_REQUIRE_LIMIT")
+            .map(|v| v.to_lowercase() == "true")
+            .unwrap_or(false),
+        max_triple_patterns: env::var("QUERY_MAX_TRIPLE_PATTERNS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0),
+        reject_transitive_paths: env::var("QUERY_REJECT_TRANSITIVE_PATHS")
+            .map(|v| v.to_lowercase() == "true")
+            .unwrap_or(false),
+        exempt_rank: env::var("QUERY_LIMITS_EXEMPT_RANK")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_QUERY_LIMITS_EXEMPT_RANK),
+    };
+
+    let query_page_size = env::var("QUERY_PAGE_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+
+    let rate_limiter = RateLimiter::new(rate_var("QUERY"), rate_var("UPDATE"), rate_var("DOWNLOAD"));
+
+    let session_idle_days = env::var("SESSION_IDLE_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_SESSION_IDLE_DAYS);
+
+    let upstream_gzip = env::var("UPSTREAM_GZIP")
+        .map(|v| v.to_lowercase() == "true")
+        .unwrap_or(false);
+
+    let upstream_timeout_var = |name: &str, default: u64| {
+        env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+    };
+    let upstream_connect_timeout_secs =
+        upstream_timeout_var("UPSTREAM_CONNECT_TIMEOUT", DEFAULT_UPSTREAM_CONNECT_TIMEOUT_SECS);
+    let upstream_timeout_secs = upstream_timeout_var("UPSTREAM_TIMEOUT", DEFAULT_UPSTREAM_TIMEOUT_SECS);
+    let upstream_write_timeout_secs =
+        upstream_timeout_var("UPSTREAM_WRITE_TIMEOUT", DEFAULT_UPSTREAM_WRITE_TIMEOUT_SECS);
+
+    let pool_idle_timeout_secs =
+        upstream_timeout_var("UPSTREAM_POOL_IDLE_TIMEOUT", DEFAULT_UPSTREAM_POOL_IDLE_TIMEOUT_SECS);
+    let upstream_pool = UpstreamPool {
+        max_idle_per_host: env::var("UPSTREAM_POOL_MAX_IDLE").ok().and_then(|v| v.parse().ok()),
+        idle_timeout: (pool_idle_timeout_secs > 0).then(|| Duration::from_secs(pool_idle_timeout_secs)),
+        http2_prior_knowledge: env::var("UPSTREAM_HTTP2")
+            .map(|v| v.to_lowercase() == "true")
+            .unwrap_or(false),
+        tcp_nodelay: env::var("UPSTREAM_TCP_NODELAY")
+            .map(|v| v.to_lowercase() != "false")
+            .unwrap_or(true),
+    };
+
+    let upstreams = match env::var("UPSTREAM_ROUTES") {
+        Ok(value) => Upstreams::parse(&value).unwrap_or_else(|e| panic!("Invalid UPSTREAM_ROUTES: {}", e)),
+        Err(_) => Upstreams::default(),
+    };
+    if !upstreams.is_empty() {
+        info!(routes = %env::var("UPSTREAM_ROUTES").unwrap_or_default(), "Upstream routes configured");
+    }
+
+    let accept_rewrites = match env::var("ACCEPT_REWRITES") {
+        Ok(value) => AcceptRewrites::parse(&value).unwrap_or_else(|e| panic!("Invalid ACCEPT_REWRITES: {}", e)),
+        Err(_) => AcceptRewrites::default(),
+    };
+    let store_pages = env::var("STORE_PAGES")
+        .map(|v| v.to_lowercase() != "false")
+        .unwrap_or(true);
+
+    let cors = CorsConfig::parse(
+        env::var("CORS_ALLOWED_ORIGINS").ok().as_deref(),
+        &env::var("CORS_ALLOWED_METHODS").unwrap_or_else(|_| DEFAULT_CORS_METHODS.to_string()),
+        env::var("CORS_ALLOWED_HEADERS").ok().as_deref(),
+    )
+    .unwrap_or_else(|e| panic!("Invalid CORS configuration: {}", e));
+
+    let compress_responses = env::var("COMPRESS_RESPONSES")
+        .map(|v| v.to_lowercase() == "true")
+        .unwrap_or(false);
+    let compress_min_bytes = env::var("COMPRESS_MIN_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_COMPRESS_MIN_BYTES);
+
+    let restrict_update_graphs = env::var("RESTRICT_UPDATE_GRAPHS")
+        .map(|v| v.to_lowercase() == "true")
+        .unwrap_or(false);
+    let restrict_query_graphs = env::var("RESTRICT_QUERY_GRAPHS")
+        .map(|v| v.to_lowercase() == "true")
+        .unwrap_or(false);
+
+    let public_graphs = env::var("PUBLIC_GRAPHS")
+        .map(|v| {
+            v.split(',')
+                .map(|g| g.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+                .filter(|g| !g.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let protected_graphs = env::var("PROTECTED_GRAPHS")
+        .map(|v| {
+            v.split(',')
+                .map(|g| g.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+                .filter(|g| !g.is_empty())
+                .collect()
+        })
+        .unwrap_or_else(|_| default_protected_graphs());
+
+    let proxy_auth = env::var("TRUSTED_PROXY_IPS").ok().map(|ips| ProxyAuthConfig {
+        trusted: ProxyAuthConfig::parse_trusted(&ips)
+            .unwrap_or_else(|e| panic!("Invalid TRUSTED_PROXY_IPS: {}", e)),
+        headers: env::var("PROXY_USER_HEADERS")
+            .unwrap_or_else(|_| DEFAULT_USER_HEADERS.to_string())
+            .split(',')
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty())
+            .collect(),
+        // Same `name:rank;...` format as LDAP_RANKS
+        ranks: env::var("PROXY_USER_RANKS")
+            .map(|v| LdapConfig::parse_ranks(&v))
+            .unwrap_or_default(),
+        default_rank: env::var("PROXY_DEFAULT_RANK")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0),
+    });
+
+    let rules = match env::var("AUTH_RULES") {
+        Ok(path) => {
+            let json = std::fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("Failed to read AUTH_RULES file {}: {}", path, e));
+            let rules = Rules::from_json(&json)
+                .unwrap_or_else(|e| panic!("Invalid AUTH_RULES file {}: {}", path, e));
+            info!(path = %path, "Authorization rules loaded");
+            rules
+        }
+        Err(_) => Rules::default(),
+    };
+
+    let plugins = env::var("PLUGINS").ok().map(|v| {
+        v.split(',')
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect()
+    });
+
+    let home_page = match env::var("HOME_PAGE").map(|v| v.to_lowercase()).as_deref() {
+        Ok("oxigraph") => HomePage::Oxigraph,
+        Ok("gate") | Err(_) => HomePage::Gate {
+            template: match env::var("HOME_TEMPLATE") {
+                Ok(path) => std::fs::read_to_string(&path)
+                    .unwrap_or_else(|e| panic!("Failed to read HOME_TEMPLATE file {}: {}", path, e)),
+                Err(_) => HOME_HTML.to_string(),
+            },
+        },
+        Ok(other) => panic!("Invalid HOME_PAGE: {} (expected gate or oxigraph)", other),
+    };
+
+    let jwt_key_file = env::var("JWT_KEY_FILE").ok().filter(|s| !s.is_empty());
+    let jwt_issuer = env::var("JWT_ISSUER").unwrap_or_else(|_| DEFAULT_JWT_ISSUER.to_string());
+    let jwt_ttl_secs = env::var("JWT_TTL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|ttl| *ttl > 0)
+        .unwrap_or(DEFAULT_JWT_TTL_SECS);
+
+    let notify_webhook_url = env::var("NOTIFY_WEBHOOK_URL").ok().filter(|s| !s.is_empty());
+    let notify_command = env::var("NOTIFY_COMMAND").ok().filter(|s| !s.is_empty());
+    let notify = (notify_webhook_url.is_some() || notify_command.is_some()).then(|| NotifyConfig {
+        webhook_url: notify_webhook_url,
+        command: notify_command,
+        events: match env::var("NOTIFY_EVENTS") {
+            Ok(events) => NotifyConfig::parse_events(&events)
+                .unwrap_or_else(|e| panic!("Invalid NOTIFY_EVENTS: {}", e)),
+            Err(_) => EVENT_NAMES.iter().map(|e| e.to_string()).collect(),
+        },
+        failed_login_threshold: env::var("NOTIFY_FAILED_LOGINS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_FAILED_LOGIN_THRESHOLD),
+    });
+
+    let asset_cache_extensions = AssetCache::parse_extensions(
+        &env::var("ASSET_CACHE_EXTENSIONS").unwrap_or_else(|_| DEFAULT_ASSET_EXTENSIONS.to_string()),
+    );
+    let asset_cache_max_age_secs = env::var("ASSET_CACHE_MAX_AGE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_ASSET_MAX_AGE_SECS);
+
+    let query_log = env::var("QUERY_LOG")
+        .map(|v| v.to_lowercase() == "true")
+        .unwrap_or(false);
+    let query_log_redaction = match env::var("QUERY_LOG_REDACT") {
+        Ok(value) => Redaction::parse(&value).unwrap_or_else(|e| panic!("Invalid QUERY_LOG_REDACT: {}", e)),
+        Err(_) => Redaction::Strings,
+    };
+    let slow_query_ms = env::var("SLOW_QUERY_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+
+    // JOURNAL_FILE: empty disables the journal
+    let journal_file = match env::var("JOURNAL_FILE") {
+        Ok(path) => Some(path).filter(|p| !p.is_empty()),
+        Err(_) => Some(DEFAULT_JOURNAL_FILE.to_string()),
+    };
+
+    let read_only = env::var("READ_ONLY")
+        .map(|v| v.to_lowercase() == "true")
+        .unwrap_or(false);
+
+    let metrics_token = env::var("METRICS_TOKEN").ok().filter(|s| !s.is_empty());
+    if metrics_token.as_ref().is_some_and(|t| t.len() < MIN_EMERGENCY_TOKEN_LEN) {
+        panic!("METRICS_TOKEN must be at least {} characters", MIN_EMERGENCY_TOKEN_LEN);
+    }
+    let metrics_permission =
+        permission_iri(&env::var("METRICS_PERMISSION").unwrap_or_else(|_| DEFAULT_METRICS_PERMISSION.to_string()));
+
+    let bind_addr = BindAddr::parse(&env::var("BIND_ADDR").unwrap_or_else(|_| DEFAULT_BIND_ADDR.to_string()))
+        .unwrap_or_else(|e| panic!("Invalid BIND_ADDR: {}", e));
+    let unix_socket_mode = env::var("UNIX_SOCKET_MODE").ok().filter(|s| !s.is_empty()).map(|v| {
+        u32::from_str_radix(&v, 8).unwrap_or_else(|e| panic!("Invalid UNIX_SOCKET_MODE: {}", e))
+    });
+
+    // TLS_CERT_FILE or ACME_DOMAINS turn on HTTPS
+    let list = |name: &str| -> Vec<String> {
+        env::var(name)
+            .map(|v| v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default()
+    };
+    let acme_domains = list("ACME_DOMAINS");
+    let cert_source = match env::var("TLS_CERT_FILE").ok().filter(|s| !s.is_empty()) {
+        Some(_) if !acme_domains.is_empty() => panic!("Invalid TLS settings: set TLS_CERT_FILE or ACME_DOMAINS, not both"),
+        Some(cert_file) => Some(CertSource::Files {
+            cert_file,
+            key_file: env::var("TLS_KEY_FILE")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .expect("TLS_KEY_FILE must be set when TLS_CERT_FILE is set"),
+        }),
+        None if !acme_domains.is_empty() => Some(CertSource::Acme {
+            domains: acme_domains,
+            contact: list("ACME_CONTACT"),
+            cache_dir: env::var("ACME_CACHE_DIR").unwrap_or_else(|_| DEFAULT_ACME_CACHE_DIR.to_string()),
+            staging: env::var("ACME_STAGING")
+                .map(|v| v.to_lowercase() == "true")
+                .unwrap_or(false),
+        }),
+        None => None,
+    };
+    let tls = cert_source.map(|source| TlsConfig {
+        bind_addr: env::var("TLS_BIND_ADDR").unwrap_or_else(|_| DEFAULT_TLS_BIND_ADDR.to_string()),
+        source,
+    });
+
+    // HSTS is only sent by default when the gate terminates HTTPS itself;
+    // behind a TLS proxy it is set with HSTS_MAX_AGE
+    let default_hsts_max_age = if tls.is_some() { DEFAULT_HSTS_MAX_AGE_SECS } else { 0 };
+    let security_headers = SecurityHeaders::new(
+        &env::var("CONTENT_SECURITY_POLICY").unwrap_or_else(|_| DEFAULT_CONTENT_SECURITY_POLICY.to_string()),
+        &env::var("REFERRER_POLICY").unwrap_or_else(|_| DEFAULT_REFERRER_POLICY.to_string()),
+        env::var("HSTS_MAX_AGE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default_hsts_max_age),
+        env::var("SECURITY_HEADERS_PROXIED")
+            .map(|v| v.to_lowercase() == "true")
+            .unwrap_or(false),
+    )
+    .unwrap_or_else(|e| panic!("Invalid security headers: {}", e));
 
     GateConfig {
         oxigraph_url,
+        replicas,
+        replica_check_interval_secs,
+        shadow,
         secure_cookies,
+        cookie,
         files_dir,
+        upload_conflict,
+        trash_retention_days,
+        storage_quota_mb,
+        emergency_token,
+        ldap,
+        github,
+        share_secret,
+        share_max_ttl_secs,
+        public_url,
+        rank_cache_ttl_secs,
+        path_cache_ttl_secs,
+        query_cache_ttl_secs,
+        idempotency_key_ttl_secs,
+        query_cache_max_mb,
+        result_conversion_max_mb,
+        query_etag_max_mb,
+        body_limits,
+        query_limits,
+        query_page_size,
+        rate_limiter,
+        session_idle_days,
+        upstream_gzip,
+        upstream_connect_timeout_secs,
+        upstream_timeout_secs,
+        upstream_write_timeout_secs,
+        upstream_pool,
+        upstreams,
+        accept_rewrites,
+        store_pages,
+        cors,
+        compress_responses,
+        compress_min_bytes,
+        restrict_update_graphs,
+        restrict_query_graphs,
+        public_graphs,
+        protected_graphs,
+        proxy_auth,
+        rules,
+        plugins,
+        home_page,
+        notify,
+        asset_cache_extensions,
+        asset_cache_max_age_secs,
+        jwt_key_file,
+        jwt_issuer,
+        jwt_ttl_secs,
+        trace_export: export_configured(),
+        query_log,
+        query_log_redaction,
+        slow_query_ms,
+        journal_file,
+        read_only,
+        metrics_token,
+        metrics_permission,
+        bind_addr,
+        unix_socket_mode,
+        tls,
+        security_headers,
     }
 }
 
+/// Generate a random 256-bit secret as 64 hex characters
+pub fn random_secret() -> String {
+    let mut bytes = [0u8; 32];
+    SystemRandom::new().fill(&mut bytes).expect("system RNG available");
+    hex::encode(bytes)
+}
+
 pub fn extract_token_from_header(headers: &HeaderMap) -> Option<String> {
     headers
         .get("X-Access-Token")
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string())
         .or_else(|| {
-            headers
-                .get(header::AUTHORIZATION)
-                .and_then(|v| v.to_str().ok())
-                .and_then(|s| s.strip_prefix("Bearer "))
-                .map(|s| s.to_string())
+            let authorization = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+            if let Some(token) = authorization.strip_prefix("Bearer ") {
+                Some(token.to_string())
+            } else {
+                authorization.strip_prefix("Basic ").and_then(token_from_basic)
+            }
         })
 }
 
-pub async fn login_page() -> Html<&'static str> {
-    Html(LOGIN_HTML)
+/// Extract the access token from HTTP Basic credentials.
+/// The password carries the token (`user:TOKEN`, user is ignored); with an
+/// empty password the username is used instead (`curl -u TOKEN:`).
+fn token_from_basic(encoded: &str) -> Option<String> {
+    let decoded = BASE64_STANDARD.decode(encoded.trim()).ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    let (user, password) = credentials.split_once(':').unwrap_or((&credentials, ""));
+    let token = if password.is_empty() { user } else { password };
+    (!token.is_empty()).then(|| token.to_string())
+}
+
+/// Insert the username/password form into a login page when a password
+/// backend is configured, and the GitHub button when GitHub login is
+pub fn render_login(state: &AppState, page: &str) -> Html<String> {
+    let form = if state.auth_backends.is_empty() { "" } else { PASSWORD_FORM_HTML };
+    let oauth = if state.github.is_some() { GITHUB_LOGIN_HTML } else { "" };
+    Html(
+        page.replace("<!-- PASSWORD_FORM -->", form)
+            .replace("<!-- OAUTH_LOGIN -->", oauth),
+    )
+}
+
+/// GET /gate/login - Users signed in through a trusted reverse proxy skip
+/// the login form
+pub async fn login_page(State(state): State<Arc<AppState>>, jar: CookieJar, headers: HeaderMap) -> Response {
+    if let Credential::Proxy { .. } = credential(&state, &headers, &jar) {
+        return Redirect::to("/").into_response();
+    }
+    render_login(&state, LOGIN_HTML).into_response()
 }
 
 #[derive(Deserialize)]
 pub struct LoginForm {
-    token: String,
+    token: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+/// Build the session cookie carrying either an access token or a session ID
+pub fn session_cookie(state: &AppState, value: String) -> Cookie<'static> {
+    let config = &state.cookie;
+
+    // Build secure cookie with all security flags
+    let mut cookie_builder = Cookie::build((config.name.clone(), value))
+        .path(config.path.clone())
+        .http_only(true)  // Prevent JavaScript access (XSS protection)
+        .same_site(config.same_site)  // CSRF protection (Strict unless COOKIE_SAMESITE)
+        .max_age(time::Duration::seconds(SESSION_MAX_AGE_SECS));  // Session expiration
+
+    // Shared with sibling hosts when COOKIE_DOMAIN is set
+    if let Some(domain) = &config.domain {
+        cookie_builder = cookie_builder.domain(domain.clone());
+    }
+
+    // Only set Secure flag if configured (requires HTTPS)
+    // In development without HTTPS, set SECURE_COOKIES=false
+    if state.secure_cookies {
+        cookie_builder = cookie_builder.secure(true);
+    }
+
+    cookie_builder.build()
+}
+
+/// Try each password backend in turn
+async fn authenticate_password(
+    state: &AppState,
+    addr: &SocketAddr,
+    username: &str,
+    password: &str,
+) -> Option<(Principal, &'static str)> {
+    for backend in &state.auth_backends {
+        match backend.authenticate(username, password).await {
+            Ok(Some(principal)) => return Some((principal, backend.name())),
+            Ok(None) => {}
+            Err(e) => {
+                warn!(client = %addr, backend = backend.name(), error = %e, "Authentication backend error");
+            }
+        }
+    }
+    None
 }
 
 pub async fn login_submit(
@@ -79,32 +916,243 @@ pub async fn login_submit(
     jar: CookieJar,
     Form(form): Form<LoginForm>,
 ) -> Response {
+    if let (Some(username), Some(password)) = (&form.username, &form.password) {
+        return match authenticate_password(&state, &addr, username, password).await {
+            Some((principal, backend)) if principal.rank > 0 => {
+                successful_login(&state, &addr, principal.identity.clone());
+                let identity = principal.identity.clone();
+                let session_id = state.sessions.create(principal.identity, backend, principal.rank);
+                info!(client = %addr, credential = %fingerprint(&session_id), backend = backend, identity = %identity, rank = principal.rank, "Login successful");
+                let jar = jar.add(session_cookie(&state, session_id));
+                (jar, Redirect::to("/")).into_response()
+            }
+            Some((principal, backend)) => {
+                warn!(client = %addr, backend = backend, identity = %principal.identity, "Login failed - no rank assigned");
+                render_login(&state, LOGIN_ERROR_HTML).into_response()
+            }
+            None => {
+                warn!(client = %addr, "Login failed - invalid credentials");
+                record_failure(&state, &addr, "password", password.chars().count());
+                render_login(&state, LOGIN_ERROR_HTML).into_response()
+            }
+        };
+    }
+
+    let Some(token) = form.token else {
+        return render_login(&state, LOGIN_ERROR_HTML).into_response();
+    };
+
     // Hash the submitted token and verify it exists in the RDF access graph
-    let token_hash = hash_token(&form.token);
+    let token_hash = hash_token(&token);
+    let fingerprint = fingerprint(&token);
+
+    // The emergency token must work when the access graph is broken, so it
+    // is not looked up there
+    if state.emergency_token_hash.as_deref() == Some(token_hash.as_str()) {
+        warn!(client = %addr, credential = %fingerprint, "EMERGENCY TOKEN LOGIN - access control is bypassed for this session");
+        emergency_token_used(&state, &addr);
+        state.sessions.token_login(&token_hash);
+        let jar = jar.add(session_cookie(&state, token));
+        return (jar, Redirect::to("/")).into_response();
+    }
     let token_exists = verify_token_exists(&state.client, &state.oxigraph_url, &token_hash).await;
 
     if token_exists {
-        info!(client = %addr, "Login successful");
-
-        // Build secure cookie with all security flags
-        let mut cookie_builder = Cookie::build((TOKEN_COOKIE_NAME, form.token))
-            .path("/")
-            .http_only(true)  // Prevent JavaScript access (XSS protection)
-            .same_site(SameSite::Strict)  // CSRF protection
-            .max_age(time::Duration::seconds(SESSION_MAX_AGE_SECS));  // Session expiration
-
-        // Only set Secure flag if configured (requires HTTPS)
-        // In development without HTTPS, set SECURE_COOKIES=false
-        if state.secure_cookies {
-            cookie_builder = cookie_builder.secure(true);
-        }
+        info!(client = %addr, credential = %fingerprint, "Login successful");
+        state.sessions.token_login(&token_hash);
+        successful_login(&state, &addr, format!("token:{}", &token_hash[..16]));
 
-        let cookie = cookie_builder.build();
-        let jar = jar.add(cookie);
+        let jar = jar.add(session_cookie(&state, token));
 
         (jar, Redirect::to("/")).into_response()
     } else {
-        warn!(client = %addr, "Login failed - invalid token");
-        Html(LOGIN_ERROR_HTML).into_response()
+        warn!(client = %addr, credential = %fingerprint, "Login failed - invalid token");
+        record_failure(&state, &addr, "token", token.chars().count());
+        render_login(&state, LOGIN_ERROR_HTML).into_response()
+    }
+}
+
+/// POST /gate/logout-all - Log the current identity out of every browser.
+///
+/// For a password login this ends all of the principal's sessions. For a
+/// token, the token stops working in every session cookie until it is used
+/// to log in again; clients sending it in a header are unaffected.
+pub async fn logout_all_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    let credential = credential(&state, &headers, &jar);
+    let fingerprint = credential.fingerprint();
+    let message = match credential {
+        Credential::Session(session) => {
+            let removed = state.sessions.remove_identity(&session.identity, session.backend);
+            info!(client = %addr, credential = %fingerprint, backend = session.backend, identity = %session.identity, sessions = removed, "Logged out everywhere");
+            format!("Ended {} session(s) for {}.", removed, session.identity)
+        }
+        Credential::Token(token_hash) => {
+            state.sessions.logout_token(&token_hash);
+            info!(client = %addr, credential = %fingerprint, "Token logged out everywhere");
+            "Your token no longer signs in any browser until you log in with it again.".to_string()
+        }
+        Credential::Proxy { .. } => {
+            return (StatusCode::BAD_REQUEST, "Signed in through the reverse proxy - log out there").into_response();
+        }
+        Credential::Emergency => {
+            if let Some(token_hash) = &state.emergency_token_hash {
+                state.sessions.logout_token(token_hash);
+            }
+            warn!(client = %addr, credential = %fingerprint, "Emergency token logged out everywhere");
+            "The emergency token no longer signs in any browser until you log in with it again.".to_string()
+        }
+        Credential::Anonymous => {
+            return (StatusCode::UNAUTHORIZED, "Not logged in").into_response();
+        }
+    };
+    state.rank_cache.invalidate_all();
+
+    let body = format!(
+        r#"<p>{}</p>
+        <a class="button" href="/gate/login">Log in</a>"#,
+        escape_html(&message),
+    );
+    let jar = jar.remove(state.cookie.removal(state.secure_cookies));
+    (jar, Html(render_page("Logged out everywhere", &body))).into_response()
+}
+
+/// Middleware logging every request made with the emergency token, which
+/// bypasses all access checks, and alerting through the notifier
+pub async fn emergency_token_middleware(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    req: Request,
+    next: Next,
+) -> Response {
+    if state.emergency_token_hash.is_some() {
+        if let Credential::Emergency = credential(&state, req.headers(), &jar) {
+            warn!(
+                client = %addr,
+                method = %req.method(),
+                path = %req.uri().path(),
+                "EMERGENCY TOKEN USED - access control bypassed"
+            );
+            emergency_token_used(&state, &addr);
+        }
+    }
+    next.run(req).await
+}
+
+/// What a guarded route's access rank is computed on
+#[derive(Clone, Copy)]
+pub enum Resource {
+    /// The file named by the route's `:uuid` parameter
+    File,
+    /// An action IRI, e.g. uploading
+    Action(&'static str),
+}
+
+/// Identity and rank of a request that passed [`require_access`]. Handlers
+/// take it as an extractor; on a route without the layer it fails closed.
+/// On [`Resource::File`] routes the `:uuid` parameter has been validated, so
+/// handlers can extract it as `Path<Uuid>`.
+#[derive(Clone)]
+pub struct AuthedUser {
+    /// Credential fingerprint for logs
+    pub fingerprint: String,
+    pub rank: i32,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuthedUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<AuthedUser>().cloned().ok_or_else(|| {
+            error!(path = %parts.uri.path(), "AuthedUser used on a route without require_access");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Access check missing").into_response()
+        })
+    }
+}
+
+/// Route layer checking the requester's rank on `resource` against the
+/// rule for the request's method and path, before the handler runs:
+///
+/// ```ignore
+/// get(res_handler).route_layer(middleware::from_fn_with_state((state, Resource::File), require_access))
+/// ```
+pub async fn require_access(
+    State((state, resource)): State<(Arc<AppState>, Resource)>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    req: Request,
+    next: Next,
+) -> Response {
+    let fingerprint = credential(&state, req.headers(), &jar).fingerprint();
+    let (mut parts, body) = req.into_parts();
+
+    let required = match state.rules.required_rank(&state.roles, &parts.method, parts.uri.path()) {
+        Ok(required) => required,
+        Err(status) => return (status, "Access denied").into_response(),
+    };
+    let rank = match resource {
+        Resource::File => {
+            let uuid_str = match Path::<HashMap<String, String>>::from_request_parts(&mut parts, &state).await {
+                Ok(Path(mut params)) => params.remove("uuid").unwrap_or_default(),
+                Err(rejection) => return rejection.into_response(),
+            };
+            let Some(uuid) = validate_uuid(&uuid_str) else {
+                warn!(client = %addr, credential = %fingerprint, uuid = %uuid_str, "Invalid UUID format");
+                return (StatusCode::BAD_REQUEST, "Invalid UUID format").into_response();
+            };
+            get_access_rank(&state, &uuid, &parts.headers, &jar).await
+        }
+        Resource::Action(iri) => get_access_rank_iri(&state, iri, &parts.headers, &jar).await,
+    };
+    if rank < required {
+        warn!(
+            client = %addr,
+            credential = %fingerprint,
+            method = %parts.method,
+            path = %parts.uri.path(),
+            rank = rank,
+            required = required,
+            "Access denied - insufficient rank"
+        );
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+
+    parts.extensions.insert(AuthedUser { fingerprint, rank });
+    next.run(Request::from_parts(parts, body)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn basic(credentials: &str) -> String {
+        BASE64_STANDARD.encode(credentials)
+    }
+
+    #[test]
+    fn test_token_from_basic() {
+        // The password carries the token; the user is ignored
+        assert_eq!(token_from_basic(&basic("alice:s3cret")), Some("s3cret".to_string()));
+        assert_eq!(token_from_basic(&basic(":s3cret")), Some("s3cret".to_string()));
+        // Only the first colon separates, so tokens may contain colons
+        assert_eq!(token_from_basic(&basic("user:a:b")), Some("a:b".to_string()));
+        // With an empty password, or none, the user is the token
+        assert_eq!(token_from_basic(&basic("s3cret:")), Some("s3cret".to_string()));
+        assert_eq!(token_from_basic(&basic("s3cret")), Some("s3cret".to_string()));
+        assert_eq!(token_from_basic(&format!(" {} ", basic("s3cret:"))), Some("s3cret".to_string()));
+    }
+
+    #[test]
+    fn test_token_from_basic_rejects_malformed() {
+        assert_eq!(token_from_basic(&basic(":")), None);
+        assert_eq!(token_from_basic(""), None);
+        assert_eq!(token_from_basic("not base64!"), None);
+        assert_eq!(token_from_basic(&BASE64_STANDARD.encode([0xff, 0xfe, b':', b'x'])), None);
     }
 }
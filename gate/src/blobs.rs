@@ -0,0 +1,98 @@
+//! Shared stored files.
+//!
+//! Stored files of identical content are kept once: a new file whose
+//! SHA-256 and extension match a stored file in use points its node's
+//! `liqk:storedAs` at that file instead of writing another. Every file and
+//! version node naming a stored file counts as a reference to it, so the
+//! graph is its reference count: a stored file is only moved to the trash
+//! or removed once no node outside the ones going away references it, and
+//! new content for a file whose stored file is shared goes to a new one.
+
+use std::{collections::HashSet, path::PathBuf};
+use uuid::Uuid;
+
+use crate::files::{binding_value, escape_sparql_string, sparql_select, FILESYSTEM_GRAPH};
+use crate::AppState;
+
+/// Candidates looked at when searching a stored file by content
+const MAX_CANDIDATES: usize = 8;
+
+/// Stored names are single file names; anything else is never shared
+fn is_file_name(stored: &str) -> bool {
+    std::path::Path::new(stored).file_name().and_then(|name| name.to_str()) == Some(stored)
+}
+
+/// A stored file in use with content of this SHA-256 and extension, other
+/// than `except`, for a new node to point at. Only stored files present in
+/// the files directory (not in the trash) qualify.
+pub async fn find_blob(
+    state: &AppState,
+    sha256: &str,
+    extension: &str,
+    except: Option<&str>,
+) -> Result<Option<String>, String> {
+    let except = except
+        .map(|stored| format!("\n    FILTER(?storedAs != \"{}\")", escape_sparql_string(stored)))
+        .unwrap_or_default();
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT DISTINCT ?storedAs FROM <{graph}> WHERE {{
+    ?node liqk:sha256 "{sha256}" ;
+        liqk:storedAs ?storedAs .
+    FILTER(STRENDS(?storedAs, ".{extension}")){except}
+}}
+LIMIT {limit}"#,
+        graph = FILESYSTEM_GRAPH,
+        sha256 = escape_sparql_string(&sha256.to_ascii_lowercase()),
+        extension = escape_sparql_string(extension),
+        except = except,
+        limit = MAX_CANDIDATES,
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+    for stored in bindings.iter().filter_map(|b| binding_value(b, "storedAs")) {
+        if !is_file_name(stored) {
+            continue;
+        }
+        if tokio::fs::try_exists(PathBuf::from(&state.files_dir).join(stored)).await.unwrap_or(false) {
+            return Ok(Some(stored.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// The stored files among `names` that a node other than `nodes` still
+/// references
+pub async fn referenced_blobs(state: &AppState, names: &[String], nodes: &[Uuid]) -> Result<HashSet<String>, String> {
+    if names.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let values: Vec<String> = names.iter().map(|name| format!("\"{}\"", escape_sparql_string(name))).collect();
+    let filter = if nodes.is_empty() {
+        String::new()
+    } else {
+        let nodes: Vec<String> = nodes.iter().map(|node| format!("<urn:uuid:{}>", node)).collect();
+        format!("\n    FILTER(?node NOT IN ({}))", nodes.join(", "))
+    };
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT DISTINCT ?storedAs FROM <{graph}> WHERE {{
+    VALUES ?storedAs {{ {values} }}
+    ?node liqk:storedAs ?storedAs .{filter}
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        values = values.join(" "),
+        filter = filter,
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+    Ok(bindings
+        .iter()
+        .filter_map(|b| binding_value(b, "storedAs").map(str::to_string))
+        .collect())
+}
+
+/// Whether a node other than `node` references the stored file
+pub async fn is_shared(state: &AppState, stored: &str, node: &Uuid) -> Result<bool, String> {
+    Ok(!referenced_blobs(state, &[stored.to_string()], &[*node]).await?.is_empty())
+}
@@ -0,0 +1,1141 @@
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form, Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::Deserialize;
+use serde_json::json;
+use futures_util::{stream, StreamExt};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    net::SocketAddr,
+    sync::Arc,
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::archive::{archive_entries, zip_response};
+use crate::auth::AuthedUser;
+use crate::collections::{render_sidebar, visible_collections};
+use crate::files::{
+    binding_value, credential, escape_sparql_string, get_access_rank, read_stored_file,
+    send_sparql_update_once, sparql_select, UploadConflict, FILESYSTEM_GRAPH,
+};
+use crate::preview::{preview_page, Delivery};
+use crate::readme::{readme_section, render_readme, Preferences};
+use crate::templates::{escape_html, LISTING_HTML};
+use crate::thumbnails::is_image;
+use crate::trash::move_to_trash;
+use crate::update_graphs::form_params;
+use crate::AppState;
+
+/// Route prefix for path-based file access
+pub const FILE_PREFIX: &str = "/file";
+
+/// Name of the root directory in its ZIP download
+const ROOT_ARCHIVE_NAME: &str = "files";
+
+/// Characters that must be escaped inside a single URL path segment
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// A node of the filesystem graph resolved from a path
+#[derive(Clone)]
+pub struct Node {
+    pub uuid: Uuid,
+    /// `liqk:storedAs` for files, `None` for directories
+    pub stored_as: Option<String>,
+}
+
+impl Node {
+    pub fn is_dir(&self) -> bool {
+        self.stored_as.is_none()
+    }
+}
+
+/// A directory entry as shown in listings
+pub struct Entry {
+    pub uuid: Option<Uuid>,
+    pub label: String,
+    pub is_dir: bool,
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+}
+
+/// Percent-encode a single path segment
+pub fn encode_segment(segment: &str) -> String {
+    utf8_percent_encode(segment, PATH_SEGMENT).to_string()
+}
+
+/// Split a raw (percent-encoded) URL path into decoded segments.
+/// Empty segments (from `//` or a trailing slash) are dropped.
+pub fn decode_segments(raw: &str) -> Option<Vec<String>> {
+    raw.split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| percent_decode_str(s).decode_utf8().ok().map(|d| d.into_owned()))
+        .collect()
+}
+
+/// Build the canonical `/file/...` URL for a node: directories end with a
+/// trailing slash, files never do.
+pub fn canonical_path(segments: &[String], is_dir: bool) -> String {
+    let mut path = String::from(FILE_PREFIX);
+    for segment in segments {
+        path.push('/');
+        path.push_str(&encode_segment(segment));
+    }
+    if is_dir || segments.is_empty() {
+        path.push('/');
+    }
+    path
+}
+
+pub fn parse_urn_uuid(iri: &str) -> Option<Uuid> {
+    iri.strip_prefix("urn:uuid:").and_then(|s| Uuid::parse_str(s).ok())
+}
+
+/// Resolve a path (list of labels below the root directory) to a node.
+/// The walk starts from the deepest cached ancestor, and every directory
+/// passed on the way is cached for the next lookup.
+pub async fn resolve_path(state: &AppState, segments: &[String]) -> Result<Option<Node>, String> {
+    if let Some(node) = state.path_cache.get(segments) {
+        return Ok(Some(node));
+    }
+
+    let (first, mut patterns) = match state.path_cache.deepest_ancestor(segments) {
+        Some((depth, ancestor)) => (
+            depth + 1,
+            format!("    VALUES ?n{} {{ <urn:uuid:{}> }}\n", depth, ancestor.uuid),
+        ),
+        None => (0, String::from("    ?n0 a posix:Directory ;\n        rdfs:label \"/\" .\n")),
+    };
+    for (i, segment) in segments.iter().enumerate().skip(first.saturating_sub(1)) {
+        patterns.push_str(&format!(
+            "    ?n{prev} posix:includes ?n{cur} .\n    ?n{cur} rdfs:label \"{label}\" .\n",
+            prev = i,
+            cur = i + 1,
+            label = escape_sparql_string(segment),
+        ));
+    }
+    let selected: Vec<String> = (first..=segments.len()).map(|i| format!("?n{}", i)).collect();
+
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT {selected} ?storedAs FROM <{graph}> WHERE {{
+{patterns}    OPTIONAL {{ ?n{last} liqk:storedAs ?storedAs }}
+}}
+LIMIT 1"#,
+        selected = selected.join(" "),
+        graph = FILESYSTEM_GRAPH,
+        patterns = patterns,
+        last = segments.len(),
+    );
+
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+    let Some(binding) = bindings.first() else {
+        return Ok(None);
+    };
+
+    let mut node = None;
+    for depth in first..=segments.len() {
+        let uuid = binding_value(binding, &format!("n{}", depth))
+            .and_then(parse_urn_uuid)
+            .ok_or_else(|| "Resolved node is not a urn:uuid IRI".to_string())?;
+        // Only the last node can be a file: the others include children
+        let stored_as = if depth == segments.len() {
+            binding_value(binding, "storedAs").map(|s| s.to_string())
+        } else {
+            None
+        };
+        let resolved = Node { uuid, stored_as };
+        state.path_cache.insert(&segments[..depth], resolved.clone());
+        node = Some(resolved);
+    }
+    Ok(node)
+}
+
+/// List the direct children of a directory, directories first
+pub async fn list_directory(
+    client: &reqwest::Client,
+    oxigraph_url: &str,
+    dir: &Uuid,
+) -> Result<Vec<Entry>, String> {
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?child ?label ?size ?sha256 ?isDir FROM <{graph}> WHERE {{
+    <urn:uuid:{dir}> posix:includes ?child .
+    ?child rdfs:label ?label .
+    OPTIONAL {{ ?child posix:size ?size }}
+    OPTIONAL {{ ?child liqk:sha256 ?sha256 }}
+    BIND(EXISTS {{ ?child a posix:Directory }} AS ?isDir)
+}}
+ORDER BY DESC(?isDir) ?label"#,
+        graph = FILESYSTEM_GRAPH,
+        dir = dir,
+    );
+
+    let bindings = sparql_select(client, oxigraph_url, &query).await?;
+
+    Ok(bindings
+        .iter()
+        .filter_map(|b| {
+            Some(Entry {
+                uuid: binding_value(b, "child").and_then(parse_urn_uuid),
+                label: binding_value(b, "label")?.to_string(),
+                is_dir: binding_value(b, "isDir") == Some("true"),
+                size: binding_value(b, "size").and_then(|s| s.parse().ok()),
+                sha256: binding_value(b, "sha256").map(str::to_string),
+            })
+        })
+        .collect())
+}
+
+/// Format a byte count for listings (e.g. 1536 -> "1.5 KB")
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Hex characters of a file's SHA-256 shown in listings; the whole hash
+/// is in the cell's tooltip
+const SHORT_HASH_LEN: usize = 12;
+
+/// Icon of directory rows
+const DIR_ICON: &str = "📁";
+
+/// Icon of file rows without a thumbnail
+const FILE_ICON: &str = "📄";
+
+/// Icon of a file row: its thumbnail for images, which falls back to the
+/// plain file icon when none can be made
+pub fn file_icon(label: &str, uuid: &Uuid) -> String {
+    if is_image(label) {
+        format!(
+            "<img class=\"thumb\" src=\"/res/{}/thumb\" alt=\"\" loading=\"lazy\" onerror=\"this.replaceWith('{}')\">",
+            uuid, FILE_ICON
+        )
+    } else {
+        FILE_ICON.to_string()
+    }
+}
+
+/// Render a listing row. `href` is relative to the directory's canonical URL.
+/// `icon` is HTML, from [`DIR_ICON`] or [`file_icon`]. Rows for files pass
+/// their UUID to get a checkbox selecting them for a download manifest.
+pub fn render_row(
+    href: &str,
+    icon: &str,
+    name: &str,
+    size: &str,
+    sha256: Option<&str>,
+    select: Option<&Uuid>,
+) -> String {
+    let checkbox = select
+        .map(|uuid| format!("<input type=\"checkbox\" name=\"file\" value=\"{}\">", uuid))
+        .unwrap_or_default();
+    let hash = sha256
+        .map(|sha256| {
+            format!(
+                "<span title=\"SHA-256 {}\">{}</span>",
+                escape_html(sha256),
+                escape_html(sha256.get(..SHORT_HASH_LEN).unwrap_or(sha256)),
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "        <tr><td class=\"select\">{}</td><td class=\"icon\">{}</td><td><a href=\"{}\">{}</a></td><td class=\"hash\">{}</td><td class=\"size\">{}</td></tr>\n",
+        checkbox,
+        icon,
+        escape_html(href),
+        escape_html(name),
+        hash,
+        escape_html(size),
+    )
+}
+
+fn render_listing(segments: &[String], entries: &[Entry], sidebar: &str, readme: &str) -> String {
+    let title = format!("/{}", segments.iter().map(|s| format!("{}/", s)).collect::<String>());
+
+    let mut rows = render_row("./", DIR_ICON, ".", "", None, None);
+    if !segments.is_empty() {
+        rows.push_str(&render_row("../", DIR_ICON, "..", "", None, None));
+    }
+    for entry in entries {
+        // "./" prefix keeps labels like "a:b" from being parsed as a URL scheme
+        let mut href = format!("./{}", encode_segment(&entry.label));
+        let mut name = entry.label.clone();
+        if entry.is_dir {
+            href.push('/');
+            name.push('/');
+        }
+        let size = entry.size.map(format_size).unwrap_or_default();
+        let select = if entry.is_dir { None } else { entry.uuid.as_ref() };
+        let icon = match select {
+            Some(uuid) => file_icon(&entry.label, uuid),
+            None => DIR_ICON.to_string(),
+        };
+        rows.push_str(&render_row(&href, &icon, &name, &size, entry.sha256.as_deref(), select));
+    }
+
+    LISTING_HTML
+        .replace("{title}", &escape_html(&title))
+        .replace("{sidebar}", sidebar)
+        .replace("{entries}", rows.trim_end())
+        .replace("{readme}", readme)
+}
+
+/// Check a new label for a file or directory: a single, non-empty path
+/// segment
+fn validate_label(label: &str) -> Result<(), &'static str> {
+    if label.is_empty() || label == "." || label == ".." {
+        return Err("name must not be empty, \".\" or \"..\"");
+    }
+    if label.contains('/') || label.chars().any(char::is_control) {
+        return Err("name must not contain \"/\" or control characters");
+    }
+    Ok(())
+}
+
+/// A file's current place in the tree: its label and the directories
+/// including it. `None` when the UUID is not a file.
+async fn file_place(state: &AppState, file: &Uuid) -> Result<Option<(Option<String>, Vec<Uuid>)>, String> {
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?label ?parent FROM <{graph}> WHERE {{
+    <urn:uuid:{file}> liqk:storedAs ?storedAs .
+    OPTIONAL {{ <urn:uuid:{file}> rdfs:label ?label }}
+    OPTIONAL {{ ?parent posix:includes <urn:uuid:{file}> }}
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        file = file,
+    );
+
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+    let Some(first) = bindings.first() else {
+        return Ok(None);
+    };
+    let label = binding_value(first, "label").map(str::to_string);
+    let mut parents: Vec<Uuid> = bindings
+        .iter()
+        .filter_map(|b| binding_value(b, "parent").and_then(parse_urn_uuid))
+        .collect();
+    parents.sort();
+    parents.dedup();
+    Ok(Some((label, parents)))
+}
+
+/// Whether another entry of one of `dirs` is labelled `label`
+pub async fn label_taken(state: &AppState, dirs: &[Uuid], label: &str, file: &Uuid) -> Result<bool, String> {
+    if dirs.is_empty() {
+        return Ok(false);
+    }
+    let values: Vec<String> = dirs.iter().map(|dir| format!("<urn:uuid:{}>", dir)).collect();
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+
+SELECT ?other FROM <{graph}> WHERE {{
+    VALUES ?dir {{ {dirs} }}
+    ?dir posix:includes ?other .
+    ?other rdfs:label "{label}" .
+    FILTER(?other != <urn:uuid:{file}>)
+}}
+LIMIT 1"#,
+        graph = FILESYSTEM_GRAPH,
+        dirs = values.join(" "),
+        label = escape_sparql_string(label),
+        file = file,
+    );
+    Ok(!sparql_select(&state.client, &state.oxigraph_url, &query).await?.is_empty())
+}
+
+/// The directory a file is put into: a path as under `/file` (e.g.
+/// `/reports/2024/`) or a directory's UUID
+pub enum TargetDir<'a> {
+    Path(&'a str),
+    Uuid(Uuid),
+}
+
+impl<'a> TargetDir<'a> {
+    /// The target named by a `dir` (path) and a `parent` (UUID) parameter,
+    /// of which at most one may be given
+    pub fn from_params(dir: Option<&'a str>, parent: Option<Uuid>) -> Result<Option<Self>, &'static str> {
+        match (dir.filter(|dir| !dir.trim().is_empty()), parent) {
+            (Some(_), Some(_)) => Err("Give either dir or parent, not both"),
+            (Some(dir), None) => Ok(Some(Self::Path(dir))),
+            (None, Some(parent)) => Ok(Some(Self::Uuid(parent))),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+impl fmt::Display for TargetDir<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => f.write_str(path),
+            Self::Uuid(uuid) => write!(f, "urn:uuid:{}", uuid),
+        }
+    }
+}
+
+/// Whether a UUID names a directory of the filesystem graph, other than
+/// the trash
+async fn is_directory(state: &AppState, uuid: &Uuid) -> Result<bool, String> {
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?dir FROM <{graph}> WHERE {{
+    VALUES ?dir {{ <urn:uuid:{uuid}> }}
+    ?dir a posix:Directory .
+    FILTER NOT EXISTS {{ ?dir a liqk:Trash }}
+}}
+LIMIT 1"#,
+        graph = FILESYSTEM_GRAPH,
+        uuid = uuid,
+    );
+    Ok(!sparql_select(&state.client, &state.oxigraph_url, &query).await?.is_empty())
+}
+
+/// Resolve the directory a file is put into. The requester needs
+/// `required` on it, so files only land where they could be written.
+pub async fn resolve_target_dir(
+    state: &AppState,
+    addr: &SocketAddr,
+    fingerprint: &str,
+    target: &TargetDir<'_>,
+    required: i32,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> Result<Uuid, Response> {
+    let found = match target {
+        TargetDir::Path(path) => {
+            let segments: Vec<String> = path.split('/').filter(|s| !s.is_empty()).map(str::to_string).collect();
+            resolve_path(state, &segments)
+                .await
+                .map(|node| node.filter(Node::is_dir).map(|node| node.uuid))
+        }
+        TargetDir::Uuid(uuid) => is_directory(state, uuid).await.map(|is_dir| is_dir.then_some(*uuid)),
+    };
+    let dir = match found {
+        Ok(Some(dir)) => dir,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, format!("Directory not found: {}", target)).into_response()),
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, dir = %target, error = %e, "Path resolution failed");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to resolve path").into_response());
+        }
+    };
+
+    let rank = get_access_rank(state, &dir, headers, jar).await;
+    if rank < required {
+        warn!(client = %addr, credential = %fingerprint, dir = %target, rank = rank, required = required, "Access denied - insufficient rank for target directory");
+        return Err((StatusCode::FORBIDDEN, "Access denied").into_response());
+    }
+    Ok(dir)
+}
+
+/// The name an uploaded file gets in its target directory
+pub struct Placement {
+    pub label: String,
+    /// Existing file the upload becomes the new version of
+    pub replaces: Option<Uuid>,
+}
+
+/// Split a file name before its extension: `report.pdf` is `report` and
+/// `.pdf`, while `.bashrc` has no extension
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(i) if i > 0 => name.split_at(i),
+        _ => (name, ""),
+    }
+}
+
+/// Place an uploaded file named `label` in `dir`, handling an entry of the
+/// same name as `conflict` says. `claimed` holds the labels of earlier
+/// files of the same upload that are not indexed yet. `None` when the name
+/// is taken and the file must be refused.
+pub async fn place_upload(
+    state: &AppState,
+    dir: &Uuid,
+    label: &str,
+    conflict: UploadConflict,
+    claimed: &HashSet<String>,
+) -> Result<Option<Placement>, String> {
+    let (stem, extension) = split_extension(label);
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?entry ?label ?storedAs FROM <{graph}> WHERE {{
+    <urn:uuid:{dir}> posix:includes ?entry .
+    ?entry rdfs:label ?label .
+    FILTER(?label = "{label}" || STRSTARTS(?label, "{renamed}"))
+    OPTIONAL {{ ?entry liqk:storedAs ?storedAs }}
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        dir = dir,
+        label = escape_sparql_string(label),
+        renamed = escape_sparql_string(&format!("{} (", stem)),
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+
+    let mut taken: HashSet<&str> = bindings.iter().filter_map(|b| binding_value(b, "label")).collect();
+    taken.extend(claimed.iter().map(String::as_str));
+    if !taken.contains(label) {
+        return Ok(Some(Placement { label: label.to_string(), replaces: None }));
+    }
+
+    match conflict {
+        UploadConflict::Reject => Ok(None),
+        UploadConflict::Rename => {
+            let mut n = 1;
+            loop {
+                let candidate = format!("{} ({}){}", stem, n, extension);
+                if !taken.contains(candidate.as_str()) {
+                    return Ok(Some(Placement { label: candidate, replaces: None }));
+                }
+                n += 1;
+            }
+        }
+        // Only a file indexed before this upload can get a new version; a
+        // directory of the same name cannot
+        UploadConflict::Version if claimed.contains(label) => Ok(None),
+        UploadConflict::Version => Ok(bindings
+            .iter()
+            .find(|b| binding_value(b, "label") == Some(label) && binding_value(b, "storedAs").is_some())
+            .and_then(|b| binding_value(b, "entry"))
+            .and_then(parse_urn_uuid)
+            .map(|old| Placement { label: label.to_string(), replaces: Some(old) })),
+    }
+}
+
+/// One SPARQL update relabelling a file and/or moving it from every
+/// directory including it into `dir`
+fn build_move_update(file: &Uuid, label: Option<&str>, dir: Option<&Uuid>) -> String {
+    let mut delete = String::new();
+    let mut insert = String::new();
+    if let Some(dir) = dir {
+        delete.push_str(&format!("        ?parent posix:includes <urn:uuid:{}> .\n", file));
+        insert.push_str(&format!("        <urn:uuid:{}> posix:includes <urn:uuid:{}> .\n", dir, file));
+    }
+    if let Some(label) = label {
+        delete.push_str(&format!("        <urn:uuid:{}> rdfs:label ?label .\n", file));
+        insert.push_str(&format!("        <urn:uuid:{}> rdfs:label \"{}\" .\n", file, escape_sparql_string(label)));
+    }
+    format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+DELETE {{
+    GRAPH <{graph}> {{
+{delete}    }}
+}}
+INSERT {{
+    GRAPH <{graph}> {{
+{insert}    }}
+}}
+WHERE {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{file}> liqk:storedAs ?storedAs .
+        OPTIONAL {{ ?parent posix:includes <urn:uuid:{file}> }}
+        OPTIONAL {{ <urn:uuid:{file}> rdfs:label ?label }}
+    }}
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        delete = delete,
+        insert = insert,
+        file = file,
+    )
+}
+
+/// Insert a new directory labelled `label` under `parent`
+fn build_mkdir_insert(dir: &Uuid, label: &str, parent: &Uuid) -> String {
+    format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+
+INSERT DATA {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{dir}> a posix:Directory ;
+            rdfs:label "{label}" .
+        <urn:uuid:{parent}> posix:includes <urn:uuid:{dir}> .
+    }}
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        dir = dir,
+        label = escape_sparql_string(label),
+        parent = parent,
+    )
+}
+
+/// Rank lookups run at once while checking a subtree before deleting it
+pub const RANK_CHECK_CONCURRENCY: usize = 16;
+
+/// A subtree about to be trashed or purged
+pub struct Subtree {
+    /// Nodes only reachable through the subtree's root, itself included
+    pub deleted: Vec<Uuid>,
+    /// `liqk:storedAs` of the deleted files
+    pub stored: Vec<String>,
+    /// Nodes below the root that another directory also includes; they
+    /// are kept and only unlinked from the subtree
+    pub shared: Vec<Uuid>,
+    pub directories: usize,
+    /// Earlier versions of the deleted files, with their `liqk:storedAs`;
+    /// they go with their file
+    pub versions: Vec<(Uuid, String)>,
+}
+
+/// Walk the subtree under `dir`, a directory or a single file. A node is
+/// deleted when every directory including it is deleted too, so files and
+/// directories also linked from elsewhere survive.
+pub async fn walk_subtree(state: &AppState, dir: &Uuid) -> Result<Subtree, String> {
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?node ?parent ?storedAs FROM <{graph}> WHERE {{
+    <urn:uuid:{dir}> posix:includes* ?node .
+    OPTIONAL {{ ?parent posix:includes ?node }}
+    OPTIONAL {{ ?node liqk:storedAs ?storedAs }}
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        dir = dir,
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+
+    let mut parents: HashMap<Uuid, HashSet<Uuid>> = HashMap::new();
+    let mut stored_as: HashMap<Uuid, String> = HashMap::new();
+    for binding in &bindings {
+        let node = binding_value(binding, "node")
+            .and_then(parse_urn_uuid)
+            .ok_or_else(|| "Subtree node is not a urn:uuid IRI".to_string())?;
+        let node_parents = parents.entry(node).or_default();
+        if let Some(parent) = binding_value(binding, "parent").and_then(parse_urn_uuid) {
+            node_parents.insert(parent);
+        }
+        if let Some(stored) = binding_value(binding, "storedAs") {
+            stored_as.insert(node, stored.to_string());
+        }
+    }
+
+    let mut deleted = HashSet::from([*dir]);
+    loop {
+        let orphaned: Vec<Uuid> = parents
+            .iter()
+            .filter(|(node, node_parents)| !deleted.contains(*node) && node_parents.iter().all(|p| deleted.contains(p)))
+            .map(|(node, _)| *node)
+            .collect();
+        if orphaned.is_empty() {
+            break;
+        }
+        deleted.extend(orphaned);
+    }
+
+    let shared = parents.keys().filter(|node| !deleted.contains(*node)).copied().collect();
+    let stored: Vec<String> = deleted.iter().filter_map(|node| stored_as.get(node).cloned()).collect();
+    let directories = deleted.len() - stored.len();
+    let files: Vec<Uuid> = deleted.iter().filter(|node| stored_as.contains_key(*node)).copied().collect();
+    let versions = file_version_nodes(state, &files).await?;
+    Ok(Subtree {
+        deleted: deleted.into_iter().collect(),
+        stored,
+        shared,
+        directories,
+        versions,
+    })
+}
+
+/// The `liqk:FileVersion` nodes of `files`, with their `liqk:storedAs`
+async fn file_version_nodes(state: &AppState, files: &[Uuid]) -> Result<Vec<(Uuid, String)>, String> {
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+    let values: Vec<String> = files.iter().map(|file| format!("<urn:uuid:{}>", file)).collect();
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT DISTINCT ?version ?storedAs FROM <{graph}> WHERE {{
+    VALUES ?file {{ {files} }}
+    ?file liqk:previousVersion+ ?version .
+    ?version a liqk:FileVersion ;
+        liqk:storedAs ?storedAs .
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        files = values.join(" "),
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+    Ok(bindings
+        .iter()
+        .filter_map(|b| {
+            Some((
+                binding_value(b, "version").and_then(parse_urn_uuid)?,
+                binding_value(b, "storedAs")?.to_string(),
+            ))
+        })
+        .collect())
+}
+
+/// One SPARQL update removing every triple of the filesystem graph that
+/// has one of `nodes` as its subject or object
+pub fn build_subtree_delete(nodes: &[Uuid]) -> String {
+    let values: Vec<String> = nodes.iter().map(|node| format!("<urn:uuid:{}>", node)).collect();
+    format!(
+        r#"DELETE {{ GRAPH <{graph}> {{ ?n ?p ?o }} }}
+WHERE {{ GRAPH <{graph}> {{ VALUES ?n {{ {values} }} ?n ?p ?o }} }} ;
+DELETE {{ GRAPH <{graph}> {{ ?s ?p ?n }} }}
+WHERE {{ GRAPH <{graph}> {{ VALUES ?n {{ {values} }} ?s ?p ?n }} }}"#,
+        graph = FILESYSTEM_GRAPH,
+        values = values.join(" "),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct MoveForm {
+    /// New label
+    #[serde(default)]
+    name: Option<String>,
+    /// Path of the directory to move the file into, e.g. `/reports/2024/`
+    #[serde(default)]
+    dir: Option<String>,
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// GET /file/{path} - Download a file or list a directory by path;
+/// `?format=zip` downloads a directory with its contents as a ZIP archive.
+///
+/// Directories are canonical with a trailing slash and files without one;
+/// other forms are permanently redirected so relative links in listings
+/// always resolve against the right base.
+pub async fn file_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response {
+    let raw_path = uri.path().strip_prefix(FILE_PREFIX).unwrap_or("");
+
+    let segments = match decode_segments(raw_path) {
+        Some(s) => s,
+        None => {
+            warn!(client = %addr, path = %uri.path(), "Invalid path encoding");
+            return (StatusCode::BAD_REQUEST, "Invalid path encoding").into_response();
+        }
+    };
+
+    let node = match resolve_path(&state, &segments).await {
+        Ok(Some(node)) => node,
+        Ok(None) => {
+            warn!(client = %addr, path = %uri.path(), "Path not found");
+            return (StatusCode::NOT_FOUND, "Not found").into_response();
+        }
+        Err(e) => {
+            warn!(client = %addr, path = %uri.path(), error = %e, "Path resolution failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to resolve path").into_response();
+        }
+    };
+
+    // Compare structure rather than exact bytes so differently percent-encoded
+    // but equivalent requests do not redirect in a loop.
+    let has_trailing_slash = raw_path.ends_with('/');
+    let has_empty_segments = raw_path.trim_end_matches('/').contains("//");
+    if has_trailing_slash != node.is_dir() || has_empty_segments || raw_path.is_empty() {
+        let mut location = canonical_path(&segments, node.is_dir());
+        if let Some(query) = uri.query() {
+            location.push('?');
+            location.push_str(query);
+        }
+        return Redirect::permanent(&location).into_response();
+    }
+
+    let required = match state.rules.required_rank(&state.roles, &Method::GET, uri.path()) {
+        Ok(required) => required,
+        Err(status) => return (status, "Access denied").into_response(),
+    };
+    let rank = get_access_rank(&state, &node.uuid, &headers, &jar).await;
+    if rank < required {
+        warn!(client = %addr, path = %uri.path(), rank = rank, required = required, "Access denied - insufficient rank for view");
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+
+    if form_params(uri.query().unwrap_or("")).iter().any(|(k, v)| k == "format" && v == "zip") {
+        if !node.is_dir() {
+            return (StatusCode::BAD_REQUEST, "Only directories can be downloaded as a zip").into_response();
+        }
+        // The archive holds the directory itself, named as in its path
+        let name = segments.last().map_or(ROOT_ARCHIVE_NAME, String::as_str);
+        return match archive_entries(&state, &node.uuid, name, &headers, &jar, required).await {
+            Ok(entries) => {
+                info!(client = %addr, path = %uri.path(), uuid = %node.uuid, entries = entries.len(), "Directory archive served");
+                zip_response(&state.files_dir, name, entries)
+            }
+            Err(e) => {
+                warn!(client = %addr, path = %uri.path(), error = %e, "Directory archive failed");
+                (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list directory").into_response()
+            }
+        };
+    }
+
+    match &node.stored_as {
+        None => match list_directory(&state.client, &state.oxigraph_url, &node.uuid).await {
+            Ok(entries) => {
+                let sidebar = match visible_collections(&state, &headers, &jar).await {
+                    Ok(collections) => render_sidebar(&collections, None),
+                    Err(e) => {
+                        warn!(client = %addr, error = %e, "Failed to list collections");
+                        String::new()
+                    }
+                };
+                let preferences = Preferences::from_jar(&jar);
+                let readme = match render_readme(&state, &headers, &jar, &entries, required).await {
+                    Ok(readme) => readme,
+                    Err(e) => {
+                        warn!(client = %addr, path = %uri.path(), error = %e, "Failed to render README");
+                        None
+                    }
+                };
+                let readme = readme_section(readme.as_deref(), &preferences, uri.path());
+                info!(client = %addr, path = %uri.path(), entries = entries.len(), "Directory listed");
+                Html(render_listing(&segments, &entries, &sidebar, &readme)).into_response()
+            }
+            Err(e) => {
+                warn!(client = %addr, path = %uri.path(), error = %e, "Directory listing failed");
+                (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list directory").into_response()
+            }
+        },
+        Some(stored_filename) => {
+            let label = segments.last().map_or("", String::as_str);
+            let delivery = Delivery::of(&headers, uri.query(), stored_filename);
+            if let Delivery::Preview(media) = delivery {
+                info!(client = %addr, path = %uri.path(), uuid = %node.uuid, "File preview served");
+                return preview_page(uri.path(), label, media);
+            }
+            match read_stored_file(&state.files_dir, stored_filename, &headers).await {
+                Ok((response, bytes)) => {
+                    info!(client = %addr, path = %uri.path(), uuid = %node.uuid, stored_as = %stored_filename, bytes = bytes, "File served");
+                    delivery.content(response, label)
+                }
+                Err(e) => {
+                    warn!(client = %addr, path = %uri.path(), stored_as = %stored_filename, error = %e, "Failed to read file from disk");
+                    (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read file").into_response()
+                }
+            }
+        }
+    }
+}
+
+/// PATCH /res/:uuid - Rename a file (form field `name`) and/or move it into
+/// another directory (`dir`, a path as under `/file`), in one update.
+///
+/// Needs the rule's rank (edit by default) on the file and on the target
+/// directory. A file included in several directories is moved out of all
+/// of them.
+pub async fn res_patch_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    AuthedUser { fingerprint, rank }: AuthedUser,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(uuid): Path<Uuid>,
+    Form(form): Form<MoveForm>,
+) -> Response {
+    let name = form.name.filter(|name| !name.is_empty());
+    let dir_path = form.dir.filter(|dir| !dir.trim().is_empty());
+    if name.is_none() && dir_path.is_none() {
+        return (StatusCode::BAD_REQUEST, "Give a new name, a target dir or both").into_response();
+    }
+    if let Some(name) = &name {
+        if let Err(e) = validate_label(name) {
+            return (StatusCode::BAD_REQUEST, format!("Invalid name: {}", e)).into_response();
+        }
+    }
+
+    let (label, parents) = match file_place(&state, &uuid).await {
+        Ok(Some(place)) => place,
+        Ok(None) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, "File not found");
+            return (StatusCode::NOT_FOUND, "File not found").into_response();
+        }
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "SPARQL lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup file").into_response();
+        }
+    };
+
+    // The target directory must be one the requester could write the file into
+    let dir = match &dir_path {
+        Some(dir_path) => {
+            let required = match state.rules.required_rank(&state.roles, &Method::PATCH, &format!("/res/{}", uuid)) {
+                Ok(required) => required,
+                Err(status) => return (status, "Access denied").into_response(),
+            };
+            let target = TargetDir::Path(dir_path);
+            match resolve_target_dir(&state, &addr, &fingerprint, &target, required, &headers, &jar).await {
+                Ok(dir) => Some(dir),
+                Err(response) => return response,
+            }
+        }
+        None => None,
+    };
+
+    let new_label = name.as_deref().or(label.as_deref());
+    let new_dirs = dir.map_or_else(|| parents.clone(), |dir| vec![dir]);
+    if let Some(new_label) = new_label {
+        match label_taken(&state, &new_dirs, new_label, &uuid).await {
+            Ok(false) => {}
+            Ok(true) => {
+                return (StatusCode::CONFLICT, format!("The directory already has an entry named {}", new_label))
+                    .into_response();
+            }
+            Err(e) => {
+                warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "SPARQL lookup failed");
+                return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup directory").into_response();
+            }
+        }
+    }
+
+    let query = build_move_update(&uuid, name.as_deref(), dir.as_ref());
+    if let Err(e) = send_sparql_update_once(&state, "move-file", &query).await {
+        warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "Failed to move file");
+        return (StatusCode::BAD_GATEWAY, "Failed to move file").into_response();
+    }
+    // Paths change, and ranks inherited through posix:includes with them
+    state.path_cache.invalidate_all();
+    if dir.is_some() {
+        state.rank_cache.invalidate_all();
+    }
+
+    info!(
+        client = %addr,
+        credential = %fingerprint,
+        uuid = %uuid,
+        rank = rank,
+        from = %label.as_deref().unwrap_or("-"),
+        name = %new_label.unwrap_or("-"),
+        dir = %dir_path.as_deref().unwrap_or("-"),
+        "File renamed or moved"
+    );
+    Json(json!({ "uuid": uuid, "name": new_label, "dir": dir_path })).into_response()
+}
+
+/// POST /file/{path}?mkdir - Create the directory at `path` under its
+/// existing parent directory, which needs the rule's rank (edit by
+/// default). Answers `201` with the new directory's canonical URL.
+pub async fn mkdir_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response {
+    let fingerprint = credential(&state, &headers, &jar).fingerprint();
+    if !form_params(uri.query().unwrap_or("")).iter().any(|(k, _)| k == "mkdir") {
+        return (StatusCode::BAD_REQUEST, "POST to /file needs ?mkdir").into_response();
+    }
+
+    let raw_path = uri.path().strip_prefix(FILE_PREFIX).unwrap_or("");
+    let Some(mut segments) = decode_segments(raw_path) else {
+        warn!(client = %addr, path = %uri.path(), "Invalid path encoding");
+        return (StatusCode::BAD_REQUEST, "Invalid path encoding").into_response();
+    };
+    let Some(label) = segments.pop() else {
+        return (StatusCode::BAD_REQUEST, "The root directory already exists").into_response();
+    };
+    if let Err(e) = validate_label(&label) {
+        return (StatusCode::BAD_REQUEST, format!("Invalid name: {}", e)).into_response();
+    }
+
+    let parent = match resolve_path(&state, &segments).await {
+        Ok(Some(node)) if node.is_dir() => node,
+        Ok(_) => {
+            warn!(client = %addr, credential = %fingerprint, path = %uri.path(), "Parent directory not found");
+            return (StatusCode::NOT_FOUND, "Parent directory not found").into_response();
+        }
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, path = %uri.path(), error = %e, "Path resolution failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to resolve path").into_response();
+        }
+    };
+
+    let required = match state.rules.required_rank(&state.roles, &Method::POST, uri.path()) {
+        Ok(required) => required,
+        Err(status) => return (status, "Access denied").into_response(),
+    };
+    let rank = get_access_rank(&state, &parent.uuid, &headers, &jar).await;
+    if rank < required {
+        warn!(client = %addr, credential = %fingerprint, path = %uri.path(), rank = rank, required = required, "Access denied - insufficient rank for mkdir");
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+
+    let dir = Uuid::new_v4();
+    match label_taken(&state, &[parent.uuid], &label, &dir).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return (StatusCode::CONFLICT, format!("The directory already has an entry named {}", label)).into_response();
+        }
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, path = %uri.path(), error = %e, "SPARQL lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup directory").into_response();
+        }
+    }
+
+    let query = build_mkdir_insert(&dir, &label, &parent.uuid);
+    if let Err(e) = send_sparql_update_once(&state, "mkdir", &query).await {
+        warn!(client = %addr, credential = %fingerprint, path = %uri.path(), error = %e, "Failed to create directory");
+        return (StatusCode::BAD_GATEWAY, "Failed to create directory").into_response();
+    }
+
+    segments.push(label);
+    let location = canonical_path(&segments, true);
+    info!(client = %addr, credential = %fingerprint, path = %location, uuid = %dir, rank = rank, "Directory created");
+    (
+        StatusCode::CREATED,
+        [(header::LOCATION, location.clone())],
+        Json(json!({ "uuid": dir, "path": location })),
+    )
+        .into_response()
+}
+
+/// DELETE /file/{path} - Move a file or directory into the trash, with
+/// everything only it includes; the stored files of those files and of
+/// their earlier versions move into the trash folder. A directory that is
+/// not empty needs `?recursive`. The rule's rank (edit by default) is
+/// required on the node and on every node below it; one node without it
+/// refuses the whole deletion.
+pub async fn delete_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response {
+    let fingerprint = credential(&state, &headers, &jar).fingerprint();
+    let raw_path = uri.path().strip_prefix(FILE_PREFIX).unwrap_or("");
+    let Some(segments) = decode_segments(raw_path) else {
+        warn!(client = %addr, path = %uri.path(), "Invalid path encoding");
+        return (StatusCode::BAD_REQUEST, "Invalid path encoding").into_response();
+    };
+    if segments.is_empty() {
+        return (StatusCode::BAD_REQUEST, "The root directory cannot be deleted").into_response();
+    }
+
+    let node = match resolve_path(&state, &segments).await {
+        Ok(Some(node)) => node,
+        Ok(None) => {
+            warn!(client = %addr, credential = %fingerprint, path = %uri.path(), "Path not found");
+            return (StatusCode::NOT_FOUND, "Not found").into_response();
+        }
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, path = %uri.path(), error = %e, "Path resolution failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to resolve path").into_response();
+        }
+    };
+
+    let subtree = match walk_subtree(&state, &node.uuid).await {
+        Ok(subtree) => subtree,
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, path = %uri.path(), error = %e, "Subtree lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list directory").into_response();
+        }
+    };
+    let recursive = form_params(uri.query().unwrap_or("")).iter().any(|(k, _)| k == "recursive");
+    if !recursive && (subtree.deleted.len() > 1 || !subtree.shared.is_empty()) {
+        return (StatusCode::CONFLICT, "Directory is not empty; add ?recursive to delete it with its contents")
+            .into_response();
+    }
+
+    let required = match state.rules.required_rank(&state.roles, &Method::DELETE, uri.path()) {
+        Ok(required) => required,
+        Err(status) => return (status, "Access denied").into_response(),
+    };
+    let nodes: Vec<Uuid> = subtree.deleted.iter().chain(&subtree.shared).copied().collect();
+    let ranks: Vec<(Uuid, i32)> = stream::iter(nodes)
+        .map(|node| {
+            let (state, headers, jar) = (&state, &headers, &jar);
+            async move { (node, get_access_rank(state, &node, headers, jar).await) }
+        })
+        .buffer_unordered(RANK_CHECK_CONCURRENCY)
+        .collect()
+        .await;
+    if let Some((denied, rank)) = ranks.into_iter().find(|(_, rank)| *rank < required) {
+        warn!(client = %addr, credential = %fingerprint, path = %uri.path(), uuid = %denied, rank = rank, required = required, "Access denied - insufficient rank for a node of the deleted subtree");
+        return (StatusCode::FORBIDDEN, format!("Access denied to urn:uuid:{}", denied)).into_response();
+    }
+
+    let parent = match resolve_path(&state, &segments[..segments.len() - 1]).await {
+        Ok(Some(parent)) => parent,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, path = %uri.path(), error = %e, "Path resolution failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to resolve path").into_response();
+        }
+    };
+    let path = canonical_path(&segments, node.is_dir());
+    let failed = match move_to_trash(&state, &node.uuid, &parent.uuid, &path, &subtree).await {
+        Ok(failed) => failed,
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, path = %uri.path(), error = %e, "Failed to move to trash");
+            return (StatusCode::BAD_GATEWAY, "Failed to move to trash").into_response();
+        }
+    };
+
+    info!(
+        client = %addr,
+        credential = %fingerprint,
+        path = %path,
+        uuid = %node.uuid,
+        directories = subtree.directories,
+        files = subtree.stored.len(),
+        versions = subtree.versions.len(),
+        failed = failed.len(),
+        "Moved to trash"
+    );
+    Json(json!({
+        "path": path,
+        "uuid": node.uuid,
+        "directories": subtree.directories,
+        "files": subtree.stored.len(),
+        "versions": subtree.versions.len(),
+        "not_moved_on_disk": failed,
+    }))
+    .into_response()
+}
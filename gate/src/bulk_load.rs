@@ -0,0 +1,224 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use bytes::Bytes;
+use futures_util::{stream, stream::BoxStream, StreamExt};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+use crate::encoding::StreamDecoder;
+use crate::files::{credential, get_access_rank_iri};
+use crate::forwarded::{client_ip, forwarding_headers};
+use crate::metrics::{counted, Metrics, UpstreamError};
+use crate::proxy::GRAPH_IRI;
+use crate::rank_cache::store_write_may_change_acl;
+use crate::rate_limit::{requester, too_many_requests, RateClass};
+use crate::roles::reload_roles;
+use crate::update_graphs::{form_params, is_valid_iri, store_target, GraphTarget};
+use crate::AppState;
+
+/// How often a running load logs its progress
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// `POST /load`: stream an RDF dump to Oxigraph's bulk loader
+/// (`/store?no_transaction`) as it arrives. `graph=` or `default` pick the
+/// target like on `/store`; without either, quad formats load the whole
+/// dataset. Bodies are neither held in memory nor limited in size, and
+/// gzip or deflate bodies are decoded on the way. Loads are not atomic:
+/// one that fails midway leaves what was loaded so far.
+pub async fn load_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    req: Request,
+) -> Response {
+    let uri = req.uri().clone();
+    let headers = req.headers().clone();
+    let credential = credential(&state, &headers, &jar);
+    let fingerprint = credential.fingerprint();
+
+    let required_rank = match state.rules.required_rank(&state.roles, &Method::POST, uri.path()) {
+        Ok(required) => required,
+        Err(status) => {
+            warn!(client = %addr, credential = %fingerprint, path = %uri, status = %status, "Bulk load rejected by authorization rules");
+            return (status, "Access denied").into_response();
+        }
+    };
+
+    // Like Graph Store writes, a load into a named graph is also allowed by
+    // the rank policies grant on that graph
+    let target = store_target(uri.query());
+    let graph_rank = match &target {
+        Some(GraphTarget::Named(graph)) => {
+            if !is_valid_iri(graph) {
+                warn!(client = %addr, credential = %fingerprint, path = %uri, "Bulk load rejected - invalid graph IRI");
+                return (StatusCode::BAD_REQUEST, "Invalid graph IRI").into_response();
+            }
+            Some(get_access_rank_iri(&state, graph, &headers, &jar).await)
+        }
+        _ => None,
+    };
+    let graph = target.as_ref().map_or("the whole dataset".to_string(), |target| target.to_string());
+    if state.restrict_update_graphs && graph_rank.is_none_or(|rank| rank < required_rank) {
+        warn!(client = %addr, credential = %fingerprint, path = %uri, graph = %graph, "Bulk load rejected - graph not allowed");
+        return (StatusCode::FORBIDDEN, format!("Load rejected: no write access to {}", graph)).into_response();
+    }
+    let rank = get_access_rank_iri(&state, GRAPH_IRI, &headers, &jar)
+        .await
+        .max(graph_rank.unwrap_or(0));
+    if rank < required_rank {
+        warn!(client = %addr, credential = %fingerprint, path = %uri, rank = rank, required = required_rank, "Access denied - insufficient rank for bulk load");
+        return (StatusCode::FORBIDDEN, "Access denied - bulk loads require edit access").into_response();
+    }
+
+    let trusted_peer = state.proxy_auth.as_ref().is_some_and(|config| config.is_trusted(addr.ip()));
+    if let Some(requester) = requester(&credential, rank, client_ip(&headers, addr.ip(), trusted_peer)) {
+        if let Err(retry_after) = state.rate_limiter.check(RateClass::Update, &requester) {
+            warn!(client = %addr, credential = %fingerprint, path = %uri, class = RateClass::Update.as_str(), "Bulk load rejected - rate limit exceeded");
+            return too_many_requests(retry_after);
+        }
+    }
+
+    // Oxigraph picks the parser by media type, so one is required
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).cloned() else {
+        return (StatusCode::UNSUPPORTED_MEDIA_TYPE, "Content-Type must name the RDF format of the dump").into_response();
+    };
+    let content_encoding = headers.get(header::CONTENT_ENCODING).and_then(|v| v.to_str().ok());
+    let decoder = match StreamDecoder::new(content_encoding) {
+        Ok(decoder) => decoder,
+        Err(encoding) => {
+            return (StatusCode::UNSUPPORTED_MEDIA_TYPE, format!("Unsupported Content-Encoding: {}", encoding))
+                .into_response();
+        }
+    };
+
+    let mut target_url = format!("{}/store?no_transaction", state.oxigraph_url);
+    match &target {
+        Some(GraphTarget::Named(graph)) => {
+            target_url.push_str(&format!("&graph={}", utf8_percent_encode(graph, NON_ALPHANUMERIC)));
+        }
+        Some(GraphTarget::Default) => target_url.push_str("&default"),
+        None => {}
+    }
+    if form_params(uri.query().unwrap_or("")).iter().any(|(k, _)| k == "lenient") {
+        target_url.push_str("&lenient");
+    }
+
+    // The body is counted and logged on its way to Oxigraph. An error in
+    // it aborts the upstream request and is kept to tell the client.
+    let started = Instant::now();
+    let loaded = Arc::new(AtomicU64::new(0));
+    let body_error = Arc::new(Mutex::new(None::<String>));
+    let progress = {
+        let loaded = Arc::clone(&loaded);
+        let body_error = Arc::clone(&body_error);
+        let (fingerprint, graph) = (fingerprint.clone(), graph.clone());
+        let mut reported = started;
+        move |chunk: io::Result<Bytes>| {
+            match &chunk {
+                Ok(bytes) => {
+                    let total = loaded.fetch_add(bytes.len() as u64, Ordering::Relaxed) + bytes.len() as u64;
+                    if reported.elapsed() >= PROGRESS_INTERVAL {
+                        reported = Instant::now();
+                        info!(client = %addr, credential = %fingerprint, graph = %graph, bytes = total, elapsed_secs = started.elapsed().as_secs(), "Bulk load in progress");
+                    }
+                }
+                Err(e) => *body_error.lock().unwrap() = Some(e.to_string()),
+            }
+            chunk
+        }
+    };
+    let body = decoded(req.into_body().into_data_stream(), decoder).map(progress);
+    let body = reqwest::Body::wrap_stream(counted(body, &state, Metrics::sent));
+
+    info!(client = %addr, credential = %fingerprint, graph = %graph, "Bulk load started");
+    let mut upstream_req = state
+        .proxy_client
+        .post(&target_url)
+        .header(header::CONTENT_TYPE, content_type);
+    for (name, value) in forwarding_headers(&headers, addr.ip(), trusted_peer) {
+        upstream_req = upstream_req.header(name, value);
+    }
+    // The response only starts once the whole dump is loaded, so no
+    // upstream timeout applies
+    let sent = upstream_req.body(body).send().await;
+
+    // Even a failed load may have been applied in part
+    state.query_cache.invalidate_all();
+    let bytes = loaded.load(Ordering::Relaxed);
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    match sent {
+        Ok(resp) => {
+            let status = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+            if status.is_server_error() {
+                state.metrics.upstream_error(UpstreamError::ServerError);
+            }
+            if status.is_success() {
+                info!(client = %addr, credential = %fingerprint, graph = %graph, status = %status, bytes = bytes, duration_ms = duration_ms, "Bulk load finished");
+                if store_write_may_change_acl(uri.query()) {
+                    state.rank_cache.invalidate_all();
+                    state.path_cache.invalidate_all();
+                    info!(client = %addr, credential = %fingerprint, graph = %graph, "Rank and path caches invalidated by ACL-affecting load");
+                    let roles_state = Arc::clone(&state);
+                    tokio::spawn(async move { reload_roles(&roles_state).await });
+                }
+            } else {
+                warn!(client = %addr, credential = %fingerprint, graph = %graph, status = %status, bytes = bytes, duration_ms = duration_ms, "Bulk load failed");
+            }
+            let response_type = resp.headers().get(header::CONTENT_TYPE).cloned();
+            let message = resp.bytes().await.unwrap_or_default();
+            state.metrics.received(message.len());
+            let mut response = (status, message).into_response();
+            if let Some(response_type) = response_type {
+                response.headers_mut().insert(header::CONTENT_TYPE, response_type);
+            }
+            response
+        }
+        Err(e) => {
+            if let Some(reason) = body_error.lock().unwrap().take() {
+                warn!(client = %addr, credential = %fingerprint, graph = %graph, bytes = bytes, duration_ms = duration_ms, error = %reason, "Bulk load aborted - request body failed");
+                return (StatusCode::BAD_REQUEST, format!("Failed to read request body: {}", reason)).into_response();
+            }
+            state.metrics.upstream_error(if e.is_connect() { UpstreamError::Connect } else { UpstreamError::Other });
+            warn!(client = %addr, credential = %fingerprint, graph = %graph, bytes = bytes, duration_ms = duration_ms, error = %e, "Bulk load failed");
+            (StatusCode::BAD_GATEWAY, format!("Bulk load failed: {}", e)).into_response()
+        }
+    }
+}
+
+/// The request body as Oxigraph should see it, decoded chunk by chunk
+fn decoded<S, E>(body: S, decoder: Option<StreamDecoder>) -> BoxStream<'static, io::Result<Bytes>>
+where
+    S: futures_util::Stream<Item = Result<Bytes, E>> + Send + Unpin + 'static,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let Some(decoder) = decoder else {
+        return body.map(|chunk| chunk.map_err(io::Error::other)).boxed();
+    };
+    stream::unfold((body, Some(decoder)), |(mut body, decoder)| async move {
+        let mut decoder = decoder?;
+        let (chunk, decoder) = match body.next().await {
+            Some(Ok(chunk)) => match decoder.write(&chunk) {
+                Ok(decoded) => (Ok(Bytes::from(decoded)), Some(decoder)),
+                Err(e) => (Err(e), None),
+            },
+            Some(Err(e)) => (Err(io::Error::other(e)), None),
+            None => (decoder.finish().map(Bytes::from), None),
+        };
+        Some((chunk, (body, decoder)))
+    })
+    .boxed()
+}
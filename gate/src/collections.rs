@@ -0,0 +1,395 @@
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde_json::json;
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::browse::{file_icon, format_size, render_row};
+use crate::files::{binding_value, get_access_rank, sparql_select, validate_uuid, FILESYSTEM_GRAPH};
+use crate::query_log;
+use crate::templates::{escape_html, COLLECTION_HTML};
+use crate::AppState;
+
+/// Route prefix for collection pages
+pub const COLLECTION_PREFIX: &str = "/collections";
+
+/// Members listed per collection; the rest of the result is ignored
+const MAX_MEMBERS: usize = 1000;
+
+/// A virtual collection: a saved query whose results are listed like a
+/// directory
+pub struct Collection {
+    pub uuid: Uuid,
+    pub label: String,
+}
+
+/// A file listed in a collection
+pub struct Member {
+    pub uuid: Uuid,
+    pub label: String,
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+}
+
+/// Form of a saved collection query
+#[derive(Debug, PartialEq)]
+enum QueryForm {
+    /// Members are the `?file` bindings
+    Select,
+    /// Members are the subjects of the constructed triples
+    Construct,
+}
+
+fn parse_urn_uuid(iri: &str) -> Option<Uuid> {
+    iri.strip_prefix("urn:uuid:").and_then(|s| Uuid::parse_str(s).ok())
+}
+
+pub fn collection_path(uuid: &Uuid) -> String {
+    format!("{}/{}", COLLECTION_PREFIX, uuid)
+}
+
+/// Determine the query form from its first query keyword, skipping the
+/// prologue and comments. ASK and DESCRIBE queries are not supported.
+fn query_form(query: &str) -> Option<QueryForm> {
+    query
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == '{'))
+        .find_map(|token| match token.to_ascii_uppercase().as_str() {
+            "SELECT" => Some(Some(QueryForm::Select)),
+            "CONSTRUCT" => Some(Some(QueryForm::Construct)),
+            "ASK" | "DESCRIBE" => Some(None),
+            _ => None,
+        })
+        .flatten()
+}
+
+/// All collections, ordered by `liqk:order` (unordered last), then label
+pub async fn list_collections(client: &reqwest::Client, oxigraph_url: &str) -> Result<Vec<Collection>, String> {
+    let query = format!(
+        r#"PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?collection ?label FROM <{graph}> WHERE {{
+    ?collection a liqk:Collection ;
+        rdfs:label ?label .
+    OPTIONAL {{ ?collection liqk:order ?order }}
+}}
+ORDER BY (!BOUND(?order)) ?order ?label"#,
+        graph = FILESYSTEM_GRAPH,
+    );
+
+    let bindings = sparql_select(client, oxigraph_url, &query).await?;
+
+    Ok(bindings
+        .iter()
+        .filter_map(|b| {
+            Some(Collection {
+                uuid: binding_value(b, "collection").and_then(parse_urn_uuid)?,
+                label: binding_value(b, "label")?.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Collections the requester may open, for the sidebar and the index
+pub async fn visible_collections(
+    state: &AppState,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> Result<Vec<Collection>, String> {
+    let mut visible = Vec::new();
+    for collection in list_collections(&state.client, &state.oxigraph_url).await? {
+        let path = collection_path(&collection.uuid);
+        let Ok(required) = state.rules.required_rank(&state.roles, &Method::GET, &path) else {
+            continue;
+        };
+        if get_access_rank(state, &collection.uuid, headers, jar).await >= required {
+            visible.push(collection);
+        }
+    }
+    Ok(visible)
+}
+
+/// Load a collection's label and saved query
+async fn lookup_collection(
+    client: &reqwest::Client,
+    oxigraph_url: &str,
+    uuid: &Uuid,
+) -> Result<Option<(String, String)>, String> {
+    let query = format!(
+        r#"PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?label ?query FROM <{graph}> WHERE {{
+    <urn:uuid:{uuid}> a liqk:Collection ;
+        rdfs:label ?label ;
+        liqk:query ?query .
+}}
+LIMIT 1"#,
+        graph = FILESYSTEM_GRAPH,
+        uuid = uuid,
+    );
+
+    let bindings = sparql_select(client, oxigraph_url, &query).await?;
+    Ok(bindings.first().and_then(|b| {
+        Some((
+            binding_value(b, "label")?.to_string(),
+            binding_value(b, "query")?.to_string(),
+        ))
+    }))
+}
+
+/// Run a CONSTRUCT query and return the `urn:uuid` subjects of its triples
+async fn construct_subjects(client: &reqwest::Client, oxigraph_url: &str, query: &str) -> Result<Vec<Uuid>, String> {
+    query_log::record("query", query);
+    let response = client
+        .post(format!("{}/query", oxigraph_url))
+        .header("Content-Type", "application/sparql-query")
+        .header("Accept", "application/n-triples")
+        .body(query.to_string())
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send SPARQL query: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("SPARQL query failed with status {}: {}", status, body));
+    }
+
+    let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+    Ok(body
+        .lines()
+        .filter_map(|line| line.strip_prefix('<')?.split_once('>'))
+        .filter_map(|(subject, _)| parse_urn_uuid(subject))
+        .collect())
+}
+
+/// Run a saved query and return the member UUIDs in result order, without
+/// duplicates
+async fn run_collection_query(client: &reqwest::Client, oxigraph_url: &str, query: &str) -> Result<Vec<Uuid>, String> {
+    let uuids = match query_form(query) {
+        Some(QueryForm::Select) => sparql_select(client, oxigraph_url, query)
+            .await?
+            .iter()
+            .filter_map(|b| binding_value(b, "file").and_then(parse_urn_uuid))
+            .collect(),
+        Some(QueryForm::Construct) => construct_subjects(client, oxigraph_url, query).await?,
+        None => return Err("Collection query must be a SELECT or CONSTRUCT query".to_string()),
+    };
+
+    let mut members = Vec::new();
+    for uuid in uuids {
+        if !members.contains(&uuid) {
+            members.push(uuid);
+            if members.len() == MAX_MEMBERS {
+                break;
+            }
+        }
+    }
+    Ok(members)
+}
+
+/// Load labels and sizes of the members that are stored files, keeping
+/// the given order
+pub async fn load_members(client: &reqwest::Client, oxigraph_url: &str, uuids: &[Uuid]) -> Result<Vec<Member>, String> {
+    if uuids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let values: String = uuids.iter().map(|u| format!(" <urn:uuid:{}>", u)).collect();
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?node ?label ?size ?sha256 FROM <{graph}> WHERE {{
+    VALUES ?node {{{values} }}
+    ?node liqk:storedAs ?storedAs ;
+        rdfs:label ?label .
+    OPTIONAL {{ ?node posix:size ?size }}
+    OPTIONAL {{ ?node liqk:sha256 ?sha256 }}
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        values = values,
+    );
+
+    let bindings = sparql_select(client, oxigraph_url, &query).await?;
+    let mut found: HashMap<Uuid, Member> = HashMap::new();
+    for b in &bindings {
+        if let (Some(uuid), Some(label)) = (binding_value(b, "node").and_then(parse_urn_uuid), binding_value(b, "label")) {
+            found.insert(
+                uuid,
+                Member {
+                    uuid,
+                    label: label.to_string(),
+                    size: binding_value(b, "size").and_then(|s| s.parse().ok()),
+                    sha256: binding_value(b, "sha256").map(str::to_string),
+                },
+            );
+        }
+    }
+
+    Ok(uuids.iter().filter_map(|uuid| found.remove(uuid)).collect())
+}
+
+/// Render the navigation sidebar: the file tree root and every visible
+/// collection. `current` is highlighted.
+pub fn render_sidebar(collections: &[Collection], current: Option<&Uuid>) -> String {
+    let mut items = String::from("            <li><a href=\"/file/\">Files</a></li>\n");
+    for collection in collections {
+        let class = if current == Some(&collection.uuid) { " class=\"current\"" } else { "" };
+        items.push_str(&format!(
+            "            <li><a href=\"{}\"{}>{}</a></li>\n",
+            escape_html(&collection_path(&collection.uuid)),
+            class,
+            escape_html(&collection.label),
+        ));
+    }
+    format!(
+        "    <nav class=\"sidebar\">\n        <h2>Collections</h2>\n        <ul>\n{}        </ul>\n    </nav>",
+        items
+    )
+}
+
+fn render_collection(label: &str, members: &[Member], sidebar: &str) -> String {
+    let mut rows = String::new();
+    for member in members {
+        let size = member.size.map(format_size).unwrap_or_default();
+        rows.push_str(&render_row(
+            &format!("/res/{}", member.uuid),
+            &file_icon(&member.label, &member.uuid),
+            &member.label,
+            &size,
+            member.sha256.as_deref(),
+            Some(&member.uuid),
+        ));
+    }
+    if rows.is_empty() {
+        rows = "        <tr><td></td><td></td><td class=\"empty\">No files</td><td></td><td></td></tr>".to_string();
+    }
+
+    COLLECTION_HTML
+        .replace("{title}", &escape_html(label))
+        .replace("{sidebar}", sidebar)
+        .replace("{entries}", rows.trim_end())
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// GET /collections - Collections visible to the requester (JSON)
+pub async fn collections_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    let collections = match visible_collections(&state, &headers, &jar).await {
+        Ok(collections) => collections,
+        Err(e) => {
+            warn!(client = %addr, error = %e, "Failed to list collections");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list collections").into_response();
+        }
+    };
+
+    let json_response = json!(collections
+        .iter()
+        .map(|c| json!({
+            "uuid": c.uuid.to_string(),
+            "label": c.label,
+            "path": collection_path(&c.uuid),
+        }))
+        .collect::<Vec<_>>());
+
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        json_response.to_string(),
+    )
+        .into_response()
+}
+
+/// GET /collections/:uuid - List the files a collection's query selects.
+///
+/// Members the requester cannot download are left out, so a collection
+/// never reveals more than browsing would.
+pub async fn collection_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    uri: Uri,
+    Path(uuid_str): Path<String>,
+) -> Response {
+    let uuid = match validate_uuid(&uuid_str) {
+        Some(u) => u,
+        None => {
+            warn!(client = %addr, uuid = %uuid_str, "Invalid UUID format");
+            return (StatusCode::BAD_REQUEST, "Invalid UUID format").into_response();
+        }
+    };
+
+    let required = match state.rules.required_rank(&state.roles, &Method::GET, uri.path()) {
+        Ok(required) => required,
+        Err(status) => return (status, "Access denied").into_response(),
+    };
+    let rank = get_access_rank(&state, &uuid, &headers, &jar).await;
+    if rank < required {
+        warn!(client = %addr, collection = %uuid, rank = rank, required = required, "Access denied - insufficient rank for view");
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+
+    let (label, query) = match lookup_collection(&state.client, &state.oxigraph_url, &uuid).await {
+        Ok(Some(found)) => found,
+        Ok(None) => {
+            warn!(client = %addr, collection = %uuid, "Collection not found");
+            return (StatusCode::NOT_FOUND, "Collection not found").into_response();
+        }
+        Err(e) => {
+            warn!(client = %addr, collection = %uuid, error = %e, "SPARQL lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup collection").into_response();
+        }
+    };
+
+    let members = match run_collection_query(&state.client, &state.oxigraph_url, &query).await {
+        Ok(uuids) => load_members(&state.client, &state.oxigraph_url, &uuids).await,
+        Err(e) => Err(e),
+    };
+    let members = match members {
+        Ok(members) => members,
+        Err(e) => {
+            warn!(client = %addr, collection = %uuid, error = %e, "Collection query failed");
+            return (StatusCode::BAD_GATEWAY, "Collection query failed").into_response();
+        }
+    };
+
+    let mut visible = Vec::new();
+    for member in members {
+        let path = format!("/res/{}", member.uuid);
+        let Ok(required) = state.rules.required_rank(&state.roles, &Method::GET, &path) else {
+            continue;
+        };
+        if get_access_rank(&state, &member.uuid, &headers, &jar).await >= required {
+            visible.push(member);
+        }
+    }
+
+    let sidebar = match visible_collections(&state, &headers, &jar).await {
+        Ok(collections) => render_sidebar(&collections, Some(&uuid)),
+        Err(e) => {
+            warn!(client = %addr, error = %e, "Failed to list collections");
+            String::new()
+        }
+    };
+
+    info!(client = %addr, collection = %uuid, entries = visible.len(), "Collection listed");
+    Html(render_collection(&label, &visible, &sidebar)).into_response()
+}
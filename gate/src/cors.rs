@@ -0,0 +1,93 @@
+use http::{header, HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowHeaders, AllowOrigin, Any, CorsLayer};
+
+/// Methods allowed cross-origin when `CORS_ALLOWED_METHODS` is unset
+pub const DEFAULT_CORS_METHODS: &str = "GET,POST,PUT,PATCH,DELETE,OPTIONS";
+
+/// The header credentials of cross-origin clients travel in
+const ACCESS_TOKEN_HEADER: HeaderName = HeaderName::from_static("x-access-token");
+
+/// Cross-origin policy for browser apps on other origins. `None` means any
+/// origin or request header.
+pub struct CorsConfig {
+    pub origins: Option<Vec<HeaderValue>>,
+    pub methods: Vec<Method>,
+    pub headers: Option<Vec<HeaderName>>,
+}
+
+impl CorsConfig {
+    /// Parse the `CORS_ALLOWED_*` values: comma-separated lists, where `*`
+    /// (or an unset variable) allows any origin or header
+    pub fn parse(origins: Option<&str>, methods: &str, headers: Option<&str>) -> Result<Self, String> {
+        let origins = match list(origins) {
+            None => None,
+            Some(origins) => Some(
+                origins
+                    .iter()
+                    .map(|o| {
+                        let origin = o.trim_end_matches('/');
+                        if !(origin.starts_with("http://") || origin.starts_with("https://")) {
+                            return Err(format!("origin {} must start with http:// or https://", o));
+                        }
+                        HeaderValue::from_str(origin).map_err(|_| format!("invalid origin {}", o))
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+        };
+        let methods = list(Some(methods))
+            .ok_or("methods cannot be *")?
+            .iter()
+            .map(|m| Method::from_bytes(m.to_uppercase().as_bytes()).map_err(|_| format!("invalid method {}", m)))
+            .collect::<Result<_, _>>()?;
+        let headers = match list(headers) {
+            None => None,
+            Some(headers) => Some(
+                headers
+                    .iter()
+                    .map(|h| HeaderName::from_bytes(h.as_bytes()).map_err(|_| format!("invalid header {}", h)))
+                    .collect::<Result<_, _>>()?,
+            ),
+        };
+        Ok(Self { origins, methods, headers })
+    }
+
+    /// The CORS layer for every route of the gate: `/query` and the other
+    /// proxied endpoints, `/file`, `/res` and the JSON APIs. Credentials
+    /// (cookies) are never allowed cross-origin, so cross-origin clients
+    /// authenticate with `X-Access-Token` or `Authorization`, which are
+    /// always allowed request headers.
+    pub fn layer(&self) -> CorsLayer {
+        let origins = match &self.origins {
+            None => AllowOrigin::from(Any),
+            Some(origins) => AllowOrigin::list(origins.iter().cloned()),
+        };
+        let headers = match &self.headers {
+            None => AllowHeaders::from(Any),
+            Some(headers) => {
+                let mut headers = headers.clone();
+                for required in [ACCESS_TOKEN_HEADER, header::AUTHORIZATION] {
+                    if !headers.contains(&required) {
+                        headers.push(required);
+                    }
+                }
+                AllowHeaders::list(headers)
+            }
+        };
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(self.methods.clone())
+            .allow_headers(headers)
+            // Lets resumable upload clients read Upload-Offset and Location
+            .expose_headers(Any)
+    }
+}
+
+/// Split a comma-separated list; `None` when unset, empty or `*`
+fn list(value: Option<&str>) -> Option<Vec<String>> {
+    let items: Vec<String> = value?
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect();
+    (!items.is_empty() && !items.iter().any(|item| item == "*")).then_some(items)
+}
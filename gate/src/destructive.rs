@@ -0,0 +1,131 @@
+use axum::{
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use std::{net::SocketAddr, sync::Arc};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::files::{escape_sparql_string, send_sparql_update, ACCESS_GRAPH, FILESYSTEM_GRAPH};
+use crate::login_audit::AUDIT_GRAPH;
+use crate::proxy::GRAPH_IRI;
+use crate::saved_queries::QUERIES_GRAPH;
+use crate::shortlinks::LINKS_GRAPH;
+use crate::update_graphs::Wipe;
+use crate::AppState;
+
+/// Header a client sends with `true` to confirm a destructive update
+pub const CONFIRM_HEADER: &str = "x-confirm-destructive";
+
+/// Graphs guarded when `PROTECTED_GRAPHS` is unset: the gate's own
+pub fn default_protected_graphs() -> Vec<String> {
+    [GRAPH_IRI, ACCESS_GRAPH, FILESYSTEM_GRAPH, AUDIT_GRAPH, QUERIES_GRAPH, LINKS_GRAPH]
+        .map(str::to_string)
+        .to_vec()
+}
+
+/// How a destructive update was let through, or not
+#[derive(Clone, Copy)]
+pub enum Outcome {
+    /// Refused for lack of a confirmation
+    Blocked,
+    /// Sent with the confirmation header
+    Confirmed,
+    /// Sent by an admin, who needs no confirmation
+    Admin,
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Blocked => "blocked",
+            Outcome::Confirmed => "confirmed",
+            Outcome::Admin => "admin",
+        }
+    }
+}
+
+/// The operations among `wipes` that need a confirmation: those emptying
+/// every graph, every named graph, a protected graph or a graph the gate
+/// cannot resolve
+pub fn guarded(wipes: Vec<Wipe>, protected: &[String]) -> Vec<String> {
+    wipes
+        .into_iter()
+        .filter(|wipe| wipe.graph.as_ref().is_none_or(|graph| protected.contains(graph)))
+        .map(|wipe| wipe.operation)
+        .collect()
+}
+
+/// Whether the request carries `X-Confirm-Destructive: true`
+pub fn is_confirmed(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONFIRM_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("true"))
+}
+
+/// `428` naming the header that lets the update through
+pub fn confirmation_required(operations: &[String]) -> Response {
+    (
+        StatusCode::PRECONDITION_REQUIRED,
+        format!(
+            "Destructive update ({}) requires admin access or the X-Confirm-Destructive: true header",
+            operations.join(", ")
+        ),
+    )
+        .into_response()
+}
+
+/// Record a destructive update in the audit graph, with the upstream
+/// status once it was sent. Runs in the background; a record written
+/// before a `DROP ALL` would be dropped along with everything else, so
+/// updates that go through are recorded after they are sent.
+pub fn record(
+    state: &Arc<AppState>,
+    addr: &SocketAddr,
+    credential: &str,
+    operations: &[String],
+    outcome: Outcome,
+    status: Option<StatusCode>,
+) {
+    let state = Arc::clone(state);
+    let ip = addr.ip().to_string();
+    let credential = credential.to_string();
+    let operation_triples: String = operations
+        .iter()
+        .map(|op| format!(" ;\n            liqk:operation \"{}\"", escape_sparql_string(op)))
+        .collect();
+    let status_triple = status
+        .map(|status| format!(" ;\n            liqk:status \"{}\"^^xsd:integer", status.as_u16()))
+        .unwrap_or_default();
+    tokio::spawn(async move {
+        let query = format!(
+            r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX dcterms: <http://purl.org/dc/terms/>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+
+INSERT DATA {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{id}> a liqk:DestructiveUpdate ;
+            liqk:client-ip "{ip}" ;
+            liqk:credential "{credential}" ;
+            liqk:outcome "{outcome}" ;
+            dcterms:created "{now}"^^xsd:dateTime{operation_triples}{status_triple} .
+    }}
+}}"#,
+            graph = AUDIT_GRAPH,
+            id = Uuid::new_v4(),
+            ip = ip,
+            credential = escape_sparql_string(&credential),
+            outcome = outcome.as_str(),
+            now = Utc::now().to_rfc3339(),
+            operation_triples = operation_triples,
+            status_triple = status_triple,
+        );
+
+        if let Err(e) = send_sparql_update(&state, "destructive-update", &query).await {
+            warn!(client = %ip, error = %e, "Failed to record destructive update");
+        }
+    });
+}
@@ -0,0 +1,249 @@
+use axum::{
+    extract::{ConnectInfo, Query, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    sync::Arc,
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::browse::resolve_path;
+use crate::files::{binding_value, get_access_rank, sparql_select, FILESYSTEM_GRAPH};
+use crate::templates::DIFF_HTML;
+use crate::AppState;
+
+#[derive(Deserialize)]
+pub struct DiffParams {
+    a: String,
+    b: String,
+}
+
+/// A node below a compared directory, keyed by its path relative to it
+#[derive(Clone)]
+struct Item {
+    is_dir: bool,
+    size: Option<u64>,
+    sha256: Option<String>,
+}
+
+impl Item {
+    /// Whether two items at the same relative path differ. Content hashes
+    /// win when both sides have one; otherwise sizes are compared.
+    fn differs(&self, other: &Item) -> bool {
+        if self.is_dir || other.is_dir {
+            return self.is_dir != other.is_dir;
+        }
+        match (&self.sha256, &other.sha256) {
+            (Some(a), Some(b)) => a != b,
+            _ => self.size != other.size,
+        }
+    }
+
+    fn to_json(&self, path: &str) -> Value {
+        json!({
+            "path": path,
+            "dir": self.is_dir,
+            "size": self.size,
+            "sha256": self.sha256,
+        })
+    }
+}
+
+/// Split a user-supplied path like `/projects/site/` into labels
+fn path_segments(path: &str) -> Vec<String> {
+    path.split('/').filter(|s| !s.is_empty()).map(|s| s.to_string()).collect()
+}
+
+/// Collect every node below a directory in a single recursive query and
+/// rebuild relative paths from the parent/child edges. Directories get a
+/// trailing slash so a file and a directory with the same label never
+/// collide.
+async fn subtree(state: &AppState, root: &Uuid) -> Result<BTreeMap<String, Item>, String> {
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?parent ?child ?label ?size ?sha256 ?isDir FROM <{graph}> WHERE {{
+    <urn:uuid:{root}> posix:includes* ?parent .
+    ?parent posix:includes ?child .
+    ?child rdfs:label ?label .
+    OPTIONAL {{ ?child posix:size ?size }}
+    OPTIONAL {{ ?child liqk:sha256 ?sha256 }}
+    BIND(EXISTS {{ ?child a posix:Directory }} AS ?isDir)
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        root = root,
+    );
+
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+
+    // child IRI -> (parent IRI, label, item)
+    let mut nodes: HashMap<String, (String, String, Item)> = HashMap::new();
+    for b in &bindings {
+        let (Some(parent), Some(child), Some(label)) = (
+            binding_value(b, "parent"),
+            binding_value(b, "child"),
+            binding_value(b, "label"),
+        ) else {
+            continue;
+        };
+        let item = Item {
+            is_dir: binding_value(b, "isDir") == Some("true"),
+            size: binding_value(b, "size").and_then(|s| s.parse().ok()),
+            sha256: binding_value(b, "sha256").map(|s| s.to_lowercase()),
+        };
+        nodes.insert(child.to_string(), (parent.to_string(), label.to_string(), item));
+    }
+
+    let root_iri = format!("urn:uuid:{}", root);
+    let mut items = BTreeMap::new();
+    for (child, (_, label, item)) in &nodes {
+        // Walk up to the compared root; give up on cycles
+        let mut path = if item.is_dir { format!("{}/", label) } else { label.clone() };
+        let mut current = child;
+        let mut reached_root = false;
+        for _ in 0..nodes.len() {
+            let (parent, _, _) = &nodes[current];
+            if *parent == root_iri {
+                reached_root = true;
+                break;
+            }
+            let Some((_, parent_label, _)) = nodes.get(parent) else {
+                break;
+            };
+            path = format!("{}/{}", parent_label, path);
+            current = parent;
+        }
+        if reached_root {
+            items.insert(path, item.clone());
+        }
+    }
+
+    Ok(items)
+}
+
+/// Resolve a path to a directory the caller has `required` rank on
+async fn resolve_dir(
+    state: &AppState,
+    path: &str,
+    required: i32,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> Result<Uuid, (StatusCode, String)> {
+    let node = match resolve_path(state, &path_segments(path)).await {
+        Ok(Some(node)) => node,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, format!("Not found: {}", path))),
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to resolve path: {}", e))),
+    };
+
+    if !node.is_dir() {
+        return Err((StatusCode::BAD_REQUEST, format!("Not a directory: {}", path)));
+    }
+
+    if get_access_rank(state, &node.uuid, headers, jar).await < required {
+        return Err((StatusCode::FORBIDDEN, format!("Access denied: {}", path)));
+    }
+
+    Ok(node.uuid)
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// GET /diff - Directory compare page (fetches /api/diff)
+pub async fn diff_page() -> Html<&'static str> {
+    Html(DIFF_HTML)
+}
+
+/// GET /api/diff?a=PATH&b=PATH - Compare two directory subtrees by relative
+/// path, reporting items only in `a`, only in `b`, and items that differ
+pub async fn diff_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    uri: Uri,
+    Query(params): Query<DiffParams>,
+) -> Response {
+    let required = match state.rules.required_rank(&state.roles, &Method::GET, uri.path()) {
+        Ok(required) => required,
+        Err(status) => return (status, "Access denied").into_response(),
+    };
+
+    let mut roots = Vec::with_capacity(2);
+    for path in [&params.a, &params.b] {
+        match resolve_dir(&state, path, required, &headers, &jar).await {
+            Ok(uuid) => roots.push(uuid),
+            Err((status, msg)) => {
+                warn!(client = %addr, path = %path, status = %status, "Diff rejected");
+                return (status, msg).into_response();
+            }
+        }
+    }
+
+    let (a, b) = match tokio::try_join!(subtree(&state, &roots[0]), subtree(&state, &roots[1])) {
+        Ok(trees) => trees,
+        Err(e) => {
+            warn!(client = %addr, error = %e, "Subtree query failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read directories").into_response();
+        }
+    };
+
+    let only_in_a: Vec<Value> = a
+        .iter()
+        .filter(|(path, _)| !b.contains_key(*path))
+        .map(|(path, item)| item.to_json(path))
+        .collect();
+    let only_in_b: Vec<Value> = b
+        .iter()
+        .filter(|(path, _)| !a.contains_key(*path))
+        .map(|(path, item)| item.to_json(path))
+        .collect();
+    let differing: Vec<Value> = a
+        .iter()
+        .filter_map(|(path, item_a)| {
+            let item_b = b.get(path)?;
+            item_a.differs(item_b).then(|| {
+                json!({
+                    "path": path,
+                    "a": item_a.to_json(path),
+                    "b": item_b.to_json(path),
+                })
+            })
+        })
+        .collect();
+
+    info!(
+        client = %addr,
+        a = %params.a,
+        b = %params.b,
+        only_in_a = only_in_a.len(),
+        only_in_b = only_in_b.len(),
+        differing = differing.len(),
+        "Directories compared"
+    );
+
+    let json_response = json!({
+        "a": params.a,
+        "b": params.b,
+        "only_in_a": only_in_a,
+        "only_in_b": only_in_b,
+        "differing": differing,
+    });
+
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        json_response.to_string(),
+    )
+        .into_response()
+}
@@ -0,0 +1,125 @@
+use flate2::{
+    read::{GzDecoder, ZlibDecoder},
+    write::{self, GzEncoder},
+    Compression,
+};
+use std::io::{self, Read, Write};
+use tower_http::compression::{
+    predicate::{NotForContentType, Predicate, SizeAbove},
+    CompressionLayer,
+};
+
+/// Why a request body could not be decoded
+pub enum DecodeError {
+    /// `Content-Encoding` other than identity, gzip or deflate
+    Unsupported(String),
+    TooLarge,
+    Invalid(String),
+}
+
+/// Decode a request body according to its `Content-Encoding` header so it
+/// can be inspected. Returns `None` when the body is not encoded; decoding
+/// stops past `max_bytes`, against decompression bombs.
+pub fn decode_request_body(
+    content_encoding: Option<&str>,
+    body: &[u8],
+    max_bytes: usize,
+) -> Result<Option<Vec<u8>>, DecodeError> {
+    let Some(encoding) = content_encoding.map(|e| e.trim().to_ascii_lowercase()) else {
+        return Ok(None);
+    };
+
+    let reader: Box<dyn Read + '_> = match encoding.as_str() {
+        "" | "identity" => return Ok(None),
+        "gzip" | "x-gzip" => Box::new(GzDecoder::new(body)),
+        "deflate" => Box::new(ZlibDecoder::new(body)),
+        other => return Err(DecodeError::Unsupported(other.to_string())),
+    };
+
+    // Read one byte past the limit to tell "exactly at" from "over"
+    let mut decoded = Vec::new();
+    reader
+        .take(max_bytes as u64 + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|e| DecodeError::Invalid(e.to_string()))?;
+    if decoded.len() > max_bytes {
+        return Err(DecodeError::TooLarge);
+    }
+    Ok(Some(decoded))
+}
+
+/// Decoder for request bodies passed on as they arrive instead of being
+/// read in full, fed one chunk at a time
+pub enum StreamDecoder {
+    Gzip(write::GzDecoder<Vec<u8>>),
+    Deflate(write::ZlibDecoder<Vec<u8>>),
+}
+
+impl StreamDecoder {
+    /// The decoder for a `Content-Encoding`, or `None` when the body is not
+    /// encoded. Fails with the encoding when it is not gzip or deflate.
+    pub fn new(content_encoding: Option<&str>) -> Result<Option<Self>, String> {
+        let Some(encoding) = content_encoding.map(|e| e.trim().to_ascii_lowercase()) else {
+            return Ok(None);
+        };
+        match encoding.as_str() {
+            "" | "identity" => Ok(None),
+            "gzip" | "x-gzip" => Ok(Some(StreamDecoder::Gzip(write::GzDecoder::new(Vec::new())))),
+            "deflate" => Ok(Some(StreamDecoder::Deflate(write::ZlibDecoder::new(Vec::new())))),
+            other => Err(other.to_string()),
+        }
+    }
+
+    /// Decode the next chunk, returning the bytes it completes
+    pub fn write(&mut self, chunk: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            StreamDecoder::Gzip(decoder) => {
+                decoder.write_all(chunk)?;
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+            StreamDecoder::Deflate(decoder) => {
+                decoder.write_all(chunk)?;
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+        }
+    }
+
+    /// Check that the body ended where the encoding does, returning the
+    /// last decoded bytes
+    pub fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            StreamDecoder::Gzip(decoder) => decoder.finish(),
+            StreamDecoder::Deflate(decoder) => decoder.finish(),
+        }
+    }
+}
+
+/// Gzip a body for the upstream request
+pub fn gzip(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+/// Default size below which responses are sent uncompressed
+pub const DEFAULT_COMPRESS_MIN_BYTES: u16 = 1024;
+
+/// Response compression (gzip or brotli, as the client's `Accept-Encoding`
+/// prefers) for gate pages, query results and file downloads. Responses
+/// that are already encoded, partial, smaller than `min_bytes`, event
+/// streams, or of already-compressed media types pass through as they are.
+/// Disabled, it never compresses.
+pub fn compression_layer(enabled: bool, min_bytes: u16) -> CompressionLayer<impl Predicate> {
+    let predicate = SizeAbove::new(min_bytes)
+        .and(NotForContentType::GRPC)
+        .and(NotForContentType::IMAGES)
+        .and(NotForContentType::SSE)
+        .and(NotForContentType::const_new("audio/"))
+        .and(NotForContentType::const_new("video/"))
+        .and(NotForContentType::const_new("application/zip"))
+        .and(NotForContentType::const_new("application/gzip"));
+    CompressionLayer::new()
+        .gzip(enabled)
+        .br(enabled)
+        .compress_when(predicate)
+}
@@ -0,0 +1,112 @@
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use serde_json::json;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::accept::prefers_html;
+use crate::results::accepted;
+use crate::templates::{escape_html, render_page};
+
+/// Header carrying the ID of a failed request
+const REQUEST_ID: &str = "x-request-id";
+
+/// A proxied request the upstream failed, with an ID the client can quote
+/// and operators find in the log line of the failure
+pub struct UpstreamFailure {
+    pub id: String,
+    status: StatusCode,
+    message: String,
+}
+
+impl UpstreamFailure {
+    /// `message` is shown to the client; the error itself is only logged
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new_v4().simple().to_string()[..12].to_string(),
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// The request could not be sent
+    pub fn unreachable() -> Self {
+        Self::new(StatusCode::BAD_GATEWAY, "The upstream server could not be reached.")
+    }
+
+    /// No connection was made within `UPSTREAM_CONNECT_TIMEOUT`
+    pub fn connect_timed_out() -> Self {
+        Self::new(StatusCode::GATEWAY_TIMEOUT, "Timed out connecting to the upstream server.")
+    }
+
+    /// No response started within the upstream timeout
+    pub fn timed_out(limit: Duration) -> Self {
+        Self::new(
+            StatusCode::GATEWAY_TIMEOUT,
+            format!("The upstream server did not respond within {} seconds.", limit.as_secs()),
+        )
+    }
+
+    /// The response broke off while being read
+    pub fn response_failed() -> Self {
+        Self::new(StatusCode::BAD_GATEWAY, "The upstream server failed while sending its response.")
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The failure as a page for browsers, JSON for clients that accept it
+    /// and plain text for everyone else, as negotiated by the client's
+    /// `Accept`
+    pub fn respond(&self, accept: Option<&str>) -> Response {
+        let mut response = if prefers_html(accept) {
+            let body = format!(
+                r#"<p>{}</p>
+        <p>If this keeps happening, report it with this request ID:</p>
+        <code>{}</code>
+        <a class="button" href="">Try again</a>"#,
+                escape_html(&self.message),
+                self.id
+            );
+            (self.status, Html(render_page(self.title(), &body))).into_response()
+        } else if accepts_json(accept) {
+            let body = json!({
+                "error": self.message,
+                "status": self.status.as_u16(),
+                "request_id": self.id,
+            });
+            (self.status, axum::Json(body)).into_response()
+        } else {
+            (self.status, format!("{} (request ID {})", self.message, self.id)).into_response()
+        };
+        if let Ok(id) = HeaderValue::from_str(&self.id) {
+            response.headers_mut().insert(REQUEST_ID, id);
+        }
+        response.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        response
+    }
+
+    fn title(&self) -> &'static str {
+        match self.status {
+            StatusCode::GATEWAY_TIMEOUT => "Upstream timed out",
+            _ => "Upstream unavailable",
+        }
+    }
+}
+
+/// Whether a client accepts JSON (`application/json` or a `+json` type)
+/// by name, not just through a wildcard
+fn accepts_json(accept: Option<&str>) -> bool {
+    accept.is_some_and(|accept| {
+        accepted(accept)
+            .into_iter()
+            .any(|(media_type, quality)| quality > 0.0 && (media_type == "application/json" || media_type.ends_with("+json")))
+    })
+}
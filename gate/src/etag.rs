@@ -0,0 +1,49 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+
+/// Default largest query result buffered only to be given an ETag, in MiB
+pub const DEFAULT_QUERY_ETAG_MAX_MB: usize = 8;
+
+/// Hex digits of the body hash kept in the tag
+const TAG_LEN: usize = 32;
+
+/// Weak ETag of a body. Weak, since responses may still be compressed on
+/// the way out, which changes the bytes but not the content.
+pub fn weak_etag(body: &[u8]) -> HeaderValue {
+    let hash = hex::encode(Sha256::digest(body));
+    HeaderValue::from_str(&format!("W/\"{}\"", &hash[..TAG_LEN])).expect("ETag value is ASCII")
+}
+
+/// Whether the request's `If-None-Match` names `etag`, compared weakly
+pub fn is_not_modified(request_headers: &HeaderMap, etag: &str) -> bool {
+    request_headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|client| {
+            client.split(',').any(|tag| {
+                let tag = tag.trim();
+                tag == "*" || tag.trim_start_matches("W/") == etag.trim_start_matches("W/")
+            })
+        })
+}
+
+/// Answer with a body read in full. A `200` gets a weak ETag unless it has
+/// a validator already, and becomes `304` when the client holds that version.
+pub fn respond(request_headers: &HeaderMap, status: StatusCode, mut headers: HeaderMap, body: Bytes) -> Response {
+    if status != StatusCode::OK {
+        return (status, headers, body).into_response();
+    }
+    if !headers.contains_key(header::ETAG) {
+        headers.insert(header::ETAG, weak_etag(&body));
+    }
+    let etag = headers.get(header::ETAG).and_then(|v| v.to_str().ok()).unwrap_or("");
+    if is_not_modified(request_headers, etag) {
+        headers.remove(header::CONTENT_LENGTH);
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+    (status, headers, body).into_response()
+}
@@ -1,25 +1,38 @@
 use axum::{
-    extract::{ConnectInfo, Multipart, Path, State},
-    http::{header, HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
+    body::Body,
+    extract::{multipart::Field, ConnectInfo, Multipart, Path, Query, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{Html, IntoResponse, Response},
 };
 use axum_extra::extract::cookie::CookieJar;
 use chrono::Utc;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use sha2::{Sha256, Digest};
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
-use tokio::io::AsyncWriteExt;
-use tracing::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, net::SocketAddr, path::PathBuf, sync::Arc};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use tracing::{info, instrument, warn};
 use uuid::Uuid;
 
-use crate::auth::{extract_token_from_header, TOKEN_COOKIE_NAME};
+use crate::auth::{extract_token_from_header, AuthedUser};
+use crate::blobs::find_blob;
+use crate::browse::{place_upload, resolve_target_dir, Placement, TargetDir};
+use crate::journal::Status;
+use crate::preview::{preview_page, Delivery};
+use crate::query_log;
+use crate::quotas::{check_quota, own_triples};
+use crate::sessions::Session;
+use crate::telemetry::trace_headers;
+use crate::templates::UPLOAD_HTML;
+use crate::versions::{record_overwrite, replace_content, staging_path};
 use crate::AppState;
 
-pub const MAX_UPLOAD_SIZE: usize = 4 * 1024 * 1024 * 1024; // 4 GB
-const FILESYSTEM_GRAPH: &str = "http://liqk.org/graph/filesystem";
-const ACCESS_GRAPH: &str = "http://liqk.org/graph/access";
+pub const FILESYSTEM_GRAPH: &str = "http://liqk.org/graph/filesystem";
+pub const ACCESS_GRAPH: &str = "http://liqk.org/graph/access";
 
 /// Escape a string for use in SPARQL string literals.
-fn escape_sparql_string(s: &str) -> String {
+pub fn escape_sparql_string(s: &str) -> String {
     let mut result = String::with_capacity(s.len());
     for c in s.chars() {
         match c {
@@ -35,7 +48,7 @@ fn escape_sparql_string(s: &str) -> String {
 }
 
 /// Validate that a string is a valid UUID format.
-fn validate_uuid(s: &str) -> Option<Uuid> {
+pub fn validate_uuid(s: &str) -> Option<Uuid> {
     Uuid::parse_str(s).ok()
 }
 
@@ -46,11 +59,14 @@ pub fn hash_token(token: &str) -> String {
     hex::encode(hasher.finalize())
 }
 
-/// Extract access token from request (header or cookie)
-fn get_access_token(headers: &HeaderMap, jar: &CookieJar) -> Option<String> {
-    extract_token_from_header(headers).or_else(|| {
-        jar.get(TOKEN_COOKIE_NAME).map(|c| c.value().to_string())
-    })
+/// Hex characters of the SHA-256 hash kept in a credential fingerprint
+const FINGERPRINT_LEN: usize = 8;
+
+/// Short, non-reversible fingerprint of a secret for logs: the first hex
+/// characters of its SHA-256 hash, so a token's fingerprint is also a
+/// prefix of its hash in the access graph
+pub fn fingerprint(secret: &str) -> String {
+    hash_token(secret)[..FINGERPRINT_LEN].to_string()
 }
 
 /// Query public access rank for a resource (UUID-based)
@@ -177,18 +193,73 @@ WHERE {{
     execute_access_query(client, oxigraph_url, &query).await
 }
 
+/// Query the named graphs policies grant at least `min_rank` on: public
+/// policies, plus the token's own when `token_hash` is given. Files and
+/// actions are policy targets too, but not graphs, and are left out.
+async fn query_granted_graphs(
+    client: &reqwest::Client,
+    oxigraph_url: &str,
+    token_hash: Option<&str>,
+    min_rank: i32,
+) -> Result<Vec<String>, String> {
+    let grantee = match token_hash {
+        Some(token_hash) => format!(
+            r#"{{ ?policy liqk:policy-type liqk:policy-type-public }}
+  UNION
+  {{
+    ?policy liqk:policy-type liqk:policy-type-token ;
+            liqk:policy-grantee ?token .
+    ?token a liqk:AccessToken ;
+           liqk:token-hash "{}" .
+  }}"#,
+            escape_sparql_string(token_hash)
+        ),
+        None => "?policy liqk:policy-type liqk:policy-type-public .".to_string(),
+    };
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT DISTINCT ?target
+FROM <{access_graph}>
+WHERE {{
+  ?policy a liqk:AccessPolicy ;
+          liqk:policy-target ?target ;
+          liqk:access-level ?level .
+  {grantee}
+
+  ?level liqk:rank ?rank .
+  FILTER(?rank >= {min_rank})
+  FILTER(!STRSTARTS(STR(?target), "urn:uuid:") && !STRSTARTS(STR(?target), "http://liqk.org/schema#"))
+}}
+ORDER BY ?target"#,
+        access_graph = ACCESS_GRAPH,
+        grantee = grantee,
+        min_rank = min_rank,
+    );
+
+    let bindings = sparql_select(client, oxigraph_url, &query).await?;
+    Ok(bindings
+        .iter()
+        .filter_map(|b| binding_value(b, "target"))
+        .map(str::to_string)
+        .collect())
+}
+
 /// Execute an access query and extract the rank
-async fn execute_access_query(
+#[instrument(name = "acl_lookup", skip_all, fields(otel.kind = "client"))]
+pub async fn execute_access_query(
     client: &reqwest::Client,
     oxigraph_url: &str,
     query: &str,
 ) -> Result<i32, String> {
     let query_url = format!("{}/query", oxigraph_url);
+    query_log::record("query", query);
 
     let response = client
         .post(&query_url)
         .header("Content-Type", "application/sparql-query")
         .header("Accept", "application/sparql-results+json")
+        .headers(trace_headers())
         .body(query.to_string())
         .send()
         .await
@@ -217,6 +288,48 @@ async fn execute_access_query(
     Ok(rank)
 }
 
+/// Execute a SPARQL SELECT query and return the result bindings
+pub async fn sparql_select(
+    client: &reqwest::Client,
+    oxigraph_url: &str,
+    query: &str,
+) -> Result<Vec<serde_json::Value>, String> {
+    let query_url = format!("{}/query", oxigraph_url);
+    query_log::record("query", query);
+
+    let response = client
+        .post(&query_url)
+        .header("Content-Type", "application/sparql-query")
+        .header("Accept", "application/sparql-results+json")
+        .headers(trace_headers())
+        .body(query.to_string())
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send SPARQL query: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("SPARQL query failed with status {}: {}", status, body));
+    }
+
+    let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+    let json: serde_json::Value = serde_json::from_str(&body)
+        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+
+    Ok(json
+        .get("results")
+        .and_then(|r| r.get("bindings"))
+        .and_then(|b| b.as_array())
+        .cloned()
+        .unwrap_or_default())
+}
+
+/// Read the string value of a variable from a SPARQL JSON result binding
+pub fn binding_value<'a>(binding: &'a serde_json::Value, var: &str) -> Option<&'a str> {
+    binding.get(var)?.get("value")?.as_str()
+}
+
 /// Verify if a token hash exists in the RDF access graph
 pub async fn verify_token_exists(
     client: &reqwest::Client,
@@ -237,6 +350,7 @@ WHERE {{
     );
 
     let query_url = format!("{}/query", oxigraph_url);
+    query_log::record("query", &query);
 
     let response = match client
         .post(&query_url)
@@ -266,56 +380,217 @@ WHERE {{
         .unwrap_or(false)
 }
 
-/// Get maximum access rank for a resource (combining public and token access)
+/// The credential presented with a request
+#[derive(Clone)]
+pub enum Credential {
+    /// Server-side session
+    Session(Session),
+    /// Access token from a header or the session cookie, hashed
+    Token(String),
+    /// User asserted by a trusted reverse proxy, with their configured rank
+    Proxy { user: String, rank: i32 },
+    /// The break-glass `EMERGENCY_TOKEN`, which bypasses the access graph
+    Emergency,
+    Anonymous,
+}
+
+impl Credential {
+    /// Rank cache identity (never the raw secret)
+    fn cache_identity(&self) -> String {
+        match self {
+            Credential::Session(session) => format!("session:{}", hash_token(&session.id)),
+            Credential::Token(token_hash) => format!("token:{}", token_hash),
+            Credential::Proxy { user, .. } => format!("proxy:{}", user),
+            Credential::Emergency => "emergency".to_string(),
+            Credential::Anonymous => "anonymous".to_string(),
+        }
+    }
+
+    /// Fingerprint for logs: of the token or session ID, never the secret
+    /// itself. Proxy users are not secret and are logged by name.
+    pub fn fingerprint(&self) -> String {
+        match self {
+            Credential::Session(session) => fingerprint(&session.id),
+            Credential::Token(token_hash) => token_hash[..FINGERPRINT_LEN].to_string(),
+            Credential::Proxy { user, .. } => format!("proxy:{}", user),
+            Credential::Emergency => "emergency".to_string(),
+            Credential::Anonymous => "-".to_string(),
+        }
+    }
+
+    /// Name recorded as the owner of things the requester creates; `None`
+    /// for anonymous requests. Tokens are named by a prefix of their hash.
+    pub fn owner(&self) -> Option<String> {
+        match self {
+            Credential::Session(session) => Some(session.identity.clone()),
+            Credential::Token(token_hash) => Some(format!("token:{}", &token_hash[..16])),
+            Credential::Proxy { user, .. } => Some(user.clone()),
+            Credential::Emergency => Some("emergency".to_string()),
+            Credential::Anonymous => None,
+        }
+    }
+}
+
+/// Resolve the request's credential: a user asserted by a trusted reverse
+/// proxy first, then a session cookie, a token header and a token cookie.
+/// Cookies of sessions or tokens idle past the inactivity timeout count as
+/// anonymous.
+pub fn credential(state: &AppState, headers: &HeaderMap, jar: &CookieJar) -> Credential {
+    if let Some((user, rank)) = state.proxy_auth.as_ref().and_then(|p| p.user(headers)) {
+        return Credential::Proxy { user, rank };
+    }
+
+    let cookie = jar.get(&state.cookie.name).map(|c| c.value().to_string());
+
+    if let Some(session) = cookie.as_deref().and_then(|id| state.sessions.get(id)) {
+        return Credential::Session(session);
+    }
+
+    if let Some(token) = extract_token_from_header(headers) {
+        return token_credential(state, hash_token(&token));
+    }
+
+    match cookie {
+        Some(token) => {
+            let token_hash = hash_token(&token);
+            if state.sessions.touch_token(&token_hash) {
+                token_credential(state, token_hash)
+            } else {
+                Credential::Anonymous
+            }
+        }
+        None => Credential::Anonymous,
+    }
+}
+
+fn token_credential(state: &AppState, token_hash: String) -> Credential {
+    if state.emergency_token_hash.as_deref() == Some(token_hash.as_str()) {
+        Credential::Emergency
+    } else {
+        Credential::Token(token_hash)
+    }
+}
+
+/// Get maximum access rank for a resource (combining public, token and session access)
+#[instrument(name = "auth_check", skip_all, fields(resource = %resource_uuid))]
 pub async fn get_access_rank(
-    client: &reqwest::Client,
-    oxigraph_url: &str,
+    state: &AppState,
     resource_uuid: &Uuid,
     headers: &HeaderMap,
     jar: &CookieJar,
 ) -> i32 {
+    let client = &state.client;
+    let oxigraph_url = &state.oxigraph_url;
+
+    let credential = credential(state, headers, jar);
+    if let Credential::Emergency = credential {
+        return i32::MAX;
+    }
+    let identity = credential.cache_identity();
+    let resource = format!("urn:uuid:{}", resource_uuid);
+    if let Some(rank) = state.rank_cache.get(&identity, &resource) {
+        return rank;
+    }
+
     let public_rank = query_public_access(client, oxigraph_url, resource_uuid)
         .await
         .unwrap_or(0);
 
-    let token_rank = if let Some(token) = get_access_token(headers, jar) {
-        let token_hash = hash_token(&token);
-        query_token_access(client, oxigraph_url, resource_uuid, &token_hash)
-            .await
-            .unwrap_or(0)
-    } else {
-        0
+    let rank = match &credential {
+        Credential::Session(session) => public_rank.max(session.rank),
+        Credential::Proxy { rank, .. } => public_rank.max(*rank),
+        Credential::Token(token_hash) => {
+            let token_rank = query_token_access(client, oxigraph_url, resource_uuid, token_hash)
+                .await
+                .unwrap_or(0);
+            public_rank.max(token_rank)
+        }
+        Credential::Emergency | Credential::Anonymous => public_rank,
     };
 
-    public_rank.max(token_rank)
+    state.rank_cache.insert(&identity, &resource, rank);
+    rank
 }
 
-/// Get maximum access rank for an IRI resource (combining public and token access)
+/// Get maximum access rank for an IRI resource (combining public, token and session access)
+#[instrument(name = "auth_check", skip_all, fields(resource = %resource_iri))]
 pub async fn get_access_rank_iri(
-    client: &reqwest::Client,
-    oxigraph_url: &str,
+    state: &AppState,
     resource_iri: &str,
     headers: &HeaderMap,
     jar: &CookieJar,
 ) -> i32 {
+    let client = &state.client;
+    let oxigraph_url = &state.oxigraph_url;
+
+    let credential = credential(state, headers, jar);
+    if let Credential::Emergency = credential {
+        return i32::MAX;
+    }
+    let identity = credential.cache_identity();
+    if let Some(rank) = state.rank_cache.get(&identity, resource_iri) {
+        return rank;
+    }
+
     let public_rank = query_public_access_iri(client, oxigraph_url, resource_iri)
         .await
         .unwrap_or(0);
 
-    let token_rank = if let Some(token) = get_access_token(headers, jar) {
-        let token_hash = hash_token(&token);
-        query_token_access_iri(client, oxigraph_url, resource_iri, &token_hash)
-            .await
-            .unwrap_or(0)
-    } else {
-        0
+    let rank = match &credential {
+        Credential::Session(session) => public_rank.max(session.rank),
+        Credential::Proxy { rank, .. } => public_rank.max(*rank),
+        Credential::Token(token_hash) => {
+            let token_rank = query_token_access_iri(client, oxigraph_url, resource_iri, token_hash)
+                .await
+                .unwrap_or(0);
+            public_rank.max(token_rank)
+        }
+        Credential::Emergency | Credential::Anonymous => public_rank,
+    };
+
+    state.rank_cache.insert(&identity, resource_iri, rank);
+    rank
+}
+
+/// Named graphs the credential may read with `min_rank`, as granted by
+/// policies targeting the graph IRIs. Sessions and proxy users hold one
+/// rank for everything, so only public policies add graphs for them. A
+/// failed lookup grants nothing.
+pub async fn get_readable_graphs(state: &AppState, credential: &Credential, min_rank: i32) -> Vec<String> {
+    let identity = credential.cache_identity();
+    if let Some(graphs) = state.rank_cache.get_graphs(&identity, min_rank) {
+        return graphs;
+    }
+
+    let token_hash = match credential {
+        Credential::Token(token_hash) => Some(token_hash.as_str()),
+        _ => None,
+    };
+    let graphs = match query_granted_graphs(&state.client, &state.oxigraph_url, token_hash, min_rank).await {
+        Ok(graphs) => graphs,
+        Err(e) => {
+            warn!(credential = %credential.fingerprint(), error = %e, "Failed to look up readable graphs");
+            return Vec::new();
+        }
     };
 
-    public_rank.max(token_rank)
+    state.rank_cache.insert_graphs(&identity, min_rank, graphs.clone());
+    graphs
+}
+
+/// Reduce a client-supplied filename to its final path component. Returns
+/// `None` for empty and hidden names.
+pub fn safe_filename(original: &str) -> Option<String> {
+    let name = PathBuf::from(original)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unnamed")
+        .to_string();
+    (!name.is_empty() && !name.starts_with('.')).then_some(name)
 }
 
 /// Extract full extension from filename (e.g., "archive.tar.gz" -> "tar.gz")
-fn extract_extension(filename: &str) -> Option<String> {
+pub fn extract_extension(filename: &str) -> Option<String> {
     let parts: Vec<&str> = filename.split('.').collect();
     if parts.len() > 1 {
         Some(parts[1..].join("."))
@@ -324,8 +599,24 @@ fn extract_extension(filename: &str) -> Option<String> {
     }
 }
 
-/// Send SPARQL update to Oxigraph
-async fn send_sparql_update(client: &reqwest::Client, oxigraph_url: &str, query: &str) -> Result<(), String> {
+/// Why a SPARQL update was not applied
+pub enum UpdateError {
+    /// Oxigraph answered with a client error; sending it again would fail too
+    Rejected(String),
+    /// Oxigraph could not be reached or failed with a server error
+    Unreachable(String),
+}
+
+impl std::fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateError::Rejected(e) | UpdateError::Unreachable(e) => f.write_str(e),
+        }
+    }
+}
+
+/// Send SPARQL update to Oxigraph, without journaling
+pub async fn post_sparql_update(client: &reqwest::Client, oxigraph_url: &str, query: &str) -> Result<(), UpdateError> {
     let update_url = format!("{}/update", oxigraph_url);
 
     let response = client
@@ -334,19 +625,47 @@ async fn send_sparql_update(client: &reqwest::Client, oxigraph_url: &str, query:
         .body(query.to_string())
         .send()
         .await
-        .map_err(|e| format!("Failed to send SPARQL update: {}", e))?;
+        .map_err(|e| UpdateError::Unreachable(format!("Failed to send SPARQL update: {}", e)))?;
 
-    if response.status().is_success() {
-        Ok(())
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = response.text().await.unwrap_or_default();
+    let message = format!("SPARQL update failed with status {}: {}", status, body);
+    if status.is_client_error() {
+        Err(UpdateError::Rejected(message))
     } else {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        Err(format!("SPARQL update failed with status {}: {}", status, body))
+        Err(UpdateError::Unreachable(message))
     }
 }
 
+/// Send SPARQL update to Oxigraph, journaled first so that an update lost
+/// to a crash or to Oxigraph being unreachable is replayed at the next
+/// start. `context` names the operation in the journal.
+pub async fn send_sparql_update(state: &AppState, context: &str, query: &str) -> Result<(), String> {
+    query_log::record("update", query);
+    let id = state.journal.append(context, query);
+    let result = post_sparql_update(&state.client, &state.oxigraph_url, query).await;
+    state.query_cache.invalidate_all();
+    state.journal.settle(id, &result, None);
+    result.map_err(|e| e.to_string())
+}
+
+/// Like [`send_sparql_update`], for callers that report a failure to the
+/// client or undo their own side effects: a failed update is discarded
+/// instead of replayed.
+pub async fn send_sparql_update_once(state: &AppState, context: &str, query: &str) -> Result<(), String> {
+    query_log::record("update", query);
+    let id = state.journal.append(context, query);
+    let result = post_sparql_update(&state.client, &state.oxigraph_url, query).await;
+    state.query_cache.invalidate_all();
+    state.journal.settle(id, &result, Some(Status::Discarded));
+    result.map_err(|e| e.to_string())
+}
+
 /// Lookup file by UUID and return stored filename
-async fn lookup_file_by_uuid(
+pub async fn lookup_file_by_uuid(
     client: &reqwest::Client,
     oxigraph_url: &str,
     uuid: &str,
@@ -361,6 +680,7 @@ SELECT ?storedAs FROM <{}> WHERE {{
     );
 
     let query_url = format!("{}/query", oxigraph_url);
+    query_log::record("query", &query);
 
     let response = client
         .post(&query_url)
@@ -395,40 +715,99 @@ SELECT ?storedAs FROM <{}> WHERE {{
     }
 }
 
-/// Update file size in RDF
-async fn update_file_size(
-    client: &reqwest::Client,
-    oxigraph_url: &str,
-    uuid: &Uuid,
-    new_size: usize,
-) -> Result<(), String> {
-    let uuid_urn = format!("urn:uuid:{}", uuid);
-
+/// The label of a file or directory, which names its downloads
+pub async fn lookup_label(state: &AppState, uuid: &Uuid) -> Result<Option<String>, String> {
     let query = format!(
-        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+        r#"PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
 
-DELETE {{ GRAPH <{graph}> {{ <{uuid_urn}> posix:size ?oldSize }} }}
-INSERT {{ GRAPH <{graph}> {{ <{uuid_urn}> posix:size {new_size} }} }}
-WHERE {{ GRAPH <{graph}> {{ <{uuid_urn}> posix:size ?oldSize }} }}"#,
-        graph = FILESYSTEM_GRAPH,
-        uuid_urn = uuid_urn,
-        new_size = new_size,
+SELECT ?label FROM <{}> WHERE {{
+    <urn:uuid:{}> rdfs:label ?label .
+}}
+LIMIT 1"#,
+        FILESYSTEM_GRAPH, uuid
     );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+    Ok(bindings.first().and_then(|b| binding_value(b, "label")).map(str::to_string))
+}
 
-    send_sparql_update(client, oxigraph_url, &query).await
+/// Parse a client-supplied SHA-256 checksum (64 hex characters, any case)
+pub fn parse_sha256(value: &str) -> Option<String> {
+    let value = value.trim().to_ascii_lowercase();
+    (value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit())).then_some(value)
 }
 
-/// Create SPARQL INSERT query for a new file (no directory linking)
-fn build_file_insert(
+/// Triples describing a new file, for [`build_files_insert`]
+pub fn file_triples(
     file_uuid: &Uuid,
     original_filename: &str,
     stored_filename: &str,
     file_size: usize,
     mime_type: &str,
     timestamp: &str,
+    sha256: &str,
 ) -> String {
     let uuid_urn = format!("urn:uuid:{}", file_uuid);
 
+    format!(
+        r#"        <{uuid_urn}> rdf:type posix:File ;
+            rdfs:label "{filename}" ;
+            posix:size {size} ;
+            dc:format "{mime}" ;
+            dc:created "{timestamp}"^^xsd:dateTime ;
+            liqk:storedAs "{stored_filename}" ;
+            liqk:sha256 "{sha256}" ."#,
+        uuid_urn = uuid_urn,
+        filename = escape_sparql_string(original_filename),
+        size = file_size,
+        mime = escape_sparql_string(mime_type),
+        timestamp = timestamp,
+        stored_filename = stored_filename,
+        sha256 = sha256,
+    )
+}
+
+fn includes_triple(dir: &Uuid, file: &Uuid) -> String {
+    format!("        <urn:uuid:{}> posix:includes <urn:uuid:{}> .", dir, file)
+}
+
+/// Add the triples placing a new file to its [`file_triples`]: the
+/// directory including it and, for a new version, the file it replaces as
+/// its `liqk:previousVersion`
+pub fn place_triples(triples: &mut String, file: &Uuid, parent: Option<&Uuid>, replaces: Option<&Uuid>) {
+    if let Some(parent) = parent {
+        triples.push('\n');
+        triples.push_str(&includes_triple(parent, file));
+    }
+    if let Some(old) = replaces {
+        triples.push_str(&format!("\n        <urn:uuid:{}> liqk:previousVersion <urn:uuid:{}> .", file, old));
+    }
+}
+
+/// Index uploaded files in one update: take the files they replace out of
+/// their directories (`(directory, file)` pairs), then insert the new ones
+pub fn build_upload_update(files: &[String], unlinked: &[(Uuid, Uuid)]) -> String {
+    let insert = build_files_insert(files);
+    if unlinked.is_empty() {
+        return insert;
+    }
+    let triples: Vec<String> = unlinked.iter().map(|(dir, file)| includes_triple(dir, file)).collect();
+    format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+
+DELETE DATA {{
+    GRAPH <{graph}> {{
+{triples}
+    }}
+}};
+{insert}"#,
+        graph = FILESYSTEM_GRAPH,
+        triples = triples.join("\n"),
+        insert = insert,
+    )
+}
+
+/// One INSERT DATA for several files, so they are indexed together or not at all
+fn build_files_insert(files: &[String]) -> String {
     format!(
         r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
 PREFIX rdf: <http://www.w3.org/1999/02/22-rdf-syntax-ns#>
@@ -439,21 +818,134 @@ PREFIX liqk: <http://liqk.org/schema#>
 
 INSERT DATA {{
     GRAPH <{graph}> {{
-        <{uuid_urn}> rdf:type posix:File ;
-            rdfs:label "{filename}" ;
-            posix:size {size} ;
-            dc:format "{mime}" ;
-            dc:created "{timestamp}"^^xsd:dateTime ;
-            liqk:storedAs "{stored_filename}" .
+{triples}
     }}
 }}"#,
         graph = FILESYSTEM_GRAPH,
-        uuid_urn = uuid_urn,
-        filename = escape_sparql_string(original_filename),
-        size = file_size,
-        mime = escape_sparql_string(mime_type),
-        timestamp = timestamp,
-        stored_filename = stored_filename,
+        triples = files.join("\n"),
+    )
+}
+
+/// Read buffer for streaming stored files to clients
+const FILE_CHUNK_BYTES: usize = 64 * 1024;
+
+/// The part of a stored file a request asks for with `Range`
+enum ByteRange {
+    Whole,
+    /// First and last byte, inclusive
+    Span(u64, u64),
+    Unsatisfiable,
+}
+
+/// Read a single `Range: bytes=...` against a file of `len` bytes. Other
+/// units, several ranges and malformed ones get the whole file, as if no
+/// range was asked for.
+fn byte_range(request_headers: &HeaderMap, len: u64) -> ByteRange {
+    let Some(spec) = request_headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().strip_prefix("bytes="))
+    else {
+        return ByteRange::Whole;
+    };
+    let Some((first, last)) = spec.split_once('-').filter(|_| !spec.contains(',')) else {
+        return ByteRange::Whole;
+    };
+    let (first, last) = (first.trim(), last.trim());
+    if first.is_empty() {
+        // The last `suffix` bytes
+        return match last.parse::<u64>() {
+            Ok(suffix) if suffix > 0 && len > 0 => ByteRange::Span(len.saturating_sub(suffix), len - 1),
+            Ok(_) => ByteRange::Unsatisfiable,
+            Err(_) => ByteRange::Whole,
+        };
+    }
+    let Ok(first) = first.parse::<u64>() else {
+        return ByteRange::Whole;
+    };
+    let last = match last.parse::<u64>() {
+        Ok(last) if last >= first => last,
+        Err(_) if last.is_empty() => u64::MAX,
+        _ => return ByteRange::Whole,
+    };
+    if first >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Span(first, last.min(len - 1))
+}
+
+/// Open a stored file and build the download response, which streams the
+/// file from disk in chunks of `FILE_CHUNK_BYTES`, so memory use does not
+/// grow with the file. A single byte range in the request's `Range` is
+/// answered with `206 Partial Content`, so media players can seek; one
+/// past the end with `416`. Returns the response together with the number
+/// of bytes sent.
+pub async fn read_stored_file(
+    files_dir: &str,
+    stored_filename: &str,
+    request_headers: &HeaderMap,
+) -> std::io::Result<(Response, u64)> {
+    let file_path = PathBuf::from(files_dir).join(stored_filename);
+    let mut file = tokio::fs::File::open(&file_path).await?;
+    let len = file.metadata().await?.len();
+
+    let mime = mime_guess::from_path(stored_filename)
+        .first_or_octet_stream()
+        .to_string();
+
+    let (first, last) = match byte_range(request_headers, len) {
+        ByteRange::Whole => {
+            let response = (
+                StatusCode::OK,
+                [
+                    (header::CONTENT_TYPE, mime),
+                    (header::CONTENT_LENGTH, len.to_string()),
+                    (header::ACCEPT_RANGES, "bytes".to_string()),
+                ],
+                Body::from_stream(ReaderStream::with_capacity(file, FILE_CHUNK_BYTES)),
+            )
+                .into_response();
+            return Ok((response, len));
+        }
+        ByteRange::Span(first, last) => (first, last),
+        ByteRange::Unsatisfiable => {
+            let response = (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", len))],
+            )
+                .into_response();
+            return Ok((response, 0));
+        }
+    };
+
+    file.seek(std::io::SeekFrom::Start(first)).await?;
+    let bytes = last - first + 1;
+    let response = (
+        StatusCode::PARTIAL_CONTENT,
+        [
+            (header::CONTENT_TYPE, mime),
+            (header::CONTENT_LENGTH, bytes.to_string()),
+            (header::CONTENT_RANGE, format!("bytes {}-{}/{}", first, last, len)),
+            (header::ACCEPT_RANGES, "bytes".to_string()),
+        ],
+        Body::from_stream(ReaderStream::with_capacity(file.take(bytes), FILE_CHUNK_BYTES)),
+    )
+        .into_response();
+
+    Ok((response, bytes))
+}
+
+/// `Content-Disposition` offering content for download as `filename`, with
+/// an ASCII fallback for clients without RFC 5987 support
+pub fn attachment(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' { c } else { '_' })
+        .collect();
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback,
+        utf8_percent_encode(filename, NON_ALPHANUMERIC)
     )
 }
 
@@ -461,263 +953,415 @@ INSERT DATA {{
 // Handlers
 // =============================================================================
 
+/// GET /upload - HTML upload page (drag & drop, file picker, clipboard paste)
+pub async fn upload_page() -> Html<&'static str> {
+    Html(UPLOAD_HTML)
+}
+
 /// GET /res/:uuid - Download file by UUID
 pub async fn res_handler(
     State(state): State<Arc<AppState>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    jar: CookieJar,
+    AuthedUser { fingerprint, rank, .. }: AuthedUser,
     headers: HeaderMap,
-    Path(uuid_str): Path<String>,
+    uri: Uri,
+    Path(uuid): Path<Uuid>,
 ) -> Response {
-    let uuid = match validate_uuid(&uuid_str) {
-        Some(u) => u,
-        None => {
-            warn!(client = %addr, uuid = %uuid_str, "Invalid UUID format");
-            return (StatusCode::BAD_REQUEST, "Invalid UUID format").into_response();
-        }
-    };
-
-    // Check access rank (requires >= 1 for view)
-    let rank = get_access_rank(&state.client, &state.oxigraph_url, &uuid, &headers, &jar).await;
-    if rank < 1 {
-        warn!(client = %addr, uuid = %uuid, rank = rank, "Access denied - insufficient rank for view");
-        return (StatusCode::FORBIDDEN, "Access denied").into_response();
-    }
 
     let stored_filename = match lookup_file_by_uuid(&state.client, &state.oxigraph_url, &uuid.to_string()).await {
         Ok(Some(name)) => name,
         Ok(None) => {
-            warn!(client = %addr, uuid = %uuid, "File not found");
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, "File not found");
             return (StatusCode::NOT_FOUND, "File not found").into_response();
         }
         Err(e) => {
-            warn!(client = %addr, uuid = %uuid, error = %e, "SPARQL lookup failed");
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "SPARQL lookup failed");
             return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup file").into_response();
         }
     };
 
-    let file_path = PathBuf::from(&state.files_dir).join(&stored_filename);
-
-    match tokio::fs::read(&file_path).await {
-        Ok(contents) => {
-            let mime = mime_guess::from_path(&stored_filename)
-                .first_or_octet_stream()
-                .to_string();
-
-            info!(client = %addr, uuid = %uuid, rank = rank, stored_as = %stored_filename, bytes = contents.len(), "File served");
+    // Pages and downloads are named by the label, the raw content needs none
+    let delivery = Delivery::of(&headers, uri.query(), &stored_filename);
+    let label = match delivery {
+        Delivery::Content { attachment: false, .. } => None,
+        _ => match lookup_label(&state, &uuid).await {
+            Ok(label) => label,
+            Err(e) => {
+                warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "SPARQL lookup failed");
+                None
+            }
+        },
+    };
+    let label = label.as_deref().unwrap_or(&stored_filename);
+    if let Delivery::Preview(media) = delivery {
+        info!(client = %addr, credential = %fingerprint, uuid = %uuid, rank = rank, "File preview served");
+        return preview_page(uri.path(), label, media);
+    }
 
-            (
-                StatusCode::OK,
-                [(header::CONTENT_TYPE, mime)],
-                contents,
-            )
-                .into_response()
+    match read_stored_file(&state.files_dir, &stored_filename, &headers).await {
+        Ok((response, bytes)) => {
+            info!(client = %addr, credential = %fingerprint, uuid = %uuid, rank = rank, stored_as = %stored_filename, bytes = bytes, "File served");
+            delivery.content(response, label)
         }
         Err(e) => {
-            warn!(client = %addr, uuid = %uuid, stored_as = %stored_filename, error = %e, "Failed to read file from disk");
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, stored_as = %stored_filename, error = %e, "Failed to read file from disk");
             (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read file").into_response()
         }
     }
 }
 
-/// PUT /res/:uuid - Update existing file
+/// PUT /res/:uuid - Update existing file; its current content is kept as
+/// a version
 pub async fn res_put_handler(
     State(state): State<Arc<AppState>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    AuthedUser { fingerprint, rank, .. }: AuthedUser,
     jar: CookieJar,
     headers: HeaderMap,
-    Path(uuid_str): Path<String>,
+    Path(uuid): Path<Uuid>,
     body: axum::body::Bytes,
 ) -> Response {
-    let uuid = match validate_uuid(&uuid_str) {
-        Some(u) => u,
-        None => {
-            warn!(client = %addr, uuid = %uuid_str, "Invalid UUID format");
-            return (StatusCode::BAD_REQUEST, "Invalid UUID format").into_response();
-        }
-    };
-
-    // Check access rank (requires >= 3 for edit)
-    let rank = get_access_rank(&state.client, &state.oxigraph_url, &uuid, &headers, &jar).await;
-    if rank < 3 {
-        warn!(client = %addr, uuid = %uuid, rank = rank, "Access denied - insufficient rank for edit");
-        return (StatusCode::FORBIDDEN, "Access denied - edit requires higher access level").into_response();
-    }
 
-    if body.len() > MAX_UPLOAD_SIZE {
-        warn!(client = %addr, uuid = %uuid, size = body.len(), "File too large");
-        return (StatusCode::PAYLOAD_TOO_LARGE, "File too large (max 4 GB)").into_response();
+    if body.len() > state.body_limits.upload {
+        warn!(client = %addr, credential = %fingerprint, uuid = %uuid, size = body.len(), "File too large");
+        return state.body_limits.upload_too_large();
     }
 
     let stored_filename = match lookup_file_by_uuid(&state.client, &state.oxigraph_url, &uuid.to_string()).await {
         Ok(Some(name)) => name,
         Ok(None) => {
-            warn!(client = %addr, uuid = %uuid, "File not found");
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, "File not found");
             return (StatusCode::NOT_FOUND, "File not found").into_response();
         }
         Err(e) => {
-            warn!(client = %addr, uuid = %uuid, error = %e, "SPARQL lookup failed");
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "SPARQL lookup failed");
             return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup file").into_response();
         }
     };
 
-    let file_path = PathBuf::from(&state.files_dir).join(&stored_filename);
-    let file_size = body.len();
+    let expected_sha256 = match checksum_header(&headers) {
+        Ok(expected) => expected,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
+    let sha256 = hex::encode(Sha256::digest(&body));
+    if let Some(expected) = &expected_sha256 {
+        if *expected != sha256 {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, expected = %expected, actual = %sha256, "Checksum mismatch");
+            return checksum_mismatch(&uuid.to_string(), expected, &sha256);
+        }
+    }
 
-    if let Err(e) = tokio::fs::write(&file_path, &body).await {
-        warn!(client = %addr, uuid = %uuid, error = %e, "Failed to write file");
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to write file").into_response();
+    let file_size = body.len();
+    let requester = credential(&state, &headers, &jar);
+    if let Err(response) = check_quota(&state, &addr, &requester, file_size as u64).await {
+        return response;
     }
 
-    if let Err(e) = update_file_size(&state.client, &state.oxigraph_url, &uuid, file_size).await {
-        warn!(client = %addr, uuid = %uuid, error = %e, "Failed to update file size in RDF");
+    // The new content is written aside and swapped in, so the file is never
+    // seen half-written and the old content can be kept
+    let staged = staging_path(&state.files_dir);
+    let written = match tokio::fs::write(&staged, &body).await {
+        Ok(()) => replace_content(&state, &uuid, &stored_filename, &staged, &sha256).await,
+        Err(e) => Err(e.to_string()),
+    };
+    let replaced = match written {
+        Ok(replaced) => replaced,
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "Failed to write file");
+            let _ = tokio::fs::remove_file(&staged).await;
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to write file").into_response();
+        }
+    };
+
+    if let Err(e) = record_overwrite(
+        &state,
+        &uuid,
+        &replaced,
+        file_size as u64,
+        &sha256,
+        requester.owner().as_deref(),
+    )
+    .await
+    {
+        warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "Failed to record file version in RDF");
+        return (StatusCode::BAD_GATEWAY, "Failed to record file version - the file is unchanged").into_response();
     }
 
-    info!(client = %addr, uuid = %uuid, rank = rank, stored_as = %stored_filename, bytes = file_size, "File updated");
+    let version = replaced.kept.map_or_else(|| "-".to_string(), |(version, _)| version.to_string());
+    let stored_as = replaced.stored_as.unwrap_or(stored_filename);
+    info!(client = %addr, credential = %fingerprint, uuid = %uuid, rank = rank, stored_as = %stored_as, bytes = file_size, version = %version, "File updated");
+    state.thumbnails.prepare(&stored_as, &sha256);
 
     (StatusCode::OK, format!("File updated ({} bytes)", file_size)).into_response()
 }
 
-const UPLOAD_ACTION_IRI: &str = "http://liqk.org/schema#action-upload-file";
+pub const UPLOAD_ACTION_IRI: &str = "http://liqk.org/schema#action-upload-file";
+
+/// Header carrying a client-computed SHA-256 of the uploaded content
+const CHECKSUM_HEADER: &str = "X-Content-SHA256";
+/// Multipart text field carrying the SHA-256 of the file part that follows it
+const CHECKSUM_FIELD: &str = "sha256";
+
+/// Read an optional checksum from the `X-Content-SHA256` header
+pub fn checksum_header(headers: &HeaderMap) -> Result<Option<String>, &'static str> {
+    match headers.get(CHECKSUM_HEADER) {
+        None => Ok(None),
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(parse_sha256)
+            .map(Some)
+            .ok_or("Invalid X-Content-SHA256 header (expected 64 hex characters)"),
+    }
+}
+
+pub fn checksum_mismatch(name: &str, expected: &str, actual: &str) -> Response {
+    (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        format!("Checksum mismatch for {}: expected {}, got {}", name, expected, actual),
+    )
+        .into_response()
+}
 
-/// POST /res - Upload new file
+/// What an upload does when its target directory already has an entry
+/// with the file's name (`UPLOAD_CONFLICT`, or `?conflict=` per upload)
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UploadConflict {
+    /// Refuse the file with `409 Conflict`
+    Reject,
+    /// Store the file under the first free name: `report (1).pdf`, ...
+    Rename,
+    /// Store the file under the name as a new version of the existing
+    /// file, which leaves the directory but stays reachable by UUID
+    Version,
+}
+
+pub const DEFAULT_UPLOAD_CONFLICT: UploadConflict = UploadConflict::Rename;
+
+impl UploadConflict {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "reject" => Ok(Self::Reject),
+            "rename" => Ok(Self::Rename),
+            "version" => Ok(Self::Version),
+            other => Err(format!("{} (expected reject, rename or version)", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Reject => "reject",
+            Self::Rename => "rename",
+            Self::Version => "version",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UploadParams {
+    /// Store all files or none: nothing is indexed until every file has
+    /// been received, and written files are removed if any part fails
+    #[serde(default)]
+    atomic: bool,
+    /// Directory to put the files in, as a path under `/file`
+    dir: Option<String>,
+    /// Directory to put the files in, by UUID
+    parent: Option<Uuid>,
+    /// How to handle a name already taken in the directory, instead of
+    /// `UPLOAD_CONFLICT`
+    conflict: Option<UploadConflict>,
+}
+
+/// POST /res - Upload new file(s); `?atomic=true` for all-or-nothing,
+/// `?dir=` (a path) or `?parent=` (a UUID) for the directory to put them in,
+/// `?conflict=` for what to do when a name is taken there
 pub async fn res_post_handler(
     State(state): State<Arc<AppState>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    AuthedUser { fingerprint, .. }: AuthedUser,
     jar: CookieJar,
     headers: HeaderMap,
+    Query(params): Query<UploadParams>,
     mut multipart: Multipart,
 ) -> Response {
-    // Check access to upload action (requires >= 3 for upload)
-    let rank = get_access_rank_iri(&state.client, &state.oxigraph_url, UPLOAD_ACTION_IRI, &headers, &jar).await;
-    if rank < 3 {
-        warn!(client = %addr, rank = rank, "Access denied - insufficient rank for upload");
-        return (StatusCode::FORBIDDEN, "Access denied - upload requires edit access").into_response();
-    }
+    // Without a target the files are indexed outside the directory tree,
+    // reachable by UUID only
+    let parent = match TargetDir::from_params(params.dir.as_deref(), params.parent) {
+        Ok(Some(target)) => {
+            let required = match state.rules.required_rank(&state.roles, &Method::POST, "/res") {
+                Ok(required) => required,
+                Err(status) => return (status, "Access denied").into_response(),
+            };
+            match resolve_target_dir(&state, &addr, &fingerprint, &target, required, &headers, &jar).await {
+                Ok(dir) => Some(dir),
+                Err(response) => return response,
+            }
+        }
+        Ok(None) => None,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
 
     let files_dir = PathBuf::from(&state.files_dir);
     if let Err(e) = tokio::fs::create_dir_all(&files_dir).await {
-        warn!(client = %addr, error = %e, "Failed to create files directory");
+        warn!(client = %addr, credential = %fingerprint, error = %e, "Failed to create files directory");
         return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create files directory").into_response();
     }
 
-    let mut uploaded_files: Vec<(String, Uuid)> = Vec::new();
-    let mut total_size: usize = 0;
+    // The header checksum applies to the first file; multi-file uploads send a
+    // `sha256` text field before each file part instead
+    let mut pending_sha256 = match checksum_header(&headers) {
+        Ok(expected) => expected,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
 
-    while let Ok(Some(field)) = multipart.next_field().await {
+    // Files are charged to the requester as they arrive
+    let requester = credential(&state, &headers, &jar);
+    let quota = match check_quota(&state, &addr, &requester, 0).await {
+        Ok(quota) => quota,
+        Err(response) => return response,
+    };
+
+    let conflict = params.conflict.unwrap_or(state.upload_conflict);
+    let mut staged: Vec<StagedFile> = Vec::new();
+    let mut total_size: usize = 0;
+    // Labels of atomic uploads are only indexed at the end
+    let mut claimed: HashSet<String> = HashSet::new();
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            // A broken stream ends a plain upload with the files received so
+            // far; an atomic upload must not be committed partially
+            Err(e) if params.atomic => {
+                warn!(client = %addr, credential = %fingerprint, error = %e, "Failed to read upload stream");
+                discard_staged(&staged, true).await;
+                return (StatusCode::BAD_REQUEST, "Failed to read upload").into_response();
+            }
+            Err(_) => break,
+        };
         let original_filename = match field.file_name() {
             Some(name) => name.to_string(),
-            None => continue,
+            None => {
+                if field.name() == Some(CHECKSUM_FIELD) {
+                    let value = field.text().await.unwrap_or_default();
+                    match parse_sha256(&value) {
+                        Some(expected) => pending_sha256 = Some(expected),
+                        None => {
+                            warn!(client = %addr, credential = %fingerprint, value = %value, "Invalid checksum field");
+                            discard_staged(&staged, params.atomic).await;
+                            return (StatusCode::BAD_REQUEST, "Invalid sha256 field (expected 64 hex characters)").into_response();
+                        }
+                    }
+                }
+                continue;
+            }
         };
+        let expected_sha256 = pending_sha256.take();
 
-        let safe_filename = PathBuf::from(&original_filename)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unnamed")
-            .to_string();
-
-        if safe_filename.is_empty() || safe_filename.starts_with('.') {
-            warn!(client = %addr, filename = %original_filename, "Invalid filename");
-            continue;
-        }
-
-        let file_uuid = Uuid::new_v4();
-        let extension = extract_extension(&safe_filename).unwrap_or_else(|| "bin".to_string());
-        let stored_filename = format!("{}.{}", file_uuid, extension);
-        let file_path = files_dir.join(&stored_filename);
-
-        let mut file = match tokio::fs::File::create(&file_path).await {
-            Ok(f) => f,
-            Err(e) => {
-                warn!(client = %addr, filename = %safe_filename, error = %e, "Failed to create file");
-                return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create file: {}", safe_filename)).into_response();
+        let Some(safe_filename) = safe_filename(&original_filename) else {
+            warn!(client = %addr, credential = %fingerprint, filename = %original_filename, "Invalid filename");
+            if params.atomic {
+                discard_staged(&staged, true).await;
+                return (StatusCode::BAD_REQUEST, format!("Invalid filename: {}", original_filename)).into_response();
             }
+            continue;
         };
 
-        let mut file_size: usize = 0;
-        let mut stream = field;
-
-        loop {
-            match stream.chunk().await {
-                Ok(Some(chunk)) => {
-                    file_size += chunk.len();
-                    total_size += chunk.len();
-
-                    if total_size > MAX_UPLOAD_SIZE {
-                        warn!(client = %addr, "Upload size limit exceeded");
-                        let _ = tokio::fs::remove_file(&file_path).await;
-                        return (StatusCode::PAYLOAD_TOO_LARGE, "Upload size limit exceeded (max 4 GB)").into_response();
-                    }
-
-                    if let Err(e) = file.write_all(&chunk).await {
-                        warn!(client = %addr, filename = %safe_filename, error = %e, "Failed to write file");
-                        let _ = tokio::fs::remove_file(&file_path).await;
-                        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to write file").into_response();
-                    }
+        let placement = match &parent {
+            Some(dir) => match place_upload(&state, dir, &safe_filename, conflict, &claimed).await {
+                Ok(Some(placement)) => placement,
+                Ok(None) => {
+                    warn!(client = %addr, credential = %fingerprint, filename = %safe_filename, conflict = %conflict.as_str(), "Upload refused - name taken");
+                    discard_staged(&staged, params.atomic).await;
+                    return (StatusCode::CONFLICT, format!("The directory already has an entry named {}", safe_filename)).into_response();
                 }
-                Ok(None) => break,
                 Err(e) => {
-                    warn!(client = %addr, filename = %safe_filename, error = %e, "Failed to read upload stream");
-                    let _ = tokio::fs::remove_file(&file_path).await;
-                    return (StatusCode::BAD_REQUEST, "Failed to read upload").into_response();
+                    warn!(client = %addr, credential = %fingerprint, filename = %safe_filename, error = %e, "SPARQL lookup failed");
+                    discard_staged(&staged, params.atomic).await;
+                    return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup directory").into_response();
                 }
+            },
+            None => Placement { label: safe_filename, replaces: None },
+        };
+
+        let mut file = match receive_file(&state, &addr, &fingerprint, field, placement.label, expected_sha256, &mut total_size).await {
+            Ok(file) => file,
+            Err(response) => {
+                discard_staged(&staged, params.atomic).await;
+                return response;
             }
+        };
+        if let Some(quota) = quota.as_ref().filter(|quota| !quota.allows(total_size as u64)) {
+            warn!(client = %addr, credential = %fingerprint, owner = %quota.owner, used = quota.used, bytes = total_size, "Upload refused - storage quota exceeded");
+            discard_staged(std::slice::from_ref(&file), true).await;
+            discard_staged(&staged, params.atomic).await;
+            return quota.exceeded();
         }
-
-        if let Err(e) = file.flush().await {
-            warn!(client = %addr, filename = %safe_filename, error = %e, "Failed to flush file");
+        file.parent = parent;
+        file.replaces = placement.replaces;
+        file.owner = requester.owner();
+        if params.atomic {
+            claimed.insert(file.filename.clone());
         }
 
-        let mime_type = mime_guess::from_path(&safe_filename)
-            .first_or_octet_stream()
-            .to_string();
-        let timestamp = Utc::now().to_rfc3339();
-
-        let sparql_query = build_file_insert(
-            &file_uuid,
-            &safe_filename,
-            &stored_filename,
-            file_size,
-            &mime_type,
-            &timestamp,
-        );
-
-        match send_sparql_update(&state.client, &state.oxigraph_url, &sparql_query).await {
-            Ok(()) => {
-                info!(
-                    client = %addr,
-                    filename = %safe_filename,
-                    stored_as = %stored_filename,
-                    uuid = %file_uuid,
-                    bytes = file_size,
-                    "File uploaded"
-                );
-            }
-            Err(e) => {
-                warn!(
-                    client = %addr,
-                    filename = %safe_filename,
-                    error = %e,
-                    "File uploaded but indexing failed"
-                );
+        // Without atomic mode each file is indexed as soon as it is stored
+        if !params.atomic {
+            match send_sparql_update(&state, "upload", &build_upload_update(&[file.triples()], file.unlinked().as_slice())).await {
+                Ok(()) => file.record_uploaded(&state, &addr, &fingerprint),
+                Err(e) => {
+                    warn!(
+                        client = %addr,
+                        credential = %fingerprint,
+                        filename = %file.filename,
+                        error = %e,
+                        "File uploaded but indexing failed"
+                    );
+                }
             }
         }
-
-        uploaded_files.push((safe_filename, file_uuid));
+        staged.push(file);
     }
 
-    if uploaded_files.is_empty() {
+    if staged.is_empty() {
         return (StatusCode::BAD_REQUEST, "No files uploaded").into_response();
     }
 
+    if params.atomic {
+        let triples: Vec<String> = staged.iter().map(StagedFile::triples).collect();
+        let unlinked: Vec<(Uuid, Uuid)> = staged.iter().flat_map(StagedFile::unlinked).collect();
+        if let Err(e) = send_sparql_update_once(&state, "upload", &build_upload_update(&triples, &unlinked)).await {
+            warn!(client = %addr, credential = %fingerprint, files = staged.len(), error = %e, "Atomic upload failed - indexing failed, files discarded");
+            discard_staged(&staged, true).await;
+            return (StatusCode::BAD_GATEWAY, "Failed to index files - nothing was uploaded").into_response();
+        }
+        for file in &staged {
+            file.record_uploaded(&state, &addr, &fingerprint);
+        }
+    }
+
+    // A replaced file's path now leads to its new version
+    if staged.iter().any(|file| file.replaces.is_some()) {
+        state.path_cache.invalidate_all();
+    }
+
     // Return JSON response
     let json_response = serde_json::json!({
         "success": true,
-        "files": uploaded_files.iter().map(|(name, uuid)| {
-            serde_json::json!({
-                "filename": name,
-                "uuid": uuid.to_string()
-            })
+        "files": staged.iter().map(|file| {
+            let mut entry = serde_json::json!({
+                "filename": file.filename,
+                "uuid": file.uuid.to_string(),
+                "sha256": file.sha256,
+            });
+            if let Some(old) = &file.replaces {
+                entry["replaces"] = serde_json::json!(old.to_string());
+            }
+            if file.path.is_none() {
+                entry["deduplicated"] = serde_json::json!(true);
+            }
+            entry
         }).collect::<Vec<_>>()
     });
 
@@ -727,3 +1371,183 @@ pub async fn res_post_handler(
         json_response.to_string(),
     ).into_response()
 }
+
+/// A file written to disk by an upload, not yet (or not necessarily) indexed
+struct StagedFile {
+    filename: String,
+    uuid: Uuid,
+    stored_filename: String,
+    /// Where the upload wrote the content; `None` when it shares a stored
+    /// file with the same content instead
+    path: Option<PathBuf>,
+    size: usize,
+    mime_type: String,
+    timestamp: String,
+    /// SHA-256 of the content, computed while it was received
+    sha256: String,
+    /// Directory the file is put in
+    parent: Option<Uuid>,
+    /// File of the same name in the directory this one is a new version of
+    replaces: Option<Uuid>,
+    /// Identity the content is charged to
+    owner: Option<String>,
+}
+
+impl StagedFile {
+    fn triples(&self) -> String {
+        let mut triples = file_triples(
+            &self.uuid,
+            &self.filename,
+            &self.stored_filename,
+            self.size,
+            &self.mime_type,
+            &self.timestamp,
+            &self.sha256,
+        );
+        place_triples(&mut triples, &self.uuid, self.parent.as_ref(), self.replaces.as_ref());
+        own_triples(&mut triples, &self.uuid, self.owner.as_deref());
+        triples
+    }
+
+    /// The `(directory, file)` link the upload removes for the file it replaces
+    fn unlinked(&self) -> Option<(Uuid, Uuid)> {
+        self.parent.zip(self.replaces)
+    }
+
+    fn record_uploaded(&self, state: &AppState, addr: &SocketAddr, fingerprint: &str) {
+        state.metrics.upload(self.size as u64);
+        info!(
+            client = %addr,
+            credential = %fingerprint,
+            filename = %self.filename,
+            stored_as = %self.stored_filename,
+            uuid = %self.uuid,
+            bytes = self.size,
+            replaces = %self.replaces.map_or_else(|| "-".to_string(), |old| old.to_string()),
+            "File uploaded"
+        );
+        state.thumbnails.prepare(&self.stored_filename, &self.sha256);
+    }
+}
+
+/// Roll back an atomic upload by deleting the files it wrote. Files of a
+/// non-atomic upload are already indexed and are kept.
+async fn discard_staged(staged: &[StagedFile], atomic: bool) {
+    if !atomic {
+        return;
+    }
+    for file in staged {
+        let Some(path) = &file.path else {
+            continue;
+        };
+        if let Err(e) = tokio::fs::remove_file(path).await {
+            warn!(stored_as = %file.stored_filename, error = %e, "Failed to remove file of an aborted upload");
+        }
+    }
+}
+
+/// Stream one multipart file to disk under a new UUID and verify its
+/// checksum. On failure nothing of this file is left on disk.
+async fn receive_file(
+    state: &AppState,
+    addr: &SocketAddr,
+    fingerprint: &str,
+    mut field: Field<'_>,
+    safe_filename: String,
+    expected_sha256: Option<String>,
+    total_size: &mut usize,
+) -> Result<StagedFile, Response> {
+    let limits = &state.body_limits;
+    let file_uuid = Uuid::new_v4();
+    let extension = extract_extension(&safe_filename).unwrap_or_else(|| "bin".to_string());
+    let stored_filename = format!("{}.{}", file_uuid, extension);
+    let file_path = PathBuf::from(&state.files_dir).join(&stored_filename);
+
+    let mut file = match tokio::fs::File::create(&file_path).await {
+        Ok(f) => f,
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, filename = %safe_filename, error = %e, "Failed to create file");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create file: {}", safe_filename)).into_response());
+        }
+    };
+
+    let mut file_size: usize = 0;
+    let mut hasher = Sha256::new();
+
+    loop {
+        match field.chunk().await {
+            Ok(Some(chunk)) => {
+                file_size += chunk.len();
+                *total_size += chunk.len();
+
+                if *total_size > limits.upload {
+                    warn!(client = %addr, credential = %fingerprint, "Upload size limit exceeded");
+                    let _ = tokio::fs::remove_file(&file_path).await;
+                    return Err(limits.upload_too_large());
+                }
+
+                hasher.update(&chunk);
+
+                if let Err(e) = file.write_all(&chunk).await {
+                    warn!(client = %addr, credential = %fingerprint, filename = %safe_filename, error = %e, "Failed to write file");
+                    let _ = tokio::fs::remove_file(&file_path).await;
+                    return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to write file").into_response());
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                warn!(client = %addr, credential = %fingerprint, filename = %safe_filename, error = %e, "Failed to read upload stream");
+                let _ = tokio::fs::remove_file(&file_path).await;
+                // The request body, multipart framing included, hit the limit
+                if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+                    return Err(limits.upload_too_large());
+                }
+                return Err((StatusCode::BAD_REQUEST, "Failed to read upload").into_response());
+            }
+        }
+    }
+
+    if let Err(e) = file.flush().await {
+        warn!(client = %addr, credential = %fingerprint, filename = %safe_filename, error = %e, "Failed to flush file");
+    }
+
+    let sha256 = hex::encode(hasher.finalize());
+    if let Some(expected) = &expected_sha256 {
+        if *expected != sha256 {
+            warn!(client = %addr, credential = %fingerprint, filename = %safe_filename, expected = %expected, actual = %sha256, "Checksum mismatch");
+            let _ = tokio::fs::remove_file(&file_path).await;
+            return Err(checksum_mismatch(&safe_filename, expected, &sha256));
+        }
+    }
+
+    // Content already stored is shared rather than kept twice
+    let (stored_filename, path) = match find_blob(state, &sha256, &extension, None).await {
+        Ok(Some(existing)) => {
+            let _ = tokio::fs::remove_file(&file_path).await;
+            (existing, None)
+        }
+        Ok(None) => (stored_filename, Some(file_path)),
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, filename = %safe_filename, error = %e, "Duplicate lookup failed");
+            (stored_filename, Some(file_path))
+        }
+    };
+
+    let mime_type = mime_guess::from_path(&safe_filename)
+        .first_or_octet_stream()
+        .to_string();
+
+    Ok(StagedFile {
+        filename: safe_filename,
+        uuid: file_uuid,
+        stored_filename,
+        path,
+        size: file_size,
+        mime_type,
+        timestamp: Utc::now().to_rfc3339(),
+        sha256,
+        parent: None,
+        replaces: None,
+        owner: None,
+    })
+}
@@ -0,0 +1,58 @@
+use axum::http::{header, HeaderMap, HeaderName};
+use std::net::IpAddr;
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// Headers the gate sets itself instead of forwarding them as sent
+pub fn is_forwarding_header(name: &HeaderName) -> bool {
+    *name == header::FORWARDED || *name == X_FORWARDED_FOR
+}
+
+/// `Forwarded` and `X-Forwarded-For` for a request from `peer`. The
+/// client's address is appended to what a trusted proxy in front of the
+/// gate reported; values sent by any other peer are dropped, since a
+/// client could otherwise claim any address.
+pub fn forwarding_headers(headers: &HeaderMap, peer: IpAddr, trusted: bool) -> [(HeaderName, String); 2] {
+    let peer = peer.to_canonical();
+    // RFC 7239: IPv6 nodes are bracketed and quoted
+    let mut element = match peer {
+        IpAddr::V4(ip) => format!("for={}", ip),
+        IpAddr::V6(ip) => format!("for=\"[{}]\"", ip),
+    };
+    if let Some(host) = headers.get(header::HOST).and_then(|v| v.to_str().ok()) {
+        element.push_str(&format!(";host=\"{}\"", host.replace(['"', '\\'], "")));
+    }
+    [
+        (header::FORWARDED, append(headers, &header::FORWARDED, trusted, element)),
+        (X_FORWARDED_FOR, append(headers, &X_FORWARDED_FOR, trusted, peer.to_string())),
+    ]
+}
+
+/// The client's address: the nearest one a trusted proxy reported in
+/// `X-Forwarded-For`, otherwise the peer's
+pub fn client_ip(headers: &HeaderMap, peer: IpAddr, trusted: bool) -> IpAddr {
+    let forwarded = trusted
+        .then(|| headers.get_all(X_FORWARDED_FOR).iter().next_back())
+        .flatten()
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+    forwarded.unwrap_or(peer).to_canonical()
+}
+
+/// The inbound values of `name` when they are trusted, followed by `value`
+fn append(headers: &HeaderMap, name: &HeaderName, trusted: bool, value: String) -> String {
+    let mut values: Vec<&str> = Vec::new();
+    if trusted {
+        values.extend(
+            headers
+                .get_all(name)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty()),
+        );
+    }
+    values.push(&value);
+    values.join(", ")
+}
@@ -0,0 +1,256 @@
+use axum::{
+    extract::{ConnectInfo, Query, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use std::{net::SocketAddr, sync::Arc};
+use tracing::{info, warn};
+
+use crate::auth::{random_secret, render_login, session_cookie};
+use crate::files::fingerprint;
+use crate::login_audit::record_failure;
+use crate::notify::successful_login;
+use crate::templates::{escape_html, render_page, LOGIN_ERROR_HTML};
+use crate::AppState;
+
+pub const DEFAULT_GITHUB_URL: &str = "https://github.com";
+pub const DEFAULT_GITHUB_API_URL: &str = "https://api.github.com";
+
+/// Cookie carrying the OAuth `state` between the redirect and the callback
+const STATE_COOKIE_NAME: &str = "oxigraph_gate_oauth_state";
+/// Route prefix the state cookie is limited to
+const STATE_COOKIE_PATH: &str = "/gate/oauth";
+/// Time allowed for signing in at GitHub (10 minutes)
+const STATE_MAX_AGE_SECS: i64 = 600;
+/// GitHub rejects API requests without a User-Agent
+const USER_AGENT: &str = "oxigraph-gate";
+
+/// GitHub OAuth login (enabled when `GITHUB_CLIENT_ID` is set)
+pub struct GithubConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Callback URL sent to GitHub; the app's registered callback when unset
+    pub redirect_url: Option<String>,
+    /// Explicit username -> rank assignments, checked before organizations
+    pub users: Vec<(String, i32)>,
+    /// Organization -> rank for members
+    pub orgs: Vec<(String, i32)>,
+    /// Web and API base URLs (differ for GitHub Enterprise Server)
+    pub url: String,
+    pub api_url: String,
+}
+
+impl GithubConfig {
+    /// Rank for a user: an explicit assignment, otherwise the highest rank of
+    /// their configured organizations, otherwise 0 (login refused)
+    fn rank_for(&self, login: &str, orgs: &[String]) -> i32 {
+        if let Some((_, rank)) = self.users.iter().find(|(user, _)| user.eq_ignore_ascii_case(login)) {
+            return *rank;
+        }
+        self.orgs
+            .iter()
+            .filter(|(org, _)| orgs.iter().any(|o| o.eq_ignore_ascii_case(org)))
+            .map(|(_, rank)| *rank)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    /// Set by GitHub when the user denies access
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    error_description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GithubUser {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct GithubOrg {
+    login: String,
+}
+
+/// Exchange the callback code for an access token
+async fn exchange_code(state: &AppState, config: &GithubConfig, code: &str) -> Result<String, String> {
+    let mut form = vec![
+        ("client_id", config.client_id.as_str()),
+        ("client_secret", config.client_secret.as_str()),
+        ("code", code),
+    ];
+    if let Some(redirect_url) = &config.redirect_url {
+        form.push(("redirect_uri", redirect_url));
+    }
+
+    let response = state
+        .client
+        .post(format!("{}/login/oauth/access_token", config.url))
+        .header(header::ACCEPT, "application/json")
+        .header(header::USER_AGENT, USER_AGENT)
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach GitHub: {}", e))?;
+    let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+    let token: TokenResponse =
+        serde_json::from_str(&body).map_err(|e| format!("Invalid token response: {}", e))?;
+
+    token
+        .access_token
+        .ok_or_else(|| token.error_description.unwrap_or_else(|| "no access token returned".to_string()))
+}
+
+/// GET a GitHub API resource with the user's token
+async fn github_api<T: for<'de> Deserialize<'de>>(
+    state: &AppState,
+    config: &GithubConfig,
+    token: &str,
+    path: &str,
+) -> Result<T, String> {
+    let response = state
+        .client
+        .get(format!("{}{}", config.api_url, path))
+        .header(header::ACCEPT, "application/vnd.github+json")
+        .header(header::USER_AGENT, USER_AGENT)
+        .bearer_auth(token)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach GitHub API: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("GitHub API {} failed with status {}", path, response.status()));
+    }
+    let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+    serde_json::from_str(&body).map_err(|e| format!("Invalid GitHub API response: {}", e))
+}
+
+/// Login name and organizations of the token's user. Organizations are only
+/// fetched when ranks are assigned by organization.
+async fn github_identity(state: &AppState, config: &GithubConfig, token: &str) -> Result<(String, Vec<String>), String> {
+    let user: GithubUser = github_api(state, config, token, "/user").await?;
+    let orgs = if config.orgs.is_empty() {
+        Vec::new()
+    } else {
+        let orgs: Vec<GithubOrg> = github_api(state, config, token, "/user/orgs?per_page=100").await?;
+        orgs.into_iter().map(|o| o.login).collect()
+    };
+    Ok((user.login, orgs))
+}
+
+fn oauth_error(message: &str) -> Response {
+    let body = format!(
+        r#"<p>{}</p>
+        <a class="button" href="/gate/login">Back to login</a>"#,
+        escape_html(message),
+    );
+    (StatusCode::BAD_GATEWAY, Html(render_page("GitHub sign-in failed", &body))).into_response()
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// GET /gate/oauth/github - Redirect to GitHub's authorization page
+pub async fn github_login_handler(State(state): State<Arc<AppState>>, jar: CookieJar) -> Response {
+    let Some(config) = &state.github else {
+        return (StatusCode::NOT_FOUND, "GitHub login is not enabled").into_response();
+    };
+
+    let oauth_state = random_secret();
+    let mut location = format!(
+        "{}/login/oauth/authorize?client_id={}&state={}&allow_signup=false",
+        config.url,
+        utf8_percent_encode(&config.client_id, NON_ALPHANUMERIC),
+        oauth_state,
+    );
+    // Private organization memberships are only listed with read:org
+    if !config.orgs.is_empty() {
+        location.push_str("&scope=read%3Aorg");
+    }
+    if let Some(redirect_url) = &config.redirect_url {
+        location.push_str(&format!("&redirect_uri={}", utf8_percent_encode(redirect_url, NON_ALPHANUMERIC)));
+    }
+
+    // Lax, not Strict: the callback is a top-level navigation from GitHub
+    let mut cookie = Cookie::build((STATE_COOKIE_NAME, oauth_state))
+        .path(state.cookie.sub_path(STATE_COOKIE_PATH))
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(STATE_MAX_AGE_SECS));
+    if state.secure_cookies {
+        cookie = cookie.secure(true);
+    }
+
+    (jar.add(cookie.build()), Redirect::to(&location)).into_response()
+}
+
+/// GET /gate/oauth/github/callback?code=...&state=... - Finish the GitHub
+/// login and create a session with the rank mapped from the user's name or
+/// organizations
+pub async fn github_callback_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    Query(params): Query<CallbackParams>,
+) -> Response {
+    let Some(config) = &state.github else {
+        return (StatusCode::NOT_FOUND, "GitHub login is not enabled").into_response();
+    };
+
+    let expected_state = jar.get(STATE_COOKIE_NAME).map(|c| c.value().to_string());
+    let jar = jar.remove(Cookie::build(STATE_COOKIE_NAME).path(state.cookie.sub_path(STATE_COOKIE_PATH)));
+
+    if let Some(error) = params.error {
+        warn!(client = %addr, error = %error, "GitHub login cancelled");
+        return (jar, Redirect::to("/gate/login")).into_response();
+    }
+    let (Some(code), Some(oauth_state)) = (params.code, params.state) else {
+        return (StatusCode::BAD_REQUEST, "Missing code or state").into_response();
+    };
+    if expected_state.as_deref() != Some(oauth_state.as_str()) {
+        warn!(client = %addr, "GitHub login rejected - state mismatch");
+        return (StatusCode::BAD_REQUEST, "Login expired or started in another browser - try again").into_response();
+    }
+
+    let identity = match exchange_code(&state, config, &code).await {
+        Ok(token) => github_identity(&state, config, &token).await,
+        Err(e) => Err(e),
+    };
+    let (login, orgs) = match identity {
+        Ok(identity) => identity,
+        Err(e) => {
+            warn!(client = %addr, error = %e, "GitHub login failed");
+            return (jar, oauth_error("GitHub could not confirm your identity. Please try again.")).into_response();
+        }
+    };
+
+    let rank = config.rank_for(&login, &orgs);
+    if rank <= 0 {
+        warn!(client = %addr, backend = "github", identity = %login, "Login failed - no rank assigned");
+        record_failure(&state, &addr, "github", login.chars().count());
+        return (jar, render_login(&state, LOGIN_ERROR_HTML)).into_response();
+    }
+
+    successful_login(&state, &addr, format!("github:{}", login));
+    let session_id = state.sessions.create(login.clone(), "github", rank);
+    info!(client = %addr, credential = %fingerprint(&session_id), backend = "github", identity = %login, rank = rank, "Login successful");
+    let jar = jar.add(session_cookie(&state, session_id));
+
+    // A SameSite=Strict session cookie is not sent on redirects that
+    // started at GitHub; continue with a same-site navigation instead
+    let body = r#"<meta http-equiv="refresh" content="0; url=/">
+        <p>Signed in with GitHub.</p>
+        <a class="button" href="/">Continue</a>"#;
+    (jar, Html(render_page("Signed in", body))).into_response()
+}
@@ -0,0 +1,99 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::{path::PathBuf, sync::Arc, time::{Duration, Instant}};
+use uuid::Uuid;
+
+use crate::AppState;
+
+/// Upper bound for the Oxigraph readiness probe
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn json_response(status: StatusCode, body: Value) -> Response {
+    (status, [(header::CONTENT_TYPE, "application/json")], body.to_string()).into_response()
+}
+
+/// Send a trivial query to an Oxigraph instance
+pub async fn probe_oxigraph(client: &Client, url: &str) -> Result<(), String> {
+    let resp = client
+        .post(format!("{}/query", url))
+        .header("Content-Type", "application/sparql-query")
+        .header("Accept", "application/sparql-results+json")
+        .body("ASK {}")
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("status {}", resp.status()))
+    }
+}
+
+/// Check that Oxigraph answers a trivial query
+async fn check_oxigraph(state: &AppState) -> Value {
+    let started = Instant::now();
+    let result = probe_oxigraph(&state.client, &state.oxigraph_url).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(()) => json!({ "ok": true, "latency_ms": latency_ms }),
+        Err(e) => json!({ "ok": false, "latency_ms": latency_ms, "error": e }),
+    }
+}
+
+/// Check that a file can be created and removed in FILES_DIR
+async fn check_files_dir(state: &AppState) -> Value {
+    let probe = PathBuf::from(&state.files_dir).join(format!(".readyz-{}", Uuid::new_v4()));
+    match tokio::fs::write(&probe, b"").await {
+        Ok(()) => {
+            let _ = tokio::fs::remove_file(&probe).await;
+            json!({ "ok": true })
+        }
+        Err(e) => json!({ "ok": false, "error": e.to_string() }),
+    }
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// GET /gate/healthz - Liveness: the process is up and serving requests
+pub async fn healthz_handler() -> Response {
+    json_response(StatusCode::OK, json!({ "status": "ok" }))
+}
+
+/// GET /gate/readyz - Readiness: Oxigraph reachable and FILES_DIR writable.
+/// Returns 503 with per-check details when any check fails. Read replicas
+/// are reported by how many are currently in rotation; read-only
+/// maintenance is reported but keeps the gate ready.
+pub async fn readyz_handler(State(state): State<Arc<AppState>>) -> Response {
+    let (oxigraph, files_dir) = tokio::join!(check_oxigraph(&state), check_files_dir(&state));
+
+    // Config is loaded before the router exists, so reaching this handler
+    // means it succeeded
+    let ready = oxigraph["ok"] == true && files_dir["ok"] == true;
+    let mut body = json!({
+        "status": if ready { "ready" } else { "not ready" },
+        "read_only": state.maintenance.is_read_only(),
+        "checks": {
+            "config": { "ok": true },
+            "oxigraph": oxigraph,
+            "files_dir": files_dir,
+        },
+    });
+    // Reads fall back to the primary, so evicted replicas do not make the
+    // gate unready
+    if !state.replicas.is_empty() {
+        let (healthy, total) = state.replicas.counts();
+        body["checks"]["replicas"] = json!({ "ok": true, "healthy": healthy, "total": total });
+    }
+
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    json_response(status, body)
+}
@@ -0,0 +1,91 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::Method,
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use std::{net::SocketAddr, sync::Arc};
+
+use crate::admin::is_admin;
+use crate::files::{credential, get_access_rank_iri};
+use crate::proxy::{proxy_handler, GRAPH_IRI};
+use crate::templates::escape_html;
+use crate::AppState;
+
+/// What `GET /` shows (`HOME_PAGE`)
+pub enum HomePage {
+    /// The gate's landing page, rendered from this template
+    Gate { template: String },
+    /// Oxigraph's query UI, as before the landing page existed
+    Oxigraph,
+}
+
+/// A landing page link: label, target and the request it leads to, whose
+/// authorization rule decides whether the link is shown
+struct HomeLink {
+    label: &'static str,
+    href: &'static str,
+    method: Method,
+    path: &'static str,
+}
+
+const HOME_LINKS: [HomeLink; 5] = [
+    HomeLink { label: "Query UI", href: "/?ui", method: Method::GET, path: "/query" },
+    HomeLink { label: "Browse files", href: "/file/", method: Method::GET, path: "/file/" },
+    HomeLink { label: "Collections", href: "/collections", method: Method::GET, path: "/collections" },
+    HomeLink { label: "Upload files", href: "/upload", method: Method::POST, path: "/res" },
+    HomeLink { label: "Compare directories", href: "/diff", method: Method::GET, path: "/api/diff" },
+];
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// GET / - The gate's landing page with the links the requester may use.
+///
+/// With `HOME_PAGE=oxigraph`, and for `/?ui` or any other request with a
+/// query string, the request is proxied to Oxigraph, which serves its query
+/// UI at the root.
+pub async fn home_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    req: Request,
+) -> Response {
+    let template = match &state.home_page {
+        HomePage::Gate { template } if req.uri().query().is_none() => template,
+        _ => return proxy_handler(State(state), ConnectInfo(addr), jar, req).await,
+    };
+
+    let headers = req.headers();
+    let rank = get_access_rank_iri(&state, GRAPH_IRI, headers, &jar).await;
+
+    let mut links: String = HOME_LINKS
+        .iter()
+        .filter(|link| {
+            state
+                .rules
+                .required_rank(&state.roles, &link.method, link.path)
+                .is_ok_and(|required| rank >= required)
+        })
+        .map(|link| format!(r#"<a class="button" href="{}">{}</a>"#, link.href, link.label))
+        .collect();
+    if is_admin(&state, headers, &jar).await {
+        links.push_str(r#"<a class="button" href="/gate/admin">Administration</a>"#);
+    }
+
+    let identity = match credential(&state, headers, &jar).owner() {
+        Some(owner) => format!("Signed in as {}", escape_html(&owner)),
+        None => {
+            links.push_str(r#"<a class="button" href="/gate/login">Log in</a>"#);
+            "Not signed in".to_string()
+        }
+    };
+
+    Html(
+        template
+            .replace("{identity}", &identity)
+            .replace("{links}", &links),
+    )
+    .into_response()
+}
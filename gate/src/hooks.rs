@@ -0,0 +1,100 @@
+use axum::{
+    http::{HeaderMap, Method, Uri},
+    response::Response,
+};
+use bytes::Bytes;
+use std::net::SocketAddr;
+
+/// A request about to be forwarded upstream, as request hooks see it. The
+/// rank check and the gate's own rewrites (graph restrictions, result
+/// negotiation) have been applied; cached query results are keyed by what
+/// the client sent, before hooks run.
+pub struct ProxyRequest<'a> {
+    pub client: SocketAddr,
+    pub method: &'a Method,
+    /// Path as the upstream sees it
+    pub path: &'a str,
+    /// Whether the request goes to Oxigraph rather than an `UPSTREAM_ROUTES` service
+    pub sparql: bool,
+    /// Whether the request is a SPARQL query
+    pub is_query: bool,
+    /// The requester's rank on the SPARQL endpoints
+    pub rank: i32,
+    /// URL the request is sent to, query string included
+    pub target_url: &'a mut String,
+    /// Client headers to forward; hop-by-hop and credential headers are
+    /// still dropped afterwards
+    pub headers: &'a mut HeaderMap,
+    /// The body, when the gate read it in full; streamed bodies pass
+    /// through untouched. Hooks that need it say so with
+    /// [`ProxyHook::needs_body`].
+    pub body: Option<&'a mut Bytes>,
+}
+
+/// What response hooks know about the request they answer
+pub struct ProxiedRequest {
+    pub client: SocketAddr,
+    pub method: Method,
+    pub uri: Uri,
+}
+
+/// Inspects or changes proxied traffic: rewriting queries, injecting
+/// headers, post-processing results. Hooks are contributed by plugins
+/// (see [`crate::plugins::Plugin::hooks`]) and run in registration order
+/// on requests and in reverse order on responses, like nested layers.
+pub trait ProxyHook: Send + Sync {
+    /// Name shown in the startup log
+    fn name(&self) -> &'static str;
+
+    /// Whether request bodies must be read in full so
+    /// [`ProxyRequest::body`] is set; otherwise they may be streamed
+    fn needs_body(&self) -> bool {
+        false
+    }
+
+    /// Called after the rank check, right before the request is forwarded.
+    /// Returning a response answers the client with it instead; later
+    /// hooks then do not run.
+    fn on_request(&self, _req: &mut ProxyRequest<'_>) -> Option<Response> {
+        None
+    }
+
+    /// Called with every response of the proxy, the gate's own rejections
+    /// included, before it is returned. Bodies may still be streaming.
+    fn on_response(&self, _req: &ProxiedRequest, response: Response) -> Response {
+        response
+    }
+}
+
+/// The registered hooks, held in [`crate::AppState`]
+#[derive(Default)]
+pub struct ProxyHooks {
+    hooks: Vec<Box<dyn ProxyHook>>,
+}
+
+impl ProxyHooks {
+    pub fn new(hooks: Vec<Box<dyn ProxyHook>>) -> Self {
+        Self { hooks }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.hooks.iter().map(|hook| hook.name())
+    }
+
+    /// Whether any hook needs request bodies read in full
+    pub fn needs_body(&self) -> bool {
+        self.hooks.iter().any(|hook| hook.needs_body())
+    }
+
+    pub fn on_request(&self, req: &mut ProxyRequest<'_>) -> Option<Response> {
+        self.hooks.iter().find_map(|hook| hook.on_request(req))
+    }
+
+    pub fn on_response(&self, req: &ProxiedRequest, response: Response) -> Response {
+        self.hooks.iter().rev().fold(response, |response, hook| hook.on_response(req, response))
+    }
+}
@@ -0,0 +1,190 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Default seconds an `Idempotency-Key` is remembered
+pub const DEFAULT_IDEMPOTENCY_KEY_TTL_SECS: u64 = 86400;
+
+/// Header naming the key of a request
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+/// Header marking a response as the replay of an earlier one
+const REPLAYED: &str = "idempotent-replayed";
+
+/// Longest accepted key
+const MAX_KEY_LEN: usize = 255;
+
+/// Most keys remembered at once; the oldest answered ones go first
+const MAX_KEYS: usize = 10_000;
+
+/// Largest response body kept for replay; longer ones are replayed with
+/// their status only
+pub const MAX_REPLAY_BYTES: usize = 16 * 1024;
+
+struct Answer {
+    status: StatusCode,
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+}
+
+struct Entry {
+    /// Hash of the request the key was first sent with
+    request: String,
+    stored: Instant,
+    /// `None` while the request is in flight
+    answer: Option<Answer>,
+}
+
+type Entries = Arc<Mutex<HashMap<String, Entry>>>;
+
+/// Recently seen `Idempotency-Key`s of SPARQL updates and the answers they
+/// got, so a client retrying an update it never heard back about does not
+/// apply it twice. Keys are scoped to the requester and kept in memory.
+pub struct IdempotencyKeys {
+    ttl: Duration,
+    entries: Entries,
+}
+
+/// What to do with a request carrying a key
+pub enum Claimed {
+    /// The key is new: send the request and complete the claim
+    Send(Claim),
+    /// The key was answered before: replay that answer
+    Replay(Response),
+    /// The key is invalid, in flight or was sent with another request
+    Refuse(Response),
+}
+
+impl IdempotencyKeys {
+    /// A zero TTL disables the keys: the header is passed on unread
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    /// Claim the request's key for `requester`, or answer as its first use
+    /// was: with the stored response once that was answered, `409` while it
+    /// is in flight and `422` when the key came with another request.
+    /// `None` when the request has no key.
+    pub fn claim(&self, requester: &str, headers: &HeaderMap, request: &[&[u8]]) -> Option<Claimed> {
+        let key = headers.get(IDEMPOTENCY_KEY)?;
+        let key = match key.to_str() {
+            Ok(key) if !key.trim().is_empty() && key.len() <= MAX_KEY_LEN => key.trim(),
+            _ => {
+                return Some(Claimed::Refuse(
+                    (StatusCode::BAD_REQUEST, "Idempotency-Key must be 1 to 255 visible ASCII characters").into_response(),
+                ))
+            }
+        };
+        let id = format!("{}\n{}", requester, key);
+        let mut hasher = Sha256::new();
+        for part in request {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+        let request = hex::encode(hasher.finalize());
+
+        let mut entries = self.entries.lock().unwrap();
+        let ttl = self.ttl;
+        entries.retain(|_, entry| entry.stored.elapsed() < ttl);
+        if let Some(entry) = entries.get(&id) {
+            let claimed = if entry.request != request {
+                Claimed::Refuse(
+                    (
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "Idempotency-Key was already used for a different request",
+                    )
+                        .into_response(),
+                )
+            } else if let Some(answer) = &entry.answer {
+                let mut response = (answer.status, answer.body.clone()).into_response();
+                match &answer.content_type {
+                    Some(content_type) => response.headers_mut().insert(header::CONTENT_TYPE, content_type.clone()),
+                    None => response.headers_mut().remove(header::CONTENT_TYPE),
+                };
+                response.headers_mut().insert(REPLAYED, HeaderValue::from_static("true"));
+                Claimed::Replay(response)
+            } else {
+                Claimed::Refuse(
+                    (StatusCode::CONFLICT, "A request with this Idempotency-Key is still in progress").into_response(),
+                )
+            };
+            return Some(claimed);
+        }
+
+        if entries.len() >= MAX_KEYS {
+            let oldest = entries
+                .iter()
+                .filter(|(_, entry)| entry.answer.is_some())
+                .min_by_key(|(_, entry)| entry.stored)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            id.clone(),
+            Entry {
+                request,
+                stored: Instant::now(),
+                answer: None,
+            },
+        );
+        Some(Claimed::Send(Claim {
+            entries: Arc::clone(&self.entries),
+            id,
+            completed: false,
+        }))
+    }
+}
+
+/// A key claimed by a request in flight. Completing it keeps the answer for
+/// retries; a claim dropped uncompleted (the request never reached
+/// Oxigraph) frees the key, so a retry is sent again.
+pub struct Claim {
+    entries: Entries,
+    id: String,
+    completed: bool,
+}
+
+impl Claim {
+    /// Keep the answer the request got. Bodies over [`MAX_REPLAY_BYTES`]
+    /// are dropped.
+    pub fn complete(mut self, status: StatusCode, headers: &HeaderMap, body: &[u8]) {
+        self.completed = true;
+        let body = if body.len() <= MAX_REPLAY_BYTES { Bytes::copy_from_slice(body) } else { Bytes::new() };
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(&self.id) {
+            entry.stored = Instant::now();
+            entry.answer = Some(Answer {
+                status,
+                content_type: headers.get(header::CONTENT_TYPE).cloned(),
+                body,
+            });
+        }
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if !self.completed {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.get(&self.id).is_some_and(|entry| entry.answer.is_none()) {
+                entries.remove(&self.id);
+            }
+        }
+    }
+}
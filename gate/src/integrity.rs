@@ -0,0 +1,135 @@
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{io::Read, net::SocketAddr, path::PathBuf, sync::Arc};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::auth::AuthedUser;
+use crate::browse::parse_urn_uuid;
+use crate::files::{binding_value, sparql_select, FILESYSTEM_GRAPH};
+use crate::AppState;
+
+/// SHA-256 of a file, read in blocks off the async runtime
+pub async fn hash_file(path: PathBuf) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path).map_err(|e| e.to_string())?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 1024 * 1024];
+        loop {
+            let read = file.read(&mut buffer).map_err(|e| e.to_string())?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        Ok(hex::encode(hasher.finalize()))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// A stored file to check, with the checksum recorded for it
+struct Stored {
+    uuid: Uuid,
+    stored_as: String,
+    sha256: Option<String>,
+}
+
+/// The stored files of a file, or of every file below a directory, and of
+/// their earlier versions
+async fn stored_files(state: &AppState, node: &Uuid) -> Result<Vec<Stored>, String> {
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT DISTINCT ?file ?storedAs ?sha256 FROM <{graph}> WHERE {{
+    {{ <urn:uuid:{node}> posix:includes* ?file }}
+    UNION
+    {{ <urn:uuid:{node}> posix:includes* ?current . ?current liqk:previousVersion+ ?file }}
+    ?file liqk:storedAs ?storedAs .
+    OPTIONAL {{ ?file liqk:sha256 ?sha256 }}
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        node = node,
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+    Ok(bindings
+        .iter()
+        .filter_map(|b| {
+            Some(Stored {
+                uuid: binding_value(b, "file").and_then(parse_urn_uuid)?,
+                stored_as: binding_value(b, "storedAs")?.to_string(),
+                sha256: binding_value(b, "sha256").map(str::to_lowercase),
+            })
+        })
+        .collect())
+}
+
+/// GET /res/{uuid}/verify - Re-hash the stored content of a file, or of
+/// every file below a directory, with their earlier versions, and compare
+/// it with the recorded `liqk:sha256`. Reports the mismatches, the stored
+/// files missing on disk and the files without a recorded checksum.
+pub async fn verify_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    AuthedUser { fingerprint, rank }: AuthedUser,
+    Path(uuid): Path<Uuid>,
+) -> Response {
+    let files = match stored_files(&state, &uuid).await {
+        Ok(files) => files,
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "SPARQL lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup files").into_response();
+        }
+    };
+
+    let mut mismatches = Vec::new();
+    let mut missing = Vec::new();
+    let mut unrecorded = Vec::new();
+    for file in &files {
+        let Some(expected) = &file.sha256 else {
+            unrecorded.push(file.uuid);
+            continue;
+        };
+        // Stored names are single file names; anything else is not read
+        if file.stored_as.contains(['/', '\\']) || file.stored_as.starts_with('.') {
+            missing.push(json!({ "uuid": file.uuid, "stored_as": file.stored_as }));
+            continue;
+        }
+        match hash_file(PathBuf::from(&state.files_dir).join(&file.stored_as)).await {
+            Ok(actual) if actual == *expected => {}
+            Ok(actual) => mismatches.push(json!({
+                "uuid": file.uuid,
+                "stored_as": file.stored_as,
+                "expected": expected,
+                "actual": actual,
+            })),
+            Err(e) => {
+                warn!(client = %addr, credential = %fingerprint, uuid = %file.uuid, stored_as = %file.stored_as, error = %e, "Failed to hash stored file");
+                missing.push(json!({ "uuid": file.uuid, "stored_as": file.stored_as }));
+            }
+        }
+    }
+
+    let checked = files.len() - unrecorded.len();
+    if mismatches.is_empty() && missing.is_empty() {
+        info!(client = %addr, credential = %fingerprint, uuid = %uuid, rank = rank, checked = checked, unrecorded = unrecorded.len(), "Integrity check passed");
+    } else {
+        warn!(client = %addr, credential = %fingerprint, uuid = %uuid, rank = rank, checked = checked, mismatches = mismatches.len(), missing = missing.len(), "Integrity check found mismatches");
+    }
+    Json(json!({
+        "uuid": uuid,
+        "ok": mismatches.is_empty() && missing.is_empty(),
+        "checked": checked,
+        "mismatches": mismatches,
+        "missing": missing,
+        "unrecorded": unrecorded,
+    }))
+    .into_response()
+}
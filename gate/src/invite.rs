@@ -0,0 +1,213 @@
+use axum::{
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use std::{net::SocketAddr, sync::Arc};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::auth::{random_secret, session_cookie};
+use crate::files::{get_access_rank_iri, hash_token, send_sparql_update_once, verify_token_exists, ACCESS_GRAPH};
+use crate::notify::{notify, AuthEvent};
+use crate::proxy::GRAPH_IRI;
+use crate::roles::PERMISSION_ADMIN;
+use crate::templates::{escape_html, render_page};
+use crate::AppState;
+
+/// Default invite lifetime in seconds (7 days)
+pub const DEFAULT_INVITE_TTL_SECS: i64 = 604_800;
+
+#[derive(Deserialize)]
+pub struct CreateInviteParams {
+    /// Rank of the role granted on the graph to the redeemed token
+    rank: i32,
+    /// Invite lifetime in seconds
+    ttl: Option<i64>,
+}
+
+/// Store a new invite in the access graph; only the hash of the code is kept
+async fn insert_invite(state: &AppState, code_hash: &str, level_iri: &str, ttl: i64) -> Result<(), String> {
+    let now = Utc::now();
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX dcterms: <http://purl.org/dc/terms/>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+
+INSERT DATA {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{invite}> a liqk:Invite ;
+            liqk:invite-hash "{code_hash}" ;
+            liqk:policy-target <{target}> ;
+            liqk:access-level <{level}> ;
+            dcterms:created "{created}"^^xsd:dateTime ;
+            liqk:expires "{expires}"^^xsd:dateTime .
+    }}
+}}"#,
+        graph = ACCESS_GRAPH,
+        invite = Uuid::new_v4(),
+        code_hash = code_hash,
+        target = GRAPH_IRI,
+        level = level_iri,
+        created = now.to_rfc3339(),
+        expires = (now + Duration::seconds(ttl)).to_rfc3339(),
+    );
+
+    send_sparql_update_once(state, "invite", &query).await
+}
+
+/// Consume an unexpired invite and create the token and policy it grants,
+/// in a single update so an invite can only ever be redeemed once
+async fn redeem_invite(state: &AppState, code_hash: &str, token_hash: &str) -> Result<(), String> {
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX dcterms: <http://purl.org/dc/terms/>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+
+DELETE {{ GRAPH <{graph}> {{ ?invite ?p ?o }} }}
+INSERT {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{token}> a liqk:AccessToken ;
+            liqk:token-hash "{token_hash}" ;
+            dcterms:created "{created}"^^xsd:dateTime .
+
+        <urn:uuid:{policy}> a liqk:AccessPolicy ;
+            liqk:policy-target ?target ;
+            liqk:policy-type liqk:policy-type-token ;
+            liqk:access-level ?level ;
+            liqk:policy-grantee <urn:uuid:{token}> ;
+            dcterms:created "{created}"^^xsd:dateTime .
+    }}
+}}
+WHERE {{
+    GRAPH <{graph}> {{
+        ?invite a liqk:Invite ;
+            liqk:invite-hash "{code_hash}" ;
+            liqk:policy-target ?target ;
+            liqk:access-level ?level ;
+            liqk:expires ?expires ;
+            ?p ?o .
+    }}
+    FILTER(?expires > NOW())
+}}"#,
+        graph = ACCESS_GRAPH,
+        token = Uuid::new_v4(),
+        policy = Uuid::new_v4(),
+        token_hash = token_hash,
+        code_hash = code_hash,
+        created = Utc::now().to_rfc3339(),
+    );
+
+    send_sparql_update_once(state, "invite-redeem", &query).await
+}
+
+fn invalid_invite() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Html(render_page(
+            "Invite not valid",
+            "<p>This invite link has already been used or has expired. Ask an administrator for a new one.</p>",
+        )),
+    )
+        .into_response()
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// POST /gate/invite?rank=N&ttl=SECONDS - Create a one-time invite (admin only)
+pub async fn invite_create_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Query(params): Query<CreateInviteParams>,
+) -> Response {
+    let rank = get_access_rank_iri(&state, GRAPH_IRI, &headers, &jar).await;
+    if rank < state.roles.required_rank(PERMISSION_ADMIN) {
+        warn!(client = %addr, rank = rank, "Access denied - invites require admin rank");
+        return (StatusCode::FORBIDDEN, "Access denied - invites require admin access").into_response();
+    }
+
+    let Some(level_iri) = state.roles.role_iri(params.rank) else {
+        return (StatusCode::BAD_REQUEST, "rank does not match a role").into_response();
+    };
+
+    let ttl = params.ttl.unwrap_or(DEFAULT_INVITE_TTL_SECS);
+    if ttl <= 0 {
+        return (StatusCode::BAD_REQUEST, "ttl must be positive").into_response();
+    }
+
+    let code = random_secret();
+    if let Err(e) = insert_invite(&state, &hash_token(&code), &level_iri, ttl).await {
+        warn!(client = %addr, error = %e, "Failed to store invite");
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create invite").into_response();
+    }
+
+    info!(client = %addr, rank = params.rank, ttl = ttl, "Invite created");
+
+    let json_response = serde_json::json!({
+        "path": format!("/gate/invite/{}", code),
+        "rank": params.rank,
+        "expires": (Utc::now() + Duration::seconds(ttl)).to_rfc3339(),
+    });
+
+    (
+        StatusCode::CREATED,
+        [(header::CONTENT_TYPE, "application/json")],
+        json_response.to_string(),
+    )
+        .into_response()
+}
+
+/// GET /gate/invite/:code - Landing page; does not consume the invite so
+/// link previews and prefetching cannot burn it
+pub async fn invite_page_handler(Path(code): Path<String>) -> Html<String> {
+    let body = format!(
+        r#"<p>You have been invited. Accepting creates a personal access token, which is shown once and signs you in on this browser.</p>
+        <form method="POST" action="/gate/invite/{}">
+            <button type="submit">Accept invite</button>
+        </form>"#,
+        escape_html(&code),
+    );
+    Html(render_page("Invitation", &body))
+}
+
+/// POST /gate/invite/:code - Redeem the invite: create a token with the
+/// preset rank, delete the invite, and log the browser in
+pub async fn invite_redeem_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    Path(code): Path<String>,
+) -> Response {
+    let token = random_secret();
+    let token_hash = hash_token(&token);
+
+    if let Err(e) = redeem_invite(&state, &hash_token(&code), &token_hash).await {
+        warn!(client = %addr, error = %e, "Failed to redeem invite");
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to redeem invite").into_response();
+    }
+
+    // The update is a no-op for unknown, used or expired invites
+    if !verify_token_exists(&state.client, &state.oxigraph_url, &token_hash).await {
+        warn!(client = %addr, "Invite rejected - unknown, used or expired");
+        return invalid_invite();
+    }
+
+    info!(client = %addr, "Invite redeemed");
+    notify(&state, AuthEvent::TokenCreated { client: addr.ip().to_string() });
+
+    let body = format!(
+        r#"<p>Your access token is shown below. Store it somewhere safe &mdash; it cannot be displayed again. You are now signed in on this browser.</p>
+        <code>{}</code>
+        <a class="button" href="/">Continue</a>"#,
+        escape_html(&token),
+    );
+    let jar = jar.add(session_cookie(&state, token));
+    (jar, Html(render_page("Welcome", &body))).into_response()
+}
@@ -0,0 +1,191 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tracing::{info, warn};
+
+use crate::files::{post_sparql_update, UpdateError};
+use crate::AppState;
+
+/// Default journal location, relative to the working directory
+pub const DEFAULT_JOURNAL_FILE: &str = "journal.jsonl";
+/// Delay between replay attempts while Oxigraph cannot be reached
+const REPLAY_RETRY_SECS: u64 = 30;
+
+/// What became of a journaled update
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Oxigraph accepted it
+    Applied,
+    /// Oxigraph refused it; replaying would fail the same way
+    Rejected,
+    /// It failed and its caller undid its own side effects instead
+    Discarded,
+}
+
+/// A journal line: an update before it is sent, or the status it reached
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Record {
+    Update { id: u64, time: String, context: String, update: String },
+    Status { id: u64, status: Status },
+}
+
+/// A journaled update without a status, found when the journal is opened
+pub struct PendingUpdate {
+    pub id: u64,
+    pub time: String,
+    pub context: String,
+    pub update: String,
+}
+
+/// Write-ahead journal of the SPARQL updates the gate sends itself. Each
+/// update is appended (and synced) before it is sent and marked once
+/// Oxigraph answers; updates still unmarked at startup were lost to a
+/// crash or to Oxigraph being unreachable, and are replayed in order.
+/// The journal is a JSON-lines file compacted to its pending updates on
+/// every start.
+pub struct Journal {
+    file: Option<Mutex<File>>,
+    next_id: AtomicU64,
+}
+
+impl Journal {
+    pub fn disabled() -> Self {
+        Self { file: None, next_id: AtomicU64::new(1) }
+    }
+
+    /// Open the journal at `path`, returning the updates that were never
+    /// marked. The file is rewritten to hold only those.
+    pub fn open(path: &str) -> Result<(Self, Vec<PendingUpdate>), String> {
+        let path = PathBuf::from(path);
+        let pending = if path.exists() { read_pending(&path)? } else { Vec::new() };
+        let next_id = pending.iter().map(|p| p.id).max().unwrap_or(0) + 1;
+
+        // Compact: write the pending updates to a new file and swap it in
+        let compacted = path.with_extension("jsonl.tmp");
+        let mut file = File::create(&compacted).map_err(|e| format!("Failed to write journal: {}", e))?;
+        for entry in &pending {
+            let record = Record::Update {
+                id: entry.id,
+                time: entry.time.clone(),
+                context: entry.context.clone(),
+                update: entry.update.clone(),
+            };
+            writeln!(file, "{}", serde_json::to_string(&record).unwrap())
+                .map_err(|e| format!("Failed to write journal: {}", e))?;
+        }
+        file.sync_all().map_err(|e| format!("Failed to write journal: {}", e))?;
+        fs::rename(&compacted, &path).map_err(|e| format!("Failed to replace journal: {}", e))?;
+
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to open journal: {}", e))?;
+        let journal = Self { file: Some(Mutex::new(file)), next_id: AtomicU64::new(next_id) };
+        Ok((journal, pending))
+    }
+
+    /// Record an update before it is sent. Returns its ID, or `None` when
+    /// journaling is off or the journal cannot be written; the update is
+    /// sent either way.
+    pub fn append(&self, context: &str, update: &str) -> Option<u64> {
+        self.file.as_ref()?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let record = Record::Update {
+            id,
+            time: Utc::now().to_rfc3339(),
+            context: context.to_string(),
+            update: update.to_string(),
+        };
+        self.write(&record).then_some(id)
+    }
+
+    /// Record what became of an appended update
+    pub fn mark(&self, id: u64, status: Status) {
+        self.write(&Record::Status { id, status });
+    }
+
+    /// Mark an update by the result of sending it. Updates Oxigraph could
+    /// not be asked about stay pending for the next start.
+    pub fn settle(&self, id: Option<u64>, result: &Result<(), UpdateError>, on_failure: Option<Status>) {
+        let Some(id) = id else {
+            return;
+        };
+        match result {
+            Ok(()) => self.mark(id, Status::Applied),
+            Err(UpdateError::Rejected(_)) => self.mark(id, Status::Rejected),
+            Err(UpdateError::Unreachable(_)) => {
+                if let Some(status) = on_failure {
+                    self.mark(id, status);
+                }
+            }
+        }
+    }
+
+    fn write(&self, record: &Record) -> bool {
+        let Some(file) = &self.file else {
+            return false;
+        };
+        let mut file = file.lock().unwrap();
+        let line = format!("{}\n", serde_json::to_string(record).unwrap());
+        match file.write_all(line.as_bytes()).and_then(|()| file.sync_data()) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(error = %e, "Failed to write journal");
+                false
+            }
+        }
+    }
+}
+
+/// Updates without a status, in journal order. A torn last line from a
+/// crash mid-write is skipped.
+fn read_pending(path: &Path) -> Result<Vec<PendingUpdate>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open journal: {}", e))?;
+    let mut pending: Vec<PendingUpdate> = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read journal: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Record>(&line) {
+            Ok(Record::Update { id, time, context, update }) => pending.push(PendingUpdate { id, time, context, update }),
+            Ok(Record::Status { id, .. }) => pending.retain(|p| p.id != id),
+            Err(e) => warn!(line = number + 1, error = %e, "Skipping unreadable journal line"),
+        }
+    }
+    Ok(pending)
+}
+
+/// Replay pending updates in order, retrying while Oxigraph is unreachable
+pub async fn replay(state: Arc<AppState>, pending: Vec<PendingUpdate>) {
+    for entry in pending {
+        loop {
+            let result = post_sparql_update(&state.client, &state.oxigraph_url, &entry.update).await;
+            state.query_cache.invalidate_all();
+            match &result {
+                Ok(()) => info!(id = entry.id, context = %entry.context, journaled = %entry.time, "Replayed journaled update"),
+                Err(UpdateError::Rejected(e)) => {
+                    warn!(id = entry.id, context = %entry.context, journaled = %entry.time, error = %e, "Journaled update rejected on replay")
+                }
+                Err(UpdateError::Unreachable(e)) => {
+                    warn!(id = entry.id, context = %entry.context, error = %e, retry_secs = REPLAY_RETRY_SECS, "Journal replay waiting for Oxigraph");
+                    tokio::time::sleep(Duration::from_secs(REPLAY_RETRY_SECS)).await;
+                    continue;
+                }
+            }
+            state.journal.settle(Some(entry.id), &result, None);
+            break;
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use base64::prelude::{Engine, BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD};
+use chrono::{TimeZone, Utc};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{net::SocketAddr, sync::Arc};
+use tracing::{info, warn};
+
+use crate::files::{credential, get_access_rank_iri, Credential};
+use crate::proxy::GRAPH_IRI;
+use crate::roles::PERMISSION_VIEW;
+use crate::AppState;
+
+/// Default JWT lifetime in seconds (5 minutes)
+pub const DEFAULT_JWT_TTL_SECS: i64 = 300;
+/// Default `iss` claim
+pub const DEFAULT_JWT_ISSUER: &str = "liqk-gate";
+
+/// Mints EdDSA (Ed25519) JWTs describing the requester, so services behind
+/// the same proxy can check identity and rank against the published key
+/// without calling the gate
+pub struct JwtIssuer {
+    key_pair: Ed25519KeyPair,
+    /// Key ID: the first 16 hex digits of the SHA-256 of the public key
+    kid: String,
+    issuer: String,
+    /// Token lifetime in seconds
+    pub ttl_secs: i64,
+}
+
+impl JwtIssuer {
+    /// Load a PKCS#8 Ed25519 key in PEM form, as written by
+    /// `openssl genpkey -algorithm ed25519`
+    pub fn from_pem(pem: &str, issuer: String, ttl_secs: i64) -> Result<Self, String> {
+        let body: String = pem
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        let der = BASE64_STANDARD.decode(body).map_err(|e| format!("invalid PEM: {}", e))?;
+        let key_pair =
+            Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der).map_err(|e| format!("invalid Ed25519 key: {}", e))?;
+        Ok(Self::new(key_pair, issuer, ttl_secs))
+    }
+
+    /// A fresh key; tokens it signed stop verifying once the gate restarts
+    pub fn generate(issuer: String, ttl_secs: i64) -> Self {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).expect("system RNG available");
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).expect("generated key is valid");
+        Self::new(key_pair, issuer, ttl_secs)
+    }
+
+    fn new(key_pair: Ed25519KeyPair, issuer: String, ttl_secs: i64) -> Self {
+        let kid = hex::encode(Sha256::digest(key_pair.public_key().as_ref()))[..16].to_string();
+        Self { key_pair, kid, issuer, ttl_secs }
+    }
+
+    /// Compact JWS over the given claims
+    pub fn sign(&self, claims: &serde_json::Value) -> String {
+        let header = json!({ "alg": "EdDSA", "typ": "JWT", "kid": self.kid });
+        let signing_input = format!(
+            "{}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+            BASE64_URL_SAFE_NO_PAD.encode(claims.to_string()),
+        );
+        let signature = self.key_pair.sign(signing_input.as_bytes());
+        format!("{}.{}", signing_input, BASE64_URL_SAFE_NO_PAD.encode(signature.as_ref()))
+    }
+
+    /// The verification key as a JSON Web Key Set (RFC 8037 `OKP` key)
+    pub fn jwks(&self) -> serde_json::Value {
+        json!({
+            "keys": [{
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "x": BASE64_URL_SAFE_NO_PAD.encode(self.key_pair.public_key().as_ref()),
+                "kid": self.kid,
+                "alg": "EdDSA",
+                "use": "sig",
+            }]
+        })
+    }
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// POST /gate/jwt - Mint a short-lived JWT for the signed-in requester
+pub async fn jwt_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    let credential = credential(&state, &headers, &jar);
+    let auth = match &credential {
+        Credential::Session(session) => session.backend,
+        Credential::Token(_) => "token",
+        Credential::Proxy { .. } => "proxy",
+        Credential::Emergency => "emergency",
+        Credential::Anonymous => {
+            warn!(client = %addr, "JWT refused - not signed in");
+            return (StatusCode::UNAUTHORIZED, "Sign in to obtain a token").into_response();
+        }
+    };
+    let Some(subject) = credential.owner() else {
+        return (StatusCode::UNAUTHORIZED, "Sign in to obtain a token").into_response();
+    };
+
+    let rank = get_access_rank_iri(&state, GRAPH_IRI, &headers, &jar).await;
+    if rank < state.roles.required_rank(PERMISSION_VIEW) {
+        warn!(client = %addr, subject = %subject, "JWT refused - no rank on the graph");
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+
+    let jwt = &state.jwt;
+    let now = Utc::now().timestamp();
+    let exp = now + jwt.ttl_secs;
+    let token = jwt.sign(&json!({
+        "iss": jwt.issuer,
+        "sub": subject,
+        "rank": rank,
+        "auth": auth,
+        "iat": now,
+        "exp": exp,
+    }));
+
+    info!(client = %addr, subject = %subject, rank = rank, exp = exp, "JWT issued");
+
+    let json_response = json!({
+        "token": token,
+        "expires": Utc.timestamp_opt(exp, 0).single().map(|t| t.to_rfc3339()),
+    });
+
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json"), (header::CACHE_CONTROL, "no-store")],
+        json_response.to_string(),
+    )
+        .into_response()
+}
+
+/// GET /gate/.well-known/jwks.json - Public key for verifying gate JWTs
+pub async fn jwks_handler(State(state): State<Arc<AppState>>) -> Response {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json"), (header::CACHE_CONTROL, "max-age=300")],
+        state.jwt.jwks().to_string(),
+    )
+        .into_response()
+}
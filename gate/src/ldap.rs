@@ -0,0 +1,125 @@
+use async_trait::async_trait;
+use ldap3::{dn_escape, LdapConnAsync};
+use tracing::warn;
+
+use crate::auth::{AuthBackend, Principal};
+use crate::files::{escape_sparql_string, execute_access_query, ACCESS_GRAPH};
+
+/// LDAP backend configuration (from `LDAP_*` environment variables)
+pub struct LdapConfig {
+    /// Server URL, e.g. `ldaps://ldap.example.org`
+    pub url: String,
+    /// Bind DN template; `{username}` is replaced by the escaped login name
+    pub user_dn_template: String,
+    /// Explicit DN -> rank assignments, checked before the graph
+    pub ranks: Vec<(String, i32)>,
+    /// Rank for DNs with no explicit or graph assignment (0 denies login)
+    pub default_rank: i32,
+}
+
+impl LdapConfig {
+    /// Parse `LDAP_RANKS`: `dn:rank` pairs separated by `;`.
+    /// The rank follows the last `:` so DNs may contain colons.
+    pub fn parse_ranks(value: &str) -> Vec<(String, i32)> {
+        value
+            .split(';')
+            .filter_map(|entry| {
+                let (dn, rank) = entry.trim().rsplit_once(':')?;
+                Some((dn.trim().to_string(), rank.trim().parse().ok()?))
+            })
+            .collect()
+    }
+}
+
+/// Authenticates users with an LDAP simple bind and maps their DN to a rank
+pub struct LdapBackend {
+    config: LdapConfig,
+    client: reqwest::Client,
+    oxigraph_url: String,
+}
+
+impl LdapBackend {
+    pub fn new(config: LdapConfig, client: reqwest::Client, oxigraph_url: String) -> Self {
+        Self { config, client, oxigraph_url }
+    }
+
+    async fn bind(&self, dn: &str, password: &str) -> Result<bool, String> {
+        let (conn, mut ldap) = LdapConnAsync::new(&self.config.url)
+            .await
+            .map_err(|e| format!("Failed to connect to LDAP server: {}", e))?;
+        ldap3::drive!(conn);
+
+        let result = ldap
+            .simple_bind(dn, password)
+            .await
+            .map_err(|e| format!("LDAP bind failed: {}", e))?;
+        let _ = ldap.unbind().await;
+
+        Ok(result.rc == 0)
+    }
+
+    /// Rank for a DN: explicit config first, then `liqk:DirectoryUser` in the
+    /// access graph, then the configured default
+    async fn rank_for_dn(&self, dn: &str) -> i32 {
+        if let Some((_, rank)) = self
+            .config
+            .ranks
+            .iter()
+            .find(|(configured, _)| configured.eq_ignore_ascii_case(dn))
+        {
+            return *rank;
+        }
+
+        let query = format!(
+            r#"PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT (COALESCE(MAX(?rank), 0) AS ?accessRank)
+FROM <{access_graph}>
+WHERE {{
+  ?user a liqk:DirectoryUser ;
+        liqk:ldap-dn "{dn}" ;
+        liqk:access-level ?level .
+
+  ?level liqk:rank ?rank .
+}}"#,
+            access_graph = ACCESS_GRAPH,
+            dn = escape_sparql_string(dn),
+        );
+
+        match execute_access_query(&self.client, &self.oxigraph_url, &query).await {
+            Ok(rank) if rank > 0 => rank,
+            Ok(_) => self.config.default_rank,
+            Err(e) => {
+                warn!(dn = %dn, error = %e, "LDAP rank lookup failed");
+                self.config.default_rank
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AuthBackend for LdapBackend {
+    fn name(&self) -> &'static str {
+        "ldap"
+    }
+
+    async fn authenticate(&self, username: &str, password: &str) -> Result<Option<Principal>, String> {
+        // An empty password turns a simple bind into an unauthenticated bind,
+        // which most servers accept for any DN
+        if username.is_empty() || password.is_empty() {
+            return Ok(None);
+        }
+
+        let dn = self
+            .config
+            .user_dn_template
+            .replace("{username}", &dn_escape(username));
+
+        if !self.bind(&dn, password).await? {
+            return Ok(None);
+        }
+
+        let rank = self.rank_for_dn(&dn).await;
+        Ok(Some(Principal { identity: dn, rank }))
+    }
+}
@@ -0,0 +1,83 @@
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use http_body_util::LengthLimitError;
+use std::error::Error;
+
+/// Default limit for `/query` bodies in MiB
+pub const DEFAULT_QUERY_BODY_LIMIT_MB: usize = 2;
+/// Default limit for `/update`, Graph Store and other proxied bodies in MiB
+pub const DEFAULT_UPDATE_BODY_LIMIT_MB: usize = 256;
+/// Default limit for uploaded files in MiB
+pub const DEFAULT_UPLOAD_BODY_LIMIT_MB: usize = 4096;
+
+const MIB: usize = 1024 * 1024;
+
+/// Request body limits in bytes, by route (`*_BODY_LIMIT_MB`). Compressed
+/// bodies are held to the same limits once decoded.
+pub struct BodyLimits {
+    /// SPARQL queries
+    pub query: usize,
+    /// SPARQL updates, Graph Store writes and anything else proxied
+    pub update: usize,
+    /// Files uploaded to `/res`, in one request or resumably
+    pub upload: usize,
+}
+
+impl BodyLimits {
+    pub fn from_mb(query: usize, update: usize, upload: usize) -> Self {
+        Self {
+            query: query.saturating_mul(MIB),
+            update: update.saturating_mul(MIB),
+            upload: upload.saturating_mul(MIB),
+        }
+    }
+
+    /// `413` for a query over the limit
+    pub fn query_too_large(&self) -> Response {
+        too_large(format!(
+            "Query too large (max {} MiB); load large data with /update or the Graph Store instead",
+            self.query / MIB
+        ))
+    }
+
+    /// `413` for an update or other proxied body over the limit
+    pub fn update_too_large(&self) -> Response {
+        too_large(format!(
+            "Request body too large (max {} MiB); send bulk loads to /load instead",
+            self.update / MIB
+        ))
+    }
+
+    /// `413` for a file over the limit
+    pub fn upload_too_large(&self) -> Response {
+        too_large(format!("File too large (max {} MiB)", self.upload / MIB))
+    }
+}
+
+/// Whether the announced `Content-Length` already exceeds `limit`
+pub fn exceeds(headers: &HeaderMap, limit: usize) -> bool {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .is_some_and(|length| length > limit as u64)
+}
+
+/// Whether a failed request was cut off by a body limit, anywhere in the
+/// error's chain of causes
+pub fn is_limit_error(error: &(dyn Error + 'static)) -> bool {
+    let mut cause = Some(error);
+    while let Some(error) = cause {
+        if error.is::<LengthLimitError>() {
+            return true;
+        }
+        cause = error.source();
+    }
+    false
+}
+
+fn too_large(message: String) -> Response {
+    (StatusCode::PAYLOAD_TOO_LARGE, message).into_response()
+}
@@ -0,0 +1,99 @@
+use axum::{
+    extract::{ConnectInfo, Request},
+    Router,
+};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+};
+use std::{
+    fmt,
+    fs::{self, Permissions},
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::UnixListener,
+};
+use tower::ServiceExt;
+use tracing::{debug, warn};
+
+/// Default address plain HTTP is served on
+pub const DEFAULT_BIND_ADDR: &str = "0.0.0.0:8080";
+
+/// Client address handlers see for Unix socket connections, which have
+/// none: the peer is a local process, so it counts as loopback
+pub const UNIX_PEER_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+/// Where plain HTTP is served (`BIND_ADDR`)
+pub enum BindAddr {
+    Tcp(String),
+    /// `unix:/path/to/gate.sock`
+    Unix(PathBuf),
+}
+
+impl BindAddr {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.strip_prefix("unix:") {
+            Some("") => Err("unix: needs a socket path".to_string()),
+            Some(path) => Ok(BindAddr::Unix(PathBuf::from(path))),
+            None => Ok(BindAddr::Tcp(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for BindAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindAddr::Tcp(addr) => write!(f, "http://{}", addr),
+            BindAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Bind a Unix socket, replacing a stale one left by an earlier run, and
+/// give it `mode` when set
+pub fn bind_unix(path: &Path, mode: Option<u32>) -> io::Result<UnixListener> {
+    if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    if let Some(mode) = mode {
+        fs::set_permissions(path, Permissions::from_mode(mode))?;
+    }
+    Ok(listener)
+}
+
+/// Serve `app` on a Unix socket. Connections carry `ConnectInfo` with
+/// [`UNIX_PEER_ADDR`].
+pub async fn serve_unix(listener: UnixListener, app: Router) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(serve_connection(stream, UNIX_PEER_ADDR, app.clone()));
+            }
+            Err(e) => warn!(error = %e, "Failed to accept connection"),
+        }
+    }
+}
+
+/// Serve HTTP/1.1 and HTTP/2 on one accepted connection, with
+/// `ConnectInfo(addr)` set on each request as `axum::serve` does
+pub async fn serve_connection<I>(io: I, addr: SocketAddr, app: Router)
+where
+    I: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let service = hyper::service::service_fn(move |mut req: Request<hyper::body::Incoming>| {
+        req.extensions_mut().insert(ConnectInfo(addr));
+        app.clone().oneshot(req.map(axum::body::Body::new))
+    });
+    if let Err(e) = Builder::new(TokioExecutor::new())
+        .serve_connection_with_upgrades(TokioIo::new(io), service)
+        .await
+    {
+        debug!(client = %addr, error = %e, "Connection closed with error");
+    }
+}
@@ -0,0 +1,207 @@
+use axum::{
+    extract::{ConnectInfo, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{Duration, TimeZone, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::admin::parse_time;
+use crate::files::{binding_value, get_access_rank_iri, send_sparql_update, sparql_select};
+use crate::notify::failed_login;
+use crate::proxy::GRAPH_IRI;
+use crate::roles::PERMISSION_ADMIN;
+use crate::AppState;
+
+/// Graph holding failed login records
+pub const AUDIT_GRAPH: &str = "http://liqk.org/graph/audit";
+
+/// Failed logins older than this are pruned (30 days)
+const RETENTION_SECS: i64 = 30 * 86_400;
+
+/// Default and maximum window for statistics, in hours
+pub const DEFAULT_STATS_HOURS: i64 = 24;
+const MAX_STATS_HOURS: i64 = RETENTION_SECS / 3600;
+
+/// Number of client addresses reported in statistics
+const TOP_CLIENTS: usize = 20;
+
+#[derive(Deserialize)]
+pub struct StatsParams {
+    hours: Option<i64>,
+}
+
+/// Failed login attempts within a window
+pub struct FailureStats {
+    pub hours: i64,
+    pub total: usize,
+    /// Failures per hour, oldest first; `(hour start, count)`
+    pub by_hour: Vec<(i64, usize)>,
+    /// Clients with the most failures; `(ip, count, last attempt)`
+    pub by_client: Vec<(String, usize, i64)>,
+}
+
+/// Store a failed login. Only the client address, time, login method and
+/// the length of the submitted credential are kept - never the credential.
+/// Runs in the background so a slow store does not delay the response.
+pub fn record_failure(state: &Arc<AppState>, addr: &SocketAddr, method: &'static str, credential_len: usize) {
+    failed_login(state, addr);
+
+    let state = Arc::clone(state);
+    let ip = addr.ip().to_string();
+    tokio::spawn(async move {
+        let now = Utc::now();
+        let query = format!(
+            r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX dcterms: <http://purl.org/dc/terms/>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+
+INSERT DATA {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{id}> a liqk:LoginFailure ;
+            liqk:client-ip "{ip}" ;
+            liqk:login-method "{method}" ;
+            liqk:credential-length "{len}"^^xsd:integer ;
+            dcterms:created "{now}"^^xsd:dateTime .
+    }}
+}} ;
+
+DELETE {{ GRAPH <{graph}> {{ ?failure ?p ?o }} }}
+WHERE {{
+    GRAPH <{graph}> {{
+        ?failure a liqk:LoginFailure ;
+            dcterms:created ?created ;
+            ?p ?o .
+        FILTER(?created < "{cutoff}"^^xsd:dateTime)
+    }}
+}}"#,
+            graph = AUDIT_GRAPH,
+            id = Uuid::new_v4(),
+            ip = ip,
+            method = method,
+            len = credential_len,
+            now = now.to_rfc3339(),
+            cutoff = (now - Duration::seconds(RETENTION_SECS)).to_rfc3339(),
+        );
+
+        if let Err(e) = send_sparql_update(&state, "login-failure", &query).await {
+            warn!(client = %ip, error = %e, "Failed to record failed login");
+        }
+    });
+}
+
+/// Aggregate failed logins over the last `hours` hours
+pub async fn failure_stats(state: &AppState, hours: i64) -> Result<FailureStats, String> {
+    let hours = hours.clamp(1, MAX_STATS_HOURS);
+    let now = Utc::now().timestamp();
+    let first_hour = (now / 3600 - hours + 1) * 3600;
+
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX dcterms: <http://purl.org/dc/terms/>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+
+SELECT ?ip ?created FROM <{graph}> WHERE {{
+    ?failure a liqk:LoginFailure ;
+        liqk:client-ip ?ip ;
+        dcterms:created ?created .
+    FILTER(?created >= "{since}"^^xsd:dateTime)
+}}"#,
+        graph = AUDIT_GRAPH,
+        since = rfc3339(first_hour).unwrap_or_default(),
+    );
+
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+
+    let mut by_hour = vec![0usize; hours as usize];
+    let mut clients: HashMap<String, (usize, i64)> = HashMap::new();
+    let mut total = 0;
+    for b in &bindings {
+        let (Some(ip), Some(created)) = (
+            binding_value(b, "ip"),
+            binding_value(b, "created").and_then(parse_time).map(|t| t.timestamp()),
+        ) else {
+            continue;
+        };
+        total += 1;
+        if let Some(bucket) = by_hour.get_mut(((created - first_hour) / 3600) as usize) {
+            *bucket += 1;
+        }
+        let entry = clients.entry(ip.to_string()).or_insert((0, created));
+        entry.0 += 1;
+        entry.1 = entry.1.max(created);
+    }
+
+    let mut by_client: Vec<(String, usize, i64)> =
+        clients.into_iter().map(|(ip, (count, last))| (ip, count, last)).collect();
+    by_client.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| b.2.cmp(&a.2)));
+    by_client.truncate(TOP_CLIENTS);
+
+    Ok(FailureStats {
+        hours,
+        total,
+        by_hour: by_hour
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| (first_hour + i as i64 * 3600, count))
+            .collect(),
+        by_client,
+    })
+}
+
+fn rfc3339(ts: i64) -> Option<String> {
+    Utc.timestamp_opt(ts, 0).single().map(|t| t.to_rfc3339())
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// GET /gate/admin/login-failures?hours=N - Failed login statistics (admin only)
+pub async fn login_failures_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Query(params): Query<StatsParams>,
+) -> Response {
+    let rank = get_access_rank_iri(&state, GRAPH_IRI, &headers, &jar).await;
+    if rank < state.roles.required_rank(PERMISSION_ADMIN) {
+        warn!(client = %addr, rank = rank, "Access denied - login statistics require admin rank");
+        return (StatusCode::FORBIDDEN, "Access denied - administration requires admin access").into_response();
+    }
+
+    let stats = match failure_stats(&state, params.hours.unwrap_or(DEFAULT_STATS_HOURS)).await {
+        Ok(stats) => stats,
+        Err(e) => {
+            warn!(client = %addr, error = %e, "Failed to load login statistics");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load login statistics").into_response();
+        }
+    };
+
+    let json_response = json!({
+        "hours": stats.hours,
+        "total": stats.total,
+        "by_hour": stats.by_hour.iter().map(|(start, count)| json!({
+            "start": rfc3339(*start),
+            "count": count,
+        })).collect::<Vec<_>>(),
+        "by_client": stats.by_client.iter().map(|(ip, count, last)| json!({
+            "ip": ip,
+            "count": count,
+            "last": rfc3339(*last),
+        })).collect::<Vec<_>>(),
+    });
+
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        json_response.to_string(),
+    )
+        .into_response()
+}
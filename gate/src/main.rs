@@ -1,43 +1,309 @@
+mod accept;
+mod admin;
+mod archive;
+mod asset_cache;
 mod auth;
+mod blobs;
+mod browse;
+mod bulk_load;
+mod collections;
+mod cors;
+mod destructive;
+mod diff;
+mod encoding;
+mod error_pages;
+mod etag;
 mod files;
+mod forwarded;
+mod github;
+mod health;
+mod home;
+mod hooks;
+mod idempotency;
+mod integrity;
+mod invite;
+mod journal;
+mod jwt;
+mod ldap;
+mod limits;
+mod listen;
+mod login_audit;
+mod manifest;
+mod maintenance;
+mod markdown;
+mod metrics;
+mod notify;
+mod pagination;
+mod path_cache;
+mod plugins;
+mod preview;
 mod proxy;
+mod proxy_auth;
+mod public_graphs;
+mod query_cache;
+mod query_limits;
+mod query_log;
+mod query_templates;
+mod quotas;
+mod rank_cache;
+mod rate_limit;
+mod readme;
+mod replicas;
+mod results;
+mod roles;
+mod rules;
+mod saved_queries;
+mod secrets;
+mod security_headers;
+mod sessions;
+mod shadow;
+mod share;
+mod shortlinks;
+mod slow_queries;
+mod telemetry;
 mod templates;
+mod thumbnails;
+mod tls;
+mod trash;
+mod update_graphs;
+mod uploads;
+mod upstreams;
+mod versions;
 
-use axum::{routing::{get, post}, Router};
-use http::Method;
+use axum::{extract::DefaultBodyLimit, middleware, routing::{delete, get, post, put}, Router};
 use reqwest::Client;
-use std::{net::SocketAddr, sync::Arc};
-use tower_http::cors::{Any, CorsLayer};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tracing::{info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use auth::{load_config, login_page, login_submit};
-use files::{res_handler, res_post_handler, res_put_handler};
+use accept::AcceptRewrites;
+use admin::{
+    admin_page, kill_session_handler, policy_rank_handler, revoke_session_handler, revoke_token_handler,
+    revoke_user_handler, sessions_handler, user_rank_handler,
+};
+use asset_cache::AssetCache;
+use auth::{
+    emergency_token_middleware, load_config, load_env_file, login_page, login_submit, logout_all_handler,
+    random_secret, require_access, AuthBackend, CookieConfig, Resource, SESSION_MAX_AGE_SECS,
+};
+use browse::{delete_handler, file_handler, mkdir_handler, res_patch_handler};
+use bulk_load::load_handler;
+use collections::{collection_handler, collections_handler};
+use diff::{diff_handler, diff_page};
+use encoding::compression_layer;
+use files::{hash_token, res_handler, res_post_handler, res_put_handler, upload_page, UploadConflict, UPLOAD_ACTION_IRI};
+use health::{healthz_handler, readyz_handler};
+use home::{home_handler, HomePage};
+use hooks::ProxyHooks;
+use idempotency::IdempotencyKeys;
+use integrity::verify_handler;
+use invite::{invite_create_handler, invite_page_handler, invite_redeem_handler};
+use journal::{replay, Journal};
+use jwt::{jwks_handler, jwt_handler, JwtIssuer};
+use github::{github_callback_handler, github_login_handler, GithubConfig};
+use ldap::LdapBackend;
+use limits::BodyLimits;
+use listen::{bind_unix, serve_unix, BindAddr};
+use login_audit::login_failures_handler;
+use maintenance::{
+    read_only_handler, read_only_middleware, read_only_status_handler, upgrade_handler, upgrade_middleware,
+    upgrade_status_handler, Maintenance,
+};
+use manifest::manifest_handler;
+use metrics::{metrics_handler, metrics_middleware, Metrics};
+use notify::Notifier;
+use path_cache::PathCache;
 use proxy::proxy_handler;
-
-const BIND_ADDR: &str = "0.0.0.0:8080";
+use proxy_auth::{strip_untrusted_user_headers, ProxyAuthConfig};
+use query_cache::QueryCache;
+use query_limits::QueryLimits;
+use query_log::{queries_admin_page, query_log_middleware, QueryLog, Redaction};
+use query_templates::{template_delete_handler, template_put_handler, templates_list_handler};
+use quotas::quota_handler;
+use rank_cache::RankCache;
+use rate_limit::RateLimiter;
+use readme::{preferences_handler, ReadmeCache};
+use replicas::{spawn_health_checks, Replicas};
+use roles::{reload_roles, Roles};
+use rules::Rules;
+use saved_queries::{query_create_handler, query_revoke_handler, query_run_handler, queries_list_handler};
+use secrets::decrypt_env;
+use security_headers::{security_headers_middleware, SecurityHeaders};
+use sessions::SessionStore;
+use shadow::Shadow;
+use share::{share_create_handler, share_handler, ShareSigner};
+use shortlinks::{link_create_handler, link_handler, link_revoke_handler, links_admin_page};
+use slow_queries::{slow_queries_admin_page, SlowQueryLog};
+use telemetry::{export_configured, otlp_layer, trace_middleware};
+use thumbnails::{thumb_handler, Thumbnails};
+use tls::{redirect_app, serve as serve_tls};
+use trash::{spawn_trash_cleanup, trash_list_handler, trash_purge_handler, trash_restore_handler};
+use uploads::{
+    spawn_cleanup, upload_append_handler, upload_cancel_handler, upload_create_handler, upload_status_handler,
+    UploadStore,
+};
+use upstreams::Upstreams;
+use versions::{restore_handler, version_handler, versions_handler};
 
 pub struct AppState {
     pub oxigraph_url: String,
+    /// Read replicas for proxied `/query` requests
+    pub replicas: Replicas,
+    /// Secondary Oxigraph proxied queries are mirrored to
+    pub shadow: Shadow,
+    /// Client for the gate's own requests, bounded by `UPSTREAM_TIMEOUT`
     pub client: Client,
+    /// Client for proxied requests, whose responses may stream indefinitely
+    pub proxy_client: Client,
+    /// How long proxied queries may wait for Oxigraph's response
+    pub upstream_timeout: Option<Duration>,
+    /// How long proxied updates and Graph Store writes may wait
+    pub upstream_write_timeout: Option<Duration>,
+    /// Prefix → upstream routes; unrouted paths go to Oxigraph
+    pub upstreams: Upstreams,
+    /// Per-route `Accept` for clients naming no media type
+    pub accept_rewrites: AcceptRewrites,
+    /// Render Graph Store reads as pages for browsers
+    pub store_pages: bool,
+    /// Request body limits for queries, updates and uploads
+    pub body_limits: BodyLimits,
+    /// Shape limits on queries from requesters below the exempt rank
+    pub query_limits: QueryLimits,
+    /// Rows per page of SELECTs without a LIMIT of their own (0 disables)
+    pub query_page_size: u64,
+    /// Per-requester rate limits for queries, updates and downloads
+    pub rate_limiter: RateLimiter,
     /// Whether to set Secure flag on cookies (requires HTTPS)
     pub secure_cookies: bool,
+    /// Session cookie name, scope and SameSite mode
+    pub cookie: CookieConfig,
     /// Directory for file storage
     pub files_dir: String,
+    /// What uploads do when a name is taken in their target directory
+    pub upload_conflict: UploadConflict,
+    /// Days a trashed item is kept before it is purged (0 keeps it)
+    pub trash_retention_days: u64,
+    /// Bytes each identity may store, in MiB (0 for no quota)
+    pub storage_quota_mb: u64,
+    /// Server-side sessions for password-backend logins
+    pub sessions: SessionStore,
+    /// Username/password login backends, tried in order
+    pub auth_backends: Vec<Box<dyn AuthBackend>>,
+    /// SHA-256 hash of the break-glass `EMERGENCY_TOKEN`, when set
+    pub emergency_token_hash: Option<String>,
+    /// Signer for expiring share links
+    pub share: ShareSigner,
+    /// Public base URL of the gate, for absolute links in manifests
+    pub public_url: Option<String>,
+    /// Short-lived cache of resolved access ranks
+    pub rank_cache: RankCache,
+    /// Short-lived cache of resolved `/file` paths
+    pub path_cache: PathCache,
+    /// Cached `/query` results, dropped on every update
+    pub query_cache: QueryCache,
+    /// Recent `Idempotency-Key`s of updates and their answers
+    pub idempotency_keys: IdempotencyKeys,
+    /// Largest query result the gate converts to XML, CSV or TSV in bytes
+    pub result_conversion_max: usize,
+    /// Largest query result buffered only to be given an ETag, in bytes
+    pub query_etag_max: usize,
+    /// Rendered directory READMEs, keyed by file revision
+    pub readme_cache: ReadmeCache,
+    /// Gzip request bodies forwarded to Oxigraph
+    pub upstream_gzip: bool,
+    /// Reject updates to graphs the requester cannot edit
+    pub restrict_update_graphs: bool,
+    /// Scope queries to the graphs the requester can read
+    pub restrict_query_graphs: bool,
+    /// Named graphs anonymous `/query` requests are restricted to
+    pub public_graphs: Vec<String>,
+    /// Graphs whose `CLEAR`/`DROP` needs admin access or a confirmation
+    pub protected_graphs: Vec<String>,
+    /// Path-based authorization rules
+    pub rules: Rules,
+    /// What `GET /` shows
+    pub home_page: HomePage,
+    /// Role hierarchy the rules' permissions resolve through
+    pub roles: Roles,
+    /// GitHub OAuth login, when enabled
+    pub github: Option<GithubConfig>,
+    /// Reverse-proxy header authentication, when enabled
+    pub proxy_auth: Option<ProxyAuthConfig>,
+    /// Resumable upload sessions, persisted under `FILES_DIR`
+    pub uploads: UploadStore,
+    /// Thumbnails of image files, cached under `FILES_DIR`
+    pub thumbnails: Thumbnails,
+    /// Auth event notifications
+    pub notifier: Notifier,
+    /// Cache of static assets of the proxied Oxigraph UI
+    pub asset_cache: AssetCache,
+    /// Signer for JWTs handed to downstream services
+    pub jwt: JwtIssuer,
+    /// Per-request log of generated SPARQL, when enabled
+    pub query_log: QueryLog,
+    /// Read-only maintenance switch
+    pub maintenance: Maintenance,
+    /// Whether spans are exported, so trace context is propagated upstream
+    pub trace_export: bool,
+    /// Counters served at `/gate/metrics`
+    pub metrics: Metrics,
+    /// Proxied queries slower than `SLOW_QUERY_MS`
+    pub slow_queries: SlowQueryLog,
+    /// Write-ahead journal of gate-initiated SPARQL updates
+    pub journal: Journal,
+    /// Hooks plugins run on proxied requests and responses
+    pub hooks: ProxyHooks,
+    /// CSP, HSTS and related headers for gate pages
+    pub security_headers: SecurityHeaders,
+}
+
+fn main() {
+    // Logging and span export are configured from the environment, so
+    // `.env` is read and its encrypted values decrypted first. Both write
+    // the process environment, which is only safe before the runtime starts
+    // its worker threads.
+    load_env_file();
+    let decrypted = decrypt_env().unwrap_or_else(|e| panic!("Invalid encrypted configuration: {}", e));
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build the async runtime")
+        .block_on(run(decrypted));
 }
 
-#[tokio::main]
-async fn main() {
+async fn run(decrypted: usize) {
+    let otlp = export_configured()
+        .then(|| otlp_layer().unwrap_or_else(|e| panic!("Invalid OpenTelemetry settings: {}", e)));
     tracing_subscriber::registry()
+        .with(otlp)
         .with(tracing_subscriber::fmt::layer())
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
         )
         .init();
+    if decrypted > 0 {
+        info!(count = decrypted, "Encrypted configuration values decrypted");
+    }
 
     let config = load_config();
-    let client = Client::new();
+    if config.trace_export {
+        info!("Exporting spans over OTLP");
+    }
+    let timeout = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+    let upstream_timeout = timeout(config.upstream_timeout_secs);
+    let upstream_write_timeout = timeout(config.upstream_write_timeout_secs);
+    let mut client_builder = config.upstream_pool.apply(Client::builder());
+    let mut proxy_client_builder = config.upstream_pool.apply(Client::builder());
+    if let Some(connect_timeout) = timeout(config.upstream_connect_timeout_secs) {
+        client_builder = client_builder.connect_timeout(connect_timeout);
+        proxy_client_builder = proxy_client_builder.connect_timeout(connect_timeout);
+    }
+    if let Some(upstream_timeout) = upstream_timeout {
+        client_builder = client_builder.timeout(upstream_timeout);
+    }
+    let client = client_builder.build().expect("Failed to build HTTP client");
+    let proxy_client = proxy_client_builder.build().expect("Failed to build HTTP client");
 
     let files_path = std::fs::canonicalize(&config.files_dir)
         .map(|p| p.display().to_string())
@@ -48,8 +314,17 @@ async fn main() {
     info!("┌──────────────────────────────────────────┐");
     info!("│         Oxigraph Gate Starting           │");
     info!("├──────────────────────────────────────────┤");
-    info!("│ {:<40} │", format!("Listen:   http://{}", BIND_ADDR));
+    info!("│ {:<40} │", format!("Listen:   {}", config.bind_addr));
+    if let Some(tls) = &config.tls {
+        info!("│ {:<40} │", format!("Listen:   https://{}", tls.bind_addr));
+    }
     info!("│ {:<40} │", format!("Upstream: {}", config.oxigraph_url));
+    for replica in config.replicas.urls() {
+        info!("│ {:<40} │", format!("Replica:  {}", replica));
+    }
+    if let Some(shadow) = config.shadow.url() {
+        info!("│ {:<40} │", format!("Shadow:   {}", shadow));
+    }
     info!("│ {:<40} │", format!("Files:    {}", files_path));
     info!("│ {:<40} │", format!("Mode:     {}", secure_mode));
     info!("└──────────────────────────────────────────┘");
@@ -59,45 +334,363 @@ async fn main() {
         warn!("⚠️  Cookies will be sent over HTTP - NOT SAFE FOR PRODUCTION");
     }
 
+    info!(
+        "Session cookie {} (path {}, domain {}, SameSite={})",
+        config.cookie.name,
+        config.cookie.path,
+        config.cookie.domain.as_deref().unwrap_or("host only"),
+        config.cookie.same_site
+    );
+
+    let mut auth_backends: Vec<Box<dyn AuthBackend>> = Vec::new();
+    if let Some(ldap) = config.ldap {
+        info!("LDAP login enabled ({})", ldap.url);
+        auth_backends.push(Box::new(LdapBackend::new(ldap, client.clone(), config.oxigraph_url.clone())));
+    }
+
+    if let Some(github) = &config.github {
+        info!(
+            "GitHub login enabled ({} user(s), {} organization(s))",
+            github.users.len(),
+            github.orgs.len()
+        );
+    }
+
+    if let Some(proxy_auth) = &config.proxy_auth {
+        info!("Reverse-proxy authentication enabled ({} trusted address(es))", proxy_auth.trusted.len());
+    }
+
+    if config.query_log {
+        info!("Generated SPARQL logging enabled");
+        if config.query_log_redaction == Redaction::None {
+            warn!("QUERY_LOG_REDACT=none - logged queries include labels, names and token hashes");
+        }
+    }
+
+    if config.emergency_token.is_some() {
+        warn!("⚠️  EMERGENCY_TOKEN is set - it grants full access regardless of the access graph");
+        warn!("⚠️  Remove it once access is restored");
+    }
+
+    if config.read_only {
+        warn!("READ_ONLY=true - starting in read-only maintenance mode, writes are rejected");
+    }
+
+    if config.metrics_token.is_some() {
+        info!("Metrics scrape token set for /gate/metrics");
+    }
+
+    if let Some(notify) = &config.notify {
+        info!("Auth notifications enabled ({})", notify.events.join(", "));
+    }
+
+    let query_cache = QueryCache::new(
+        Duration::from_secs(config.query_cache_ttl_secs),
+        config.query_cache_max_mb * 1024 * 1024,
+    );
+    if query_cache.is_enabled() {
+        info!("Caching query results for {}s (up to {} MiB)", config.query_cache_ttl_secs, config.query_cache_max_mb);
+    }
+    let asset_cache = AssetCache::new(config.asset_cache_extensions, config.asset_cache_max_age_secs);
+    if asset_cache.is_enabled() {
+        info!("Caching proxied UI assets (client max-age {}s)", config.asset_cache_max_age_secs);
+    }
+    if config.compress_responses {
+        info!("Compressing responses of {} bytes and more", config.compress_min_bytes);
+    }
+
+    if !config.public_graphs.is_empty() {
+        info!("Anonymous queries allowed on {} public graph(s)", config.public_graphs.len());
+    }
+
+    let share_key = match config.share_secret {
+        Some(secret) => secret.into_bytes(),
+        None => {
+            warn!("SHARE_SECRET not set - share links will stop working when the gate restarts");
+            random_secret().into_bytes()
+        }
+    };
+
+    let jwt = match &config.jwt_key_file {
+        Some(path) => {
+            let pem = std::fs::read_to_string(path)
+                .unwrap_or_else(|e| panic!("Failed to read JWT_KEY_FILE {}: {}", path, e));
+            JwtIssuer::from_pem(&pem, config.jwt_issuer, config.jwt_ttl_secs)
+                .unwrap_or_else(|e| panic!("Invalid JWT_KEY_FILE {}: {}", path, e))
+        }
+        None => {
+            warn!("JWT_KEY_FILE not set - issued JWTs will stop verifying when the gate restarts");
+            JwtIssuer::generate(config.jwt_issuer, config.jwt_ttl_secs)
+        }
+    };
+
+    let (journal, pending_updates) = match &config.journal_file {
+        Some(path) => {
+            let (journal, pending) =
+                Journal::open(path).unwrap_or_else(|e| panic!("Invalid JOURNAL_FILE {}: {}", path, e));
+            if !pending.is_empty() {
+                warn!("{} journaled update(s) were not applied - replaying", pending.len());
+            }
+            (journal, pending)
+        }
+        None => {
+            warn!("JOURNAL_FILE is empty - updates lost while Oxigraph is unreachable will not be replayed");
+            (Journal::disabled(), Vec::new())
+        }
+    };
+
+    let plugins = plugins::enabled(config.plugins.as_deref()).unwrap_or_else(|e| panic!("Invalid PLUGINS: {}", e));
+    let hooks = ProxyHooks::new(plugins.iter().flat_map(|plugin| plugin.hooks()).collect());
+    for name in hooks.names() {
+        info!(hook = name, "Proxy hook registered");
+    }
+
     let state = Arc::new(AppState {
         oxigraph_url: config.oxigraph_url,
+        replicas: config.replicas,
+        shadow: config.shadow,
         client,
+        proxy_client,
+        upstream_timeout,
+        upstream_write_timeout,
+        upstreams: config.upstreams,
+        accept_rewrites: config.accept_rewrites,
+        store_pages: config.store_pages,
+        body_limits: config.body_limits,
+        query_limits: config.query_limits,
+        query_page_size: config.query_page_size,
+        rate_limiter: config.rate_limiter,
         secure_cookies: config.secure_cookies,
+        cookie: config.cookie,
+        uploads: UploadStore::new(&config.files_dir),
+        thumbnails: Thumbnails::new(&config.files_dir),
         files_dir: config.files_dir,
+        upload_conflict: config.upload_conflict,
+        trash_retention_days: config.trash_retention_days,
+        storage_quota_mb: config.storage_quota_mb,
+        sessions: SessionStore::new(SESSION_MAX_AGE_SECS, config.session_idle_days * 86_400),
+        auth_backends,
+        emergency_token_hash: config.emergency_token.as_deref().map(hash_token),
+        share: ShareSigner::new(share_key, config.share_max_ttl_secs),
+        public_url: config.public_url,
+        rank_cache: RankCache::new(Duration::from_secs(config.rank_cache_ttl_secs)),
+        path_cache: PathCache::new(Duration::from_secs(config.path_cache_ttl_secs)),
+        query_cache,
+        idempotency_keys: IdempotencyKeys::new(Duration::from_secs(config.idempotency_key_ttl_secs)),
+        result_conversion_max: config.result_conversion_max_mb * 1024 * 1024,
+        query_etag_max: config.query_etag_max_mb * 1024 * 1024,
+        readme_cache: ReadmeCache::new(),
+        upstream_gzip: config.upstream_gzip,
+        restrict_update_graphs: config.restrict_update_graphs,
+        restrict_query_graphs: config.restrict_query_graphs,
+        public_graphs: config.public_graphs,
+        protected_graphs: config.protected_graphs,
+        rules: config.rules,
+        home_page: config.home_page,
+        roles: Roles::default(),
+        github: config.github,
+        proxy_auth: config.proxy_auth,
+        notifier: Notifier::new(config.notify),
+        asset_cache,
+        jwt,
+        query_log: QueryLog::new(config.query_log, config.query_log_redaction),
+        maintenance: Maintenance::new(config.read_only),
+        trace_export: config.trace_export,
+        metrics: Metrics::new(config.metrics_token.as_deref(), config.metrics_permission),
+        slow_queries: SlowQueryLog::new(Duration::from_millis(config.slow_query_ms), config.query_log_redaction),
+        journal,
+        hooks,
+        security_headers: config.security_headers,
     });
 
-    // CORS Configuration for SPARQL endpoint access
-    // - allow_origin(Any): Required for SPARQL clients from any domain
-    // - NOT setting allow_credentials: Cookies won't be sent cross-origin
-    //   (Cross-origin clients must use X-Access-Token or Authorization header)
-    // - This is safe because:
-    //   1. Browser won't send cookies with cross-origin requests by default
+    if !pending_updates.is_empty() {
+        tokio::spawn(replay(Arc::clone(&state), pending_updates));
+    }
+
+    spawn_cleanup(Arc::clone(&state));
+    spawn_trash_cleanup(Arc::clone(&state));
+    spawn_health_checks(Arc::clone(&state), Duration::from_secs(config.replica_check_interval_secs));
+
+    let roles_state = Arc::clone(&state);
+    tokio::spawn(async move { reload_roles(&roles_state).await });
+
+    // CORS for browser apps on other origins (CORS_ALLOWED_*). Credentials
+    // are never allowed cross-origin:
+    //   1. Browsers don't send cookies with cross-origin requests
     //   2. Cross-origin clients authenticate via headers, not cookies
     //   3. Same-origin requests (from the gate's own UI) use cookies normally
-    let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::OPTIONS])
-        .allow_origin(Any)
-        .allow_headers(Any)
-        // Note: We explicitly do NOT call .allow_credentials(true)
-        // This prevents cross-origin requests from sending cookies
-        // Cross-origin SPARQL clients must use X-Access-Token header instead
-        ;
-
-    let app = Router::new()
+    let cors = config.cors.layer();
+
+    let routes = Router::new()
+        .route("/", get(home_handler).fallback(proxy_handler))
         .route("/gate/login", get(login_page))
         .route("/gate/login", post(login_submit))
-        .route("/res", post(res_post_handler))
-        .route("/res/:uuid", get(res_handler).put(res_put_handler))
+        .route("/gate/oauth/github", get(github_login_handler))
+        .route("/gate/oauth/github/callback", get(github_callback_handler))
+        .route("/gate/logout-all", post(logout_all_handler))
+        .route("/gate/preferences", post(preferences_handler))
+        .route("/gate/jwt", post(jwt_handler))
+        .route("/gate/quota", get(quota_handler))
+        .route("/gate/.well-known/jwks.json", get(jwks_handler))
+        .route("/gate/healthz", get(healthz_handler))
+        .route("/gate/readyz", get(readyz_handler))
+        .route("/gate/metrics", get(metrics_handler))
+        .route("/gate/admin", get(admin_page))
+        .route("/gate/admin/login-failures", get(login_failures_handler))
+        .route("/gate/admin/sessions", get(sessions_handler))
+        .route("/gate/admin/sessions/:handle", delete(kill_session_handler))
+        .route("/gate/admin/sessions/:handle/revoke", post(revoke_session_handler))
+        .route("/gate/admin/tokens/:uuid/revoke", post(revoke_token_handler))
+        .route("/gate/admin/policies/:uuid/rank", post(policy_rank_handler))
+        .route("/gate/admin/users/:uuid/rank", post(user_rank_handler))
+        .route("/gate/admin/users/:uuid/revoke", post(revoke_user_handler))
+        .route("/gate/admin/read-only", get(read_only_status_handler).post(read_only_handler))
+        .route("/gate/admin/upgrade", get(upgrade_status_handler).post(upgrade_handler))
+        .route("/gate/admin/links", get(links_admin_page))
+        .route("/gate/admin/links/:uuid/revoke", post(link_revoke_handler))
+        .route("/gate/admin/queries", get(queries_admin_page))
+        .route("/gate/admin/slow-queries", get(slow_queries_admin_page))
+        .route("/gate/links", post(link_create_handler))
+        .route("/s/:code", get(link_handler))
+        .route("/gate/queries", get(queries_list_handler).post(query_create_handler))
+        .route("/gate/queries/:uuid/revoke", post(query_revoke_handler))
+        .route("/gate/templates", get(templates_list_handler))
+        .route("/gate/templates/:name", put(template_put_handler).delete(template_delete_handler))
+        .route("/q/:capability", get(query_run_handler))
+        .route("/gate/invite", post(invite_create_handler))
+        .route("/gate/invite/:code", get(invite_page_handler).post(invite_redeem_handler))
+        .route("/file", get(file_handler))
+        .route("/file/", get(file_handler))
+        .route(
+            "/file/*path",
+            get(file_handler)
+                .post(mkdir_handler)
+                .delete(delete_handler)
+                .route_layer(middleware::from_fn_with_state(Arc::clone(&state), read_only_middleware)),
+        )
+        .route("/trash", get(trash_list_handler))
+        .route(
+            "/trash/:uuid",
+            delete(trash_purge_handler)
+                .route_layer(middleware::from_fn_with_state(Arc::clone(&state), read_only_middleware)),
+        )
+        .route(
+            "/trash/:uuid/restore",
+            post(trash_restore_handler)
+                .route_layer(middleware::from_fn_with_state(Arc::clone(&state), read_only_middleware)),
+        )
+        .route("/collections", get(collections_handler))
+        .route("/collections/:uuid", get(collection_handler))
+        .route("/upload", get(upload_page))
+        .route("/diff", get(diff_page))
+        .route("/api/diff", get(diff_handler))
+        .route(
+            "/load",
+            post(load_handler).route_layer(middleware::from_fn_with_state(Arc::clone(&state), read_only_middleware)),
+        )
+        .route(
+            "/res",
+            post(res_post_handler)
+                .layer(DefaultBodyLimit::max(state.body_limits.upload))
+                .route_layer(middleware::from_fn_with_state(
+                    (Arc::clone(&state), Resource::Action(UPLOAD_ACTION_IRI)),
+                    require_access,
+                ))
+                .route_layer(middleware::from_fn_with_state(Arc::clone(&state), read_only_middleware)),
+        )
+        .route("/res/manifest", post(manifest_handler))
+        .route(
+            "/res/uploads",
+            post(upload_create_handler)
+                .route_layer(middleware::from_fn_with_state(Arc::clone(&state), read_only_middleware)),
+        )
+        .route(
+            "/res/uploads/:id",
+            get(upload_status_handler)
+                .patch(upload_append_handler)
+                .delete(upload_cancel_handler)
+                .route_layer(middleware::from_fn_with_state(Arc::clone(&state), read_only_middleware)),
+        )
+        .route(
+            "/res/:uuid",
+            get(res_handler)
+                .put(res_put_handler)
+                .patch(res_patch_handler)
+                .layer(DefaultBodyLimit::max(state.body_limits.upload))
+                .route_layer(middleware::from_fn_with_state((Arc::clone(&state), Resource::File), require_access))
+                .route_layer(middleware::from_fn_with_state(Arc::clone(&state), read_only_middleware)),
+        )
+        .route(
+            "/res/:uuid/thumb",
+            get(thumb_handler)
+                .route_layer(middleware::from_fn_with_state((Arc::clone(&state), Resource::File), require_access)),
+        )
+        .route(
+            "/res/:uuid/verify",
+            get(verify_handler)
+                .route_layer(middleware::from_fn_with_state((Arc::clone(&state), Resource::File), require_access)),
+        )
+        .route(
+            "/res/:uuid/versions",
+            get(versions_handler)
+                .route_layer(middleware::from_fn_with_state((Arc::clone(&state), Resource::File), require_access)),
+        )
+        .route(
+            "/res/:uuid/versions/:version",
+            get(version_handler)
+                .route_layer(middleware::from_fn_with_state((Arc::clone(&state), Resource::File), require_access)),
+        )
+        .route(
+            "/res/:uuid/versions/:version/restore",
+            post(restore_handler)
+                .route_layer(middleware::from_fn_with_state((Arc::clone(&state), Resource::File), require_access))
+                .route_layer(middleware::from_fn_with_state(Arc::clone(&state), read_only_middleware)),
+        )
+        .route("/res/:uuid/share", post(share_create_handler))
+        .route("/share/:uuid", get(share_handler));
+
+    let app = plugins::mount(routes, &plugins, &state)
         .fallback(proxy_handler)
+        .layer(middleware::from_fn_with_state(Arc::clone(&state), upgrade_middleware))
+        .layer(middleware::from_fn_with_state(Arc::clone(&state), emergency_token_middleware))
+        .layer(middleware::from_fn_with_state(Arc::clone(&state), query_log_middleware))
+        .layer(middleware::from_fn_with_state(Arc::clone(&state), strip_untrusted_user_headers))
+        .layer(middleware::from_fn_with_state(Arc::clone(&state), metrics_middleware))
+        .layer(middleware::from_fn_with_state(Arc::clone(&state), trace_middleware))
+        .layer(middleware::from_fn_with_state(Arc::clone(&state), security_headers_middleware))
+        .layer(compression_layer(config.compress_responses, config.compress_min_bytes))
         .layer(cors)
         .with_state(state);
 
-    let listener = tokio::net::TcpListener::bind(BIND_ADDR).await.unwrap();
+    // With HTTPS on, plain HTTP only redirects to it
+    let Some(tls) = &config.tls else {
+        serve_plain(&config.bind_addr, config.unix_socket_mode, app).await;
+        return;
+    };
+    let tls_config = tls.server_config().unwrap_or_else(|e| panic!("Invalid TLS settings: {}", e));
+    let tls_listener = tokio::net::TcpListener::bind(&tls.bind_addr).await.unwrap();
+    tokio::join!(
+        serve_plain(&config.bind_addr, config.unix_socket_mode, redirect_app(tls)),
+        serve_tls(tls_listener, tls_config, app),
+    );
+}
 
-    axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .await
-    .unwrap();
+/// Serve plain HTTP on a TCP address or a Unix socket
+async fn serve_plain(bind_addr: &BindAddr, unix_socket_mode: Option<u32>, app: Router) {
+    match bind_addr {
+        BindAddr::Tcp(addr) => {
+            let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap();
+        }
+        BindAddr::Unix(path) => {
+            let listener = bind_unix(path, unix_socket_mode)
+                .unwrap_or_else(|e| panic!("Failed to bind {}: {}", path.display(), e));
+            serve_unix(listener, app).await;
+        }
+    }
 }
@@ -0,0 +1,292 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Redirect, Response},
+    Form, Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+use tracing::{info, warn};
+
+use crate::accept::prefers_html;
+use crate::admin::{forbidden, is_admin, ADMIN_PATH};
+use crate::files::{credential, Credential};
+use crate::templates::{escape_html, render_page};
+use crate::AppState;
+
+/// Seconds clients are asked to wait before retrying a rejected request
+const RETRY_AFTER_SECS: u32 = 60;
+
+/// Paths still served during an upgrade, so admins can sign in and end it
+/// and health checks and metrics keep reporting
+const UPGRADE_EXEMPT_PATHS: &[&str] = &[
+    "/gate/admin",
+    "/gate/login",
+    "/gate/logout",
+    "/gate/oauth",
+    "/gate/healthz",
+    "/gate/readyz",
+    "/gate/metrics",
+];
+
+/// Read-only maintenance mode, for backups and store compactions. While
+/// on, writes to Oxigraph and uploads are rejected with `503`; queries and
+/// downloads are served as usual. Starts as `READ_ONLY` and is switched at
+/// `/gate/admin/read-only`; it is not persisted across restarts.
+///
+/// Upgrade mode, for upgrades of Oxigraph or the data behind it, takes
+/// everything offline instead: browsers get a "we are upgrading" page and
+/// API clients `503`. It is switched at `/gate/admin/upgrade` and always
+/// starts off.
+pub struct Maintenance {
+    read_only: AtomicBool,
+    upgrading: AtomicBool,
+    /// Admin's note shown on the upgrade page, e.g. when service resumes
+    notice: RwLock<String>,
+}
+
+impl Maintenance {
+    pub fn new(read_only: bool) -> Self {
+        Self {
+            read_only: AtomicBool::new(read_only),
+            upgrading: AtomicBool::new(false),
+            notice: RwLock::new(String::new()),
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Switch read-only mode; returns whether it was on before
+    fn set_read_only(&self, read_only: bool) -> bool {
+        self.read_only.swap(read_only, Ordering::Relaxed)
+    }
+
+    pub fn is_upgrading(&self) -> bool {
+        self.upgrading.load(Ordering::Relaxed)
+    }
+
+    pub fn notice(&self) -> String {
+        self.notice.read().unwrap().clone()
+    }
+
+    /// Switch upgrade mode and set its notice; returns whether it was on
+    /// before
+    fn set_upgrading(&self, upgrading: bool, notice: &str) -> bool {
+        *self.notice.write().unwrap() = notice.trim().to_string();
+        self.upgrading.swap(upgrading, Ordering::Relaxed)
+    }
+}
+
+/// `503` for a write made during read-only maintenance
+pub fn read_only() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, RETRY_AFTER_SECS.to_string())],
+        "Read-only maintenance in progress - writes are disabled, try again later",
+    )
+        .into_response()
+}
+
+/// Whether a request method only reads
+pub fn is_read_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Route middleware rejecting every non-read method during maintenance,
+/// before the request body is read
+pub async fn read_only_middleware(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if state.maintenance.is_read_only() && !is_read_method(req.method()) {
+        warn!(client = %addr, method = %req.method(), path = %req.uri().path(), "Write rejected - read-only maintenance");
+        return read_only();
+    }
+    next.run(req).await
+}
+
+/// `503` for a request made during an upgrade: the upgrade page for
+/// browsers, plain text for everyone else
+pub fn upgrading(maintenance: &Maintenance, accept: Option<&str>) -> Response {
+    let notice = maintenance.notice();
+    let retry_after = [(header::RETRY_AFTER, RETRY_AFTER_SECS.to_string()), (header::CACHE_CONTROL, "no-store".to_string())];
+    if prefers_html(accept) {
+        let mut body = "<p>We are upgrading the service and will be back shortly.</p>".to_string();
+        if !notice.is_empty() {
+            body.push_str(&format!("\n        <p>{}</p>", escape_html(&notice)));
+        }
+        body.push_str("\n        <a class=\"button\" href=\"\">Try again</a>");
+        return (StatusCode::SERVICE_UNAVAILABLE, retry_after, Html(render_page("Upgrading", &body))).into_response();
+    }
+    let mut message = "Upgrade in progress - the service is unavailable, try again later".to_string();
+    if !notice.is_empty() {
+        message.push_str(&format!(" ({})", notice));
+    }
+    (StatusCode::SERVICE_UNAVAILABLE, retry_after, message).into_response()
+}
+
+/// Whether `path` is served during an upgrade
+fn is_upgrade_exempt(path: &str) -> bool {
+    UPGRADE_EXEMPT_PATHS.iter().any(|exempt| {
+        path.strip_prefix(exempt)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || rest.starts_with('-'))
+    })
+}
+
+/// Middleware answering every request with [`upgrading`] during an
+/// upgrade, except on the exempt paths and for the emergency token
+pub async fn upgrade_middleware(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    req: Request,
+    next: Next,
+) -> Response {
+    if !state.maintenance.is_upgrading()
+        || is_upgrade_exempt(req.uri().path())
+        || matches!(credential(&state, req.headers(), &jar), Credential::Emergency)
+    {
+        return next.run(req).await;
+    }
+    let accept = req.headers().get(header::ACCEPT).and_then(|v| v.to_str().ok());
+    upgrading(&state.maintenance, accept)
+}
+
+/// Maintenance status and switches for the admin page
+pub fn render_maintenance(state: &AppState) -> String {
+    let (status, enabled, label) = if state.maintenance.is_read_only() {
+        ("Read-only mode is on: writes and uploads are rejected.", false, "Leave read-only mode")
+    } else {
+        ("Writes are accepted.", true, "Enter read-only mode")
+    };
+    let upgrade = if state.maintenance.is_upgrading() {
+        format!(
+            r#"<p class="summary">Upgrade mode is on: everything but administration is offline.</p>
+    <form method="POST" action="{}/upgrade"><input type="hidden" name="enabled" value="false"><button type="submit">Leave upgrade mode</button></form>"#,
+            ADMIN_PATH
+        )
+    } else {
+        format!(
+            r#"<form method="POST" action="{}/upgrade"><input type="hidden" name="enabled" value="true"><input type="text" name="notice" placeholder="Notice, e.g. back by 14:00"> <button type="submit">Enter upgrade mode</button></form>"#,
+            ADMIN_PATH
+        )
+    };
+    format!(
+        r#"<p class="summary">{}</p>
+    <form method="POST" action="{}/read-only"><input type="hidden" name="enabled" value="{}"><button type="submit">{}</button></form>
+    {}"#,
+        status, ADMIN_PATH, enabled, label, upgrade
+    )
+}
+
+#[derive(Deserialize)]
+pub struct ReadOnlyForm {
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+pub struct UpgradeForm {
+    enabled: bool,
+    #[serde(default)]
+    notice: String,
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// GET /gate/admin/read-only - Whether read-only maintenance is on (admin only)
+pub async fn read_only_status_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    if !is_admin(&state, &headers, &jar).await {
+        warn!(client = %addr, "Access denied - administration requires admin rank");
+        return forbidden();
+    }
+    Json(json!({ "read_only": state.maintenance.is_read_only() })).into_response()
+}
+
+/// POST /gate/admin/read-only - Switch read-only maintenance (form field
+/// `enabled`, admin only)
+pub async fn read_only_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Form(form): Form<ReadOnlyForm>,
+) -> Response {
+    if !is_admin(&state, &headers, &jar).await {
+        warn!(client = %addr, "Access denied - administration requires admin rank");
+        return forbidden();
+    }
+
+    let was_read_only = state.maintenance.set_read_only(form.enabled);
+    if was_read_only != form.enabled {
+        if form.enabled {
+            warn!(client = %addr, "Read-only maintenance started - writes are rejected");
+        } else {
+            info!(client = %addr, "Read-only maintenance ended - writes are accepted");
+        }
+    }
+    Redirect::to(ADMIN_PATH).into_response()
+}
+
+/// GET /gate/admin/upgrade - Whether upgrade mode is on, with its notice
+/// (admin only)
+pub async fn upgrade_status_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    if !is_admin(&state, &headers, &jar).await {
+        warn!(client = %addr, "Access denied - administration requires admin rank");
+        return forbidden();
+    }
+    Json(json!({
+        "upgrading": state.maintenance.is_upgrading(),
+        "notice": state.maintenance.notice(),
+    }))
+    .into_response()
+}
+
+/// POST /gate/admin/upgrade - Switch upgrade mode (form fields `enabled`
+/// and an optional `notice`, admin only)
+pub async fn upgrade_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Form(form): Form<UpgradeForm>,
+) -> Response {
+    if !is_admin(&state, &headers, &jar).await {
+        warn!(client = %addr, "Access denied - administration requires admin rank");
+        return forbidden();
+    }
+
+    let notice = if form.enabled { form.notice.as_str() } else { "" };
+    let was_upgrading = state.maintenance.set_upgrading(form.enabled, notice);
+    if was_upgrading != form.enabled {
+        if form.enabled {
+            warn!(client = %addr, notice = %notice.trim(), "Upgrade mode started - requests are answered with the upgrade page");
+        } else {
+            info!(client = %addr, "Upgrade mode ended - requests are served");
+        }
+    }
+    Redirect::to(ADMIN_PATH).into_response()
+}
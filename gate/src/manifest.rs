@@ -0,0 +1,188 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{TimeZone, Utc};
+use percent_encoding::percent_decode_str;
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::collections::{load_members, Member};
+use crate::files::{get_access_rank, validate_uuid};
+use crate::AppState;
+
+/// Default lifetime of manifest download links in seconds (1 hour)
+pub const DEFAULT_MANIFEST_TTL_SECS: i64 = 3_600;
+/// Upper bound for files in one manifest
+const MAX_MANIFEST_FILES: usize = 1_000;
+
+#[derive(PartialEq)]
+enum Format {
+    /// JSON with names, sizes and links
+    Json,
+    /// One URL per line, for `wget -i` or `aria2c -i`
+    Urls,
+}
+
+/// A manifest request: form fields `file` (repeated), `ttl` and `format`
+struct ManifestRequest {
+    files: Vec<Uuid>,
+    ttl: Option<i64>,
+    format: Format,
+}
+
+impl ManifestRequest {
+    /// Parse a form-encoded body. The standard form extractor cannot
+    /// collect repeated fields, so the body is split here.
+    fn parse(body: &str) -> Result<Self, String> {
+        let mut request = ManifestRequest {
+            files: Vec::new(),
+            ttl: None,
+            format: Format::Json,
+        };
+        for pair in body.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode_str(&value.replace('+', " ")).decode_utf8_lossy().into_owned();
+            match key {
+                "file" => {
+                    let uuid = validate_uuid(&value).ok_or_else(|| format!("invalid file UUID {}", value))?;
+                    if !request.files.contains(&uuid) {
+                        request.files.push(uuid);
+                    }
+                }
+                "ttl" => request.ttl = Some(value.parse().map_err(|_| "ttl must be a number".to_string())?),
+                "format" => {
+                    request.format = match value.as_str() {
+                        "json" => Format::Json,
+                        "urls" => Format::Urls,
+                        _ => return Err("format must be json or urls".to_string()),
+                    }
+                }
+                _ => {}
+            }
+        }
+        if request.files.is_empty() {
+            return Err("select at least one file".to_string());
+        }
+        if request.files.len() > MAX_MANIFEST_FILES {
+            return Err(format!("at most {} files per manifest", MAX_MANIFEST_FILES));
+        }
+        Ok(request)
+    }
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// POST /res/manifest - Signed, expiring download links for several files.
+///
+/// Each link is a share link, so download managers can fetch the files in
+/// parallel without the requester's cookie or token. Every file must be
+/// shareable by the requester; otherwise nothing is issued.
+pub async fn manifest_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    uri: Uri,
+    body: String,
+) -> Response {
+    let request = match ManifestRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let ttl = request.ttl.unwrap_or(DEFAULT_MANIFEST_TTL_SECS.min(state.share.max_ttl_secs));
+    if ttl <= 0 || ttl > state.share.max_ttl_secs {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("ttl must be between 1 and {} seconds", state.share.max_ttl_secs),
+        )
+            .into_response();
+    }
+
+    if let Err(status) = state.rules.required_rank(&state.roles, &Method::POST, uri.path()) {
+        return (status, "Access denied").into_response();
+    }
+
+    // A manifest hands out the same access as a share link per file
+    for uuid in &request.files {
+        let share_path = format!("/res/{}/share", uuid);
+        let required = match state.rules.required_rank(&state.roles, &Method::POST, &share_path) {
+            Ok(required) => required,
+            Err(status) => return (status, "Access denied").into_response(),
+        };
+        let rank = get_access_rank(&state, uuid, &headers, &jar).await;
+        if rank < required {
+            warn!(client = %addr, uuid = %uuid, rank = rank, required = required, "Access denied - insufficient rank for manifest");
+            return (StatusCode::FORBIDDEN, format!("Access denied to {}", uuid)).into_response();
+        }
+    }
+
+    let members = match load_members(&state.client, &state.oxigraph_url, &request.files).await {
+        Ok(members) => members,
+        Err(e) => {
+            warn!(client = %addr, error = %e, "SPARQL lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup files").into_response();
+        }
+    };
+    if let Some(missing) = request.files.iter().find(|uuid| !members.iter().any(|m| m.uuid == **uuid)) {
+        return (StatusCode::NOT_FOUND, format!("File not found: {}", missing)).into_response();
+    }
+
+    let exp = Utc::now().timestamp() + ttl;
+    // Links are only made absolute with the configured base URL: the
+    // `Host` header is chosen by the client
+    let base = state.public_url.as_deref().unwrap_or("");
+    let links: Vec<(String, &Member)> = members
+        .iter()
+        .map(|m| (format!("/share/{}?sig={}&exp={}", m.uuid, state.share.sign(&m.uuid, exp), exp), m))
+        .collect();
+
+    info!(client = %addr, files = links.len(), exp = exp, "Download manifest created");
+
+    if request.format == Format::Urls {
+        let body: String = links.iter().map(|(path, _)| format!("{}{}\n", base, path)).collect();
+        return (
+            StatusCode::CREATED,
+            [
+                (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+                (header::CONTENT_DISPOSITION, "attachment; filename=\"manifest.txt\""),
+                (header::CACHE_CONTROL, "no-store"),
+            ],
+            body,
+        )
+            .into_response();
+    }
+
+    let json_response = json!({
+        "expires": Utc.timestamp_opt(exp, 0).single().map(|t| t.to_rfc3339()),
+        "files": links
+            .iter()
+            .map(|(path, m)| json!({
+                "uuid": m.uuid.to_string(),
+                "name": m.label,
+                "size": m.size,
+                "sha256": m.sha256,
+                "path": path,
+                "url": format!("{}{}", base, path),
+            }))
+            .collect::<Vec<_>>(),
+    });
+
+    (
+        StatusCode::CREATED,
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"manifest.json\""),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        json_response.to_string(),
+    )
+        .into_response()
+}
@@ -0,0 +1,218 @@
+//! Minimal Markdown to HTML conversion for directory READMEs.
+//!
+//! Only a common subset is supported: headings, paragraphs, lists, block
+//! quotes, fenced code, rules, emphasis, inline code, links and images.
+//! Output is safe by construction: raw HTML in the source is escaped like
+//! any other text, and link targets are limited to relative URLs and
+//! `http`, `https` and `mailto`.
+
+use crate::templates::escape_html;
+
+/// URL schemes links and images may use; anything else is rendered as text
+const ALLOWED_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+enum Block {
+    Paragraph(Vec<String>),
+    List { ordered: bool, items: Vec<String> },
+    Quote(Vec<String>),
+}
+
+/// Convert Markdown to sanitized HTML
+pub fn render(markdown: &str) -> String {
+    let mut out = String::new();
+    let mut block: Option<Block> = None;
+    let mut lines = markdown.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+
+        if let Some(fence) = ["```", "~~~"].into_iter().find(|f| trimmed.starts_with(f)) {
+            flush(&mut out, block.take());
+            let code: Vec<&str> = lines.by_ref().take_while(|l| !l.trim_start().starts_with(fence)).collect();
+            out.push_str(&format!("<pre><code>{}</code></pre>\n", escape_html(&code.join("\n"))));
+            continue;
+        }
+        if trimmed.is_empty() {
+            flush(&mut out, block.take());
+            continue;
+        }
+        if let Some((level, text)) = heading(trimmed) {
+            flush(&mut out, block.take());
+            out.push_str(&format!("<h{level}>{}</h{level}>\n", inline(text), level = level));
+            continue;
+        }
+        if is_rule(trimmed) {
+            flush(&mut out, block.take());
+            out.push_str("<hr>\n");
+            continue;
+        }
+        if let Some(text) = trimmed.strip_prefix('>') {
+            match &mut block {
+                Some(Block::Quote(lines)) => lines.push(text.trim().to_string()),
+                _ => {
+                    flush(&mut out, block.take());
+                    block = Some(Block::Quote(vec![text.trim().to_string()]));
+                }
+            }
+            continue;
+        }
+        if let Some((ordered, text)) = list_item(trimmed) {
+            match &mut block {
+                Some(Block::List { ordered: o, items }) if *o == ordered => items.push(text.to_string()),
+                _ => {
+                    flush(&mut out, block.take());
+                    block = Some(Block::List { ordered, items: vec![text.to_string()] });
+                }
+            }
+            continue;
+        }
+
+        match &mut block {
+            Some(Block::Paragraph(lines)) | Some(Block::Quote(lines)) => lines.push(trimmed.to_string()),
+            // A non-marker line continues the last list item
+            Some(Block::List { items, .. }) => {
+                if let Some(last) = items.last_mut() {
+                    last.push(' ');
+                    last.push_str(trimmed);
+                }
+            }
+            None => block = Some(Block::Paragraph(vec![trimmed.to_string()])),
+        }
+    }
+    flush(&mut out, block);
+    out
+}
+
+fn flush(out: &mut String, block: Option<Block>) {
+    match block {
+        Some(Block::Paragraph(lines)) => out.push_str(&format!("<p>{}</p>\n", inline(&lines.join(" ")))),
+        Some(Block::Quote(lines)) => {
+            out.push_str(&format!("<blockquote><p>{}</p></blockquote>\n", inline(&lines.join(" "))))
+        }
+        Some(Block::List { ordered, items }) => {
+            let tag = if ordered { "ol" } else { "ul" };
+            out.push_str(&format!("<{}>\n", tag));
+            for item in items {
+                out.push_str(&format!("<li>{}</li>\n", inline(&item)));
+            }
+            out.push_str(&format!("</{}>\n", tag));
+        }
+        None => {}
+    }
+}
+
+/// `# Title` to `###### Title`
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim_end()))
+}
+
+/// `---`, `***` or `___`, optionally spaced
+fn is_rule(line: &str) -> bool {
+    let chars: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3 && ['-', '*', '_'].iter().any(|m| chars.iter().all(|c| c == m))
+}
+
+/// `- item`, `* item`, `+ item` or `1. item`; returns whether it is ordered
+fn list_item(line: &str) -> Option<(bool, &str)> {
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(text) = line.strip_prefix(marker) {
+            return Some((false, text.trim()));
+        }
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if (1..=9).contains(&digits) {
+        if let Some(text) = line[digits..].strip_prefix(". ") {
+            return Some((true, text.trim()));
+        }
+    }
+    None
+}
+
+/// Render inline markup; all other text is escaped
+fn inline(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    // `_` inside a word (snake_case names) is not emphasis
+    let mut after_word = false;
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '`' => {
+                if let Some(end) = rest[1..].find('`') {
+                    out.push_str(&format!("<code>{}</code>", escape_html(&rest[1..1 + end])));
+                    rest = &rest[end + 2..];
+                    after_word = false;
+                    continue;
+                }
+            }
+            '*' | '_' if !(c == '_' && after_word) => {
+                let delimiter = if rest[1..].starts_with(c) { &rest[..2] } else { &rest[..1] };
+                let inner = &rest[delimiter.len()..];
+                if let Some(end) = inner.find(delimiter).filter(|end| *end > 0) {
+                    let tag = if delimiter.len() == 2 { "strong" } else { "em" };
+                    out.push_str(&format!("<{tag}>{}</{tag}>", inline(&inner[..end]), tag = tag));
+                    rest = &inner[end + delimiter.len()..];
+                    after_word = false;
+                    continue;
+                }
+            }
+            '!' | '[' => {
+                let image = c == '!';
+                let start = if image { 1 } else { 0 };
+                if let Some((label, url, len)) = link(&rest[start..]) {
+                    if image {
+                        out.push_str(&format!("<img src=\"{}\" alt=\"{}\">", escape_html(url), escape_html(label)));
+                    } else {
+                        out.push_str(&format!(
+                            "<a href=\"{}\" rel=\"nofollow noopener\">{}</a>",
+                            escape_html(url),
+                            inline(label)
+                        ));
+                    }
+                    rest = &rest[start + len..];
+                    after_word = false;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        out.push_str(&escape_html(&rest[..c.len_utf8()]));
+        rest = &rest[c.len_utf8()..];
+        after_word = c.is_alphanumeric();
+    }
+    out
+}
+
+/// Parse `[label](url)` at the start of `text`: label, URL and the length
+/// consumed. Links with a disallowed target are not parsed.
+fn link(text: &str) -> Option<(&str, &str, usize)> {
+    let rest = text.strip_prefix('[')?;
+    let label_end = rest.find("](")?;
+    let target = &rest[label_end + 2..];
+    let url_end = target.find(')')?;
+    let url = target[..url_end].trim();
+    safe_url(url).then_some((&rest[..label_end], url, 1 + label_end + 2 + url_end + 1))
+}
+
+/// Relative URLs and allowed schemes only
+fn safe_url(url: &str) -> bool {
+    if url.is_empty() || url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return false;
+    }
+    // A scheme is whatever precedes the first ':' before any path, query or fragment
+    let scheme_end = url.find([':', '/', '?', '#']);
+    match scheme_end {
+        Some(i) if url[i..].starts_with(':') => {
+            ALLOWED_SCHEMES.iter().any(|s| s.eq_ignore_ascii_case(&url[..i]))
+        }
+        _ => true,
+    }
+}
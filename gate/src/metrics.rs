@@ -0,0 +1,327 @@
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+use tracing::warn;
+
+use crate::auth::extract_token_from_header;
+use crate::files::{get_access_rank_iri, hash_token};
+use crate::proxy::GRAPH_IRI;
+use crate::AppState;
+
+/// Default permission whose rank is required to read `/gate/metrics`
+pub const DEFAULT_METRICS_PERMISSION: &str = "admin";
+
+/// Request latency buckets in seconds
+const DURATION_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+/// Uploaded file size buckets in bytes, from 1 KiB to 16 GiB
+const UPLOAD_BUCKETS: &[f64] = &[
+    1024.0,
+    16384.0,
+    262144.0,
+    4194304.0,
+    67108864.0,
+    1073741824.0,
+    17179869184.0,
+];
+
+/// Route label of requests no route matched, i.e. proxied to Oxigraph
+const PROXY_ROUTE: &str = "proxy";
+
+/// Why a proxied request got no usable answer from upstream
+#[derive(Clone, Copy)]
+pub enum UpstreamError {
+    /// No response head within the upstream timeout
+    Timeout,
+    /// The upstream could not be reached
+    Connect,
+    /// The request failed after connecting, or the response body broke off
+    Other,
+    /// The upstream answered with a 5xx status
+    ServerError,
+}
+
+impl UpstreamError {
+    const ALL: [UpstreamError; 4] = [
+        UpstreamError::Timeout,
+        UpstreamError::Connect,
+        UpstreamError::Other,
+        UpstreamError::ServerError,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            UpstreamError::Timeout => "timeout",
+            UpstreamError::Connect => "connect",
+            UpstreamError::Other => "other",
+            UpstreamError::ServerError => "5xx",
+        }
+    }
+}
+
+struct Histogram {
+    buckets: &'static [f64],
+    /// Observations per bucket, not cumulative; the last counts `+Inf`
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+impl Histogram {
+    fn new(buckets: &'static [f64]) -> Self {
+        Self {
+            buckets,
+            counts: vec![0; buckets.len() + 1],
+            sum: 0.0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        let bucket = self.buckets.iter().position(|&le| value <= le).unwrap_or(self.buckets.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+    }
+
+    /// Append the `_bucket`, `_sum` and `_count` series, with `labels`
+    /// (already formatted, possibly empty) on each
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            let le = self.buckets.get(i).map_or("+Inf".to_string(), |le| le.to_string());
+            let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, sep, le, cumulative);
+        }
+        let braced = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+        let _ = writeln!(out, "{}_sum{} {}", name, braced, self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, braced, cumulative);
+    }
+}
+
+/// Counters behind `/gate/metrics`, in the Prometheus text format. Routes
+/// are labelled by their pattern (`/res/:uuid`), not the requested path, so
+/// the number of series stays bounded.
+pub struct Metrics {
+    /// SHA-256 hash of `METRICS_TOKEN`, when set
+    token_hash: Option<String>,
+    /// Permission IRI whose rank grants access without the token
+    permission: String,
+    /// Responses by (method, route, status)
+    requests: Mutex<HashMap<(&'static str, String, u16), u64>>,
+    /// Time to the response head, by route
+    durations: Mutex<HashMap<String, Histogram>>,
+    upstream_errors: [AtomicU64; UpstreamError::ALL.len()],
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    uploads: Mutex<Histogram>,
+}
+
+impl Metrics {
+    pub fn new(token: Option<&str>, permission: String) -> Self {
+        Self {
+            token_hash: token.map(hash_token),
+            permission,
+            requests: Mutex::new(HashMap::new()),
+            durations: Mutex::new(HashMap::new()),
+            upstream_errors: Default::default(),
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            uploads: Mutex::new(Histogram::new(UPLOAD_BUCKETS)),
+        }
+    }
+
+    fn record_request(&self, method: &Method, route: &str, status: StatusCode, elapsed: f64) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((method_label(method), route.to_string(), status.as_u16()))
+            .or_insert(0) += 1;
+        self.durations
+            .lock()
+            .unwrap()
+            .entry(route.to_string())
+            .or_insert_with(|| Histogram::new(DURATION_BUCKETS))
+            .observe(elapsed);
+    }
+
+    pub fn upstream_error(&self, kind: UpstreamError) {
+        self.upstream_errors[kind as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count request body bytes sent to an upstream
+    pub fn sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Count response body bytes received from an upstream
+    pub fn received(&self, bytes: usize) {
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Record a stored and indexed upload
+    pub fn upload(&self, bytes: u64) {
+        self.uploads.lock().unwrap().observe(bytes as f64);
+    }
+
+    fn render(&self, sessions: &BTreeMap<&'static str, usize>) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP gate_requests_total Responses by method, route and status.\n");
+        out.push_str("# TYPE gate_requests_total counter\n");
+        let mut requests: Vec<_> = self.requests.lock().unwrap().iter().map(|(k, v)| (k.clone(), *v)).collect();
+        requests.sort();
+        for ((method, route, status), count) in requests {
+            let _ = writeln!(
+                out,
+                "gate_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                method,
+                escape_label(&route),
+                status,
+                count
+            );
+        }
+
+        out.push_str("# HELP gate_request_duration_seconds Time until the response head is sent, by route.\n");
+        out.push_str("# TYPE gate_request_duration_seconds histogram\n");
+        let durations = self.durations.lock().unwrap();
+        let mut routes: Vec<&String> = durations.keys().collect();
+        routes.sort();
+        for route in routes {
+            durations[route].render(&mut out, "gate_request_duration_seconds", &format!("route=\"{}\"", escape_label(route)));
+        }
+        drop(durations);
+
+        out.push_str("# HELP gate_upstream_errors_total Proxied requests without a usable upstream answer, by kind.\n");
+        out.push_str("# TYPE gate_upstream_errors_total counter\n");
+        for kind in UpstreamError::ALL {
+            let count = self.upstream_errors[kind as usize].load(Ordering::Relaxed);
+            let _ = writeln!(out, "gate_upstream_errors_total{{kind=\"{}\"}} {}", kind.label(), count);
+        }
+
+        out.push_str("# HELP gate_upstream_bytes_total Body bytes proxied to and from upstreams.\n");
+        out.push_str("# TYPE gate_upstream_bytes_total counter\n");
+        let _ = writeln!(out, "gate_upstream_bytes_total{{direction=\"sent\"}} {}", self.bytes_sent.load(Ordering::Relaxed));
+        let _ = writeln!(
+            out,
+            "gate_upstream_bytes_total{{direction=\"received\"}} {}",
+            self.bytes_received.load(Ordering::Relaxed)
+        );
+
+        out.push_str("# HELP gate_active_sessions Live server-side login sessions, by backend.\n");
+        out.push_str("# TYPE gate_active_sessions gauge\n");
+        for (backend, count) in sessions {
+            let _ = writeln!(out, "gate_active_sessions{{backend=\"{}\"}} {}", escape_label(backend), count);
+        }
+
+        out.push_str("# HELP gate_upload_size_bytes Sizes of uploaded files.\n");
+        out.push_str("# TYPE gate_upload_size_bytes histogram\n");
+        self.uploads.lock().unwrap().render(&mut out, "gate_upload_size_bytes", "");
+
+        out
+    }
+}
+
+/// Standard methods by name; anything else is `other`, so clients cannot
+/// create series at will
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::PATCH => "PATCH",
+        Method::DELETE => "DELETE",
+        Method::OPTIONS => "OPTIONS",
+        _ => "other",
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// The pattern of the route that handled `req`, or `proxy`
+pub fn route_label(req: &Request) -> String {
+    req.extensions()
+        .get::<MatchedPath>()
+        .map_or(PROXY_ROUTE.to_string(), |path| path.as_str().to_string())
+}
+
+/// Count the bytes of a body stream as it passes, with `count`
+pub fn counted<S, E>(stream: S, state: &Arc<AppState>, count: fn(&Metrics, usize)) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    let state = Arc::clone(state);
+    stream.map(move |chunk| {
+        if let Ok(bytes) = &chunk {
+            count(&state.metrics, bytes.len());
+        }
+        chunk
+    })
+}
+
+/// Middleware counting every response and timing it to the response head
+pub async fn metrics_middleware(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = req.method().clone();
+    let route = route_label(&req);
+    let response = next.run(req).await;
+    state
+        .metrics
+        .record_request(&method, &route, response.status(), started.elapsed().as_secs_f64());
+    response
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// GET /gate/metrics - Prometheus metrics (`METRICS_TOKEN` or sufficient rank)
+pub async fn metrics_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    let scrape_token = state.metrics.token_hash.as_deref().is_some_and(|expected| {
+        extract_token_from_header(&headers).is_some_and(|token| hash_token(&token) == expected)
+    });
+    if !scrape_token
+        && get_access_rank_iri(&state, GRAPH_IRI, &headers, &jar).await
+            < state.roles.required_rank(&state.metrics.permission)
+    {
+        warn!(client = %addr, "Access denied - metrics require the scrape token or sufficient rank");
+        return (StatusCode::FORBIDDEN, "Access denied - metrics require the scrape token or sufficient rank")
+            .into_response();
+    }
+
+    let mut sessions: BTreeMap<&'static str, usize> = state.auth_backends.iter().map(|b| (b.name(), 0)).collect();
+    if state.github.is_some() {
+        sessions.insert("github", 0);
+    }
+    for session in state.sessions.list() {
+        *sessions.entry(session.backend).or_insert(0) += 1;
+    }
+
+    (
+        [(header::CONTENT_TYPE, HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8"))],
+        state.metrics.render(&sessions),
+    )
+        .into_response()
+}
@@ -0,0 +1,330 @@
+use axum::http::header;
+use chrono::Utc;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::files::{binding_value, escape_sparql_string, hash_token, send_sparql_update, sparql_select};
+use crate::login_audit::AUDIT_GRAPH;
+use crate::AppState;
+
+/// Failed logins from one client within the window that trigger an alert
+pub const DEFAULT_FAILED_LOGIN_THRESHOLD: u32 = 5;
+/// Window for counting failed logins per client (15 minutes)
+const FAILED_LOGIN_WINDOW: Duration = Duration::from_secs(15 * 60);
+/// Upper bound for a webhook delivery
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Every event kind, as named in `NOTIFY_EVENTS`
+pub const EVENT_NAMES: [&str; 5] = [
+    "failed-logins",
+    "new-ip-login",
+    "token-created",
+    "token-revoked",
+    "emergency-token",
+];
+
+/// Where and what to notify, from the `NOTIFY_*` settings
+pub struct NotifyConfig {
+    /// URL receiving a JSON `POST` per event
+    pub webhook_url: Option<String>,
+    /// Shell command receiving the event text on stdin (e.g. `mail`)
+    pub command: Option<String>,
+    /// Enabled event kinds
+    pub events: Vec<String>,
+    pub failed_login_threshold: u32,
+}
+
+impl NotifyConfig {
+    /// Parse `NOTIFY_EVENTS`: event names separated by `,`
+    pub fn parse_events(value: &str) -> Result<Vec<String>, String> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+            .map(|e| {
+                EVENT_NAMES
+                    .contains(&e)
+                    .then(|| e.to_string())
+                    .ok_or_else(|| format!("unknown event {} (expected one of {})", e, EVENT_NAMES.join(", ")))
+            })
+            .collect()
+    }
+}
+
+/// A security-relevant authentication event
+pub enum AuthEvent {
+    /// A client reached the failed login threshold within the window
+    FailedLogins { client: String, count: u32 },
+    /// A known identity logged in from an address it has not used before
+    NewIpLogin { client: String, identity: String },
+    /// An invite was redeemed for a new access token
+    TokenCreated { client: String },
+    /// An admin revoked an access token
+    TokenRevoked { client: String, token: String },
+    /// The break-glass `EMERGENCY_TOKEN` was used
+    EmergencyToken { client: String },
+}
+
+impl AuthEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            AuthEvent::FailedLogins { .. } => "failed-logins",
+            AuthEvent::NewIpLogin { .. } => "new-ip-login",
+            AuthEvent::TokenCreated { .. } => "token-created",
+            AuthEvent::TokenRevoked { .. } => "token-revoked",
+            AuthEvent::EmergencyToken { .. } => "emergency-token",
+        }
+    }
+
+    fn client(&self) -> &str {
+        match self {
+            AuthEvent::FailedLogins { client, .. }
+            | AuthEvent::NewIpLogin { client, .. }
+            | AuthEvent::TokenCreated { client }
+            | AuthEvent::TokenRevoked { client, .. }
+            | AuthEvent::EmergencyToken { client } => client,
+        }
+    }
+
+    /// One-line summary, also used as the webhook `text` so chat webhooks
+    /// (Slack, Mattermost) display it
+    fn text(&self) -> String {
+        match self {
+            AuthEvent::FailedLogins { client, count } => format!(
+                "liqk gate: {} failed logins from {} within {} minutes",
+                count,
+                client,
+                FAILED_LOGIN_WINDOW.as_secs() / 60
+            ),
+            AuthEvent::NewIpLogin { client, identity } => {
+                format!("liqk gate: {} logged in from new address {}", identity, client)
+            }
+            AuthEvent::TokenCreated { client } => {
+                format!("liqk gate: invite redeemed for a new access token from {}", client)
+            }
+            AuthEvent::TokenRevoked { client, token } => {
+                format!("liqk gate: access token {} revoked from {}", token, client)
+            }
+            AuthEvent::EmergencyToken { client } => {
+                format!("liqk gate: EMERGENCY TOKEN used from {} - access control bypassed", client)
+            }
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let mut payload = json!({
+            "event": self.name(),
+            "time": Utc::now().to_rfc3339(),
+            "client": self.client(),
+            "text": self.text(),
+        });
+        match self {
+            AuthEvent::FailedLogins { count, .. } => payload["count"] = json!(count),
+            AuthEvent::NewIpLogin { identity, .. } => payload["identity"] = json!(identity),
+            AuthEvent::TokenRevoked { token, .. } => payload["token"] = json!(token),
+            AuthEvent::TokenCreated { .. } | AuthEvent::EmergencyToken { .. } => {}
+        }
+        payload
+    }
+}
+
+/// Sends auth event notifications and keeps the per-client failed login
+/// counts they are derived from. Does nothing when not configured.
+pub struct Notifier {
+    config: Option<NotifyConfig>,
+    /// Failed logins per client address: (count, window start)
+    failures: Mutex<HashMap<String, (u32, Instant)>>,
+    /// Last emergency token alert per client address
+    emergency_uses: Mutex<HashMap<String, Instant>>,
+}
+
+impl Notifier {
+    pub fn new(config: Option<NotifyConfig>) -> Self {
+        Self {
+            config,
+            failures: Mutex::new(HashMap::new()),
+            emergency_uses: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn wants(&self, event: &str) -> bool {
+        self.config.as_ref().is_some_and(|c| c.events.iter().any(|e| e == event))
+    }
+
+    /// Count a failed login; returns the count when it just reached the
+    /// threshold, so each client alerts at most once per window
+    fn count_failure(&self, client: &str) -> Option<u32> {
+        let threshold = self.config.as_ref()?.failed_login_threshold;
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, (_, start)| start.elapsed() < FAILED_LOGIN_WINDOW);
+        let (count, _) = failures.entry(client.to_string()).or_insert((0, Instant::now()));
+        *count += 1;
+        (*count == threshold).then_some(*count)
+    }
+
+    /// Whether an emergency token use by this client should alert: the
+    /// first use, then at most once per window
+    fn alert_emergency_use(&self, client: &str) -> bool {
+        let mut uses = self.emergency_uses.lock().unwrap();
+        uses.retain(|_, last| last.elapsed() < FAILED_LOGIN_WINDOW);
+        if uses.contains_key(client) {
+            return false;
+        }
+        uses.insert(client.to_string(), Instant::now());
+        true
+    }
+}
+
+/// Deliver an event in the background so notification never delays or
+/// fails the request that caused it
+pub fn notify(state: &Arc<AppState>, event: AuthEvent) {
+    if !state.notifier.wants(event.name()) {
+        return;
+    }
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        let Some(config) = &state.notifier.config else {
+            return;
+        };
+        let payload = event.to_json().to_string();
+        info!(event = event.name(), client = %event.client(), "Sending auth notification");
+
+        if let Some(url) = &config.webhook_url {
+            let result = state
+                .client
+                .post(url)
+                .header(header::CONTENT_TYPE, "application/json")
+                .timeout(WEBHOOK_TIMEOUT)
+                .body(payload.clone())
+                .send()
+                .await;
+            match result {
+                Ok(resp) if resp.status().is_success() => {}
+                Ok(resp) => warn!(event = event.name(), status = %resp.status(), "Notification webhook rejected event"),
+                Err(e) => warn!(event = event.name(), error = %e, "Notification webhook failed"),
+            }
+        }
+
+        if let Some(command) = &config.command {
+            if let Err(e) = run_command(command, &event, &payload).await {
+                warn!(event = event.name(), error = %e, "Notification command failed");
+            }
+        }
+    });
+}
+
+/// Run the notification command through `sh -c` with the event text on
+/// stdin and the event in `$LIQK_EVENT` / `$LIQK_EVENT_JSON`
+async fn run_command(command: &str, event: &AuthEvent, payload: &str) -> Result<(), String> {
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("LIQK_EVENT", event.name())
+        .env("LIQK_EVENT_JSON", payload)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(format!("{}\n", event.text()).as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    let status = child.wait().await.map_err(|e| e.to_string())?;
+    if !status.success() {
+        return Err(format!("exited with {}", status));
+    }
+    Ok(())
+}
+
+/// Count a failed login and alert when the client reaches the threshold
+pub fn failed_login(state: &Arc<AppState>, addr: &SocketAddr) {
+    let client = addr.ip().to_string();
+    if let Some(count) = state.notifier.count_failure(&client) {
+        notify(state, AuthEvent::FailedLogins { client, count });
+    }
+}
+
+/// Alert on use of the emergency token; repeated requests from the same
+/// client alert once per window
+pub fn emergency_token_used(state: &Arc<AppState>, addr: &SocketAddr) {
+    let client = addr.ip().to_string();
+    if state.notifier.wants("emergency-token") && state.notifier.alert_emergency_use(&client) {
+        notify(state, AuthEvent::EmergencyToken { client });
+    }
+}
+
+/// Remember the address of a successful login and alert when a known
+/// identity uses a new one. The first login of an identity only records
+/// the address. Addresses are kept in the audit graph as
+/// `liqk:LoginOrigin` records keyed by a hash of the identity.
+pub fn successful_login(state: &Arc<AppState>, addr: &SocketAddr, identity: String) {
+    if !state.notifier.wants("new-ip-login") {
+        return;
+    }
+    let state = Arc::clone(state);
+    let client = addr.ip().to_string();
+    tokio::spawn(async move {
+        let identity_hash = hash_token(&identity);
+        let query = format!(
+            r#"PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?ip FROM <{graph}> WHERE {{
+    ?origin a liqk:LoginOrigin ;
+        liqk:identity-hash "{identity_hash}" ;
+        liqk:client-ip ?ip .
+}}"#,
+            graph = AUDIT_GRAPH,
+            identity_hash = identity_hash,
+        );
+        let known: Vec<String> = match sparql_select(&state.client, &state.oxigraph_url, &query).await {
+            Ok(bindings) => bindings
+                .iter()
+                .filter_map(|b| binding_value(b, "ip").map(str::to_string))
+                .collect(),
+            Err(e) => {
+                warn!(client = %client, error = %e, "Failed to load login origins");
+                return;
+            }
+        };
+        if known.contains(&client) {
+            return;
+        }
+
+        let query = format!(
+            r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX dcterms: <http://purl.org/dc/terms/>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+
+INSERT DATA {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{id}> a liqk:LoginOrigin ;
+            liqk:identity-hash "{identity_hash}" ;
+            liqk:client-ip "{ip}" ;
+            dcterms:created "{now}"^^xsd:dateTime .
+    }}
+}}"#,
+            graph = AUDIT_GRAPH,
+            id = Uuid::new_v4(),
+            identity_hash = identity_hash,
+            ip = escape_sparql_string(&client),
+            now = Utc::now().to_rfc3339(),
+        );
+        if let Err(e) = send_sparql_update(&state, "login", &query).await {
+            warn!(client = %client, error = %e, "Failed to record login origin");
+        }
+
+        if !known.is_empty() {
+            notify(&state, AuthEvent::NewIpLogin { client, identity });
+        }
+    });
+}
@@ -0,0 +1,146 @@
+use axum::http::{header, HeaderMap, HeaderValue};
+use serde_json::Value;
+
+use crate::public_graphs::encode_params;
+use crate::query_limits::Shape;
+use crate::results::is_json_results;
+use crate::update_graphs::form_params;
+
+/// URL parameter picking the page, taken off before the query is sent on
+const PAGE_PARAM: &str = "page";
+
+/// The page of results a paginated query was cut to
+pub struct Page {
+    /// 1 for the first page
+    pub number: u64,
+    /// Rows per page (`QUERY_PAGE_SIZE`)
+    pub size: u64,
+}
+
+impl Page {
+    /// `query` bounded to this page, or `None` when it is not a `SELECT` or
+    /// bounds its results itself with a top-level `LIMIT` or `OFFSET`
+    fn bound(&self, query: &str) -> Option<String> {
+        let shape = Shape::of(query);
+        if shape.form.as_deref() != Some("SELECT") || shape.limit || shape.offset {
+            return None;
+        }
+        // Solution modifiers go before a trailing VALUES clause; the line
+        // breaks keep them out of a trailing comment
+        let at = shape
+            .values_at
+            .and_then(|at| query.char_indices().nth(at))
+            .map_or(query.len(), |(byte, _)| byte);
+        Some(format!(
+            "{}\nLIMIT {} OFFSET {}\n{}",
+            &query[..at],
+            self.size,
+            (self.number - 1) * self.size,
+            &query[at..]
+        ))
+    }
+
+    /// Set `Link` to the pages before and after this one on the client's
+    /// `path` and `query_string`. `body` is the page when it was read: JSON
+    /// results with fewer rows than a page are the last, and get no `next`.
+    pub fn link(&self, headers: &mut HeaderMap, path: &str, query_string: Option<&str>, body: Option<&[u8]>) {
+        let last = body.is_some_and(|body| is_json_results(headers) && rows(body).is_some_and(|rows| rows < self.size));
+        let params: Vec<(String, String)> = form_params(query_string.unwrap_or(""))
+            .into_iter()
+            .filter(|(k, _)| k != PAGE_PARAM)
+            .collect();
+        let page_url = |number: u64| {
+            let mut params = params.clone();
+            params.push((PAGE_PARAM.to_string(), number.to_string()));
+            format!("{}?{}", path, encode_params(&params))
+        };
+
+        let mut links = Vec::new();
+        if self.number > 1 {
+            links.push(format!("<{}>; rel=\"prev\"", page_url(self.number - 1)));
+        }
+        if !last {
+            links.push(format!("<{}>; rel=\"next\"", page_url(self.number + 1)));
+        }
+        headers.remove(header::LINK);
+        if !links.is_empty() {
+            if let Ok(value) = HeaderValue::from_str(&links.join(", ")) {
+                headers.insert(header::LINK, value);
+            }
+        }
+    }
+}
+
+/// Number of rows in SPARQL JSON results
+fn rows(json: &[u8]) -> Option<u64> {
+    let results: Value = serde_json::from_slice(json).ok()?;
+    let bindings = results.pointer("/results/bindings")?.as_array()?;
+    Some(bindings.len() as u64)
+}
+
+/// A query request rewritten for pagination; `None` fields are unchanged
+pub struct Paginated {
+    pub query_string: Option<String>,
+    pub body: Option<Vec<u8>>,
+    /// The page an unbounded `SELECT` was cut to
+    pub page: Option<Page>,
+}
+
+/// Take the `page` parameter off a query request and cut an unbounded
+/// `SELECT` to that page of `page_size` rows, the first by default. The
+/// query may be in the URL, a form or an `application/sparql-query` body.
+pub fn paginate(
+    query_string: Option<&str>,
+    content_type: Option<&str>,
+    body: &[u8],
+    page_size: u64,
+) -> Result<Paginated, String> {
+    let mut url_params = form_params(query_string.unwrap_or(""));
+    let requested = url_params.iter().rev().find(|(k, _)| k == PAGE_PARAM).map(|(_, v)| v.clone());
+    let number = match &requested {
+        Some(number) => number
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|&n| n > 0 && (n - 1).checked_mul(page_size).is_some())
+            .ok_or_else(|| format!("page must be a positive number: {}", number))?,
+        None => 1,
+    };
+    url_params.retain(|(k, _)| k != PAGE_PARAM);
+    let page = Page { number, size: page_size };
+
+    let media_type = content_type
+        .and_then(|ct| ct.split(';').next())
+        .map(|ct| ct.trim().to_ascii_lowercase());
+    let mut paginated = Paginated {
+        query_string: requested.is_some().then(|| encode_params(&url_params)),
+        body: None,
+        page: None,
+    };
+    if let Some(query) = url_params.iter_mut().find(|(k, _)| k == "query").map(|(_, v)| v) {
+        if let Some(bounded) = page.bound(query) {
+            *query = bounded;
+            paginated.query_string = Some(encode_params(&url_params));
+            paginated.page = Some(page);
+        }
+    } else if media_type.as_deref() == Some("application/x-www-form-urlencoded") {
+        let mut body_params = form_params(&String::from_utf8_lossy(body));
+        if let Some(query) = body_params.iter_mut().find(|(k, _)| k == "query").map(|(_, v)| v) {
+            if let Some(bounded) = page.bound(query) {
+                *query = bounded;
+                paginated.body = Some(encode_params(&body_params).into_bytes());
+                paginated.page = Some(page);
+            }
+        }
+    } else if media_type.as_deref() == Some("application/sparql-query") {
+        if let Some(bounded) = page.bound(&String::from_utf8_lossy(body)) {
+            paginated.body = Some(bounded.into_bytes());
+            paginated.page = Some(page);
+        }
+    }
+
+    if requested.is_some() && paginated.page.is_none() {
+        return Err("page applies only to SELECT queries without LIMIT or OFFSET".to_string());
+    }
+    Ok(paginated)
+}
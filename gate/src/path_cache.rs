@@ -0,0 +1,73 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use crate::browse::Node;
+
+/// Default lifetime of cached path resolutions in seconds
+pub const DEFAULT_PATH_CACHE_TTL_SECS: u64 = 60;
+
+/// Entries beyond which expired paths are pruned on insert
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// In-memory cache of resolved `/file` paths, keyed by their decoded
+/// segments. Resolving a path also caches every directory on the way, so
+/// navigation within a subtree resumes from the deepest cached ancestor
+/// instead of walking down from the root again. Only successful
+/// resolutions are cached. Entries expire after the TTL and the whole
+/// cache is dropped when an update touching the filesystem graph passes
+/// through the gate (renames, moves and deletes all do).
+pub struct PathCache {
+    entries: RwLock<HashMap<Vec<String>, (Node, Instant)>>,
+    ttl: Duration,
+}
+
+impl PathCache {
+    /// A zero TTL disables caching
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    pub fn get(&self, segments: &[String]) -> Option<Node> {
+        if self.ttl.is_zero() {
+            return None;
+        }
+        let entries = self.entries.read().unwrap();
+        let (node, stored) = entries.get(segments)?;
+        (stored.elapsed() < self.ttl).then(|| node.clone())
+    }
+
+    /// The deepest cached directory strictly above `segments`, with the
+    /// number of segments it covers
+    pub fn deepest_ancestor(&self, segments: &[String]) -> Option<(usize, Node)> {
+        if self.ttl.is_zero() {
+            return None;
+        }
+        let entries = self.entries.read().unwrap();
+        (0..segments.len()).rev().find_map(|depth| {
+            let (node, stored) = entries.get(&segments[..depth])?;
+            (node.is_dir() && stored.elapsed() < self.ttl).then(|| (depth, node.clone()))
+        })
+    }
+
+    pub fn insert(&self, segments: &[String], node: Node) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.write().unwrap();
+        if entries.len() >= PRUNE_THRESHOLD {
+            let ttl = self.ttl;
+            entries.retain(|_, (_, stored)| stored.elapsed() < ttl);
+        }
+        entries.insert(segments.to_vec(), (node, Instant::now()));
+    }
+
+    pub fn invalidate_all(&self) {
+        self.entries.write().unwrap().clear();
+    }
+}
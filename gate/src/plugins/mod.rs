@@ -0,0 +1,73 @@
+//! Site-specific routes that live outside the core handlers.
+//!
+//! A plugin is a module in this directory behind its own `plugin-<name>`
+//! Cargo feature, listed in [`registry`]. Its routes are mounted under
+//! `/gate/plugins/<name>` and receive the shared [`AppState`], so handlers
+//! can use the SPARQL helpers in `files` and guard themselves with the
+//! `require_access` route layer and the `AuthedUser` extractor, like the
+//! core file routes do. Paths are also subject to the authorization rules:
+//! the built-in `/` rule requires `view`. Plugins may also hook into the
+//! proxy with [`ProxyHook`]s, to rewrite what is forwarded upstream or
+//! returned to clients.
+
+#[cfg(feature = "plugin-stats")]
+mod stats;
+
+use axum::Router;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::hooks::ProxyHook;
+use crate::AppState;
+
+/// Route prefix plugins are mounted under
+pub const PLUGIN_PREFIX: &str = "/gate/plugins";
+
+/// A set of routes added to the gate by a site-specific module
+pub trait Plugin: Send + Sync {
+    /// Name used in `PLUGINS` and as the plugin's path below `PLUGIN_PREFIX`
+    fn name(&self) -> &'static str;
+
+    /// Routes relative to the plugin's prefix. Called once at startup,
+    /// after the state is built; plugin configuration is read from the
+    /// environment here.
+    fn routes(&self, state: &Arc<AppState>) -> Router<Arc<AppState>>;
+
+    /// Hooks run on every proxied request and response, in plugin order.
+    /// Called once at startup, before the state is built.
+    fn hooks(&self) -> Vec<Box<dyn ProxyHook>> {
+        Vec::new()
+    }
+}
+
+/// Every plugin compiled into this build
+fn registry() -> Vec<Box<dyn Plugin>> {
+    vec![
+        #[cfg(feature = "plugin-stats")]
+        Box::new(stats::Stats),
+    ]
+}
+
+/// The plugins to mount: those named in `enabled`, or all compiled-in
+/// plugins when it is `None`. Fails on names that are not compiled in.
+pub fn enabled(enabled: Option<&[String]>) -> Result<Vec<Box<dyn Plugin>>, String> {
+    let mut plugins = registry();
+    let Some(names) = enabled else {
+        return Ok(plugins);
+    };
+    if let Some(unknown) = names.iter().find(|name| !plugins.iter().any(|p| p.name() == name.as_str())) {
+        return Err(format!("plugin {} is not compiled in", unknown));
+    }
+    plugins.retain(|p| names.iter().any(|name| name == p.name()));
+    Ok(plugins)
+}
+
+/// Mount plugin routes on the gate's router
+pub fn mount(mut app: Router<Arc<AppState>>, plugins: &[Box<dyn Plugin>], state: &Arc<AppState>) -> Router<Arc<AppState>> {
+    for plugin in plugins {
+        let prefix = format!("{}/{}", PLUGIN_PREFIX, plugin.name());
+        app = app.nest(&prefix, plugin.routes(state));
+        info!(plugin = plugin.name(), prefix = %prefix, "Plugin mounted");
+    }
+    app
+}
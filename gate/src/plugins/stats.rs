@@ -0,0 +1,79 @@
+//! Example plugin: `GET /gate/plugins/stats` reports how many files and
+//! directories the filesystem graph holds and their total size, to anyone
+//! who may view the SPARQL endpoint's graphs.
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+use tracing::{info, warn};
+
+use super::Plugin;
+use crate::auth::{require_access, AuthedUser, Resource};
+use crate::files::{binding_value, sparql_select, FILESYSTEM_GRAPH};
+use crate::proxy::GRAPH_IRI;
+use crate::AppState;
+
+pub struct Stats;
+
+impl Plugin for Stats {
+    fn name(&self) -> &'static str {
+        "stats"
+    }
+
+    fn routes(&self, state: &Arc<AppState>) -> Router<Arc<AppState>> {
+        Router::new().route(
+            "/",
+            get(stats_handler).route_layer(middleware::from_fn_with_state(
+                (Arc::clone(state), Resource::Action(GRAPH_IRI)),
+                require_access,
+            )),
+        )
+    }
+}
+
+async fn stats_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    AuthedUser { fingerprint, .. }: AuthedUser,
+) -> Response {
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+
+SELECT (COUNT(?file) AS ?files) (SUM(?size) AS ?bytes) (COUNT(?dir) AS ?directories) FROM <{graph}> WHERE {{
+    {{ ?file a posix:File . OPTIONAL {{ ?file posix:size ?size }} }}
+    UNION
+    {{ ?dir a posix:Directory }}
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+    );
+
+    let bindings = match sparql_select(&state.client, &state.oxigraph_url, &query).await {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, error = %e, "Stats query failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to query statistics").into_response();
+        }
+    };
+    let count = |var| {
+        bindings
+            .first()
+            .and_then(|b| binding_value(b, var))
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0)
+    };
+
+    let body = json!({
+        "files": count("files"),
+        "directories": count("directories"),
+        "bytes": count("bytes"),
+    });
+    info!(client = %addr, credential = %fingerprint, "Stats served");
+    ([(header::CONTENT_TYPE, "application/json")], body.to_string()).into_response()
+}
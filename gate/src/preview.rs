@@ -0,0 +1,112 @@
+//! Preview pages for media files.
+//!
+//! A browser opening an image, audio or video file, by path or by UUID,
+//! gets a page showing it in an `<img>`, `<audio>` or `<video>` element
+//! with a button to download it, instead of the bytes. The element loads
+//! the same URL with `?raw`, which answers the content whatever the client
+//! accepts, as do requests not preferring HTML; stored files are served
+//! with `Range` support, so audio and video can seek. `?download` answers
+//! the content as an attachment under the file's name.
+
+use axum::{
+    http::{header, HeaderMap, HeaderValue},
+    response::{Html, IntoResponse, Response},
+};
+
+use crate::accept::prefers_html;
+use crate::files::attachment;
+use crate::templates::{escape_html, fill, PREVIEW_HTML};
+use crate::update_graphs::form_params;
+
+/// Kind of media a preview page shows
+#[derive(Clone, Copy)]
+pub enum Media {
+    Image,
+    Audio,
+    Video,
+}
+
+impl Media {
+    /// The media kind of a stored file, by its extension
+    fn of(stored_as: &str) -> Option<Self> {
+        match mime_guess::from_path(stored_as).first()?.type_().as_str() {
+            "image" => Some(Self::Image),
+            "audio" => Some(Self::Audio),
+            "video" => Some(Self::Video),
+            _ => None,
+        }
+    }
+
+    fn element(self, src: &str, label: &str) -> String {
+        let src = escape_html(src);
+        match self {
+            Self::Image => format!("<img src=\"{}\" alt=\"{}\">", src, escape_html(label)),
+            Self::Audio => format!("<audio src=\"{}\" controls preload=\"metadata\"></audio>", src),
+            Self::Video => format!("<video src=\"{}\" controls preload=\"metadata\"></video>", src),
+        }
+    }
+}
+
+/// How a request for a file's content is answered
+pub enum Delivery {
+    /// The preview page
+    Preview(Media),
+    /// The content itself
+    Content {
+        /// Offered for download (`?download`)
+        attachment: bool,
+        /// Whether the URL answers a preview page to other clients, so
+        /// caches must tell them apart by `Accept`
+        negotiated: bool,
+    },
+}
+
+impl Delivery {
+    /// Decide from the request's `?raw` / `?download` and `Accept`
+    pub fn of(headers: &HeaderMap, query: Option<&str>, stored_as: &str) -> Self {
+        let params = form_params(query.unwrap_or(""));
+        let has = |name: &str| params.iter().any(|(k, _)| k == name);
+        if has("download") || has("raw") {
+            return Self::Content { attachment: has("download"), negotiated: false };
+        }
+        let Some(media) = Media::of(stored_as) else {
+            return Self::Content { attachment: false, negotiated: false };
+        };
+        if prefers_html(headers.get(header::ACCEPT).and_then(|v| v.to_str().ok())) {
+            Self::Preview(media)
+        } else {
+            Self::Content { attachment: false, negotiated: true }
+        }
+    }
+
+    /// Finish a response carrying the content of the file named `label`
+    pub fn content(&self, mut response: Response, label: &str) -> Response {
+        let Self::Content { attachment: download, negotiated } = *self else {
+            return response;
+        };
+        if download {
+            if let Ok(value) = HeaderValue::from_str(&attachment(label)) {
+                response.headers_mut().insert(header::CONTENT_DISPOSITION, value);
+            }
+        }
+        if negotiated {
+            response.headers_mut().append(header::VARY, HeaderValue::from_static("Accept"));
+        }
+        response
+    }
+}
+
+/// The preview page of the file named `label`, requested at `path`
+pub fn preview_page(path: &str, label: &str, media: Media) -> Response {
+    let page = fill(
+        PREVIEW_HTML,
+        &[
+            ("title", &escape_html(label)),
+            ("download", &escape_html(&format!("{}?download", path))),
+            ("media", &media.element(&format!("{}?raw", path), label)),
+        ],
+    );
+    let mut response = Html(page).into_response();
+    response.headers_mut().append(header::VARY, HeaderValue::from_static("Accept"));
+    response
+}
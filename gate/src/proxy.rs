@@ -1,23 +1,78 @@
 use axum::{
+    body::Body,
     extract::{ConnectInfo, Request, State},
-    http::{HeaderMap, HeaderValue, Method, StatusCode},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
     response::{IntoResponse, Response},
 };
 use axum_extra::extract::cookie::CookieJar;
-use std::{net::SocketAddr, sync::Arc};
-use tracing::{info, warn};
+use bytes::Bytes;
+use futures_util::{stream, StreamExt};
+use http_body_util::{BodyExt, LengthLimitError};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tracing::{field, info, info_span, warn, Instrument};
 
-use crate::files::get_access_rank_iri;
+use crate::accept::{prefers_html, store_page, STORE_PAGE_MAX_BYTES, TURTLE};
+use crate::admin::is_admin;
+use crate::asset_cache::AssetCache;
+use crate::destructive::{self, confirmation_required, guarded, is_confirmed, Outcome};
+use crate::encoding::{decode_request_body, gzip, DecodeError};
+use crate::error_pages::UpstreamFailure;
+use crate::etag;
+use crate::files::{credential, get_access_rank_iri};
+use crate::forwarded::{client_ip, forwarding_headers, is_forwarding_header};
+use crate::hooks::{ProxiedRequest, ProxyRequest};
+use crate::idempotency::{Claimed, MAX_REPLAY_BYTES};
+use crate::limits::{exceeds, is_limit_error};
+use crate::maintenance::{is_read_method, read_only};
+use crate::metrics::{counted, Metrics, UpstreamError};
+use crate::pagination::paginate;
+use crate::public_graphs::{is_public_query, permitted_graphs, restrict_to_graphs};
+use crate::query_cache::{query_params, QueryCache};
+use crate::rank_cache::{store_write_may_change_acl, update_may_change_acl};
+use crate::rate_limit::{requester, too_many_requests, RateClass};
+use crate::results::{is_json_results, negotiate, respond_converted, upstream_accept};
+use crate::roles::reload_roles;
+use crate::security_headers::Proxied;
+use crate::slow_queries::{timed, QueryTimer};
+use crate::telemetry::{is_trace_header, trace_headers, traced};
+use crate::update_graphs::{is_valid_iri, store_target, wiped_graphs, written_graphs, GraphTarget};
+use crate::upstreams::Upstream;
 use crate::AppState;
 
-const GRAPH_IRI: &str = "http://liqk.org/graph";
+/// Policy target guarding the SPARQL endpoints
+pub const GRAPH_IRI: &str = "http://liqk.org/graph";
 
-/// Determine the minimum required access rank for a given path
-fn required_rank_for_path(path: &str) -> i32 {
-    if path.to_lowercase().starts_with("/update") {
-        3 // edit access required for SPARQL update
-    } else {
-        1 // view access required for all other endpoints
+/// Default seconds to wait for a connection to Oxigraph
+pub const DEFAULT_UPSTREAM_CONNECT_TIMEOUT_SECS: u64 = 5;
+/// Default seconds Oxigraph may take to answer queries and other requests
+pub const DEFAULT_UPSTREAM_TIMEOUT_SECS: u64 = 60;
+/// Default seconds Oxigraph may take to answer updates and bulk loads
+pub const DEFAULT_UPSTREAM_WRITE_TIMEOUT_SECS: u64 = 600;
+/// Default seconds idle upstream connections are kept open for reuse
+pub const DEFAULT_UPSTREAM_POOL_IDLE_TIMEOUT_SECS: u64 = 90;
+
+/// Connection pool of the clients talking to the upstreams (`UPSTREAM_POOL_*`,
+/// `UPSTREAM_HTTP2`, `UPSTREAM_TCP_NODELAY`)
+pub struct UpstreamPool {
+    /// Idle connections kept per host; unlimited when `None`
+    pub max_idle_per_host: Option<usize>,
+    /// How long idle connections are kept; until closed upstream when `None`
+    pub idle_timeout: Option<Duration>,
+    /// Speak HTTP/2 without negotiation, for upstreams that accept h2c
+    pub http2_prior_knowledge: bool,
+    pub tcp_nodelay: bool,
+}
+
+impl UpstreamPool {
+    pub fn apply(&self, builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        let mut builder = builder.pool_idle_timeout(self.idle_timeout).tcp_nodelay(self.tcp_nodelay);
+        if let Some(max_idle) = self.max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        builder
     }
 }
 
@@ -27,6 +82,22 @@ pub async fn proxy_handler(
     jar: CookieJar,
     req: Request,
 ) -> Response {
+    let mut response = if state.hooks.is_empty() {
+        proxy(state, addr, jar, req).await
+    } else {
+        let proxied = ProxiedRequest {
+            client: addr,
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+        };
+        let response = proxy(Arc::clone(&state), addr, jar, req).await;
+        state.hooks.on_response(&proxied, response)
+    };
+    response.extensions_mut().insert(Proxied);
+    response
+}
+
+async fn proxy(state: Arc<AppState>, addr: SocketAddr, jar: CookieJar, req: Request) -> Response {
     let method = req.method().clone();
     let uri = req.uri().clone();
     let path_and_query = uri
@@ -34,18 +105,90 @@ pub async fn proxy_handler(
         .map(|pq| pq.as_str())
         .unwrap_or("/");
 
-    // Extract just the path (without query string) for access check
-    let path = uri.path();
+    // Oxigraph serves every path no upstream route claims. Oxigraph routes
+    // are handled as if mounted at the root, down to the authorization
+    // rules; other upstreams are checked by the path the client used.
+    let route = state.upstreams.route(uri.path());
+    let (upstream_url, sparql) = match &route {
+        Some((Upstream::Url(url), _)) => (url.as_str(), false),
+        _ => (state.oxigraph_url.as_str(), true),
+    };
+    let path = match &route {
+        Some((Upstream::Oxigraph, rest)) => rest.as_str(),
+        _ => uri.path(),
+    };
+    let upstream_path = route.as_ref().map_or(path, |(_, rest)| rest.as_str());
+    let is_update = sparql && is_update(path);
+    let is_graph_write = sparql && is_graph_write(&method, path);
+    let is_query = sparql && is_public_query(&method, path);
+
+    let mut headers = req.headers().clone();
+
+    // Browsers reading the Graph Store get its Turtle as a page; other
+    // clients naming no media type get the route's ACCEPT_REWRITES type
+    let client_accept = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()).map(str::to_string);
+    let render_store_page = sparql
+        && state.store_pages
+        && method == Method::GET
+        && is_graph_store(path)
+        && prefers_html(client_accept.as_deref());
+    if render_store_page {
+        headers.insert(header::ACCEPT, HeaderValue::from_static(TURTLE));
+        headers.remove(header::ACCEPT_ENCODING);
+    } else if let Some(accept) = state.accept_rewrites.rewrite(uri.path(), client_accept.as_deref()).cloned() {
+        headers.insert(header::ACCEPT, accept);
+    }
+
+    // Oxigraph is asked for JSON when the client prefers XML, CSV or TSV
+    // results, which the gate converts to. Graph formats stay acceptable
+    // for CONSTRUCT, and the JSON is not compressed so it can be read.
+    let accept = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()).unwrap_or("");
+    let convert_to = (is_query && state.result_conversion_max > 0).then(|| negotiate(accept)).flatten();
+    if convert_to.is_some() {
+        if let Ok(accept) = HeaderValue::from_str(&upstream_accept(accept)) {
+            headers.insert(header::ACCEPT, accept);
+        }
+        headers.remove(header::ACCEPT_ENCODING);
+    }
 
-    let headers = req.headers().clone();
+    let credential = credential(&state, &headers, &jar);
+    let fingerprint = credential.fingerprint();
 
     // Check access rank on the graph IRI
-    let rank = get_access_rank_iri(&state.client, &state.oxigraph_url, GRAPH_IRI, &headers, &jar).await;
-    let required_rank = required_rank_for_path(path);
+    let mut rank = get_access_rank_iri(&state, GRAPH_IRI, &headers, &jar).await;
+    let required_rank = match state.rules.required_rank(&state.roles, &method, path) {
+        Ok(required) => required,
+        Err(status) => {
+            warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, status = %status, "Request rejected by authorization rules");
+            return (status, "Access denied").into_response();
+        }
+    };
 
-    if rank < required_rank {
+    // Graph Store requests naming a graph may also be allowed by the rank
+    // policies grant on that graph
+    let store_graph = (sparql && is_graph_store(path)).then(|| store_target(uri.query())).flatten();
+    if let Some(GraphTarget::Named(graph)) = &store_graph {
+        if !is_valid_iri(graph) {
+            warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, "Graph Store request rejected - invalid graph IRI");
+            return (StatusCode::BAD_REQUEST, "Invalid graph IRI").into_response();
+        }
+        if rank < required_rank {
+            rank = rank.max(get_access_rank_iri(&state, graph, &headers, &jar).await);
+        }
+    }
+
+    // Queries lacking the rank may still read the graphs the requester is
+    // ranked for
+    let mut scope: Option<Vec<String>> = None;
+    if rank < required_rank && is_query {
+        let graphs = permitted_graphs(&state, &credential, required_rank).await;
+        scope = (!graphs.is_empty()).then_some(graphs);
+    }
+
+    if rank < required_rank && scope.is_none() {
         warn!(
             client = %addr,
+            credential = %fingerprint,
             method = %method,
             path = %path_and_query,
             rank = rank,
@@ -54,29 +197,334 @@ pub async fn proxy_handler(
         );
         return (StatusCode::FORBIDDEN, "Access denied").into_response();
     }
-    let target_url = format!("{}{}", state.oxigraph_url, path_and_query);
 
-    let body_bytes = match axum::body::to_bytes(req.into_body(), usize::MAX).await {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            warn!(
-                client = %addr,
-                method = %method,
-                path = %path_and_query,
-                error = %e,
-                "Failed to read request body"
-            );
-            return (
-                StatusCode::BAD_REQUEST,
-                format!("Failed to read request body: {}", e),
-            )
-                .into_response();
+    // Requests that passed the access check count against the requester's
+    // rate limit for their kind. Behind a trusted proxy anonymous clients
+    // are told apart by the address it forwards.
+    let rate_class = if is_query {
+        RateClass::Query
+    } else if is_read_method(&method) {
+        RateClass::Download
+    } else {
+        RateClass::Update
+    };
+    let trusted_peer = state.proxy_auth.as_ref().is_some_and(|config| config.is_trusted(addr.ip()));
+    let client = client_ip(&headers, addr.ip(), trusted_peer);
+    if let Some(requester) = requester(&credential, rank, client) {
+        if let Err(retry_after) = state.rate_limiter.check(rate_class, &requester) {
+            warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, class = rate_class.as_str(), "Request rejected - rate limit exceeded");
+            return too_many_requests(retry_after);
+        }
+    }
+
+    // During maintenance Oxigraph only answers queries and reads
+    if sparql && !is_query && !is_read_method(&method) && state.maintenance.is_read_only() {
+        warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, "Write rejected - read-only maintenance");
+        return read_only();
+    }
+
+    // Static UI assets may be answered from the cache; stale copies are
+    // revalidated below
+    let asset_key = state
+        .asset_cache
+        .is_asset(&method, path)
+        .then(|| AssetCache::key(path_and_query, &headers));
+    let cached_asset = asset_key.as_deref().and_then(|key| state.asset_cache.get(key));
+    if let Some(asset) = cached_asset.as_ref().filter(|asset| asset.is_fresh()) {
+        info!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, "Asset served from cache");
+        return state.asset_cache.respond(asset, &headers);
+    }
+    let revalidate_etag = cached_asset.as_ref().and_then(|asset| asset.etag.clone());
+    let query_cacheable = state.query_cache.is_enabled() && is_query;
+
+    // Queries are spread over the read replicas, everything else goes to
+    // the primary
+    let replica = if is_query { state.replicas.pick() } else { None };
+    let upstream_url = replica.unwrap_or(upstream_url);
+
+    let mut target_url = format!("{}{}", upstream_url, upstream_path);
+    if let Some(query) = uri.query() {
+        target_url.push('?');
+        target_url.push_str(query);
+    }
+
+    // Queries are small; updates and bulk loads may be large
+    let body_limit = if is_query { state.body_limits.query } else { state.body_limits.update };
+    let too_large = || {
+        warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, limit = body_limit, "Request body too large");
+        if is_query { state.body_limits.query_too_large() } else { state.body_limits.update_too_large() }
+    };
+    if exceeds(&headers, body_limit) {
+        return too_large();
+    }
+
+    // Bodies are streamed to Oxigraph unless they must be inspected: SPARQL
+    // updates, queries restricted to the public graphs, looked up in the
+    // query cache, timed for the slow query log, checked against the query
+    // limits, paginated or mirrored to the shadow store, bodies proxy hooks
+    // ask for, and encoded bodies, which are decoded first
+    let content_encoding = headers.get(header::CONTENT_ENCODING).and_then(|v| v.to_str().ok());
+    let encoded = content_encoding.is_some_and(|e| !matches!(e.trim().to_ascii_lowercase().as_str(), "" | "identity"));
+    let timed_query = is_query && state.slow_queries.is_enabled();
+    let limited_query = is_query && state.query_limits.applies_to(rank);
+    let paged_query = is_query && state.query_page_size > 0;
+    let shadowed_query = is_query && state.shadow.url().is_some();
+    let mut query_string = uri.query().map(str::to_string);
+    let mut page = None;
+    let mut body = if scope.is_some()
+        || query_cacheable
+        || timed_query
+        || limited_query
+        || paged_query
+        || shadowed_query
+        || is_update
+        || encoded
+        || state.hooks.needs_body()
+    {
+        let body_bytes = match http_body_util::Limited::new(req.into_body(), body_limit).collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(e) if e.is::<LengthLimitError>() => return too_large(),
+            Err(e) => {
+                warn!(
+                    client = %addr,
+                    credential = %fingerprint,
+                    method = %method,
+                    path = %path_and_query,
+                    error = %e,
+                    "Failed to read request body"
+                );
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Failed to read request body: {}", e),
+                )
+                    .into_response();
+            }
+        };
+
+        // Work on the decoded body so inspection sees the same SPARQL whatever
+        // the client's Content-Encoding
+        let body_bytes = match decode_request_body(content_encoding, &body_bytes, body_limit) {
+            Ok(Some(decoded)) => Bytes::from(decoded),
+            Ok(None) => body_bytes,
+            Err(e) => {
+                let (status, reason) = match e {
+                    DecodeError::Unsupported(encoding) => (
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                        format!("Unsupported Content-Encoding: {}", encoding),
+                    ),
+                    DecodeError::TooLarge => return too_large(),
+                    DecodeError::Invalid(e) => (StatusCode::BAD_REQUEST, format!("Invalid encoded request body: {}", e)),
+                };
+                warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, reason = %reason, "Failed to decode request body");
+                return (status, reason).into_response();
+            }
+        };
+
+        let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+        let mut body_bytes = body_bytes;
+        if let Some(graphs) = &scope {
+            let (restricted, body) = restrict_to_graphs(query_string.as_deref(), content_type, &body_bytes, graphs);
+            query_string = Some(restricted);
+            if let Some(body) = body {
+                body_bytes = Bytes::from(body);
+            }
+            info!(client = %addr, credential = %fingerprint, method = %method, path = %path, graphs = graphs.len(), "Query restricted to permitted graphs");
+        }
+
+        // With QUERY_PAGE_SIZE, SELECTs without a LIMIT of their own are
+        // cut to the page the client asks for with `page=`
+        if paged_query {
+            let paginated = match paginate(query_string.as_deref(), content_type, &body_bytes, state.query_page_size) {
+                Ok(paginated) => paginated,
+                Err(e) => {
+                    warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, reason = %e, "Query rejected - invalid page");
+                    return (StatusCode::BAD_REQUEST, e).into_response();
+                }
+            };
+            if let Some(paged) = paginated.query_string {
+                query_string = Some(paged);
+            }
+            if let Some(body) = paginated.body {
+                body_bytes = Bytes::from(body);
+            }
+            page = paginated.page;
+        }
+
+        if query_string.as_deref() != uri.query() {
+            target_url = format!("{}{}", upstream_url, path);
+            if let Some(query_string) = query_string.as_deref().filter(|qs| !qs.is_empty()) {
+                target_url.push('?');
+                target_url.push_str(query_string);
+            }
         }
+        UpstreamBody::Buffered(body_bytes)
+    } else {
+        let limited = Body::new(http_body_util::Limited::new(req.into_body(), body_limit));
+        let stream = counted(limited.into_data_stream(), &state, Metrics::sent);
+        UpstreamBody::Streamed(reqwest::Body::wrap_stream(stream))
+    };
+    // Graph Store writes are judged by their query string, so a streamed
+    // body is never needed here
+    let inspected: &[u8] = match &body {
+        UpstreamBody::Buffered(bytes) => bytes,
+        UpstreamBody::Streamed(_) => &[],
     };
 
-    let mut proxy_req = state.client.request(method_to_reqwest(&method), &target_url);
+    // Low-rank requesters may not send queries beyond the limits
+    if limited_query {
+        let query = query_params(&headers, query_string.as_deref(), inspected)
+            .and_then(|params| params.into_iter().find(|(name, _)| name == "query"))
+            .map(|(_, query)| query)
+            .unwrap_or_default();
+        if let Err(reason) = state.query_limits.check(&query) {
+            warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, rank = rank, reason = %reason, "Query rejected - over the query limits");
+            return (StatusCode::FORBIDDEN, format!("Query rejected: {}", reason)).into_response();
+        }
+    }
+
+    let query_key = query_cacheable
+        .then(|| QueryCache::key(scope.as_deref(), &headers, query_string.as_deref(), inspected))
+        .flatten();
+    if let Some(result) = query_key.as_deref().and_then(|key| state.query_cache.get(key)) {
+        info!(client = %addr, credential = %fingerprint, method = %method, path = %path, "Query answered from cache");
+        return state.query_cache.respond(&headers, &result, convert_to);
+    }
+    let query_generation = state.query_cache.generation();
+    let mut query_timer =
+        timed_query.then(|| QueryTimer::start(&state, addr, &fingerprint, &headers, uri.query(), inspected));
+
+    // Every graph the write touches must be one the requester can edit
+    if state.restrict_update_graphs && is_graph_write {
+        let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+        let targets = match written_graphs(path, uri.query(), content_type, inspected) {
+            Ok(targets) => targets,
+            Err(e) => {
+                warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, reason = %e, "Update rejected - written graphs cannot be determined");
+                return (StatusCode::FORBIDDEN, format!("Update rejected: {}", e)).into_response();
+            }
+        };
+        for target in &targets {
+            let allowed = match target {
+                GraphTarget::Default => false,
+                GraphTarget::Named(iri) => get_access_rank_iri(&state, iri, &headers, &jar).await >= required_rank,
+            };
+            if !allowed {
+                warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, graph = %target, "Update rejected - graph not allowed");
+                return (StatusCode::FORBIDDEN, format!("Update rejected: no write access to {}", target)).into_response();
+            }
+        }
+    }
+
+    // Emptying every graph or a protected one takes the admin rank or a
+    // confirmation; each attempt is recorded in the audit graph
+    let mut destructive = None;
+    if is_graph_write {
+        let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+        let wipes = wiped_graphs(&method, path, query_string.as_deref(), content_type, inspected);
+        let operations = guarded(wipes, &state.protected_graphs);
+        if !operations.is_empty() {
+            let outcome = if is_admin(&state, &headers, &jar).await {
+                Outcome::Admin
+            } else if is_confirmed(&headers) {
+                Outcome::Confirmed
+            } else {
+                warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, operations = %operations.join(", "), "Destructive update rejected - not confirmed");
+                destructive::record(&state, &addr, &fingerprint, &operations, Outcome::Blocked, None);
+                return confirmation_required(&operations);
+            };
+            warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, operations = %operations.join(", "), outcome = outcome.as_str(), "Destructive update allowed");
+            destructive = Some((operations, outcome));
+        }
+    }
+
+    // Cached ranks may be stale once this request is applied
+    let invalidates_ranks = is_graph_write
+        && if is_update {
+            let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+            update_may_change_acl(uri.query(), content_type, inspected)
+        } else {
+            store_write_may_change_acl(uri.query())
+        };
+
+    // An update sent again with the Idempotency-Key of an earlier one gets
+    // that one's answer instead of being applied twice
+    let mut idempotency = None;
+    if is_update && state.idempotency_keys.is_enabled() {
+        let scope = requester(&credential, rank, client).unwrap_or_else(|| fingerprint.clone());
+        let request = [
+            path.as_bytes(),
+            query_string.as_deref().unwrap_or("").as_bytes(),
+            inspected,
+        ];
+        match state.idempotency_keys.claim(&scope, &headers, &request) {
+            Some(Claimed::Send(claim)) => idempotency = Some(claim),
+            Some(Claimed::Replay(response)) => {
+                info!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, status = %response.status(), "Update replayed for a repeated Idempotency-Key");
+                return response;
+            }
+            Some(Claimed::Refuse(response)) => {
+                warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, status = %response.status(), "Update rejected - Idempotency-Key invalid, in flight or reused");
+                return response;
+            }
+            None => {}
+        }
+    }
+
+    // Proxy hooks see the request last, once every check has passed
+    if !state.hooks.is_empty() {
+        let mut hook_req = ProxyRequest {
+            client: addr,
+            method: &method,
+            path: upstream_path,
+            sparql,
+            is_query,
+            rank,
+            target_url: &mut target_url,
+            headers: &mut headers,
+            body: match &mut body {
+                UpstreamBody::Buffered(bytes) => Some(bytes),
+                UpstreamBody::Streamed(_) => None,
+            },
+        };
+        if let Some(response) = state.hooks.on_request(&mut hook_req) {
+            info!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, status = %response.status(), "Request answered by proxy hook");
+            return response;
+        }
+    }
 
+    // With SHADOW_URL, the query as sent to Oxigraph is also sent to the
+    // secondary store, whose answer is dropped
+    if shadowed_query {
+        if let (Some(path_and_query), UpstreamBody::Buffered(body_bytes)) =
+            (target_url.strip_prefix(upstream_url), &body)
+        {
+            state.shadow.mirror(
+                &state.proxy_client,
+                state.upstream_timeout,
+                &method,
+                path_and_query,
+                &headers,
+                body_bytes.clone(),
+            );
+        }
+    }
+
+    let mut proxy_req = state.proxy_client.request(method_to_reqwest(&method), &target_url);
+
+    // A buffered body is re-encoded below, so its length and encoding are
+    // set anew; a streamed one is forwarded as sent
+    let streamed = matches!(body, UpstreamBody::Streamed(_));
     for (name, value) in headers.iter() {
+        if !streamed && (*name == header::CONTENT_ENCODING || *name == header::CONTENT_LENGTH) {
+            continue;
+        }
+        // The client's validators are checked against the cached copy instead
+        if revalidate_etag.is_some() && (*name == header::IF_NONE_MATCH || *name == header::IF_MODIFIED_SINCE) {
+            continue;
+        }
+        if is_forwarding_header(name) || (state.trace_export && is_trace_header(name)) {
+            continue;
+        }
         if should_forward_header(name.as_str()) {
             if let Ok(v) = value.to_str() {
                 proxy_req = proxy_req.header(name.as_str(), v);
@@ -84,13 +532,114 @@ pub async fn proxy_handler(
         }
     }
 
-    if !body_bytes.is_empty() {
-        proxy_req = proxy_req.body(body_bytes);
+    if let Some(etag) = &revalidate_etag {
+        proxy_req = proxy_req.header(header::IF_NONE_MATCH, etag.as_str());
     }
 
-    match proxy_req.send().await {
+    // Tell the upstream who the client is
+    for (name, value) in forwarding_headers(&headers, addr.ip(), trusted_peer) {
+        proxy_req = proxy_req.header(name, value);
+    }
+
+    match body {
+        UpstreamBody::Buffered(body_bytes) if body_bytes.is_empty() => {}
+        UpstreamBody::Buffered(body_bytes) => {
+            if state.upstream_gzip {
+                match gzip(&body_bytes) {
+                    Ok(compressed) => {
+                        state.metrics.sent(compressed.len());
+                        proxy_req = proxy_req.header(header::CONTENT_ENCODING, "gzip").body(compressed);
+                    }
+                    Err(e) => {
+                        warn!(client = %addr, credential = %fingerprint, path = %path_and_query, error = %e, "Failed to compress request body");
+                        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to compress request body").into_response();
+                    }
+                }
+            } else {
+                state.metrics.sent(body_bytes.len());
+                proxy_req = proxy_req.body(body_bytes);
+            }
+        }
+        // GET and HEAD carry no body; sending an empty stream would make
+        // the request chunked
+        UpstreamBody::Streamed(_) if matches!(method, Method::GET | Method::HEAD) => {}
+        UpstreamBody::Streamed(stream) => proxy_req = proxy_req.body(stream),
+    }
+
+    // The upstream continues the trace from this span
+    let upstream_span = info_span!(
+        "upstream",
+        otel.name = %format!("{} {}", method, upstream_path),
+        otel.kind = "client",
+        http.request.method = %method,
+        server.address = %upstream_url,
+        http.response.status_code = field::Empty,
+    );
+    if state.trace_export {
+        proxy_req = proxy_req.headers(upstream_span.in_scope(trace_headers));
+    }
+
+    // Bounds the wait for the response head; bodies then stream as long as
+    // Oxigraph keeps sending
+    let timeout = if is_graph_write { state.upstream_write_timeout } else { state.upstream_timeout };
+    let sent = match timeout {
+        Some(limit) => match tokio::time::timeout(limit, proxy_req.send().instrument(upstream_span.clone())).await {
+            Ok(sent) => sent,
+            Err(_) => {
+                // The request may still be applied, so cached results go too
+                if is_graph_write {
+                    state.query_cache.invalidate_all();
+                }
+                if let Some((operations, outcome)) = &destructive {
+                    destructive::record(&state, &addr, &fingerprint, operations, *outcome, None);
+                }
+                state.metrics.upstream_error(UpstreamError::Timeout);
+                let failure = UpstreamFailure::timed_out(limit);
+                warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, request_id = %failure.id, timeout_secs = limit.as_secs(), "Upstream did not respond in time");
+                if let Some(claim) = idempotency {
+                    claim.complete(failure.status(), &HeaderMap::new(), failure.message().as_bytes());
+                }
+                return failure.respond(client_accept.as_deref());
+            }
+        },
+        None => proxy_req.send().instrument(upstream_span.clone()).await,
+    };
+    // Even a failed write may have been applied in part
+    if is_graph_write {
+        state.query_cache.invalidate_all();
+    }
+    if let Some((operations, outcome)) = &destructive {
+        let status = sent.as_ref().ok().and_then(|resp| StatusCode::from_u16(resp.status().as_u16()).ok());
+        destructive::record(&state, &addr, &fingerprint, operations, *outcome, status);
+    }
+
+    match sent {
         Ok(resp) => {
             let status = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::OK);
+            upstream_span.record("http.response.status_code", status.as_u16());
+            let transfer = info_span!("body_transfer");
+            if let Some(timer) = query_timer.as_mut() {
+                timer.set_status(status);
+            }
+            if status.is_server_error() {
+                state.metrics.upstream_error(UpstreamError::ServerError);
+            }
+
+            if invalidates_ranks && status.is_success() {
+                state.rank_cache.invalidate_all();
+                state.path_cache.invalidate_all();
+                info!(client = %addr, credential = %fingerprint, path = %path_and_query, "Rank and path caches invalidated by ACL-affecting update");
+                let roles_state = Arc::clone(&state);
+                tokio::spawn(async move { reload_roles(&roles_state).await });
+            }
+
+            if let (Some(key), Some(asset), Some(_)) = (&asset_key, &cached_asset, &revalidate_etag) {
+                if status == StatusCode::NOT_MODIFIED {
+                    state.asset_cache.touch(key);
+                    info!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, "Asset revalidated and served from cache");
+                    return state.asset_cache.respond(asset, &headers);
+                }
+            }
             let mut response_headers = HeaderMap::new();
 
             for (name, value) in resp.headers().iter() {
@@ -101,10 +650,196 @@ pub async fn proxy_handler(
                 }
             }
 
-            match resp.bytes().await {
+            // The answer to an update with an Idempotency-Key is kept for
+            // retries; updates answer with little or nothing
+            if let Some(claim) = idempotency {
+                return match read_limited(resp, MAX_REPLAY_BYTES).instrument(transfer).await {
+                    Ok(Limited::Complete(body)) => {
+                        state.metrics.received(body.len());
+                        claim.complete(status, &response_headers, &body);
+                        info!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, status = %status, bytes = body.len(), "Request proxied");
+                        (status, response_headers, body).into_response()
+                    }
+                    Ok(Limited::TooLarge(head, rest)) => {
+                        claim.complete(status, &response_headers, &[]);
+                        info!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, status = %status, "Request proxied");
+                        let head = stream::once(async move { Ok::<_, reqwest::Error>(head) });
+                        let body = counted(head.chain(rest.bytes_stream()), &state, Metrics::received);
+                        (status, response_headers, Body::from_stream(body)).into_response()
+                    }
+                    Err(e) => {
+                        claim.complete(status, &response_headers, &[]);
+                        state.metrics.upstream_error(UpstreamError::Other);
+                        let failure = UpstreamFailure::response_failed();
+                        warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, request_id = %failure.id, error = %e, "Failed to read response body");
+                        failure.respond(client_accept.as_deref())
+                    }
+                };
+            }
+
+            // The page is rendered once read in full; larger graphs are
+            // passed on as plain-text Turtle
+            if render_store_page && status == StatusCode::OK {
+                response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain; charset=utf-8"));
+                response_headers.remove(header::CONTENT_ENCODING);
+                let body = match read_limited(resp, STORE_PAGE_MAX_BYTES).instrument(transfer.clone()).await {
+                    Ok(Limited::Complete(body)) => body,
+                    Ok(Limited::TooLarge(head, rest)) => {
+                        info!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, "Graph too large to render - sent as plain text");
+                        response_headers.remove(header::CONTENT_LENGTH);
+                        let head = stream::once(async move { Ok::<_, reqwest::Error>(head) });
+                        let body = traced(counted(head.chain(rest.bytes_stream()), &state, Metrics::received), transfer);
+                        return (status, response_headers, Body::from_stream(body)).into_response();
+                    }
+                    Err(e) => {
+                        state.metrics.upstream_error(UpstreamError::Other);
+                        let failure = UpstreamFailure::response_failed();
+                        warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, request_id = %failure.id, error = %e, "Failed to read response body");
+                        return failure.respond(client_accept.as_deref());
+                    }
+                };
+                state.metrics.received(body.len());
+                info!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, status = %status, bytes = body.len(), "Graph rendered as a page");
+                let page = store_page(store_graph.as_ref(), &String::from_utf8_lossy(&body));
+                return (status, [(header::CONTENT_TYPE, "text/html; charset=utf-8")], page).into_response();
+            }
+
+            // Pages link to their neighbours; `next` is dropped below once
+            // a page read in full turns out to be the last
+            if let Some(page) = page.as_ref().filter(|_| status == StatusCode::OK) {
+                page.link(&mut response_headers, uri.path(), uri.query(), None);
+            }
+
+            // Event streams never end on their own: pass each event on as it
+            // arrives and ask intermediaries not to hold them back
+            if is_event_stream(resp.headers()) {
+                response_headers.remove(header::CONTENT_LENGTH);
+                response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+                response_headers.insert("x-accel-buffering", HeaderValue::from_static("no"));
+                info!(
+                    client = %addr,
+                    credential = %fingerprint,
+                    method = %method,
+                    path = %path_and_query,
+                    status = %status,
+                    "Event stream proxied"
+                );
+                let body = traced(counted(resp.bytes_stream(), &state, Metrics::received), transfer);
+                return (status, response_headers, Body::from_stream(body)).into_response();
+            }
+
+            // Results to convert are read in full, and cached as JSON
+            if let Some(format) = convert_to.filter(|_| status == StatusCode::OK && is_json_results(&response_headers)) {
+                let body = match read_limited(resp, state.result_conversion_max).instrument(transfer).await {
+                    Ok(Limited::Complete(body)) => body,
+                    Ok(Limited::TooLarge(..)) => {
+                        warn!(client = %addr, credential = %fingerprint, method = %method, path = %path, limit = state.result_conversion_max, "Query result too large to convert");
+                        return (
+                            StatusCode::NOT_ACCEPTABLE,
+                            "Query result too large to convert - request application/sparql-results+json instead",
+                        )
+                            .into_response();
+                    }
+                    Err(e) => {
+                        state.metrics.upstream_error(UpstreamError::Other);
+                        let failure = UpstreamFailure::response_failed();
+                        warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, request_id = %failure.id, error = %e, "Failed to read response body");
+                        return failure.respond(client_accept.as_deref());
+                    }
+                };
+                state.metrics.received(body.len());
+                if let Some(page) = &page {
+                    page.link(&mut response_headers, uri.path(), uri.query(), Some(&body));
+                }
+                if let Some(key) = query_key {
+                    state.query_cache.insert(key, query_generation, &response_headers, body.clone());
+                }
+                info!(
+                    client = %addr,
+                    credential = %fingerprint,
+                    method = %method,
+                    path = %path_and_query,
+                    status = %status,
+                    bytes = body.len(),
+                    "Request proxied with converted results"
+                );
+                return respond_converted(&headers, format, status, response_headers, body);
+            }
+
+            // Query results are buffered to be cached, up to the entry limit,
+            // and to be given an ETag, up to QUERY_ETAG_MAX_MB
+            let buffer_limit = match &query_key {
+                Some(_) => state.query_cache.max_entry_bytes().max(state.query_etag_max),
+                None => state.query_etag_max,
+            };
+            if is_query && status == StatusCode::OK && buffer_limit > 0 {
+                let body = match read_limited(resp, buffer_limit).instrument(transfer.clone()).await {
+                    Ok(Limited::Complete(body)) => {
+                        state.metrics.received(body.len());
+                        if let Some(page) = &page {
+                            page.link(&mut response_headers, uri.path(), uri.query(), Some(&body));
+                        }
+                        if let Some(key) = query_key {
+                            state.query_cache.insert(key, query_generation, &response_headers, body.clone());
+                        }
+                        body
+                    }
+                    Ok(Limited::TooLarge(head, rest)) => {
+                        if query_key.is_some() {
+                            info!(client = %addr, credential = %fingerprint, method = %method, path = %path, status = %status, "Query result too large to cache");
+                        }
+                        let head = stream::once(async move { Ok::<_, reqwest::Error>(head) });
+                        let body = counted(head.chain(rest.bytes_stream()), &state, Metrics::received);
+                        let body = traced(timed(body, query_timer.take()), transfer);
+                        return (status, response_headers, Body::from_stream(body)).into_response();
+                    }
+                    Err(e) => {
+                        state.metrics.upstream_error(UpstreamError::Other);
+                        let failure = UpstreamFailure::response_failed();
+                        warn!(client = %addr, credential = %fingerprint, method = %method, path = %path_and_query, request_id = %failure.id, error = %e, "Failed to read response body");
+                        return failure.respond(client_accept.as_deref());
+                    }
+                };
+                info!(
+                    client = %addr,
+                    credential = %fingerprint,
+                    method = %method,
+                    path = %path_and_query,
+                    status = %status,
+                    bytes = body.len(),
+                    "Request proxied"
+                );
+                return etag::respond(&headers, status, response_headers, body);
+            }
+
+            // Assets are buffered to be cached; everything else is streamed
+            let Some(key) = asset_key else {
+                info!(
+                    client = %addr,
+                    credential = %fingerprint,
+                    method = %method,
+                    path = %path_and_query,
+                    status = %status,
+                    bytes = ?resp.content_length(),
+                    "Request proxied"
+                );
+                let body = timed(counted(resp.bytes_stream(), &state, Metrics::received), query_timer.take());
+                let body = traced(body, transfer);
+                return (status, response_headers, Body::from_stream(body)).into_response();
+            };
+
+            match resp.bytes().instrument(transfer).await {
                 Ok(body) => {
+                    state.metrics.received(body.len());
+                    if status == StatusCode::OK && method == Method::GET {
+                        state.asset_cache.insert(key, &response_headers, body.clone());
+                    }
+                    if matches!(status, StatusCode::OK | StatusCode::NOT_MODIFIED) {
+                        response_headers.insert(header::CACHE_CONTROL, state.asset_cache.cache_control());
+                    }
                     info!(
                         client = %addr,
+                        credential = %fingerprint,
                         method = %method,
                         path = %path_and_query,
                         status = %status,
@@ -114,36 +849,95 @@ pub async fn proxy_handler(
                     (status, response_headers, body).into_response()
                 }
                 Err(e) => {
+                    state.metrics.upstream_error(UpstreamError::Other);
+                    let failure = UpstreamFailure::response_failed();
                     warn!(
                         client = %addr,
+                        credential = %fingerprint,
                         method = %method,
                         path = %path_and_query,
+                        request_id = %failure.id,
                         error = %e,
                         "Failed to read response body"
                     );
-                    (
-                        StatusCode::BAD_GATEWAY,
-                        format!("Failed to read response body: {}", e),
-                    )
-                        .into_response()
+                    failure.respond(client_accept.as_deref())
                 }
             }
         }
         Err(e) => {
+            let failure = if e.is_timeout() {
+                UpstreamFailure::connect_timed_out()
+            } else {
+                UpstreamFailure::unreachable()
+            };
             warn!(
                 client = %addr,
+                credential = %fingerprint,
                 method = %method,
                 path = %path_and_query,
+                request_id = %failure.id,
                 error = %e,
                 "Proxy request failed"
             );
-            (
-                StatusCode::BAD_GATEWAY,
-                format!("Proxy request failed: {}", e),
-            )
-                .into_response()
+            if is_limit_error(&e) {
+                return too_large();
+            }
+            let kind = if e.is_timeout() {
+                UpstreamError::Timeout
+            } else if e.is_connect() {
+                UpstreamError::Connect
+            } else {
+                UpstreamError::Other
+            };
+            state.metrics.upstream_error(kind);
+            if let Some(replica) = replica.filter(|_| e.is_connect()) {
+                state.replicas.evict(replica, &e.to_string());
+            }
+            failure.respond(client_accept.as_deref())
+        }
+    }
+}
+
+/// A response body read up to a limit
+enum Limited {
+    Complete(Bytes),
+    /// What was read before the limit was passed, and the unread rest
+    TooLarge(Bytes, reqwest::Response),
+}
+
+async fn read_limited(mut resp: reqwest::Response, limit: usize) -> Result<Limited, reqwest::Error> {
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() > limit {
+            return Ok(Limited::TooLarge(Bytes::from(body), resp));
         }
     }
+    Ok(Limited::Complete(Bytes::from(body)))
+}
+
+/// Request body as sent upstream
+enum UpstreamBody {
+    /// Read in full to be inspected or rewritten
+    Buffered(Bytes),
+    /// Passed through as it arrives
+    Streamed(reqwest::Body),
+}
+
+/// Whether a request is a SPARQL update
+fn is_update(path: &str) -> bool {
+    path.to_lowercase().starts_with("/update")
+}
+
+/// Whether a request goes to the Graph Store Protocol endpoint
+fn is_graph_store(path: &str) -> bool {
+    path.to_lowercase().starts_with("/store")
+}
+
+/// Whether a request writes to the store: SPARQL updates, or Graph Store
+/// Protocol writes (any non-GET/HEAD method on `/store`)
+fn is_graph_write(method: &Method, path: &str) -> bool {
+    is_update(path) || (is_graph_store(path) && !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS))
 }
 
 fn method_to_reqwest(method: &Method) -> reqwest::Method {
@@ -159,6 +953,15 @@ fn method_to_reqwest(method: &Method) -> reqwest::Method {
     }
 }
 
+/// Whether an upstream response is a Server-Sent Events stream
+fn is_event_stream(headers: &reqwest::header::HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("text/event-stream"))
+}
+
 fn should_forward_header(name: &str) -> bool {
     let name_lower = name.to_lowercase();
     !matches!(
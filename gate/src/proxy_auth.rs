@@ -0,0 +1,89 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tracing::debug;
+
+use crate::AppState;
+
+/// Headers checked for the user name when `PROXY_USER_HEADERS` is unset
+pub const DEFAULT_USER_HEADERS: &str = "X-Forwarded-User,Remote-User";
+
+/// Authentication delegated to a reverse proxy (e.g. oauth2-proxy or
+/// Authelia) that puts the logged-in user in a request header. Enabled by
+/// `TRUSTED_PROXY_IPS`; the header is only honoured from those addresses.
+pub struct ProxyAuthConfig {
+    /// Peer addresses allowed to assert a user
+    pub trusted: Vec<IpAddr>,
+    /// Headers carrying the user name, checked in order
+    pub headers: Vec<String>,
+    /// Explicit user -> rank assignments
+    pub ranks: Vec<(String, i32)>,
+    /// Rank for users without an explicit assignment (0 ignores them)
+    pub default_rank: i32,
+}
+
+impl ProxyAuthConfig {
+    /// Parse `TRUSTED_PROXY_IPS`: IP addresses separated by `,`
+    pub fn parse_trusted(value: &str) -> Result<Vec<IpAddr>, String> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty())
+            .map(|ip| {
+                ip.parse::<IpAddr>()
+                    .map(|ip| ip.to_canonical())
+                    .map_err(|_| format!("invalid IP address {}", ip))
+            })
+            .collect()
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted.contains(&ip.to_canonical())
+    }
+
+    fn rank_for(&self, user: &str) -> i32 {
+        self.ranks
+            .iter()
+            .find(|(name, _)| name == user)
+            .map(|(_, rank)| *rank)
+            .unwrap_or(self.default_rank)
+    }
+
+    /// The asserted user and their rank. Only meaningful after
+    /// [`strip_untrusted_user_headers`] removed headers from other peers.
+    pub fn user(&self, headers: &HeaderMap) -> Option<(String, i32)> {
+        let user = self.headers.iter().find_map(|name| {
+            let value = headers.get(name.as_str())?.to_str().ok()?.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        })?;
+        let rank = self.rank_for(&user);
+        (rank > 0).then_some((user, rank))
+    }
+}
+
+/// Middleware removing the user headers from requests that do not come
+/// from a trusted proxy, so clients cannot assert a user themselves
+pub async fn strip_untrusted_user_headers(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    if let Some(config) = &state.proxy_auth {
+        if !config.is_trusted(addr.ip()) {
+            for name in &config.headers {
+                if req.headers_mut().remove(name.as_str()).is_some() {
+                    debug!(client = %addr, header = %name, "Dropped user header from untrusted peer");
+                }
+            }
+        }
+    }
+    next.run(req).await
+}
@@ -0,0 +1,135 @@
+use axum::http::Method;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+use crate::files::{get_readable_graphs, Credential};
+use crate::update_graphs::form_params;
+use crate::AppState;
+
+/// SPARQL protocol parameters that set the query dataset
+const DATASET_PARAMS: [&str; 2] = ["default-graph-uri", "named-graph-uri"];
+
+/// Whether a request is a SPARQL query that may be answered from the
+/// public graphs
+pub fn is_public_query(method: &Method, path: &str) -> bool {
+    matches!(*method, Method::GET | Method::POST) && path.eq_ignore_ascii_case("/query")
+}
+
+/// Graphs a query from a requester lacking `required_rank` may still read:
+/// the public graphs when anonymous and, with `RESTRICT_QUERY_GRAPHS`, those
+/// policies grant the rank on. Empty when the query must be refused.
+pub async fn permitted_graphs(state: &AppState, credential: &Credential, required_rank: i32) -> Vec<String> {
+    let mut graphs = match credential {
+        Credential::Anonymous => state.public_graphs.clone(),
+        _ => Vec::new(),
+    };
+    if state.restrict_query_graphs {
+        for graph in get_readable_graphs(state, credential, required_rank).await {
+            if !graphs.contains(&graph) {
+                graphs.push(graph);
+            }
+        }
+    }
+    graphs
+}
+
+/// Percent-encode parameters as a URL query string or form body
+pub fn encode_params(params: &[(String, String)]) -> String {
+    params
+        .iter()
+        .map(|(k, v)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(k, NON_ALPHANUMERIC),
+                utf8_percent_encode(v, NON_ALPHANUMERIC)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Rewrite a query request so it can only read `graphs`. The public graphs
+/// become both the default graph (their union) and the only named graphs,
+/// via the protocol's dataset parameters, which override `FROM` and
+/// `FROM NAMED` in the query. Dataset parameters sent by the client are
+/// dropped.
+///
+/// Returns the new URL query string and, for form-encoded requests, the
+/// new body.
+pub fn restrict_to_graphs(
+    query_string: Option<&str>,
+    content_type: Option<&str>,
+    body: &[u8],
+    graphs: &[String],
+) -> (String, Option<Vec<u8>>) {
+    let without_dataset = |params: Vec<(String, String)>| -> Vec<(String, String)> {
+        params
+            .into_iter()
+            .filter(|(k, _)| !DATASET_PARAMS.contains(&k.as_str()))
+            .collect()
+    };
+    let dataset: Vec<(String, String)> = DATASET_PARAMS
+        .iter()
+        .flat_map(|param| graphs.iter().map(|g| (param.to_string(), g.clone())))
+        .collect();
+
+    let mut url_params = without_dataset(form_params(query_string.unwrap_or("")));
+
+    let is_form = content_type.is_some_and(|ct| ct.starts_with("application/x-www-form-urlencoded"));
+    if is_form {
+        let mut body_params = without_dataset(form_params(&String::from_utf8_lossy(body)));
+        body_params.extend(dataset);
+        (encode_params(&url_params), Some(encode_params(&body_params).into_bytes()))
+    } else {
+        url_params.extend(dataset);
+        (encode_params(&url_params), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORM: Option<&str> = Some("application/x-www-form-urlencoded");
+
+    fn graphs() -> Vec<String> {
+        vec!["http://ex.org/a".to_string(), "http://ex.org/b".to_string()]
+    }
+
+    /// `params` followed by the dataset of `graphs()`
+    fn with_dataset(params: &[(&str, &str)]) -> Vec<(String, String)> {
+        let mut params: Vec<(String, String)> = params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        for param in DATASET_PARAMS {
+            params.extend(graphs().into_iter().map(|g| (param.to_string(), g)));
+        }
+        params
+    }
+
+    #[test]
+    fn test_restrict_url_query() {
+        let query_string = "query=SELECT+*+%7B%3Fs+%3Fp+%3Fo%7D&default-graph-uri=http%3A%2F%2Fex.org%2Fsecret";
+        let (url, body) = restrict_to_graphs(Some(query_string), None, b"", &graphs());
+        assert_eq!(form_params(&url), with_dataset(&[("query", "SELECT * {?s ?p ?o}")]));
+        assert_eq!(body, None);
+    }
+
+    #[test]
+    fn test_restrict_form_body() {
+        // Dataset parameters are dropped from the URL and the body, however encoded
+        let query_string = "named-graph-uri=http%3A%2F%2Fex.org%2Fsecret&timeout=5";
+        let body = b"default%2Dgraph%2Duri=http%3A%2F%2Fex.org%2Fsecret&query=ASK+%7B%7D";
+        let (url, body) = restrict_to_graphs(Some(query_string), FORM, body, &graphs());
+        assert_eq!(form_params(&url), vec![("timeout".to_string(), "5".to_string())]);
+        let body = String::from_utf8(body.expect("form body rewritten")).unwrap();
+        assert_eq!(form_params(&body), with_dataset(&[("query", "ASK {}")]));
+    }
+
+    #[test]
+    fn test_restrict_sparql_query_body() {
+        // A query body is left alone; the dataset goes into the URL, where
+        // it overrides FROM and FROM NAMED in the query
+        let body = b"SELECT * FROM <http://ex.org/secret> {}";
+        let (url, body) = restrict_to_graphs(None, Some("application/sparql-query"), body, &graphs());
+        assert_eq!(form_params(&url), with_dataset(&[]));
+        assert_eq!(body, None);
+    }
+}
@@ -0,0 +1,195 @@
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+};
+use bytes::Bytes;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::etag;
+use crate::results::{respond_converted, ResultFormat};
+use crate::update_graphs::form_params;
+
+/// Default upper bound for all cached results together in MiB
+pub const DEFAULT_QUERY_CACHE_MAX_MB: usize = 64;
+
+/// Share of the cache a single result may take (1/8)
+const MAX_ENTRY_SHARE: usize = 8;
+
+#[derive(Clone)]
+pub struct CachedResult {
+    headers: HeaderMap,
+    body: Bytes,
+    stored: Instant,
+}
+
+/// In-memory cache of `/query` results, so repeated dashboard queries are
+/// answered without Oxigraph.
+///
+/// Entries are keyed by the normalized query (its parameters decoded and
+/// sorted, whether sent in the URL, as a form or as a
+/// `application/sparql-query` body), the negotiated `Accept` and
+/// `Accept-Encoding`, and the requester's scope: queries restricted to a
+/// set of graphs only share entries with queries restricted to the same
+/// set, never with unrestricted ones. The cache sits behind the rank check, and the whole cache is
+/// dropped whenever an update passes through the gate or the gate sends
+/// one itself. Results still in flight when that happens are not stored.
+pub struct QueryCache {
+    ttl: Duration,
+    max_bytes: usize,
+    entries: RwLock<HashMap<String, CachedResult>>,
+    generation: AtomicU64,
+}
+
+impl QueryCache {
+    /// A zero TTL or size disables the cache
+    pub fn new(ttl: Duration, max_bytes: usize) -> Self {
+        Self {
+            ttl,
+            max_bytes,
+            entries: RwLock::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero() && self.max_bytes > 0
+    }
+
+    /// Largest result that is cached
+    pub fn max_entry_bytes(&self) -> usize {
+        self.max_bytes / MAX_ENTRY_SHARE
+    }
+
+    /// Cache key for a query request restricted to `scope` (all graphs when
+    /// `None`), or `None` when its body is neither a form nor a SPARQL query
+    pub fn key(
+        scope: Option<&[String]>,
+        headers: &HeaderMap,
+        query_string: Option<&str>,
+        body: &[u8],
+    ) -> Option<String> {
+        let mut params = query_params(headers, query_string, body)?;
+        for (key, value) in &mut params {
+            if key == "query" {
+                *value = value.trim().to_string();
+            }
+        }
+        params.sort();
+
+        let scope = match scope {
+            Some(graphs) => {
+                let mut graphs = graphs.to_vec();
+                graphs.sort();
+                serde_json::to_string(&graphs).unwrap()
+            }
+            None => "all".to_string(),
+        };
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or("");
+        let mut key = format!(
+            "{}\n{}\n{}",
+            scope,
+            header(header::ACCEPT),
+            header(header::ACCEPT_ENCODING),
+        );
+        for (name, value) in params {
+            key.push('\n');
+            key.push_str(&serde_json::to_string(&(name, value)).unwrap());
+        }
+        Some(key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedResult> {
+        let entries = self.entries.read().unwrap();
+        let result = entries.get(key)?;
+        (result.stored.elapsed() < self.ttl).then(|| result.clone())
+    }
+
+    /// Current generation, to be passed back to [`QueryCache::insert`]
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Store a `200` result fetched during `generation`. Results fetched
+    /// before the last invalidation and oversized ones are dropped; when
+    /// the cache is full, expired and then the oldest entries go first.
+    pub fn insert(&self, key: String, generation: u64, headers: &HeaderMap, body: Bytes) {
+        if body.len() > self.max_entry_bytes() {
+            return;
+        }
+        let mut entries = self.entries.write().unwrap();
+        if generation != self.generation() {
+            return;
+        }
+        entries.remove(&key);
+
+        let ttl = self.ttl;
+        entries.retain(|_, result| result.stored.elapsed() < ttl);
+        let mut total: usize = entries.values().map(|r| r.body.len()).sum();
+        while total + body.len() > self.max_bytes {
+            let Some(oldest) = entries.iter().min_by_key(|(_, r)| r.stored).map(|(k, _)| k.clone()) else {
+                break;
+            };
+            if let Some(removed) = entries.remove(&oldest) {
+                total -= removed.body.len();
+            }
+        }
+
+        entries.insert(
+            key,
+            CachedResult {
+                headers: headers.clone(),
+                body,
+                stored: Instant::now(),
+            },
+        );
+    }
+
+    pub fn invalidate_all(&self) {
+        let mut entries = self.entries.write().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        entries.clear();
+    }
+
+    /// Answer with a cached result, converted when the client asked for
+    /// another result format, or `304` when the client holds it already
+    pub fn respond(
+        &self,
+        request_headers: &HeaderMap,
+        result: &CachedResult,
+        convert_to: Option<ResultFormat>,
+    ) -> Response {
+        let (headers, body) = (result.headers.clone(), result.body.clone());
+        match convert_to {
+            Some(format) => respond_converted(request_headers, format, StatusCode::OK, headers, body),
+            None => etag::respond(request_headers, StatusCode::OK, headers, body),
+        }
+    }
+}
+
+/// The protocol parameters of a query request, decoded, whether sent in
+/// the URL, as a form or as a `application/sparql-query` body (`query`);
+/// `None` when the body is none of these
+pub fn query_params(headers: &HeaderMap, query_string: Option<&str>, body: &[u8]) -> Option<Vec<(String, String)>> {
+    let mut params = form_params(query_string.unwrap_or(""));
+    if !body.is_empty() {
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map(|v| v.trim().to_ascii_lowercase());
+        let body = std::str::from_utf8(body).ok()?;
+        match content_type.as_deref() {
+            Some("application/x-www-form-urlencoded") => params.extend(form_params(body)),
+            Some("application/sparql-query") => params.push(("query".to_string(), body.to_string())),
+            _ => return None,
+        }
+    }
+    Some(params)
+}
@@ -0,0 +1,331 @@
+/// Rank from which requesters are exempt from the limits when
+/// `QUERY_LIMITS_EXEMPT_RANK` is unset (edit)
+pub const DEFAULT_QUERY_LIMITS_EXEMPT_RANK: i32 = 3;
+
+/// Limits on the shape of queries from low-rank requesters, so one viewer
+/// cannot tie up the store with unbounded or runaway queries
+/// (`QUERY_REQUIRE_LIMIT`, `QUERY_MAX_TRIPLE_PATTERNS`,
+/// `QUERY_REJECT_TRANSITIVE_PATHS`)
+pub struct QueryLimits {
+    /// `SELECT`, `CONSTRUCT` and `DESCRIBE` need a top-level `LIMIT`
+    pub require_limit: bool,
+    /// Most triple patterns a query may have (0 for no limit)
+    pub max_triple_patterns: usize,
+    /// Reject `*` and `+` property paths
+    pub reject_transitive_paths: bool,
+    /// Requesters with at least this rank on the SPARQL endpoints are not checked
+    pub exempt_rank: i32,
+}
+
+impl QueryLimits {
+    /// Whether queries from a requester with `rank` are checked
+    pub fn applies_to(&self, rank: i32) -> bool {
+        (self.require_limit || self.max_triple_patterns > 0 || self.reject_transitive_paths) && rank < self.exempt_rank
+    }
+
+    /// The reason `query` is refused, if it is. The query is only scanned,
+    /// not parsed, so malformed queries are left to Oxigraph.
+    pub fn check(&self, query: &str) -> Result<(), String> {
+        let shape = Shape::of(query);
+        let unbounded = !shape.limit && matches!(shape.form.as_deref(), Some("SELECT" | "CONSTRUCT" | "DESCRIBE"));
+        if self.require_limit && unbounded {
+            return Err("add a LIMIT clause".to_string());
+        }
+        if self.max_triple_patterns > 0 && shape.triple_patterns > self.max_triple_patterns {
+            return Err(format!(
+                "{} triple patterns, at most {} allowed",
+                shape.triple_patterns, self.max_triple_patterns
+            ));
+        }
+        if self.reject_transitive_paths && shape.transitive_paths {
+            return Err("property paths with * or + are not allowed".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Dot,
+    Semicolon,
+    Comma,
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    /// `*` or `+`: a path repeated any number of times, outside expressions
+    Repeat,
+    /// Keyword or function name, uppercased
+    Word(String),
+    /// IRI, prefixed name, variable, literal or `a`
+    Term,
+    /// Operators and punctuation irrelevant to the shape
+    Other,
+}
+
+/// Tokens with the char index they start at
+fn tokenize(text: &str) -> Vec<(Token, usize)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let is_name = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | ':' | '.' | '%');
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let token = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '<' => {
+                // An IRI, or a comparison operator
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|c| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\'));
+                match end {
+                    Some(len) if chars[i + 1 + len] == '>' => {
+                        i += len + 2;
+                        Token::Term
+                    }
+                    _ => {
+                        i += 1;
+                        Token::Other
+                    }
+                }
+            }
+            '"' | '\'' => {
+                let long = chars[i..].starts_with(&[c, c, c]);
+                i += if long { 3 } else { 1 };
+                while i < chars.len() {
+                    if chars[i] == '\\' {
+                        i += 2;
+                    } else if long && chars[i..].starts_with(&[c, c, c]) {
+                        i += 3;
+                        break;
+                    } else if !long && chars[i] == c {
+                        i += 1;
+                        break;
+                    } else {
+                        i += 1;
+                    }
+                }
+                // Language tag
+                if chars.get(i) == Some(&'@') {
+                    i += 1;
+                    while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '-') {
+                        i += 1;
+                    }
+                }
+                Token::Term
+            }
+            '?' | '$' if chars.get(i + 1).is_some_and(|c| c.is_alphanumeric() || *c == '_') => {
+                i += 1;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                Token::Term
+            }
+            '+' | '-' if chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                    i += 1;
+                }
+                Token::Term
+            }
+            '*' | '+' => {
+                i += 1;
+                Token::Repeat
+            }
+            '{' | '}' | '.' | ';' | ',' | '(' | ')' | '[' | ']' => {
+                i += 1;
+                match c {
+                    '{' => Token::Open,
+                    '}' => Token::Close,
+                    '.' => Token::Dot,
+                    ';' => Token::Semicolon,
+                    ',' => Token::Comma,
+                    '(' => Token::OpenParen,
+                    ')' => Token::CloseParen,
+                    '[' => Token::OpenBracket,
+                    _ => Token::CloseBracket,
+                }
+            }
+            _ if is_name(c) && !matches!(c, '-' | '%') => {
+                let start = i;
+                while i < chars.len() && is_name(chars[i]) {
+                    i += 1;
+                }
+                // A trailing `.` ends the triple rather than the name
+                let mut end = i;
+                while end > start + 1 && chars[end - 1] == '.' {
+                    end -= 1;
+                }
+                i = end;
+                let word: String = chars[start..end].iter().collect();
+                let upper = word.to_uppercase();
+                if word.contains(':') || word == "a" || c.is_ascii_digit() || upper == "TRUE" || upper == "FALSE" {
+                    Token::Term
+                } else {
+                    Token::Word(upper)
+                }
+            }
+            _ => {
+                i += 1;
+                Token::Other
+            }
+        };
+        tokens.push((token, start));
+    }
+    tokens
+}
+
+/// What the limits and pagination look at in a query
+#[derive(Default)]
+pub struct Shape {
+    /// `SELECT`, `CONSTRUCT`, `DESCRIBE` or `ASK`
+    pub form: Option<String>,
+    /// Whether the outermost query has a `LIMIT`
+    pub limit: bool,
+    /// Whether the outermost query has an `OFFSET`
+    pub offset: bool,
+    /// Char index of a `VALUES` clause ending the outermost query
+    pub values_at: Option<usize>,
+    pub triple_patterns: usize,
+    pub transitive_paths: bool,
+}
+
+impl Shape {
+    /// Scan the query's groups statement by statement. A triple statement
+    /// counts one pattern, plus one per `;` and `,` continuing it and per
+    /// blank node property list. Expressions (`FILTER`, `BIND`, solution
+    /// modifiers), `VALUES` data and the `CONSTRUCT` template are skipped.
+    pub fn of(query: &str) -> Self {
+        let (tokens, starts): (Vec<Token>, Vec<usize>) = tokenize(query).into_iter().unzip();
+        let mut shape = Shape::default();
+        let mut depth = 0usize;
+        // Patterns of the statement being read; 0 while it is empty
+        let mut statement = 0usize;
+        let mut i = 0;
+
+        while i < tokens.len() {
+            match &tokens[i] {
+                Token::Open | Token::Close | Token::Dot => {
+                    // A term before `{` names a graph or service, not a triple
+                    if tokens[i] != Token::Open {
+                        shape.triple_patterns += statement;
+                    }
+                    statement = 0;
+                    match tokens[i] {
+                        Token::Open => depth += 1,
+                        Token::Close => depth = depth.saturating_sub(1),
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                Token::Word(word) if depth == 0 => {
+                    match word.as_str() {
+                        "SELECT" | "CONSTRUCT" | "DESCRIBE" | "ASK" if shape.form.is_none() => {
+                            shape.form = Some(word.clone());
+                        }
+                        "LIMIT" => shape.limit = true,
+                        "OFFSET" => shape.offset = true,
+                        "VALUES" if shape.form.is_some() => shape.values_at = Some(starts[i]),
+                        _ => {}
+                    }
+                    i += 1;
+                    if word == "CONSTRUCT" && tokens.get(i) == Some(&Token::Open) {
+                        i = skip_group(&tokens, i, &Token::Open, &Token::Close);
+                    }
+                    if word == "VALUES" {
+                        while i < tokens.len() && tokens[i] != Token::Open {
+                            i += 1;
+                        }
+                        i = skip_group(&tokens, i, &Token::Open, &Token::Close);
+                    }
+                }
+                _ if depth == 0 => i += 1,
+                Token::Word(word) => {
+                    shape.triple_patterns += statement;
+                    statement = 0;
+                    i += 1;
+                    match word.as_str() {
+                        "FILTER" => {
+                            if matches!(tokens.get(i), Some(Token::Word(_) | Token::Term))
+                                && tokens.get(i + 1) == Some(&Token::OpenParen)
+                            {
+                                i += 1;
+                            }
+                            if tokens.get(i) == Some(&Token::OpenParen) {
+                                i = skip_group(&tokens, i, &Token::OpenParen, &Token::CloseParen);
+                            }
+                        }
+                        "BIND" if tokens.get(i) == Some(&Token::OpenParen) => {
+                            i = skip_group(&tokens, i, &Token::OpenParen, &Token::CloseParen);
+                        }
+                        "VALUES" => {
+                            while i < tokens.len() && tokens[i] != Token::Open {
+                                i += 1;
+                            }
+                            i = skip_group(&tokens, i, &Token::Open, &Token::Close);
+                        }
+                        // Group keywords are followed by patterns
+                        "OPTIONAL" | "MINUS" | "UNION" | "NOT" | "EXISTS" => {}
+                        // Subquery clauses, GRAPH and SERVICE run to the next group
+                        _ => {
+                            while i < tokens.len() && !matches!(tokens[i], Token::Open | Token::Close | Token::Dot) {
+                                i += 1;
+                            }
+                        }
+                    }
+                }
+                token => {
+                    if statement == 0 {
+                        statement = 1;
+                    }
+                    let next = tokens.get(i + 1);
+                    match token {
+                        // A trailing `;` continues nothing
+                        Token::Semicolon
+                            if !matches!(next, Some(Token::Dot | Token::Close | Token::Semicolon | Token::CloseBracket)) =>
+                        {
+                            statement += 1
+                        }
+                        Token::Comma => statement += 1,
+                        Token::OpenBracket if next != Some(&Token::CloseBracket) => statement += 1,
+                        Token::Repeat => shape.transitive_paths = true,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+            }
+        }
+        shape
+    }
+}
+
+/// Index after the group opened at `start`, nested groups included
+fn skip_group(tokens: &[Token], start: usize, open: &Token, close: &Token) -> usize {
+    let mut depth = 0usize;
+    let mut i = start;
+    while i < tokens.len() {
+        if tokens[i] == *open {
+            depth += 1;
+        } else if tokens[i] == *close {
+            depth -= 1;
+            if depth == 0 {
+                return i + 1;
+            }
+        }
+        i += 1;
+    }
+    i
+}
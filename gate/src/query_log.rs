@@ -0,0 +1,311 @@
+use axum::{
+    extract::{ConnectInfo, Query, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::admin::{forbidden, format_time, is_admin};
+use crate::templates::{escape_html, QUERIES_HTML};
+use crate::AppState;
+
+/// Generated queries kept for the admin page
+const RECENT_QUERIES: usize = 200;
+
+/// Placeholder for redacted string literals
+const REDACTED_STRING: &str = "\"***\"";
+/// Placeholder for redacted numeric literals
+const REDACTED_NUMBER: &str = "***";
+
+/// Which literal values are masked in logged queries (`QUERY_LOG_REDACT`)
+#[derive(Clone, Copy, PartialEq)]
+pub enum Redaction {
+    /// Log queries as sent
+    None,
+    /// Mask string literals (labels, names, token hashes)
+    Strings,
+    /// Mask string and numeric literals
+    Literals,
+}
+
+impl Redaction {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "none" => Ok(Redaction::None),
+            "strings" => Ok(Redaction::Strings),
+            "literals" => Ok(Redaction::Literals),
+            other => Err(format!("unknown redaction {} (expected none, strings or literals)", other)),
+        }
+    }
+}
+
+/// A generated query, as shown on the admin page
+#[derive(Clone)]
+pub struct LoggedQuery {
+    pub time: DateTime<Utc>,
+    pub request: u64,
+    pub method: String,
+    pub path: String,
+    /// `query` or `update`
+    pub kind: &'static str,
+    /// Query text after redaction
+    pub query: String,
+}
+
+/// The request a generated query is run for
+#[derive(Clone)]
+struct RequestContext {
+    state: Arc<AppState>,
+    id: u64,
+    method: String,
+    path: String,
+}
+
+tokio::task_local! {
+    static REQUEST: RequestContext;
+}
+
+/// Opt-in log of the SPARQL the gate generates itself (access checks,
+/// metadata lookups, admin changes), never the queries clients send through
+/// the proxy. Each query is logged in the span of the request it was run
+/// for and kept in a short in-memory tail for `/gate/admin/queries`.
+/// Queries run outside a request, such as background tasks, are not logged.
+pub struct QueryLog {
+    enabled: bool,
+    redaction: Redaction,
+    next_request: AtomicU64,
+    recent: Mutex<VecDeque<LoggedQuery>>,
+}
+
+impl QueryLog {
+    pub fn new(enabled: bool, redaction: Redaction) -> Self {
+        Self {
+            enabled,
+            redaction,
+            next_request: AtomicU64::new(1),
+            recent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Logged queries, newest first
+    pub fn recent(&self) -> Vec<LoggedQuery> {
+        self.recent.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    fn push(&self, entry: LoggedQuery) {
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() >= RECENT_QUERIES {
+            recent.pop_front();
+        }
+        recent.push_back(entry);
+    }
+}
+
+/// Middleware giving each request a span with a request ID, under which
+/// its generated queries are logged. Does nothing when logging is off.
+pub async fn query_log_middleware(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if !state.query_log.is_enabled() {
+        return next.run(req).await;
+    }
+    let context = RequestContext {
+        id: state.query_log.next_request.fetch_add(1, Ordering::Relaxed),
+        method: req.method().to_string(),
+        path: req.uri().path().to_string(),
+        state: Arc::clone(&state),
+    };
+    let span = info_span!(
+        "request",
+        id = context.id,
+        client = %addr,
+        method = %context.method,
+        path = %context.path,
+    );
+    REQUEST.scope(context, next.run(req).instrument(span)).await
+}
+
+/// Log a generated query for the current request, if logging is on
+pub fn record(kind: &'static str, query: &str) {
+    let _ = REQUEST.try_with(|context| {
+        let log = &context.state.query_log;
+        let query = redact(query, log.redaction);
+        info!(kind = kind, query = ?query, "Generated SPARQL");
+        log.push(LoggedQuery {
+            time: Utc::now(),
+            request: context.id,
+            method: context.method.clone(),
+            path: context.path.clone(),
+            kind,
+            query,
+        });
+    });
+}
+
+/// Mask literal values in a SPARQL query. IRIs (`<...>`) are kept, so
+/// `#` and quotes inside them are not mistaken for literals; a `<` that
+/// does not open an IRI is a comparison operator.
+pub fn redact(query: &str, redaction: Redaction) -> String {
+    if redaction == Redaction::None {
+        return query.to_string();
+    }
+    let chars: Vec<char> = query.chars().collect();
+    let mut out = String::with_capacity(query.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '<' {
+            if let Some(len) = iri_len(&chars[i..]) {
+                out.extend(&chars[i..i + len]);
+                i += len;
+                continue;
+            }
+        }
+        if c == '"' || c == '\'' {
+            i += string_len(&chars[i..]);
+            out.push_str(REDACTED_STRING);
+            continue;
+        }
+        if redaction == Redaction::Literals && c.is_ascii_digit() && starts_number(&chars, i) {
+            i += number_len(&chars[i..]);
+            out.push_str(REDACTED_NUMBER);
+            continue;
+        }
+        out.push(c);
+        i += 1;
+    }
+    out
+}
+
+/// Length of an IRI reference at the start of `chars`, if it is one
+fn iri_len(chars: &[char]) -> Option<usize> {
+    let end = chars[1..].iter().position(|c| {
+        c.is_whitespace() || matches!(c, '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\')
+    })? + 1;
+    (chars[end] == '>').then_some(end + 1)
+}
+
+/// Length of a string literal (short or long form, with escapes) at the
+/// start of `chars`; an unterminated literal runs to the end
+fn string_len(chars: &[char]) -> usize {
+    let quote = chars[0];
+    let long = chars.len() >= 3 && chars[1] == quote && chars[2] == quote;
+    let mut i = if long { 3 } else { 1 };
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            c if c == quote && !long => return i + 1,
+            c if c == quote && chars.len() >= i + 3 && chars[i + 1] == quote && chars[i + 2] == quote => {
+                return i + 3;
+            }
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+/// Whether the digit at `i` starts a numeric literal rather than being
+/// part of a name such as `?v1` or `liqk:access-level-2`
+fn starts_number(chars: &[char], i: usize) -> bool {
+    i == 0 || !(chars[i - 1].is_alphanumeric() || matches!(chars[i - 1], '_' | ':' | '?' | '$' | '-' | '.'))
+}
+
+/// Length of a numeric literal (integer, decimal or double) at the start of `chars`
+fn number_len(chars: &[char]) -> usize {
+    let digits = |from: usize| chars[from..].iter().take_while(|c| c.is_ascii_digit()).count();
+    let mut i = digits(0);
+    if chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) {
+        i += 1 + digits(i + 1);
+    }
+    if matches!(chars.get(i), Some('e' | 'E')) {
+        let sign = usize::from(matches!(chars.get(i + 1), Some('+' | '-')));
+        let exponent = digits(i + 1 + sign);
+        if exponent > 0 {
+            i += 1 + sign + exponent;
+        }
+    }
+    i
+}
+
+fn render_queries(queries: &[LoggedQuery]) -> String {
+    if queries.is_empty() {
+        return r#"<tr><td class="empty" colspan="4">No queries logged</td></tr>"#.to_string();
+    }
+    queries
+        .iter()
+        .map(|q| {
+            format!(
+                r#"<tr><td>{}</td><td><a href="?request={}">#{}</a> {} {}</td><td>{}</td><td><pre>{}</pre></td></tr>"#,
+                format_time(q.time),
+                q.request,
+                q.request,
+                escape_html(&q.method),
+                escape_html(&q.path),
+                q.kind,
+                escape_html(&q.query),
+            )
+        })
+        .collect()
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+#[derive(Deserialize)]
+pub struct QueriesParams {
+    /// Only show the queries of this request
+    request: Option<u64>,
+}
+
+/// GET /gate/admin/queries?request=N - Recent generated queries (admin only)
+pub async fn queries_admin_page(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Query(params): Query<QueriesParams>,
+) -> Response {
+    if !is_admin(&state, &headers, &jar).await {
+        warn!(client = %addr, "Access denied - administration requires admin rank");
+        return forbidden();
+    }
+
+    let status = if state.query_log.is_enabled() {
+        "Newest first; refreshes every 5 seconds."
+    } else {
+        "Query logging is off. Set QUERY_LOG=true to record generated queries."
+    };
+    let queries: Vec<LoggedQuery> = state
+        .query_log
+        .recent()
+        .into_iter()
+        .filter(|q| params.request.is_none_or(|id| q.request == id))
+        .collect();
+
+    Html(
+        QUERIES_HTML
+            .replace("{status}", status)
+            .replace("{queries}", &render_queries(&queries)),
+    )
+    .into_response()
+}
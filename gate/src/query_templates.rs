@@ -0,0 +1,539 @@
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::{net::SocketAddr, sync::Arc};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::admin::{forbidden, is_admin};
+use crate::files::{
+    binding_value, credential, escape_sparql_string, get_access_rank_iri, send_sparql_update, send_sparql_update_once,
+    sparql_select, Credential,
+};
+use crate::proxy::GRAPH_IRI;
+use crate::public_graphs::permitted_graphs;
+use crate::saved_queries::{execute_query, QUERIES_GRAPH};
+use crate::update_graphs::{form_params, is_valid_iri};
+use crate::AppState;
+
+/// Maximum length of a template name; shorter than a saved query
+/// capability, so `/q/{name}` and `/q/{capability}` never collide
+const MAX_NAME_LEN: usize = 48;
+/// Maximum length of a template
+const MAX_TEMPLATE_LEN: usize = 64 * 1024;
+/// Maximum number of parameters of a template
+const MAX_PARAMS: usize = 32;
+
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// Type of a template parameter, which decides how values are checked and
+/// written into the query
+#[derive(Clone, Copy, PartialEq)]
+enum ParamType {
+    String,
+    Integer,
+    Decimal,
+    Boolean,
+    Date,
+    DateTime,
+    Iri,
+}
+
+impl ParamType {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "string" => Some(ParamType::String),
+            "integer" => Some(ParamType::Integer),
+            "decimal" => Some(ParamType::Decimal),
+            "boolean" => Some(ParamType::Boolean),
+            "date" => Some(ParamType::Date),
+            "dateTime" => Some(ParamType::DateTime),
+            "iri" => Some(ParamType::Iri),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            ParamType::String => "string",
+            ParamType::Integer => "integer",
+            ParamType::Decimal => "decimal",
+            ParamType::Boolean => "boolean",
+            ParamType::Date => "date",
+            ParamType::DateTime => "dateTime",
+            ParamType::Iri => "iri",
+        }
+    }
+
+    /// The SPARQL term for `value`. Numbers and booleans are written bare,
+    /// so they also fit where the grammar wants a number (`LIMIT $n`).
+    fn term(self, value: &str) -> Result<String, String> {
+        let invalid = || format!("not a valid {}", self.as_str());
+        match self {
+            ParamType::String => Ok(format!("\"{}\"", escape_sparql_string(value))),
+            ParamType::Integer => value.trim().parse::<i64>().map(|n| n.to_string()).map_err(|_| invalid()),
+            ParamType::Decimal => {
+                let value = value.trim();
+                let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+                let (whole, fraction) = digits.split_once('.').unwrap_or((digits, "0"));
+                let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+                if fraction.is_empty() || !all_digits(whole) || !all_digits(fraction) {
+                    return Err(invalid());
+                }
+                let sign = if value.starts_with('-') { "-" } else { "" };
+                Ok(format!("{}{}.{}", sign, if whole.is_empty() { "0" } else { whole }, fraction))
+            }
+            ParamType::Boolean => match value.trim() {
+                "true" | "false" => Ok(value.trim().to_string()),
+                _ => Err(invalid()),
+            },
+            ParamType::Date => NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+                .map(|date| format!("\"{}\"^^<{}date>", date.format("%Y-%m-%d"), XSD))
+                .map_err(|_| invalid()),
+            ParamType::DateTime => DateTime::parse_from_rfc3339(value.trim())
+                .map(|time| format!("\"{}\"^^<{}dateTime>", time.to_rfc3339(), XSD))
+                .map_err(|_| invalid()),
+            ParamType::Iri => {
+                let iri = value.trim();
+                if is_valid_iri(iri) && reqwest::Url::parse(iri).is_ok() {
+                    Ok(format!("<{}>", iri))
+                } else {
+                    Err("not an absolute IRI".to_string())
+                }
+            }
+        }
+    }
+}
+
+/// A named query with `$name` placeholders for its parameters
+struct QueryTemplate {
+    name: String,
+    text: String,
+    params: Vec<(String, ParamType)>,
+}
+
+impl QueryTemplate {
+    /// The query with every placeholder replaced by the value given for it
+    /// in `query_string`. Parameters not declared by the template are
+    /// refused rather than ignored, to catch typos.
+    fn bind(&self, query_string: Option<&str>) -> Result<String, String> {
+        let given = form_params(query_string.unwrap_or(""));
+        if let Some((name, _)) = given.iter().find(|(name, _)| !self.params.iter().any(|(p, _)| p == name)) {
+            return Err(format!("Unknown parameter: {}", name));
+        }
+        let mut terms = Vec::with_capacity(self.params.len());
+        for (name, param_type) in &self.params {
+            let Some((_, value)) = given.iter().find(|(n, _)| n == name) else {
+                return Err(format!("Missing parameter: {}", name));
+            };
+            let term = param_type.term(value).map_err(|e| format!("Parameter {}: {}", name, e))?;
+            terms.push((name.as_str(), term));
+        }
+        Ok(substitute(&self.text, &terms))
+    }
+}
+
+/// Replace `$name` placeholders with their terms. Strings, IRIs and
+/// comments are copied as they are, and `?name` variables are never
+/// placeholders.
+fn substitute(text: &str, terms: &[(&str, String)]) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    let is_name = |c: char| c.is_alphanumeric() || c == '_';
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        match c {
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '<' => {
+                // An IRI, or a comparison operator
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|c| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\'));
+                i += match end {
+                    Some(len) if chars[i + 1 + len] == '>' => len + 2,
+                    _ => 1,
+                };
+            }
+            '"' | '\'' => {
+                let long = chars[i..].starts_with(&[c, c, c]);
+                i += if long { 3 } else { 1 };
+                while i < chars.len() {
+                    if chars[i] == '\\' {
+                        i += 2;
+                    } else if long && chars[i..].starts_with(&[c, c, c]) {
+                        i += 3;
+                        break;
+                    } else if !long && chars[i] == c {
+                        i += 1;
+                        break;
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+            '?' | '$' => {
+                i += 1;
+                while i < chars.len() && is_name(chars[i]) {
+                    i += 1;
+                }
+                if c == '$' {
+                    let name: String = chars[start + 1..i].iter().collect();
+                    if let Some((_, term)) = terms.iter().find(|(n, _)| *n == name) {
+                        out.push_str(term);
+                        continue;
+                    }
+                }
+            }
+            _ => i += 1,
+        }
+        out.extend(&chars[start..i.min(chars.len())]);
+    }
+    out
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Parameters from repeated `param=name:type` declarations
+fn declared_params(query_string: Option<&str>, text: &str) -> Result<Vec<(String, ParamType)>, String> {
+    let mut params: Vec<(String, ParamType)> = Vec::new();
+    for (_, declaration) in form_params(query_string.unwrap_or("")).into_iter().filter(|(k, _)| k == "param") {
+        let Some((name, type_name)) = declaration.split_once(':') else {
+            return Err(format!("parameter must be name:type: {}", declaration));
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("parameter names are letters, digits and '_': {}", name));
+        }
+        let Some(param_type) = ParamType::parse(type_name) else {
+            return Err(format!(
+                "unknown type {} (string, integer, decimal, boolean, date, dateTime or iri)",
+                type_name
+            ));
+        };
+        if params.iter().any(|(p, _)| p == name) {
+            return Err(format!("parameter declared twice: {}", name));
+        }
+        if !text.contains(&format!("${}", name)) {
+            return Err(format!("parameter ${} does not occur in the query", name));
+        }
+        params.push((name.to_string(), param_type));
+    }
+    if params.len() > MAX_PARAMS {
+        return Err(format!("at most {} parameters", MAX_PARAMS));
+    }
+    Ok(params)
+}
+
+/// Store a template, replacing any of the same name
+async fn upsert_template(state: &AppState, template: &QueryTemplate, owner: &str) -> Result<(), String> {
+    let param_triples: String = template
+        .params
+        .iter()
+        .map(|(name, param_type)| format!(" ;\n            liqk:template-parameter \"{}:{}\"", name, param_type.as_str()))
+        .collect();
+    let update = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX dcterms: <http://purl.org/dc/terms/>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+
+DELETE {{ GRAPH <{graph}> {{ ?template ?p ?o }} }}
+WHERE {{
+    GRAPH <{graph}> {{
+        ?template a liqk:QueryTemplate ;
+            liqk:template-name "{name}" ;
+            ?p ?o .
+    }}
+}};
+INSERT DATA {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{template}> a liqk:QueryTemplate ;
+            liqk:template-name "{name}" ;
+            liqk:query-text "{text}" ;
+            liqk:owner "{owner}" ;
+            dcterms:created "{created}"^^xsd:dateTime{param_triples} .
+    }}
+}}"#,
+        graph = QUERIES_GRAPH,
+        template = Uuid::new_v4(),
+        name = template.name,
+        text = escape_sparql_string(&template.text),
+        owner = escape_sparql_string(owner),
+        created = Utc::now().to_rfc3339(),
+        param_triples = param_triples,
+    );
+
+    send_sparql_update_once(state, "query-template", &update).await
+}
+
+/// All templates, or the one named `name`
+async fn load_templates(state: &AppState, name: Option<&str>) -> Result<Vec<QueryTemplate>, String> {
+    let name_filter = name
+        .map(|name| format!("\n    FILTER(?name = \"{}\")", name))
+        .unwrap_or_default();
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?name ?text ?param FROM <{graph}> WHERE {{
+    ?template a liqk:QueryTemplate ;
+        liqk:template-name ?name ;
+        liqk:query-text ?text .
+    OPTIONAL {{ ?template liqk:template-parameter ?param }}{name_filter}
+}}
+ORDER BY ?name ?param"#,
+        graph = QUERIES_GRAPH,
+        name_filter = name_filter,
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+
+    // One row per parameter; rows of a template are adjacent thanks to the ordering
+    let mut templates: Vec<QueryTemplate> = Vec::new();
+    for b in &bindings {
+        let (Some(name), Some(text)) = (binding_value(b, "name"), binding_value(b, "text")) else {
+            continue;
+        };
+        let param = binding_value(b, "param")
+            .and_then(|p| p.split_once(':'))
+            .and_then(|(p, t)| Some((p.to_string(), ParamType::parse(t)?)));
+        match templates.last_mut() {
+            Some(last) if last.name == name => last.params.extend(param),
+            _ => templates.push(QueryTemplate {
+                name: name.to_string(),
+                text: text.to_string(),
+                params: param.into_iter().collect(),
+            }),
+        }
+    }
+    Ok(templates)
+}
+
+async fn delete_template(state: &AppState, name: &str) -> Result<(), String> {
+    let update = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+DELETE {{ GRAPH <{graph}> {{ ?template ?p ?o }} }}
+WHERE {{
+    GRAPH <{graph}> {{
+        ?template a liqk:QueryTemplate ;
+            liqk:template-name "{name}" ;
+            ?p ?o .
+    }}
+}}"#,
+        graph = QUERIES_GRAPH,
+        name = name,
+    );
+
+    send_sparql_update(state, "query-template-delete", &update).await
+}
+
+/// Graphs the requester's template runs are restricted to: `None` with the
+/// query rank, otherwise the graphs they may read like on `/query`.
+/// `Err` when they may read nothing.
+async fn query_scope(
+    state: &AppState,
+    credential: &Credential,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> Result<Option<Vec<String>>, Response> {
+    let required = match state.rules.required_rank(&state.roles, &Method::POST, "/query") {
+        Ok(required) => required,
+        Err(status) => return Err((status, "Access denied").into_response()),
+    };
+    if get_access_rank_iri(state, GRAPH_IRI, headers, jar).await >= required {
+        return Ok(None);
+    }
+    let graphs = permitted_graphs(state, credential, required).await;
+    if graphs.is_empty() {
+        return Err((StatusCode::FORBIDDEN, "Access denied").into_response());
+    }
+    Ok(Some(graphs))
+}
+
+fn template_json(template: &QueryTemplate) -> serde_json::Value {
+    serde_json::json!({
+        "name": template.name,
+        "path": format!("/q/{}", template.name),
+        "query": template.text,
+        "parameters": template.params.iter().map(|(name, param_type)| {
+            serde_json::json!({ "name": name, "type": param_type.as_str() })
+        }).collect::<Vec<_>>(),
+    })
+}
+
+/// GET /q/:name?param=value - Run a query template with the given values,
+/// with the caller's query access. Called for `/q/` paths that are not
+/// saved query capabilities.
+pub async fn run_template(
+    state: &AppState,
+    addr: SocketAddr,
+    jar: &CookieJar,
+    headers: &HeaderMap,
+    query_string: Option<&str>,
+    name: &str,
+) -> Response {
+    let credential = credential(state, headers, jar);
+    let fingerprint = credential.fingerprint();
+    if !valid_name(name) {
+        return (StatusCode::NOT_FOUND, "Query template not found").into_response();
+    }
+    let scope = match query_scope(state, &credential, headers, jar).await {
+        Ok(scope) => scope,
+        Err(response) => {
+            warn!(client = %addr, credential = %fingerprint, template = %name, "Access denied - query template requires query access");
+            return response;
+        }
+    };
+
+    let template = match load_templates(state, Some(name)).await {
+        Ok(templates) => match templates.into_iter().next() {
+            Some(template) => template,
+            None => return (StatusCode::NOT_FOUND, "Query template not found").into_response(),
+        },
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, template = %name, error = %e, "SPARQL lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup query template").into_response();
+        }
+    };
+    let query = match template.bind(query_string) {
+        Ok(query) => query,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let accept = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok());
+    match execute_query(state, &query, scope.as_deref(), accept).await {
+        Ok(response) => {
+            info!(client = %addr, credential = %fingerprint, template = %name, status = %response.status(), "Query template executed");
+            response
+        }
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, template = %name, error = %e, "Query template failed");
+            (StatusCode::BAD_GATEWAY, "Query template failed").into_response()
+        }
+    }
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// PUT /gate/templates/:name?param=name:type&... - Register or replace a
+/// query template, the SPARQL in the body (admin only)
+pub async fn template_put_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    uri: Uri,
+    body: String,
+) -> Response {
+    if !is_admin(&state, &headers, &jar).await {
+        return forbidden();
+    }
+    let credential = credential(&state, &headers, &jar);
+    let owner = credential.owner().unwrap_or_default();
+
+    if !valid_name(&name) {
+        return (StatusCode::BAD_REQUEST, "name must be 1-48 letters, digits, '-' or '_'").into_response();
+    }
+    let text = body.trim();
+    if text.is_empty() || text.len() > MAX_TEMPLATE_LEN {
+        return (StatusCode::BAD_REQUEST, "query must be between 1 byte and 64 KiB").into_response();
+    }
+    let params = match declared_params(uri.query(), text) {
+        Ok(params) => params,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let template = QueryTemplate {
+        name,
+        text: text.to_string(),
+        params,
+    };
+    if let Err(e) = upsert_template(&state, &template, &owner).await {
+        warn!(client = %addr, credential = %credential.fingerprint(), template = %template.name, error = %e, "Failed to store query template");
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save query template").into_response();
+    }
+
+    info!(
+        client = %addr,
+        credential = %credential.fingerprint(),
+        template = %template.name,
+        parameters = template.params.len(),
+        "Query template saved"
+    );
+
+    (
+        StatusCode::CREATED,
+        [(header::CONTENT_TYPE, "application/json")],
+        template_json(&template).to_string(),
+    )
+        .into_response()
+}
+
+/// GET /gate/templates - The query templates (callers with query access)
+pub async fn templates_list_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    let credential = credential(&state, &headers, &jar);
+    if let Err(response) = query_scope(&state, &credential, &headers, &jar).await {
+        return response;
+    }
+
+    match load_templates(&state, None).await {
+        Ok(templates) => {
+            let json_response = serde_json::json!({
+                "templates": templates.iter().map(template_json).collect::<Vec<_>>()
+            });
+            ([(header::CONTENT_TYPE, "application/json")], json_response.to_string()).into_response()
+        }
+        Err(e) => {
+            warn!(client = %addr, credential = %credential.fingerprint(), error = %e, "Failed to load query templates");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load query templates").into_response()
+        }
+    }
+}
+
+/// DELETE /gate/templates/:name - Remove a query template (admin only)
+pub async fn template_delete_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Response {
+    if !is_admin(&state, &headers, &jar).await {
+        return forbidden();
+    }
+    if !valid_name(&name) {
+        return (StatusCode::NOT_FOUND, "Query template not found").into_response();
+    }
+    let fingerprint = credential(&state, &headers, &jar).fingerprint();
+
+    match delete_template(&state, &name).await {
+        Ok(()) => {
+            info!(client = %addr, credential = %fingerprint, template = %name, "Query template deleted");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, template = %name, error = %e, "Failed to delete query template");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete query template").into_response()
+        }
+    }
+}
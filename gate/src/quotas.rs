@@ -0,0 +1,166 @@
+//! Per-identity storage quotas.
+//!
+//! Every file and version node records the identity that wrote its content
+//! as `liqk:owner`, so an identity's usage is the sum of the `posix:size`
+//! of the nodes it owns, counted from the graph. Trashed files and earlier
+//! versions count until they are purged, and a file sharing another's
+//! stored file counts in full. `STORAGE_QUOTA_MB` caps the usage of every
+//! identity but the emergency token's; anonymous uploads own nothing.
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::files::{binding_value, credential, escape_sparql_string, sparql_select, Credential, FILESYSTEM_GRAPH};
+use crate::AppState;
+
+/// Default storage quota per identity in MiB (0 for none)
+pub const DEFAULT_STORAGE_QUOTA_MB: u64 = 0;
+
+const MIB: u64 = 1024 * 1024;
+
+/// Add the triple recording the identity that wrote a new file's content
+/// to its [`file_triples`](crate::files::file_triples)
+pub fn own_triples(triples: &mut String, file: &Uuid, owner: Option<&str>) {
+    if let Some(owner) = owner {
+        triples.push_str(&format!(
+            "\n        <urn:uuid:{}> liqk:owner \"{}\" .",
+            file,
+            escape_sparql_string(owner)
+        ));
+    }
+}
+
+/// Bytes stored by an identity, over the file and version nodes it owns
+pub struct Usage {
+    pub bytes: u64,
+    pub files: u64,
+}
+
+pub async fn usage(state: &AppState, owner: &str) -> Result<Usage, String> {
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT (COALESCE(SUM(?size), 0) AS ?bytes) (COUNT(?node) AS ?files) FROM <{graph}> WHERE {{
+    ?node liqk:owner "{owner}" ;
+        posix:size ?size .
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        owner = escape_sparql_string(owner),
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+    let value = |var: &str| {
+        bindings
+            .first()
+            .and_then(|b| binding_value(b, var))
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0)
+    };
+    Ok(Usage { bytes: value("bytes"), files: value("files") })
+}
+
+/// An identity's usage against the configured quota
+pub struct Quota {
+    pub owner: String,
+    pub used: u64,
+    pub limit: u64,
+}
+
+impl Quota {
+    /// Whether `bytes` more still fit
+    pub fn allows(&self, bytes: u64) -> bool {
+        self.used.saturating_add(bytes) <= self.limit
+    }
+
+    /// `507` for content that does not fit
+    pub fn exceeded(&self) -> Response {
+        (
+            StatusCode::INSUFFICIENT_STORAGE,
+            format!(
+                "Storage quota exceeded ({:.1} of {} MiB used)",
+                self.used as f64 / MIB as f64,
+                self.limit / MIB
+            ),
+        )
+            .into_response()
+    }
+}
+
+/// The identity a request's content is charged to, when a quota applies
+/// to it
+fn charged_owner(state: &AppState, credential: &Credential) -> Option<String> {
+    if state.storage_quota_mb == 0 || matches!(credential, Credential::Emergency) {
+        return None;
+    }
+    credential.owner()
+}
+
+/// The requester's quota; `None` when none applies to them
+async fn requester_quota(state: &AppState, credential: &Credential) -> Result<Option<Quota>, String> {
+    let Some(owner) = charged_owner(state, credential) else {
+        return Ok(None);
+    };
+    let used = usage(state, &owner).await?.bytes;
+    Ok(Some(Quota { owner, used, limit: state.storage_quota_mb.saturating_mul(MIB) }))
+}
+
+/// Look up the requester's quota and check `bytes` more against it,
+/// answering the request when they do not fit
+pub async fn check_quota(
+    state: &AppState,
+    addr: &SocketAddr,
+    credential: &Credential,
+    bytes: u64,
+) -> Result<Option<Quota>, Response> {
+    let quota = match requester_quota(state, credential).await {
+        Ok(quota) => quota,
+        Err(e) => {
+            warn!(client = %addr, credential = %credential.fingerprint(), error = %e, "Storage usage lookup failed");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup storage usage").into_response());
+        }
+    };
+    if let Some(quota) = quota.as_ref().filter(|quota| !quota.allows(bytes)) {
+        warn!(client = %addr, credential = %credential.fingerprint(), owner = %quota.owner, used = quota.used, bytes = bytes, "Upload refused - storage quota exceeded");
+        return Err(quota.exceeded());
+    }
+    Ok(quota)
+}
+
+/// GET /gate/quota - The caller's storage usage and quota
+pub async fn quota_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    let credential = credential(&state, &headers, &jar);
+    let Some(owner) = credential.owner() else {
+        return (StatusCode::FORBIDDEN, "Access denied - storage usage requires authentication").into_response();
+    };
+
+    match usage(&state, &owner).await {
+        Ok(usage) => {
+            let limit = charged_owner(&state, &credential).map(|_| state.storage_quota_mb.saturating_mul(MIB));
+            let json_response = json!({
+                "owner": owner,
+                "used": usage.bytes,
+                "files": usage.files,
+                "quota": limit,
+                "available": limit.map(|limit| limit.saturating_sub(usage.bytes)),
+            });
+            ([(header::CONTENT_TYPE, "application/json")], json_response.to_string()).into_response()
+        }
+        Err(e) => {
+            warn!(client = %addr, credential = %credential.fingerprint(), error = %e, "Storage usage lookup failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup storage usage").into_response()
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use crate::files::{ACCESS_GRAPH, FILESYSTEM_GRAPH};
+use crate::update_graphs::{form_params, written_graphs, GraphTarget};
+
+/// Default lifetime of cached ranks in seconds
+pub const DEFAULT_RANK_CACHE_TTL_SECS: u64 = 30;
+
+/// Entries beyond which expired ranks are pruned on insert
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Graphs readable at a rank, and when they were resolved
+type GraphList = (Vec<String>, Instant);
+
+/// In-memory cache of resolved access ranks keyed by (identity, resource IRI),
+/// and of the graphs an identity may read at a given rank.
+///
+/// Identities are derived from the presented credential (token hash, session
+/// ID, or anonymous), never the raw secret. Entries expire after the TTL and
+/// the whole cache is dropped when an update that may change ACLs passes
+/// through the gate.
+pub struct RankCache {
+    entries: RwLock<HashMap<(String, String), (i32, Instant)>>,
+    graphs: RwLock<HashMap<(String, i32), GraphList>>,
+    ttl: Duration,
+}
+
+impl RankCache {
+    /// A zero TTL disables caching
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+            graphs: RwLock::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    pub fn get(&self, identity: &str, resource: &str) -> Option<i32> {
+        if self.ttl.is_zero() {
+            return None;
+        }
+        let entries = self.entries.read().unwrap();
+        let (rank, stored) = entries.get(&(identity.to_string(), resource.to_string()))?;
+        (stored.elapsed() < self.ttl).then_some(*rank)
+    }
+
+    pub fn insert(&self, identity: &str, resource: &str, rank: i32) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.write().unwrap();
+        if entries.len() >= PRUNE_THRESHOLD {
+            let ttl = self.ttl;
+            entries.retain(|_, (_, stored)| stored.elapsed() < ttl);
+        }
+        entries.insert((identity.to_string(), resource.to_string()), (rank, Instant::now()));
+    }
+
+    pub fn get_graphs(&self, identity: &str, rank: i32) -> Option<Vec<String>> {
+        if self.ttl.is_zero() {
+            return None;
+        }
+        let graphs = self.graphs.read().unwrap();
+        let (list, stored) = graphs.get(&(identity.to_string(), rank))?;
+        (stored.elapsed() < self.ttl).then(|| list.clone())
+    }
+
+    pub fn insert_graphs(&self, identity: &str, rank: i32, list: Vec<String>) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut graphs = self.graphs.write().unwrap();
+        if graphs.len() >= PRUNE_THRESHOLD {
+            let ttl = self.ttl;
+            graphs.retain(|_, (_, stored)| stored.elapsed() < ttl);
+        }
+        graphs.insert((identity.to_string(), rank), (list, Instant::now()));
+    }
+
+    pub fn invalidate_all(&self) {
+        self.entries.write().unwrap().clear();
+        self.graphs.write().unwrap().clear();
+    }
+}
+
+/// Whether a SPARQL update may change access decisions: it writes the
+/// access or filesystem graph (policies, `posix:includes` inheritance), as
+/// resolved through its `PREFIX` and `BASE` declarations, or its targets
+/// cannot be determined (`GRAPH ?g`, `DROP ALL`, an unreadable update).
+pub fn update_may_change_acl(query_string: Option<&str>, content_type: Option<&str>, body: &[u8]) -> bool {
+    match written_graphs("/update", query_string, content_type, body) {
+        Ok(targets) => targets
+            .iter()
+            .any(|target| matches!(target, GraphTarget::Named(iri) if iri == ACCESS_GRAPH || iri == FILESYSTEM_GRAPH)),
+        Err(_) => true,
+    }
+}
+
+/// Whether a Graph Store Protocol write may change access decisions, judged
+/// by its target graph alone so the body can be streamed. Writes without a
+/// `graph` parameter (whole-dataset loads) are assumed to.
+pub fn store_write_may_change_acl(query_string: Option<&str>) -> bool {
+    let graph = form_params(query_string.unwrap_or(""))
+        .into_iter()
+        .find(|(k, _)| k == "graph" || k == "default");
+    match graph {
+        Some((k, iri)) if k == "graph" => [ACCESS_GRAPH, FILESYSTEM_GRAPH].contains(&iri.as_str()),
+        Some(_) => false,
+        None => true,
+    }
+}
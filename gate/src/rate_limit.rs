@@ -0,0 +1,142 @@
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::files::Credential;
+
+/// Buckets beyond which full ones are pruned on insert
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Kind of proxied request, each limited separately (`RATE_LIMIT_<CLASS>_*`)
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateClass {
+    /// SPARQL queries
+    Query,
+    /// SPARQL updates, Graph Store writes and other writes
+    Update,
+    /// Everything else read through the proxy: Graph Store reads, dumps,
+    /// UI assets and other upstreams
+    Download,
+}
+
+impl RateClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RateClass::Query => "query",
+            RateClass::Update => "update",
+            RateClass::Download => "download",
+        }
+    }
+}
+
+/// Sustained requests per second and the burst allowed above it
+#[derive(Clone, Copy)]
+pub struct Rate {
+    pub per_second: f64,
+    pub burst: f64,
+}
+
+impl Rate {
+    /// `None` when `per_second` is not positive, which disables the limit.
+    /// The burst defaults to one second's worth, and at least one request.
+    pub fn new(per_second: f64, burst: Option<f64>) -> Option<Self> {
+        (per_second > 0.0).then(|| Rate {
+            per_second,
+            burst: burst.unwrap_or(per_second).max(1.0),
+        })
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token-bucket limits on proxied requests, one bucket per class and
+/// requester. Requesters are known by their identity when authenticated
+/// and by their address otherwise; the emergency token is never limited.
+pub struct RateLimiter {
+    query: Option<Rate>,
+    update: Option<Rate>,
+    download: Option<Rate>,
+    buckets: Mutex<HashMap<(RateClass, String), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(query: Option<Rate>, update: Option<Rate>, download: Option<Rate>) -> Self {
+        Self {
+            query,
+            update,
+            download,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn rate(&self, class: RateClass) -> Option<Rate> {
+        match class {
+            RateClass::Query => self.query,
+            RateClass::Update => self.update,
+            RateClass::Download => self.download,
+        }
+    }
+
+    /// Take a request from the requester's bucket. `Err` holds how long
+    /// until one is available again.
+    pub fn check(&self, class: RateClass, requester: &str) -> Result<(), Duration> {
+        let Some(rate) = self.rate(class) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= PRUNE_THRESHOLD {
+            // Buckets refilled to the burst are the same as absent ones
+            buckets.retain(|(class, _), bucket| {
+                self.rate(*class).is_some_and(|rate| {
+                    bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate.per_second < rate.burst
+                })
+            });
+        }
+        let bucket = buckets.entry((class, requester.to_string())).or_insert(Bucket {
+            tokens: rate.burst,
+            updated: now,
+        });
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() * rate.per_second;
+        bucket.tokens = (bucket.tokens + refilled).min(rate.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate.per_second))
+        }
+    }
+}
+
+/// Who a request is counted against: sessions and trusted proxy users by
+/// identity, tokens by their hash once they carry a rank (so made-up tokens
+/// cannot dodge the address limit), and everyone else by client address.
+/// `None` for the emergency token.
+pub fn requester(credential: &Credential, rank: i32, client: IpAddr) -> Option<String> {
+    match credential {
+        Credential::Session(session) => Some(format!("user:{}", session.identity)),
+        Credential::Proxy { user, .. } => Some(format!("user:{}", user)),
+        Credential::Token(token_hash) if rank > 0 => Some(format!("token:{}", token_hash)),
+        Credential::Emergency => None,
+        _ => Some(format!("ip:{}", client)),
+    }
+}
+
+/// `429` telling the client when to retry, in whole seconds
+pub fn too_many_requests(retry_after: Duration) -> Response {
+    let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    let mut response = (StatusCode::TOO_MANY_REQUESTS, "Too many requests").into_response();
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(secs));
+    response
+}
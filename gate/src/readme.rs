@@ -0,0 +1,195 @@
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use crate::browse::Entry;
+use crate::files::{get_access_rank, lookup_file_by_uuid};
+use crate::markdown;
+use crate::templates::escape_html;
+use crate::AppState;
+
+/// File name (any case) rendered below a directory listing
+pub const README_NAME: &str = "README.md";
+/// READMEs larger than this are not rendered (256 KiB)
+const MAX_README_BYTES: u64 = 256 * 1024;
+/// Rendered READMEs kept in memory before the cache is emptied
+const MAX_CACHED: usize = 256;
+
+/// Cookie holding the browser's display preferences
+const PREFERENCES_COOKIE_NAME: &str = "oxigraph_gate_prefs";
+/// Preferences outlive sessions (1 year)
+const PREFERENCES_MAX_AGE_SECS: i64 = 31_536_000;
+
+/// Display preferences of a browser, kept in a cookie as `key=value` pairs
+/// separated by `&`
+pub struct Preferences {
+    pub show_readme: bool,
+}
+
+impl Preferences {
+    pub fn from_jar(jar: &CookieJar) -> Self {
+        let value = jar.get(PREFERENCES_COOKIE_NAME).map(|c| c.value().to_string()).unwrap_or_default();
+        let show_readme = !value.split('&').any(|pair| pair == "readme=off");
+        Self { show_readme }
+    }
+
+    fn cookie_value(&self) -> String {
+        format!("readme={}", if self.show_readme { "on" } else { "off" })
+    }
+}
+
+/// Revision of a stored README: its size and modification time on disk,
+/// which change whenever the content is replaced
+#[derive(Clone, PartialEq)]
+struct Revision {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// Rendered READMEs by stored file name, valid while the revision matches
+pub struct ReadmeCache {
+    entries: RwLock<HashMap<String, (Revision, String)>>,
+}
+
+impl ReadmeCache {
+    pub fn new() -> Self {
+        Self { entries: RwLock::new(HashMap::new()) }
+    }
+
+    fn get(&self, stored_filename: &str, revision: &Revision) -> Option<String> {
+        let entries = self.entries.read().unwrap();
+        entries
+            .get(stored_filename)
+            .filter(|(cached, _)| cached == revision)
+            .map(|(_, html)| html.clone())
+    }
+
+    fn insert(&self, stored_filename: &str, revision: Revision, html: String) {
+        let mut entries = self.entries.write().unwrap();
+        if entries.len() >= MAX_CACHED && !entries.contains_key(stored_filename) {
+            entries.clear();
+        }
+        entries.insert(stored_filename.to_string(), (revision, html));
+    }
+}
+
+/// Rendered README of a directory listing, if the directory has one that is
+/// small enough and the requester may view it (`required` rank on the file)
+pub async fn render_readme(
+    state: &AppState,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+    entries: &[Entry],
+    required: i32,
+) -> Result<Option<String>, String> {
+    let Some(uuid) = entries
+        .iter()
+        .find(|e| !e.is_dir && e.label.eq_ignore_ascii_case(README_NAME))
+        .and_then(|e| e.uuid)
+    else {
+        return Ok(None);
+    };
+    if get_access_rank(state, &uuid, headers, jar).await < required {
+        return Ok(None);
+    }
+
+    let Some(stored_filename) = lookup_file_by_uuid(&state.client, &state.oxigraph_url, &uuid.to_string()).await? else {
+        return Ok(None);
+    };
+    let path = Path::new(&state.files_dir).join(&stored_filename);
+
+    let metadata = tokio::fs::metadata(&path).await.map_err(|e| format!("Failed to read README: {}", e))?;
+    if metadata.len() > MAX_README_BYTES {
+        return Ok(None);
+    }
+    let revision = Revision { len: metadata.len(), modified: metadata.modified().ok() };
+    if let Some(html) = state.readme_cache.get(&stored_filename, &revision) {
+        return Ok(Some(html));
+    }
+
+    let content = tokio::fs::read(&path).await.map_err(|e| format!("Failed to read README: {}", e))?;
+    let html = markdown::render(&String::from_utf8_lossy(&content));
+    state.readme_cache.insert(&stored_filename, revision, html.clone());
+    Ok(Some(html))
+}
+
+/// README section of a listing: the rendered README with a button hiding
+/// it, or only a button showing it again when the preference is off
+pub fn readme_section(readme: Option<&str>, preferences: &Preferences, return_to: &str) -> String {
+    let toggle = |value: &str, label: &str| {
+        format!(
+            r#"<form method="POST" action="/gate/preferences" class="toggle">
+        <input type="hidden" name="readme" value="{}">
+        <input type="hidden" name="return" value="{}">
+        <button type="submit" class="secondary">{}</button>
+    </form>"#,
+            value,
+            escape_html(return_to),
+            label,
+        )
+    };
+    match readme {
+        Some(html) if preferences.show_readme => format!(
+            r#"<section class="readme">
+    <div class="readme-header"><span>{}</span>{}</div>
+    {}
+    </section>"#,
+            README_NAME,
+            toggle("off", "Hide"),
+            html,
+        ),
+        Some(_) => toggle("on", "Show README"),
+        None => String::new(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PreferencesForm {
+    /// `on` or `off`
+    readme: Option<String>,
+    /// Gate path to return to
+    #[serde(rename = "return")]
+    return_to: Option<String>,
+}
+
+/// POST /gate/preferences - Update this browser's display preferences and
+/// return to the page the form was sent from
+pub async fn preferences_handler(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Form(form): Form<PreferencesForm>,
+) -> Response {
+    let mut preferences = Preferences::from_jar(&jar);
+    match form.readme.as_deref() {
+        Some("on") => preferences.show_readme = true,
+        Some("off") => preferences.show_readme = false,
+        _ => {}
+    }
+
+    let mut cookie = Cookie::build((PREFERENCES_COOKIE_NAME, preferences.cookie_value()))
+        .path(state.cookie.sub_path("/"))
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(PREFERENCES_MAX_AGE_SECS));
+    if state.secure_cookies {
+        cookie = cookie.secure(true);
+    }
+
+    // Only local paths, so the form cannot redirect elsewhere
+    let location = form
+        .return_to
+        .filter(|r| r.starts_with('/') && !r.starts_with("//") && !r.starts_with("/\\"))
+        .unwrap_or_else(|| "/".to_string());
+    (jar.add(cookie.build()), Redirect::to(&location)).into_response()
+}
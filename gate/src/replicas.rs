@@ -0,0 +1,110 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tracing::{info, warn};
+
+use crate::health::probe_oxigraph;
+use crate::AppState;
+
+/// Seconds between replica health checks when `REPLICA_CHECK_INTERVAL` is unset
+pub const DEFAULT_REPLICA_CHECK_INTERVAL_SECS: u64 = 10;
+
+struct Replica {
+    url: String,
+    healthy: AtomicBool,
+}
+
+/// Read replicas of Oxigraph (`OXIGRAPH_REPLICA_URLS`). Proxied `/query`
+/// requests are spread over the replicas in rotation; a replica that
+/// refuses a connection or fails its health check leaves the rotation
+/// until a later check succeeds. With no replica in rotation, reads go to
+/// the primary like everything else.
+#[derive(Default)]
+pub struct Replicas {
+    replicas: Vec<Replica>,
+    next: AtomicUsize,
+}
+
+impl Replicas {
+    /// Parse a comma-separated list of replica URLs
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut replicas: Vec<Replica> = Vec::new();
+        for url in value.split(',').map(str::trim).filter(|u| !u.is_empty()) {
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return Err(format!("replica {} must start with http:// or https://", url));
+            }
+            let url = url.trim_end_matches('/').to_string();
+            if replicas.iter().any(|r| r.url == url) {
+                return Err(format!("replica {} is listed twice", url));
+            }
+            replicas.push(Replica { url, healthy: AtomicBool::new(true) });
+        }
+        Ok(Self { replicas, next: AtomicUsize::new(0) })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replicas.is_empty()
+    }
+
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        self.replicas.iter().map(|r| r.url.as_str())
+    }
+
+    /// Replicas in rotation and configured
+    pub fn counts(&self) -> (usize, usize) {
+        let healthy = self.replicas.iter().filter(|r| r.healthy.load(Ordering::Relaxed)).count();
+        (healthy, self.replicas.len())
+    }
+
+    /// The next replica in rotation, or `None` when reads go to the primary
+    pub fn pick(&self) -> Option<&str> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..self.replicas.len())
+            .map(|i| &self.replicas[(start + i) % self.replicas.len()])
+            .find(|r| r.healthy.load(Ordering::Relaxed))
+            .map(|r| r.url.as_str())
+    }
+
+    /// Take a replica out of rotation after a failed connection
+    pub fn evict(&self, url: &str, reason: &str) {
+        if let Some(replica) = self.replicas.iter().find(|r| r.url == url) {
+            if replica.healthy.swap(false, Ordering::Relaxed) {
+                warn!(replica = %url, reason = %reason, "Read replica evicted");
+            }
+        }
+    }
+
+    /// Probe every replica, evicting those that fail and restoring those
+    /// that answer again
+    async fn check(&self, client: &reqwest::Client) {
+        let probes = self.replicas.iter().map(|replica| async move {
+            match probe_oxigraph(client, &replica.url).await {
+                Ok(()) => {
+                    if !replica.healthy.swap(true, Ordering::Relaxed) {
+                        info!(replica = %replica.url, "Read replica back in rotation");
+                    }
+                }
+                Err(e) => self.evict(&replica.url, &e),
+            }
+        });
+        futures_util::future::join_all(probes).await;
+    }
+}
+
+/// Periodically check the read replicas
+pub fn spawn_health_checks(state: Arc<AppState>, interval: Duration) {
+    if state.replicas.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            state.replicas.check(&state.client).await;
+        }
+    });
+}
@@ -0,0 +1,315 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use serde_json::Value;
+
+use crate::etag;
+
+/// Default largest query result converted to another format in MiB
+pub const DEFAULT_RESULT_CONVERSION_MAX_MB: usize = 64;
+
+/// Media type Oxigraph is asked for when the gate converts results
+pub const JSON_RESULTS: &str = "application/sparql-results+json";
+
+const XSD_STRING: &str = "http://www.w3.org/2001/XMLSchema#string";
+
+/// SPARQL result formats the gate produces from JSON results
+#[derive(Clone, Copy, PartialEq)]
+pub enum ResultFormat {
+    Xml,
+    Csv,
+    Tsv,
+}
+
+impl ResultFormat {
+    fn media_type(self) -> &'static str {
+        match self {
+            ResultFormat::Xml => "application/sparql-results+xml",
+            ResultFormat::Csv => "text/csv; charset=utf-8",
+            ResultFormat::Tsv => "text/tab-separated-values; charset=utf-8",
+        }
+    }
+
+    fn parse(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/sparql-results+xml" => Some(ResultFormat::Xml),
+            "text/csv" => Some(ResultFormat::Csv),
+            "text/tab-separated-values" => Some(ResultFormat::Tsv),
+            _ => None,
+        }
+    }
+}
+
+/// Media types of an `Accept` header with their quality, in order
+pub fn accepted(accept: &str) -> Vec<(String, f32)> {
+    accept
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let media_type = parts.next()?.trim().to_ascii_lowercase();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (!media_type.is_empty()).then_some((media_type, quality))
+        })
+        .collect()
+}
+
+/// The format to convert JSON results to, when the client prefers XML, CSV
+/// or TSV over JSON; `None` leaves negotiation to Oxigraph. Equally
+/// preferred types go by their order in the header.
+pub fn negotiate(accept: &str) -> Option<ResultFormat> {
+    let mut best: Option<(Option<ResultFormat>, f32)> = None;
+    for (media_type, quality) in accepted(accept) {
+        let candidate = match ResultFormat::parse(&media_type) {
+            Some(format) => Some(format),
+            None if media_type == JSON_RESULTS || media_type == "application/json" => None,
+            None => continue,
+        };
+        if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+            best = Some((candidate, quality));
+        }
+    }
+    best.and_then(|(format, _)| format)
+}
+
+/// `Accept` to send upstream instead: JSON results first, then whatever
+/// else the client accepts apart from the formats the gate converts to,
+/// so graph results of `CONSTRUCT` and `DESCRIBE` are still negotiated
+pub fn upstream_accept(accept: &str) -> String {
+    let mut ranges = vec![JSON_RESULTS.to_string()];
+    ranges.extend(
+        accept
+            .split(',')
+            .map(str::trim)
+            .filter(|range| {
+                let media_type = range.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+                !range.is_empty() && ResultFormat::parse(&media_type).is_none() && media_type != JSON_RESULTS
+            })
+            .map(str::to_string),
+    );
+    ranges.join(", ")
+}
+
+/// Whether a response carries SPARQL JSON results
+pub fn is_json_results(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case(JSON_RESULTS))
+}
+
+/// A `200` JSON result converted to `format`, with an ETag of the
+/// conversion; anything else is passed on unchanged
+pub fn respond_converted(
+    request_headers: &HeaderMap,
+    format: ResultFormat,
+    status: StatusCode,
+    mut headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if status != StatusCode::OK || !is_json_results(&headers) {
+        return (status, headers, body).into_response();
+    }
+    let converted = match convert(&body, format) {
+        Ok(converted) => converted,
+        Err(e) => return (StatusCode::BAD_GATEWAY, format!("Failed to convert query results: {}", e)).into_response(),
+    };
+    // Validators and length described the JSON
+    for name in [header::CONTENT_TYPE, header::CONTENT_LENGTH, header::ETAG, header::LAST_MODIFIED] {
+        headers.remove(name);
+    }
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(format.media_type()));
+    headers.append(header::VARY, HeaderValue::from_static("accept"));
+    etag::respond(request_headers, status, headers, Bytes::from(converted))
+}
+
+/// Transcode SPARQL JSON results (SELECT or ASK)
+pub fn convert(json: &[u8], format: ResultFormat) -> Result<String, String> {
+    let results: Value = serde_json::from_slice(json).map_err(|e| format!("invalid JSON: {}", e))?;
+    let vars: Vec<&str> = results
+        .pointer("/head/vars")
+        .and_then(Value::as_array)
+        .map(|vars| vars.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    if let Some(boolean) = results.get("boolean").and_then(Value::as_bool) {
+        return Ok(match format {
+            ResultFormat::Xml => format!(
+                "<?xml version=\"1.0\"?>\n<sparql xmlns=\"http://www.w3.org/2005/sparql-results#\"><head/><boolean>{}</boolean></sparql>\n",
+                boolean
+            ),
+            // CSV and TSV have no boolean form; a lone value is what
+            // clients of other stores expect
+            ResultFormat::Csv => format!("{}\r\n", boolean),
+            ResultFormat::Tsv => format!("{}\n", boolean),
+        });
+    }
+
+    let bindings = results
+        .pointer("/results/bindings")
+        .and_then(Value::as_array)
+        .ok_or("neither bindings nor a boolean")?;
+    let mut out = String::new();
+    match format {
+        ResultFormat::Xml => {
+            out.push_str("<?xml version=\"1.0\"?>\n<sparql xmlns=\"http://www.w3.org/2005/sparql-results#\"><head>");
+            for var in &vars {
+                out.push_str(&format!("<variable name=\"{}\"/>", escape_xml(var)));
+            }
+            out.push_str("</head><results>");
+            for binding in bindings {
+                out.push_str("<result>");
+                for var in &vars {
+                    if let Some(term) = binding.get(*var) {
+                        out.push_str(&format!("<binding name=\"{}\">{}</binding>", escape_xml(var), xml_term(term)?));
+                    }
+                }
+                out.push_str("</result>");
+            }
+            out.push_str("</results></sparql>\n");
+        }
+        ResultFormat::Csv => {
+            out.push_str(&vars.iter().map(|var| csv_field(var)).collect::<Vec<_>>().join(","));
+            out.push_str("\r\n");
+            for binding in bindings {
+                let row: Result<Vec<String>, String> = vars
+                    .iter()
+                    .map(|var| binding.get(*var).map_or(Ok(String::new()), |term| csv_term(term).map(|t| csv_field(&t))))
+                    .collect();
+                out.push_str(&row?.join(","));
+                out.push_str("\r\n");
+            }
+        }
+        ResultFormat::Tsv => {
+            out.push_str(&vars.iter().map(|var| format!("?{}", var)).collect::<Vec<_>>().join("\t"));
+            out.push('\n');
+            for binding in bindings {
+                let row: Result<Vec<String>, String> = vars
+                    .iter()
+                    .map(|var| binding.get(*var).map_or(Ok(String::new()), nt_term))
+                    .collect();
+                out.push_str(&row?.join("\t"));
+                out.push('\n');
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Parts of an RDF term in SPARQL JSON results
+struct Term<'a> {
+    kind: &'a str,
+    value: &'a str,
+    lang: Option<&'a str>,
+    datatype: Option<&'a str>,
+}
+
+fn term(json: &Value) -> Result<Term<'_>, String> {
+    let kind = json.get("type").and_then(Value::as_str).ok_or("term without a type")?;
+    let value = json.get("value").and_then(Value::as_str).unwrap_or("");
+    Ok(Term {
+        kind,
+        value,
+        lang: json.get("xml:lang").and_then(Value::as_str),
+        datatype: json.get("datatype").and_then(Value::as_str),
+    })
+}
+
+/// Subject, predicate and object of a quoted triple term
+fn triple_parts(json: &Value) -> Result<[&Value; 3], String> {
+    let triple = json.get("value").ok_or("triple term without a value")?;
+    let part = |name| triple.get(name).ok_or(format!("triple term without a {}", name));
+    Ok([part("subject")?, part("predicate")?, part("object")?])
+}
+
+/// A term in N-Triples syntax, as TSV results write it
+fn nt_term(json: &Value) -> Result<String, String> {
+    let t = term(json)?;
+    Ok(match t.kind {
+        "uri" => format!("<{}>", t.value),
+        "bnode" => format!("_:{}", t.value),
+        "literal" | "typed-literal" => {
+            let mut literal = String::from("\"");
+            for c in t.value.chars() {
+                match c {
+                    '\\' => literal.push_str("\\\\"),
+                    '"' => literal.push_str("\\\""),
+                    '\n' => literal.push_str("\\n"),
+                    '\r' => literal.push_str("\\r"),
+                    '\t' => literal.push_str("\\t"),
+                    c => literal.push(c),
+                }
+            }
+            literal.push('"');
+            match (t.lang, t.datatype) {
+                (Some(lang), _) => format!("{}@{}", literal, lang),
+                (None, Some(datatype)) if datatype != XSD_STRING => format!("{}^^<{}>", literal, datatype),
+                _ => literal,
+            }
+        }
+        "triple" => {
+            let [s, p, o] = triple_parts(json)?;
+            format!("<< {} {} {} >>", nt_term(s)?, nt_term(p)?, nt_term(o)?)
+        }
+        other => return Err(format!("unknown term type {}", other)),
+    })
+}
+
+/// A term as CSV results write it: IRIs and literals by their plain
+/// value, so language tags and datatypes are dropped
+fn csv_term(json: &Value) -> Result<String, String> {
+    let t = term(json)?;
+    Ok(match t.kind {
+        "bnode" => format!("_:{}", t.value),
+        "triple" => nt_term(json)?,
+        _ => t.value.to_string(),
+    })
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(['"', ',', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn xml_term(json: &Value) -> Result<String, String> {
+    let t = term(json)?;
+    Ok(match t.kind {
+        "uri" => format!("<uri>{}</uri>", escape_xml(t.value)),
+        "bnode" => format!("<bnode>{}</bnode>", escape_xml(t.value)),
+        "literal" | "typed-literal" => match (t.lang, t.datatype) {
+            (Some(lang), _) => format!("<literal xml:lang=\"{}\">{}</literal>", escape_xml(lang), escape_xml(t.value)),
+            (None, Some(datatype)) => format!(
+                "<literal datatype=\"{}\">{}</literal>",
+                escape_xml(datatype),
+                escape_xml(t.value)
+            ),
+            (None, None) => format!("<literal>{}</literal>", escape_xml(t.value)),
+        },
+        "triple" => {
+            let [s, p, o] = triple_parts(json)?;
+            format!(
+                "<triple><subject>{}</subject><predicate>{}</predicate><object>{}</object></triple>",
+                xml_term(s)?,
+                xml_term(p)?,
+                xml_term(o)?
+            )
+        }
+        other => return Err(format!("unknown term type {}", other)),
+    })
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,180 @@
+use std::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::files::{binding_value, sparql_select, ACCESS_GRAPH};
+use crate::AppState;
+
+/// Namespace of the built-in permissions (`liqk:permission-*`)
+const PERMISSION_PREFIX: &str = "http://liqk.org/schema#permission-";
+
+pub const PERMISSION_VIEW: &str = "http://liqk.org/schema#permission-view";
+pub const PERMISSION_COMMENT: &str = "http://liqk.org/schema#permission-comment";
+pub const PERMISSION_EDIT: &str = "http://liqk.org/schema#permission-edit";
+pub const PERMISSION_ADMIN: &str = "http://liqk.org/schema#permission-admin";
+
+/// Expand a permission name from `AUTH_RULES`: a full IRI is kept, a short
+/// name such as `edit` becomes `liqk:permission-edit`
+pub fn permission_iri(name: &str) -> String {
+    if name.contains(':') {
+        name.to_string()
+    } else {
+        format!("{}{}", PERMISSION_PREFIX, name)
+    }
+}
+
+/// A `liqk:Role`: the value of a policy's `liqk:access-level`
+#[derive(Clone)]
+pub struct Role {
+    pub iri: String,
+    pub label: String,
+    pub rank: i32,
+    /// Permission IRIs (`liqk:permits`)
+    pub permits: Vec<String>,
+}
+
+impl Role {
+    fn builtin(name: &str, rank: i32, permits: &[&str]) -> Self {
+        Self {
+            iri: format!("http://liqk.org/schema#access-level-{}", name),
+            label: name.to_string(),
+            rank,
+            permits: permits.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+}
+
+/// The role hierarchy. Roles are ordered by rank, and a permission is
+/// granted to the lowest rank of any role that permits it and every rank
+/// above, so access checks still compare the single rank resolved from the
+/// access policies.
+///
+/// The built-in access levels are always present; `liqk:Role` resources in
+/// the access graph replace the built-in role with the same IRI or add new
+/// ones, and are reloaded after updates that may change the access graph.
+pub struct Roles {
+    roles: RwLock<Vec<Role>>,
+}
+
+impl Default for Roles {
+    fn default() -> Self {
+        Self {
+            roles: RwLock::new(Self::builtin()),
+        }
+    }
+}
+
+impl Roles {
+    fn builtin() -> Vec<Role> {
+        vec![
+            Role::builtin(
+                "admin",
+                4,
+                &[PERMISSION_VIEW, PERMISSION_COMMENT, PERMISSION_EDIT, PERMISSION_ADMIN],
+            ),
+            Role::builtin("edit", 3, &[PERMISSION_VIEW, PERMISSION_COMMENT, PERMISSION_EDIT]),
+            Role::builtin("comment", 2, &[PERMISSION_VIEW, PERMISSION_COMMENT]),
+            Role::builtin("view", 1, &[PERMISSION_VIEW]),
+        ]
+    }
+
+    /// All roles, highest rank first
+    pub fn list(&self) -> Vec<Role> {
+        self.roles.read().unwrap().clone()
+    }
+
+    /// Minimum rank holding a permission; `i32::MAX` when no role permits
+    /// it, which denies everyone
+    pub fn required_rank(&self, permission: &str) -> i32 {
+        self.roles
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|r| r.permits.iter().any(|p| p == permission))
+            .map(|r| r.rank)
+            .min()
+            .unwrap_or(i32::MAX)
+    }
+
+    /// IRI of the role with exactly this rank, for granting it
+    pub fn role_iri(&self, rank: i32) -> Option<String> {
+        self.roles.read().unwrap().iter().find(|r| r.rank == rank).map(|r| r.iri.clone())
+    }
+
+    /// Inverse of [`Roles::role_iri`]
+    pub fn rank_of(&self, iri: &str) -> Option<i32> {
+        self.roles.read().unwrap().iter().find(|r| r.iri == iri).map(|r| r.rank)
+    }
+
+    /// Replace the graph-defined roles, keeping built-in ones not redefined
+    fn set(&self, loaded: Vec<Role>) {
+        let mut roles: Vec<Role> = Self::builtin()
+            .into_iter()
+            .filter(|b| !loaded.iter().any(|r| r.iri == b.iri))
+            .collect();
+        roles.extend(loaded);
+        roles.sort_by_key(|r| std::cmp::Reverse(r.rank));
+        *self.roles.write().unwrap() = roles;
+    }
+}
+
+/// Read `liqk:Role` resources from the access graph
+async fn load_roles(state: &AppState) -> Result<Vec<Role>, String> {
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+
+SELECT ?role ?rank ?label ?permits FROM <{graph}> WHERE {{
+    ?role a liqk:Role ;
+        liqk:rank ?rank .
+    OPTIONAL {{ ?role rdfs:label ?label }}
+    OPTIONAL {{ ?role liqk:permits ?permits }}
+}}
+ORDER BY ?role"#,
+        graph = ACCESS_GRAPH,
+    );
+
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+
+    // Rows are ordered by role, so each role's permissions are contiguous
+    let mut roles: Vec<Role> = Vec::new();
+    for b in &bindings {
+        let (Some(iri), Some(rank)) = (
+            binding_value(b, "role"),
+            binding_value(b, "rank").and_then(|s| s.parse().ok()),
+        ) else {
+            continue;
+        };
+        if roles.last().map(|r| r.iri.as_str()) != Some(iri) {
+            let label = binding_value(b, "label")
+                .unwrap_or_else(|| iri.rsplit(['#', '/']).next().unwrap_or(iri))
+                .trim_start_matches("access-level-")
+                .to_string();
+            roles.push(Role {
+                iri: iri.to_string(),
+                label,
+                rank,
+                permits: Vec::new(),
+            });
+        }
+        if let (Some(role), Some(permits)) = (roles.last_mut(), binding_value(b, "permits")) {
+            if !role.permits.iter().any(|p| p == permits) {
+                role.permits.push(permits.to_string());
+            }
+        }
+    }
+
+    Ok(roles)
+}
+
+/// Refresh the role hierarchy from the access graph. On failure the
+/// previous roles stay in effect.
+pub async fn reload_roles(state: &AppState) {
+    match load_roles(state).await {
+        Ok(loaded) => {
+            let count = loaded.len();
+            state.roles.set(loaded);
+            info!(roles = count, "Roles loaded from the access graph");
+        }
+        Err(e) => warn!(error = %e, "Failed to load roles - keeping the current ones"),
+    }
+}
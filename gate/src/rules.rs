@@ -0,0 +1,126 @@
+use axum::http::{Method, StatusCode};
+use serde::Deserialize;
+
+use crate::roles::{permission_iri, Roles, PERMISSION_EDIT, PERMISSION_VIEW};
+
+/// An authorization rule: requests whose path starts with `prefix` (compared
+/// case-insensitively) and whose method is in `methods` (any method when
+/// omitted) need at least `rank` on the resource the handler guards, or a
+/// role granting `permission` there.
+#[derive(Deserialize, Clone)]
+pub struct Rule {
+    pub prefix: String,
+    #[serde(default)]
+    pub methods: Option<Vec<String>>,
+    #[serde(default)]
+    pub rank: Option<i32>,
+    #[serde(default)]
+    pub permission: Option<String>,
+}
+
+impl Rule {
+    fn new(prefix: &str, methods: &[&str], permission: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            methods: (!methods.is_empty()).then(|| methods.iter().map(|m| m.to_string()).collect()),
+            rank: None,
+            permission: Some(permission.to_string()),
+        }
+    }
+
+    fn required_rank(&self, roles: &Roles) -> i32 {
+        match (&self.permission, self.rank) {
+            (Some(permission), _) => roles.required_rank(permission),
+            (None, Some(rank)) => rank,
+            (None, None) => i32::MAX,
+        }
+    }
+
+    fn allows(&self, method: &Method) -> bool {
+        match &self.methods {
+            None => true,
+            // HEAD is answered by GET routes, so a GET rule covers it
+            Some(methods) => methods.iter().any(|m| {
+                m.eq_ignore_ascii_case(method.as_str()) || (*method == Method::HEAD && m.eq_ignore_ascii_case("GET"))
+            }),
+        }
+    }
+}
+
+/// Path-based authorization rules shared by the proxy and file handlers.
+/// Rules are tried from the longest prefix to the shortest; the first one
+/// that allows the request method decides the required rank.
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Default for Rules {
+    /// The built-in rules, used when `AUTH_RULES` is not set
+    fn default() -> Self {
+        Self::new(vec![
+            Rule::new("/update", &[], PERMISSION_EDIT),
+            Rule::new("/load", &[], PERMISSION_EDIT),
+            Rule::new("/res", &["POST"], PERMISSION_EDIT),
+            Rule::new("/res/uploads", &[], PERMISSION_EDIT),
+            Rule::new("/res/", &["GET"], PERMISSION_VIEW),
+            Rule::new("/res/", &["PUT", "PATCH"], PERMISSION_EDIT),
+            Rule::new("/res/", &["POST"], PERMISSION_VIEW),
+            Rule::new("/file", &["GET"], PERMISSION_VIEW),
+            Rule::new("/file", &["POST", "DELETE"], PERMISSION_EDIT),
+            Rule::new("/trash", &["GET"], PERMISSION_VIEW),
+            Rule::new("/trash", &["POST", "DELETE"], PERMISSION_EDIT),
+            Rule::new("/api/diff", &["GET"], PERMISSION_VIEW),
+            Rule::new("/collections", &["GET"], PERMISSION_VIEW),
+            Rule::new("/", &[], PERMISSION_VIEW),
+        ])
+    }
+}
+
+impl Rules {
+    pub fn new(mut rules: Vec<Rule>) -> Self {
+        for rule in &mut rules {
+            rule.prefix = rule.prefix.to_lowercase();
+        }
+        // Stable sort keeps file order among rules with the same prefix
+        rules.sort_by_key(|r| std::cmp::Reverse(r.prefix.len()));
+        Self { rules }
+    }
+
+    /// Parse rules from a JSON array of `{"prefix", "methods"?, "rank" or
+    /// "permission"}`
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let mut rules: Vec<Rule> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        for rule in &mut rules {
+            match (&rule.permission, rule.rank) {
+                (Some(_), Some(_)) | (None, None) => {
+                    return Err(format!("rule for {} needs exactly one of rank and permission", rule.prefix));
+                }
+                (None, Some(rank)) if rank < 0 => {
+                    return Err(format!("rank for {} must not be negative", rule.prefix));
+                }
+                _ => {}
+            }
+            rule.permission = rule.permission.as_deref().map(permission_iri);
+        }
+        Ok(Self::new(rules))
+    }
+
+    /// Minimum rank for a request, with permissions resolved through the
+    /// role hierarchy. Fails with `405` when rules cover the path but none
+    /// allows the method, and `403` when no rule covers it.
+    pub fn required_rank(&self, roles: &Roles, method: &Method, path: &str) -> Result<i32, StatusCode> {
+        let path = path.to_lowercase();
+        let mut covered = false;
+        for rule in self.rules.iter().filter(|r| path.starts_with(&r.prefix)) {
+            if rule.allows(method) {
+                return Ok(rule.required_rank(roles));
+            }
+            covered = true;
+        }
+        Err(if covered {
+            StatusCode::METHOD_NOT_ALLOWED
+        } else {
+            StatusCode::FORBIDDEN
+        })
+    }
+}
@@ -0,0 +1,416 @@
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Utc};
+use std::{net::SocketAddr, sync::Arc};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::admin::{is_admin, parse_time, uuid_of};
+use crate::auth::random_secret;
+use crate::files::{
+    binding_value, credential, escape_sparql_string, fingerprint, get_access_rank_iri, hash_token,
+    send_sparql_update, send_sparql_update_once, sparql_select, validate_uuid,
+};
+use crate::proxy::GRAPH_IRI;
+use crate::public_graphs::restrict_to_graphs;
+use crate::query_log;
+use crate::query_templates::run_template;
+use crate::update_graphs::form_params;
+use crate::AppState;
+
+/// Graph holding saved queries
+pub const QUERIES_GRAPH: &str = "http://liqk.org/graph/queries";
+
+/// Maximum length of a saved query
+const MAX_QUERY_LEN: usize = 64 * 1024;
+/// Maximum number of graphs a saved query may read
+const MAX_GRAPHS: usize = 32;
+
+/// A saved query as listed to its owner; the capability itself is not stored
+struct SavedQuery {
+    query: String,
+    text: String,
+    graphs: Vec<String>,
+    created: Option<DateTime<Utc>>,
+}
+
+/// Graphs from repeated `graph=` parameters; each must be an absolute IRI
+fn requested_graphs(query_string: Option<&str>) -> Result<Vec<String>, String> {
+    let graphs: Vec<String> = form_params(query_string.unwrap_or(""))
+        .into_iter()
+        .filter(|(k, _)| k == "graph")
+        .map(|(_, v)| v.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+        .collect();
+    if graphs.is_empty() {
+        return Err("at least one graph= parameter is required".to_string());
+    }
+    if graphs.len() > MAX_GRAPHS {
+        return Err(format!("at most {} graphs can be saved", MAX_GRAPHS));
+    }
+    for graph in &graphs {
+        if reqwest::Url::parse(graph).is_err() {
+            return Err(format!("graph must be an absolute IRI: {}", graph));
+        }
+    }
+    Ok(graphs)
+}
+
+async fn insert_query(
+    state: &AppState,
+    query: &Uuid,
+    capability_hash: &str,
+    text: &str,
+    graphs: &[String],
+    owner: &str,
+) -> Result<(), String> {
+    let graph_triples: String = graphs
+        .iter()
+        .map(|g| format!(" ;\n            liqk:query-graph \"{}\"", escape_sparql_string(g)))
+        .collect();
+    let update = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX dcterms: <http://purl.org/dc/terms/>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+
+INSERT DATA {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{query}> a liqk:SavedQuery ;
+            liqk:capability-hash "{capability_hash}" ;
+            liqk:query-text "{text}" ;
+            liqk:owner "{owner}" ;
+            dcterms:created "{created}"^^xsd:dateTime{graph_triples} .
+    }}
+}}"#,
+        graph = QUERIES_GRAPH,
+        query = query,
+        capability_hash = capability_hash,
+        text = escape_sparql_string(text),
+        owner = escape_sparql_string(owner),
+        created = Utc::now().to_rfc3339(),
+        graph_triples = graph_triples,
+    );
+
+    send_sparql_update_once(state, "saved-query", &update).await
+}
+
+/// Query text and graphs of the saved query a capability grants
+async fn lookup_query(state: &AppState, capability_hash: &str) -> Result<Option<(String, Vec<String>)>, String> {
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?text ?graph FROM <{graph}> WHERE {{
+    ?query a liqk:SavedQuery ;
+        liqk:capability-hash "{capability_hash}" ;
+        liqk:query-text ?text ;
+        liqk:query-graph ?graph .
+}}"#,
+        graph = QUERIES_GRAPH,
+        capability_hash = capability_hash,
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+
+    let Some(text) = bindings.first().and_then(|b| binding_value(b, "text")) else {
+        return Ok(None);
+    };
+    let graphs = bindings
+        .iter()
+        .filter_map(|b| binding_value(b, "graph"))
+        .map(str::to_string)
+        .collect();
+    Ok(Some((text.to_string(), graphs)))
+}
+
+/// Saved queries created by `owner`
+async fn load_queries(state: &AppState, owner: &str) -> Result<Vec<SavedQuery>, String> {
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX dcterms: <http://purl.org/dc/terms/>
+
+SELECT ?query ?text ?graph ?created FROM <{graph}> WHERE {{
+    ?query a liqk:SavedQuery ;
+        liqk:owner "{owner}" ;
+        liqk:query-text ?text ;
+        liqk:query-graph ?graph .
+    OPTIONAL {{ ?query dcterms:created ?created }}
+}}
+ORDER BY DESC(?created) ?query"#,
+        graph = QUERIES_GRAPH,
+        owner = escape_sparql_string(owner),
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+
+    // One row per graph; rows of a query are adjacent thanks to the ordering
+    let mut queries: Vec<SavedQuery> = Vec::new();
+    for b in &bindings {
+        let (Some(iri), Some(text), Some(graph)) =
+            (binding_value(b, "query"), binding_value(b, "text"), binding_value(b, "graph"))
+        else {
+            continue;
+        };
+        match queries.last_mut() {
+            Some(last) if last.query == iri => last.graphs.push(graph.to_string()),
+            _ => queries.push(SavedQuery {
+                query: iri.to_string(),
+                text: text.to_string(),
+                graphs: vec![graph.to_string()],
+                created: binding_value(b, "created").and_then(parse_time),
+            }),
+        }
+    }
+    Ok(queries)
+}
+
+async fn query_owner(state: &AppState, query: &Uuid) -> Result<Option<String>, String> {
+    let select = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?owner FROM <{graph}> WHERE {{ <urn:uuid:{query}> a liqk:SavedQuery ; liqk:owner ?owner }}"#,
+        graph = QUERIES_GRAPH,
+        query = query,
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &select).await?;
+    Ok(bindings.first().and_then(|b| binding_value(b, "owner")).map(str::to_string))
+}
+
+async fn delete_query(state: &AppState, query: &Uuid) -> Result<(), String> {
+    let update = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+DELETE {{ GRAPH <{graph}> {{ ?query ?p ?o }} }}
+WHERE {{
+    GRAPH <{graph}> {{
+        BIND(<urn:uuid:{query}> AS ?query)
+        ?query a liqk:SavedQuery ;
+            ?p ?o .
+    }}
+}}"#,
+        graph = QUERIES_GRAPH,
+        query = query,
+    );
+
+    send_sparql_update(state, "saved-query-revoke", &update).await
+}
+
+/// Run a query on Oxigraph's query endpoint, with the dataset fixed to
+/// `graphs` when given. The query endpoint rejects updates, so a capability
+/// or template can only ever read.
+pub async fn execute_query(
+    state: &AppState,
+    text: &str,
+    graphs: Option<&[String]>,
+    accept: Option<&str>,
+) -> Result<Response, String> {
+    let dataset = graphs
+        .map(|graphs| restrict_to_graphs(None, None, &[], graphs).0)
+        .unwrap_or_default();
+    query_log::record("query", text);
+
+    let mut request = state
+        .client
+        .post(format!("{}/query?{}", state.oxigraph_url, dataset))
+        .header(header::CONTENT_TYPE, "application/sparql-query")
+        .body(text.to_string());
+    if let Some(accept) = accept {
+        request = request.header(header::ACCEPT, accept);
+    }
+    let response = request.send().await.map_err(|e| format!("Failed to send SPARQL query: {}", e))?;
+
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let body = response.bytes().await.map_err(|e| format!("Failed to read response: {}", e))?;
+
+    Ok((status, [(header::CONTENT_TYPE, content_type)], body).into_response())
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// POST /gate/queries?graph=IRI&graph=... - Save the SPARQL query in the body
+/// and return a capability URL that runs it without authentication
+pub async fn query_create_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    uri: Uri,
+    body: String,
+) -> Response {
+    let credential = credential(&state, &headers, &jar);
+    let Some(owner) = credential.owner() else {
+        warn!(client = %addr, "Access denied - saved queries require a signed-in user");
+        return (StatusCode::FORBIDDEN, "Access denied - saved queries require authentication").into_response();
+    };
+
+    // The capability hands out the creator's query access
+    let required = match state.rules.required_rank(&state.roles, &Method::POST, "/query") {
+        Ok(required) => required,
+        Err(status) => return (status, "Access denied").into_response(),
+    };
+    let rank = get_access_rank_iri(&state, GRAPH_IRI, &headers, &jar).await;
+    if rank < required {
+        warn!(client = %addr, credential = %credential.fingerprint(), rank = rank, required = required, "Access denied - insufficient rank for saved query");
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+
+    let graphs = match requested_graphs(uri.query()) {
+        Ok(graphs) => graphs,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let text = body.trim();
+    if text.is_empty() || text.len() > MAX_QUERY_LEN {
+        return (StatusCode::BAD_REQUEST, "query must be between 1 byte and 64 KiB").into_response();
+    }
+
+    let query = Uuid::new_v4();
+    let capability = random_secret();
+    if let Err(e) = insert_query(&state, &query, &hash_token(&capability), text, &graphs, &owner).await {
+        warn!(client = %addr, credential = %credential.fingerprint(), error = %e, "Failed to store saved query");
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save query").into_response();
+    }
+
+    info!(
+        client = %addr,
+        credential = %credential.fingerprint(),
+        query = %query,
+        capability = %fingerprint(&capability),
+        owner = %owner,
+        graphs = graphs.len(),
+        "Saved query created"
+    );
+
+    let json_response = serde_json::json!({
+        "path": format!("/q/{}", capability),
+        "uuid": query.to_string(),
+        "graphs": graphs,
+    });
+
+    (
+        StatusCode::CREATED,
+        [(header::CONTENT_TYPE, "application/json")],
+        json_response.to_string(),
+    )
+        .into_response()
+}
+
+/// GET /gate/queries - The caller's saved queries (without their capabilities)
+pub async fn queries_list_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    let credential = credential(&state, &headers, &jar);
+    let Some(owner) = credential.owner() else {
+        return (StatusCode::FORBIDDEN, "Access denied - saved queries require authentication").into_response();
+    };
+
+    match load_queries(&state, &owner).await {
+        Ok(queries) => {
+            let json_response = serde_json::json!({
+                "queries": queries.iter().map(|q| {
+                    serde_json::json!({
+                        "uuid": uuid_of(&q.query).map(|u| u.to_string()),
+                        "query": q.text,
+                        "graphs": q.graphs,
+                        "created": q.created.map(|t| t.to_rfc3339()),
+                    })
+                }).collect::<Vec<_>>()
+            });
+            ([(header::CONTENT_TYPE, "application/json")], json_response.to_string()).into_response()
+        }
+        Err(e) => {
+            warn!(client = %addr, credential = %credential.fingerprint(), error = %e, "Failed to load saved queries");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load saved queries").into_response()
+        }
+    }
+}
+
+/// POST /gate/queries/:uuid/revoke - Delete a saved query (its owner or an admin)
+pub async fn query_revoke_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(uuid_str): Path<String>,
+) -> Response {
+    let Some(query) = validate_uuid(&uuid_str) else {
+        return (StatusCode::BAD_REQUEST, "Invalid UUID format").into_response();
+    };
+    let credential = credential(&state, &headers, &jar);
+
+    let owner = match query_owner(&state, &query).await {
+        Ok(Some(owner)) => owner,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Saved query not found").into_response(),
+        Err(e) => {
+            warn!(client = %addr, query = %query, error = %e, "SPARQL lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup saved query").into_response();
+        }
+    };
+    if credential.owner().as_deref() != Some(owner.as_str()) && !is_admin(&state, &headers, &jar).await {
+        warn!(client = %addr, credential = %credential.fingerprint(), query = %query, "Access denied - saved query belongs to another user");
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+
+    match delete_query(&state, &query).await {
+        Ok(()) => {
+            info!(client = %addr, credential = %credential.fingerprint(), query = %query, "Saved query revoked");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            warn!(client = %addr, query = %query, error = %e, "Failed to revoke saved query");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to revoke saved query").into_response()
+        }
+    }
+}
+
+/// GET /q/:capability - Run a saved query (no token needed). The client's
+/// Accept header picks the result format. Paths that are not capabilities
+/// name query templates.
+pub async fn query_run_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(capability): Path<String>,
+    uri: Uri,
+) -> Response {
+    let is_capability = capability.len() == 64 && capability.chars().all(|c| c.is_ascii_hexdigit());
+    if !is_capability {
+        return run_template(&state, addr, &jar, &headers, uri.query(), &capability).await;
+    }
+    let capability_fingerprint = fingerprint(&capability);
+
+    let (text, graphs) = match lookup_query(&state, &hash_token(&capability)).await {
+        Ok(Some(saved)) => saved,
+        Ok(None) => {
+            warn!(client = %addr, credential = %capability_fingerprint, "Saved query not found");
+            return (StatusCode::NOT_FOUND, "Saved query not found").into_response();
+        }
+        Err(e) => {
+            warn!(client = %addr, credential = %capability_fingerprint, error = %e, "SPARQL lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup saved query").into_response();
+        }
+    };
+
+    let accept = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok());
+    match execute_query(&state, &text, Some(&graphs), accept).await {
+        Ok(response) => {
+            info!(client = %addr, credential = %capability_fingerprint, status = %response.status(), "Saved query executed");
+            response
+        }
+        Err(e) => {
+            warn!(client = %addr, credential = %capability_fingerprint, error = %e, "Saved query failed");
+            (StatusCode::BAD_GATEWAY, "Saved query failed").into_response()
+        }
+    }
+}
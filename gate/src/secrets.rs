@@ -0,0 +1,65 @@
+use std::{
+    env,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Marks a configuration value encrypted with `liqk-crypto conf encrypt`
+pub const ENCRYPTED_PREFIX: &str = "enc:";
+/// `liqk-crypto` binary used when `LIQK_CRYPTO_BIN` is unset
+pub const DEFAULT_LIQK_CRYPTO_BIN: &str = "liqk-crypto";
+
+/// Replace every `enc:` value in the environment with its plaintext, so the
+/// rest of the configuration, logging and span export included, reads
+/// secrets as usual. Values are decrypted by `liqk-crypto conf decrypt`
+/// with the secret key at `CONF_SECRET_KEY`; errors name the variable,
+/// never the value. Returns the number of values decrypted.
+///
+/// This writes the process environment, so it must run before any other
+/// thread is started, the async runtime's included.
+pub fn decrypt_env() -> Result<usize, String> {
+    let encrypted: Vec<(String, String)> = env::vars()
+        .filter(|(_, value)| value.starts_with(ENCRYPTED_PREFIX))
+        .collect();
+    if encrypted.is_empty() {
+        return Ok(0);
+    }
+
+    let secret_key = env::var("CONF_SECRET_KEY")
+        .map_err(|_| format!("{} is encrypted but CONF_SECRET_KEY is not set", encrypted[0].0))?;
+    let bin = env::var("LIQK_CRYPTO_BIN").unwrap_or_else(|_| DEFAULT_LIQK_CRYPTO_BIN.to_string());
+
+    for (name, value) in &encrypted {
+        let plaintext = decrypt_value(&bin, &secret_key, value).map_err(|e| format!("{}: {}", name, e))?;
+        env::set_var(name, plaintext);
+    }
+    Ok(encrypted.len())
+}
+
+/// Decrypt one value, passed on stdin to keep it out of the process list
+fn decrypt_value(bin: &str, secret_key: &str, value: &str) -> Result<String, String> {
+    let mut child = Command::new(bin)
+        .args(["conf", "decrypt", "--sk", secret_key])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {}: {}", bin, e))?;
+    // A child that exits without reading (e.g. a missing key) is reported
+    // by its status below rather than as a broken pipe
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(value.as_bytes());
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("decryption failed ({}): {}", output.status, stderr.trim()));
+    }
+
+    let mut plaintext = String::from_utf8(output.stdout).map_err(|_| "decrypted value is not UTF-8".to_string())?;
+    // `conf decrypt` ends its output with a newline
+    if plaintext.ends_with('\n') {
+        plaintext.pop();
+    }
+    Ok(plaintext)
+}
@@ -0,0 +1,90 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+
+use crate::AppState;
+
+/// `Content-Security-Policy` when `CONTENT_SECURITY_POLICY` is unset. The
+/// gate's pages carry their scripts and styles inline; rendered READMEs
+/// may show images from elsewhere over HTTPS.
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; script-src 'self' 'unsafe-inline'; \
+    style-src 'self' 'unsafe-inline'; img-src 'self' data: https:; object-src 'none'; base-uri 'self'; \
+    frame-ancestors 'none'";
+/// `Referrer-Policy` when `REFERRER_POLICY` is unset, so share and query
+/// links in page URLs do not leak to other sites
+pub const DEFAULT_REFERRER_POLICY: &str = "same-origin";
+/// HSTS lifetime in seconds when the gate serves HTTPS itself
+pub const DEFAULT_HSTS_MAX_AGE_SECS: u64 = 31_536_000;
+
+/// Marks responses that came from an upstream, which only get the headers
+/// with `SECURITY_HEADERS_PROXIED`
+#[derive(Clone, Copy)]
+pub struct Proxied;
+
+/// Security headers set on the gate's HTML pages (login, upload, directory
+/// listings, admin pages and the rest) and, optionally, on proxied
+/// responses. Headers a response already has are left alone.
+pub struct SecurityHeaders {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    /// Also set them on proxied responses
+    pub proxied: bool,
+}
+
+impl SecurityHeaders {
+    /// Empty policies and a zero HSTS lifetime leave those headers out;
+    /// `X-Content-Type-Options: nosniff` is always set
+    pub fn new(
+        content_security_policy: &str,
+        referrer_policy: &str,
+        hsts_max_age_secs: u64,
+        proxied: bool,
+    ) -> Result<Self, String> {
+        let mut headers = vec![(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"))];
+        if !content_security_policy.is_empty() {
+            let value = HeaderValue::from_str(content_security_policy)
+                .map_err(|_| "CONTENT_SECURITY_POLICY is not a valid header value".to_string())?;
+            headers.push((header::CONTENT_SECURITY_POLICY, value));
+        }
+        if !referrer_policy.is_empty() {
+            let value = HeaderValue::from_str(referrer_policy)
+                .map_err(|_| "REFERRER_POLICY is not a valid header value".to_string())?;
+            headers.push((header::REFERRER_POLICY, value));
+        }
+        if hsts_max_age_secs > 0 {
+            let value = HeaderValue::from_str(&format!("max-age={}", hsts_max_age_secs)).unwrap();
+            headers.push((header::STRICT_TRANSPORT_SECURITY, value));
+        }
+        Ok(Self { headers, proxied })
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        for (name, value) in &self.headers {
+            if !headers.contains_key(name) {
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Middleware setting the security headers on HTML the gate serves itself
+/// and, with `SECURITY_HEADERS_PROXIED`, on every proxied response
+pub async fn security_headers_middleware(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let mut response = next.run(req).await;
+    let applies = if response.extensions().get::<Proxied>().is_some() {
+        state.security_headers.proxied
+    } else {
+        response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.trim_start().to_ascii_lowercase().starts_with("text/html"))
+    };
+    if applies {
+        state.security_headers.apply(response.headers_mut());
+    }
+    response
+}
@@ -0,0 +1,150 @@
+use chrono::{DateTime, Duration, Utc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+
+use crate::auth::random_secret;
+
+/// A server-side login session for principals that are not backed by an
+/// access token in the graph (e.g. LDAP users). The session ID is stored in
+/// the session cookie in place of a token.
+#[derive(Clone)]
+pub struct Session {
+    pub id: String,
+    /// Stable identity of the principal (e.g. an LDAP DN)
+    pub identity: String,
+    /// Name of the backend that authenticated the principal
+    pub backend: &'static str,
+    /// Access rank granted to the principal on every resource
+    pub rank: i32,
+    pub created: DateTime<Utc>,
+    /// Last request made with the session, refreshed on use
+    pub last_seen: DateTime<Utc>,
+}
+
+/// In-memory session store. Sessions do not survive a gate restart.
+///
+/// Also tracks when each access token was last presented in the session
+/// cookie, so token logins are subject to the same inactivity timeout.
+/// After a restart, token cookies count as active from their next use.
+pub struct SessionStore {
+    sessions: RwLock<HashMap<String, Session>>,
+    /// Last cookie use per token hash
+    token_last_seen: RwLock<HashMap<String, DateTime<Utc>>>,
+    /// Token hashes whose cookies were logged out everywhere
+    logged_out_tokens: RwLock<HashSet<String>>,
+    max_age: Duration,
+    /// `None` disables the inactivity timeout
+    idle_timeout: Option<Duration>,
+}
+
+impl SessionStore {
+    /// An `idle_timeout_secs` of 0 disables the inactivity timeout
+    pub fn new(max_age_secs: i64, idle_timeout_secs: i64) -> Self {
+        Self {
+            sessions: RwLock::new(HashMap::new()),
+            token_last_seen: RwLock::new(HashMap::new()),
+            logged_out_tokens: RwLock::new(HashSet::new()),
+            max_age: Duration::seconds(max_age_secs),
+            idle_timeout: (idle_timeout_secs > 0).then(|| Duration::seconds(idle_timeout_secs)),
+        }
+    }
+
+    fn is_idle(&self, last_seen: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        self.idle_timeout.is_some_and(|idle| now - last_seen > idle)
+    }
+
+    /// Create a session and return its ID
+    pub fn create(&self, identity: String, backend: &'static str, rank: i32) -> String {
+        let id = random_secret();
+        let now = Utc::now();
+        let session = Session {
+            id: id.clone(),
+            identity,
+            backend,
+            rank,
+            created: now,
+            last_seen: now,
+        };
+        self.sessions.write().unwrap().insert(id.clone(), session);
+        id
+    }
+
+    /// Look up a live session and mark it as used. Sessions past their
+    /// maximum age or idle for longer than the timeout are dropped.
+    pub fn get(&self, id: &str) -> Option<Session> {
+        let now = Utc::now();
+        let mut sessions = self.sessions.write().unwrap();
+        let session = sessions.get_mut(id)?;
+        if now - session.created > self.max_age || self.is_idle(session.last_seen, now) {
+            sessions.remove(id);
+            return None;
+        }
+        session.last_seen = now;
+        Some(session.clone())
+    }
+
+    /// All live sessions, oldest first. Expired sessions are pruned.
+    pub fn list(&self) -> Vec<Session> {
+        let mut sessions = self.sessions.write().unwrap();
+        let now = Utc::now();
+        sessions.retain(|_, s| now - s.created <= self.max_age && !self.is_idle(s.last_seen, now));
+        let mut live: Vec<Session> = sessions.values().cloned().collect();
+        live.sort_by_key(|s| s.created);
+        live
+    }
+
+    /// Remove a session; returns whether it existed
+    pub fn remove(&self, id: &str) -> bool {
+        self.sessions.write().unwrap().remove(id).is_some()
+    }
+
+    /// Remove every session of a principal; returns how many were removed
+    pub fn remove_identity(&self, identity: &str, backend: &str) -> usize {
+        let mut sessions = self.sessions.write().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, s| !(s.identity == identity && s.backend == backend));
+        before - sessions.len()
+    }
+
+    /// Reject a token in every session cookie until its next explicit
+    /// login. Tokens sent in headers are unaffected.
+    pub fn logout_token(&self, token_hash: &str) {
+        self.logged_out_tokens.write().unwrap().insert(token_hash.to_string());
+    }
+
+    /// Record a token login, restarting its inactivity window
+    pub fn token_login(&self, token_hash: &str) {
+        self.logged_out_tokens.write().unwrap().remove(token_hash);
+        self.token_last_seen
+            .write()
+            .unwrap()
+            .insert(token_hash.to_string(), Utc::now());
+    }
+
+    /// Mark a token presented in the session cookie as used. Returns `false`
+    /// when the token has been idle too long or was logged out everywhere;
+    /// it then stays rejected until the next explicit login.
+    pub fn touch_token(&self, token_hash: &str) -> bool {
+        if self.logged_out_tokens.read().unwrap().contains(token_hash) {
+            return false;
+        }
+        if self.idle_timeout.is_none() {
+            return true;
+        }
+        let now = Utc::now();
+        let mut seen = self.token_last_seen.write().unwrap();
+        match seen.get_mut(token_hash) {
+            Some(last_seen) if self.is_idle(*last_seen, now) => false,
+            Some(last_seen) => {
+                *last_seen = now;
+                true
+            }
+            None => {
+                seen.insert(token_hash.to_string(), now);
+                true
+            }
+        }
+    }
+}
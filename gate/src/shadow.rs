@@ -0,0 +1,116 @@
+use axum::http::{header, HeaderMap, Method};
+use bytes::Bytes;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{debug, warn};
+
+/// Most shadow queries in flight at once; queries beyond it are not
+/// mirrored, so a slow secondary store cannot pile up requests in the gate
+const MAX_IN_FLIGHT: usize = 64;
+
+/// Headers a shadow query is sent with: enough for the secondary store to
+/// parse the query and pick the same result format
+const MIRRORED_HEADERS: [header::HeaderName; 2] = [header::CONTENT_TYPE, header::ACCEPT];
+
+/// A secondary Oxigraph (`SHADOW_URL`) the queries sent to the primary are
+/// mirrored to, to try a new store version on production traffic. Shadow
+/// queries are sent in the background alongside the primary's request, so
+/// clients never wait for them; their responses are read and dropped, and
+/// their failures are only logged.
+#[derive(Default)]
+pub struct Shadow {
+    url: Option<String>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Shadow {
+    /// Parse `SHADOW_URL`; an empty value disables shadowing
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let url = value.trim();
+        if url.is_empty() {
+            return Ok(Self::default());
+        }
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(format!("{} must start with http:// or https://", url));
+        }
+        Ok(Self {
+            url: Some(url.trim_end_matches('/').to_string()),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// Send a copy of a query to the secondary store in the background.
+    /// `path_and_query` is the part of the primary's URL after its base.
+    pub fn mirror(
+        &self,
+        client: &reqwest::Client,
+        timeout: Option<Duration>,
+        method: &Method,
+        path_and_query: &str,
+        headers: &HeaderMap,
+        body: Bytes,
+    ) {
+        let Some(url) = &self.url else {
+            return;
+        };
+        if self.in_flight.fetch_add(1, Ordering::Relaxed) >= MAX_IN_FLIGHT {
+            self.in_flight.fetch_sub(1, Ordering::Relaxed);
+            debug!(path = %path_and_query, "Shadow query skipped - too many in flight");
+            return;
+        }
+
+        let target_url = format!("{}{}", url, path_and_query);
+        let mut request = client.request(method.clone(), &target_url);
+        for name in MIRRORED_HEADERS {
+            if let Some(value) = headers.get(&name) {
+                request = request.header(name, value.clone());
+            }
+        }
+        if !body.is_empty() {
+            request = request.body(body);
+        }
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+
+        let in_flight = Arc::clone(&self.in_flight);
+        let path_and_query = path_and_query.to_string();
+        tokio::spawn(async move {
+            let started = Instant::now();
+            match request.send().await {
+                // The whole result is read so the secondary does the work
+                // the primary does
+                Ok(resp) => {
+                    let status = resp.status();
+                    let read = resp.bytes().await;
+                    let duration_ms = started.elapsed().as_millis() as u64;
+                    match read {
+                        Ok(body) if status.is_success() => {
+                            debug!(path = %path_and_query, status = %status, bytes = body.len(), duration_ms = duration_ms, "Shadow query answered");
+                        }
+                        Ok(body) => {
+                            warn!(path = %path_and_query, status = %status, bytes = body.len(), duration_ms = duration_ms, "Shadow query answered with an error");
+                        }
+                        Err(e) => {
+                            warn!(path = %path_and_query, status = %status, duration_ms = duration_ms, error = %e, "Shadow query failed");
+                        }
+                    }
+                }
+                Err(e) => {
+                    let duration_ms = started.elapsed().as_millis() as u64;
+                    warn!(path = %path_and_query, duration_ms = duration_ms, error = %e, "Shadow query failed");
+                }
+            }
+            in_flight.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
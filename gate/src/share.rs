@@ -0,0 +1,187 @@
+use axum::{
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::{net::SocketAddr, sync::Arc};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::files::{get_access_rank, lookup_file_by_uuid, read_stored_file, validate_uuid};
+use crate::AppState;
+
+/// Default lifetime of a share link in seconds (1 day)
+pub const DEFAULT_SHARE_TTL_SECS: i64 = 86_400;
+/// Default upper bound for requested lifetimes in seconds (7 days)
+pub const DEFAULT_SHARE_MAX_TTL_SECS: i64 = 604_800;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signs and verifies share links with HMAC-SHA256 over `{uuid}:{exp}`
+pub struct ShareSigner {
+    key: Vec<u8>,
+    /// Upper bound for requested link lifetimes in seconds
+    pub max_ttl_secs: i64,
+}
+
+impl ShareSigner {
+    pub fn new(key: Vec<u8>, max_ttl_secs: i64) -> Self {
+        Self { key, max_ttl_secs }
+    }
+
+    fn mac(&self, uuid: &Uuid, exp: i64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(format!("{}:{}", uuid, exp).as_bytes());
+        mac
+    }
+
+    /// Hex signature for a file UUID and expiry (Unix seconds)
+    pub fn sign(&self, uuid: &Uuid, exp: i64) -> String {
+        hex::encode(self.mac(uuid, exp).finalize().into_bytes())
+    }
+
+    /// Constant-time signature check; does not check expiry
+    pub fn verify(&self, uuid: &Uuid, exp: i64, sig: &str) -> bool {
+        match hex::decode(sig) {
+            Ok(sig) => self.mac(uuid, exp).verify_slice(&sig).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateShareParams {
+    /// Requested lifetime in seconds
+    ttl: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct ShareParams {
+    sig: String,
+    exp: i64,
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// POST /res/:uuid/share?ttl=SECONDS - Create a signed, expiring download link
+pub async fn share_create_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    uri: Uri,
+    Path(uuid_str): Path<String>,
+    Query(params): Query<CreateShareParams>,
+) -> Response {
+    let uuid = match validate_uuid(&uuid_str) {
+        Some(u) => u,
+        None => {
+            warn!(client = %addr, uuid = %uuid_str, "Invalid UUID format");
+            return (StatusCode::BAD_REQUEST, "Invalid UUID format").into_response();
+        }
+    };
+
+    // Sharing hands out view access, so the default rule requires it
+    let required = match state.rules.required_rank(&state.roles, &Method::POST, uri.path()) {
+        Ok(required) => required,
+        Err(status) => return (status, "Access denied").into_response(),
+    };
+    let rank = get_access_rank(&state, &uuid, &headers, &jar).await;
+    if rank < required {
+        warn!(client = %addr, uuid = %uuid, rank = rank, required = required, "Access denied - insufficient rank for share");
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+
+    let ttl = params.ttl.unwrap_or(DEFAULT_SHARE_TTL_SECS);
+    if ttl <= 0 || ttl > state.share.max_ttl_secs {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("ttl must be between 1 and {} seconds", state.share.max_ttl_secs),
+        )
+            .into_response();
+    }
+
+    match lookup_file_by_uuid(&state.client, &state.oxigraph_url, &uuid.to_string()).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "File not found").into_response(),
+        Err(e) => {
+            warn!(client = %addr, uuid = %uuid, error = %e, "SPARQL lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup file").into_response();
+        }
+    }
+
+    let exp = Utc::now().timestamp() + ttl;
+    let sig = state.share.sign(&uuid, exp);
+    let path = format!("/share/{}?sig={}&exp={}", uuid, sig, exp);
+
+    info!(client = %addr, uuid = %uuid, rank = rank, exp = exp, "Share link created");
+
+    let json_response = serde_json::json!({
+        "path": path,
+        "expires": Utc.timestamp_opt(exp, 0).single().map(|t| t.to_rfc3339()),
+    });
+
+    (
+        StatusCode::CREATED,
+        [(header::CONTENT_TYPE, "application/json")],
+        json_response.to_string(),
+    )
+        .into_response()
+}
+
+/// GET /share/:uuid?sig=...&exp=... - Download a file with a signed link (no token needed)
+pub async fn share_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(uuid_str): Path<String>,
+    Query(params): Query<ShareParams>,
+) -> Response {
+    let uuid = match validate_uuid(&uuid_str) {
+        Some(u) => u,
+        None => {
+            warn!(client = %addr, uuid = %uuid_str, "Invalid UUID format");
+            return (StatusCode::BAD_REQUEST, "Invalid UUID format").into_response();
+        }
+    };
+
+    if !state.share.verify(&uuid, params.exp, &params.sig) {
+        warn!(client = %addr, uuid = %uuid, "Share link rejected - invalid signature");
+        return (StatusCode::FORBIDDEN, "Invalid share link").into_response();
+    }
+
+    if Utc::now().timestamp() > params.exp {
+        warn!(client = %addr, uuid = %uuid, exp = params.exp, "Share link rejected - expired");
+        return (StatusCode::GONE, "Share link expired").into_response();
+    }
+
+    let stored_filename = match lookup_file_by_uuid(&state.client, &state.oxigraph_url, &uuid.to_string()).await {
+        Ok(Some(name)) => name,
+        Ok(None) => {
+            warn!(client = %addr, uuid = %uuid, "File not found");
+            return (StatusCode::NOT_FOUND, "File not found").into_response();
+        }
+        Err(e) => {
+            warn!(client = %addr, uuid = %uuid, error = %e, "SPARQL lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup file").into_response();
+        }
+    };
+
+    match read_stored_file(&state.files_dir, &stored_filename, &headers).await {
+        Ok((response, bytes)) => {
+            info!(client = %addr, uuid = %uuid, stored_as = %stored_filename, bytes = bytes, "File served via share link");
+            response
+        }
+        Err(e) => {
+            warn!(client = %addr, uuid = %uuid, stored_as = %stored_filename, error = %e, "Failed to read file from disk");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read file").into_response()
+        }
+    }
+}
@@ -0,0 +1,408 @@
+use axum::{
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::{net::SocketAddr, sync::Arc};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::admin::{admin_target, button, format_time, forbidden, is_admin, parse_time, uuid_of};
+use crate::files::{
+    binding_value, credential, escape_sparql_string, get_access_rank_iri, send_sparql_update, send_sparql_update_once,
+    sparql_select,
+};
+use crate::proxy::GRAPH_IRI;
+use crate::roles::PERMISSION_VIEW;
+use crate::templates::{escape_html, render_page, LINKS_HTML};
+use crate::AppState;
+
+/// Graph holding short links
+pub const LINKS_GRAPH: &str = "http://liqk.org/graph/links";
+
+const LINKS_ADMIN_PATH: &str = "/gate/admin/links";
+
+/// Length of generated codes
+const CODE_LEN: usize = 7;
+const CODE_ALPHABET: &[u8] = b"abcdefghijkmnpqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Maximum length of a custom code and of a link target
+const MAX_CODE_LEN: usize = 64;
+const MAX_TARGET_LEN: usize = 2048;
+
+#[derive(Deserialize)]
+pub struct CreateLinkParams {
+    /// Gate path (`/file/...`) or absolute `http(s)` URL
+    target: String,
+    /// Custom code; generated when omitted
+    code: Option<String>,
+}
+
+struct LinkRow {
+    link: String,
+    code: String,
+    target: String,
+    owner: Option<String>,
+    hits: i64,
+    created: Option<DateTime<Utc>>,
+}
+
+/// Random code without look-alike characters (0/O, 1/l/I)
+fn random_code() -> String {
+    Uuid::new_v4()
+        .as_bytes()
+        .iter()
+        .take(CODE_LEN)
+        .map(|b| CODE_ALPHABET[*b as usize % CODE_ALPHABET.len()] as char)
+        .collect()
+}
+
+fn valid_code(code: &str) -> bool {
+    !code.is_empty()
+        && code.len() <= MAX_CODE_LEN
+        && code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Accept gate paths and absolute `http(s)` URLs. Protocol-relative paths
+/// (`//host`) would leave the gate, so they must be written as URLs.
+fn validate_target(target: &str) -> Result<(), &'static str> {
+    if target.is_empty() || target.len() > MAX_TARGET_LEN {
+        return Err("target must be between 1 and 2048 characters");
+    }
+    // Non-ASCII must be percent-encoded to fit in the Location header
+    if !target.chars().all(|c| c.is_ascii_graphic()) {
+        return Err("target must be printable ASCII without spaces (percent-encode the rest)");
+    }
+    if let Some(rest) = target.strip_prefix('/') {
+        if rest.starts_with('/') || rest.starts_with('\\') {
+            return Err("target must be a gate path or an absolute URL");
+        }
+        return Ok(());
+    }
+    match reqwest::Url::parse(target) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => Ok(()),
+        _ => Err("target must be a gate path or an absolute http(s) URL"),
+    }
+}
+
+/// Store a link unless the code is taken. Returns whether it was stored.
+async fn insert_link(state: &AppState, code: &str, target: &str, owner: &str) -> Result<bool, String> {
+    let link = Uuid::new_v4();
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX dcterms: <http://purl.org/dc/terms/>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+
+INSERT {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{link}> a liqk:ShortLink ;
+            liqk:short-code "{code}" ;
+            liqk:link-target "{target}" ;
+            liqk:owner "{owner}" ;
+            liqk:hits "0"^^xsd:integer ;
+            dcterms:created "{created}"^^xsd:dateTime .
+    }}
+}}
+WHERE {{
+    FILTER NOT EXISTS {{ GRAPH <{graph}> {{ ?other liqk:short-code "{code}" }} }}
+}}"#,
+        graph = LINKS_GRAPH,
+        link = link,
+        code = code,
+        target = escape_sparql_string(target),
+        owner = escape_sparql_string(owner),
+        created = Utc::now().to_rfc3339(),
+    );
+    send_sparql_update_once(state, "link", &query).await?;
+
+    // The update is a no-op when the code already existed
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?code FROM <{graph}> WHERE {{ <urn:uuid:{link}> liqk:short-code ?code }}"#,
+        graph = LINKS_GRAPH,
+        link = link,
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+    Ok(!bindings.is_empty())
+}
+
+async fn lookup_target(state: &AppState, code: &str) -> Result<Option<String>, String> {
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?target FROM <{graph}> WHERE {{
+    ?link a liqk:ShortLink ;
+        liqk:short-code "{code}" ;
+        liqk:link-target ?target .
+}}
+LIMIT 1"#,
+        graph = LINKS_GRAPH,
+        code = code,
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+    Ok(bindings.first().and_then(|b| binding_value(b, "target")).map(str::to_string))
+}
+
+/// Count a visit. Runs in the background so the redirect is not delayed.
+fn record_hit(state: &Arc<AppState>, code: String) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        let query = format!(
+            r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+
+DELETE {{ GRAPH <{graph}> {{ ?link liqk:hits ?hits }} }}
+INSERT {{ GRAPH <{graph}> {{ ?link liqk:hits ?next }} }}
+WHERE {{
+    GRAPH <{graph}> {{
+        ?link a liqk:ShortLink ;
+            liqk:short-code "{code}" .
+        OPTIONAL {{ ?link liqk:hits ?hits }}
+    }}
+    BIND(xsd:integer(COALESCE(?hits, 0) + 1) AS ?next)
+}}"#,
+            graph = LINKS_GRAPH,
+            code = code,
+        );
+
+        if let Err(e) = send_sparql_update(&state, "link-hit", &query).await {
+            warn!(code = %code, error = %e, "Failed to count short link hit");
+        }
+    });
+}
+
+async fn load_links(state: &AppState) -> Result<Vec<LinkRow>, String> {
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+PREFIX dcterms: <http://purl.org/dc/terms/>
+
+SELECT ?link ?code ?target ?owner ?hits ?created FROM <{graph}> WHERE {{
+    ?link a liqk:ShortLink ;
+        liqk:short-code ?code ;
+        liqk:link-target ?target .
+    OPTIONAL {{ ?link liqk:owner ?owner }}
+    OPTIONAL {{ ?link liqk:hits ?hits }}
+    OPTIONAL {{ ?link dcterms:created ?created }}
+}}
+ORDER BY DESC(?created) ?code"#,
+        graph = LINKS_GRAPH,
+    );
+
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+
+    Ok(bindings
+        .iter()
+        .filter_map(|b| {
+            Some(LinkRow {
+                link: binding_value(b, "link")?.to_string(),
+                code: binding_value(b, "code")?.to_string(),
+                target: binding_value(b, "target")?.to_string(),
+                owner: binding_value(b, "owner").map(str::to_string),
+                hits: binding_value(b, "hits").and_then(|s| s.parse().ok()).unwrap_or(0),
+                created: binding_value(b, "created").and_then(parse_time),
+            })
+        })
+        .collect())
+}
+
+async fn delete_link(state: &AppState, link: &Uuid) -> Result<(), String> {
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+DELETE {{ GRAPH <{graph}> {{ ?link ?p ?o }} }}
+WHERE {{
+    GRAPH <{graph}> {{
+        BIND(<urn:uuid:{link}> AS ?link)
+        ?link a liqk:ShortLink ;
+            ?p ?o .
+    }}
+}}"#,
+        graph = LINKS_GRAPH,
+        link = link,
+    );
+
+    send_sparql_update(state, "link-revoke", &query).await
+}
+
+fn render_links(links: &[LinkRow]) -> String {
+    if links.is_empty() {
+        return r#"<tr><td class="empty" colspan="6">No short links</td></tr>"#.to_string();
+    }
+    links
+        .iter()
+        .map(|l| {
+            let path = format!("/s/{}", l.code);
+            let created = l.created.map(format_time).unwrap_or_default();
+            let revoke = uuid_of(&l.link)
+                .map(|link| button(&format!("{}/{}/revoke", LINKS_ADMIN_PATH, link), "Revoke"))
+                .unwrap_or_default();
+            format!(
+                "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&path),
+                escape_html(&path),
+                escape_html(&l.target),
+                escape_html(l.owner.as_deref().unwrap_or("")),
+                l.hits,
+                created,
+                revoke,
+            )
+        })
+        .collect()
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// POST /gate/links?target=...&code=... - Create a short link (signed-in users)
+pub async fn link_create_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Query(params): Query<CreateLinkParams>,
+) -> Response {
+    let Some(owner) = credential(&state, &headers, &jar).owner() else {
+        warn!(client = %addr, "Access denied - short links require a signed-in user");
+        return (StatusCode::FORBIDDEN, "Access denied - short links require authentication").into_response();
+    };
+    let rank = get_access_rank_iri(&state, GRAPH_IRI, &headers, &jar).await;
+    if rank < state.roles.required_rank(PERMISSION_VIEW) {
+        warn!(client = %addr, rank = rank, "Access denied - short links require view rank");
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+
+    if let Err(e) = validate_target(&params.target) {
+        return (StatusCode::BAD_REQUEST, e).into_response();
+    }
+    if let Some(code) = &params.code {
+        if !valid_code(code) {
+            return (
+                StatusCode::BAD_REQUEST,
+                "code must be 1-64 letters, digits, '-' or '_'",
+            )
+                .into_response();
+        }
+    }
+
+    // A generated code is retried a few times in the unlikely event it is taken
+    let attempts = if params.code.is_some() { 1 } else { 3 };
+    let mut created = None;
+    for _ in 0..attempts {
+        let code = params.code.clone().unwrap_or_else(random_code);
+        match insert_link(&state, &code, &params.target, &owner).await {
+            Ok(true) => {
+                created = Some(code);
+                break;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                warn!(client = %addr, error = %e, "Failed to store short link");
+                return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create short link").into_response();
+            }
+        }
+    }
+    let Some(code) = created else {
+        return (StatusCode::CONFLICT, "Short link code already in use").into_response();
+    };
+
+    info!(client = %addr, code = %code, owner = %owner, target = %params.target, "Short link created");
+
+    let json_response = serde_json::json!({
+        "path": format!("/s/{}", code),
+        "code": code,
+        "target": params.target,
+    });
+
+    (
+        StatusCode::CREATED,
+        [(header::CONTENT_TYPE, "application/json")],
+        json_response.to_string(),
+    )
+        .into_response()
+}
+
+/// GET /s/:code - Follow a short link. Gate targets still check access when
+/// the client arrives there.
+pub async fn link_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(code): Path<String>,
+) -> Response {
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            Html(render_page("Link not found", "<p>This short link does not exist or has been revoked.</p>")),
+        )
+            .into_response()
+    };
+    if !valid_code(&code) {
+        return not_found();
+    }
+
+    match lookup_target(&state, &code).await {
+        Ok(Some(target)) => {
+            info!(client = %addr, code = %code, "Short link followed");
+            record_hit(&state, code);
+            Redirect::temporary(&target).into_response()
+        }
+        Ok(None) => {
+            warn!(client = %addr, code = %code, "Short link not found");
+            not_found()
+        }
+        Err(e) => {
+            warn!(client = %addr, code = %code, error = %e, "SPARQL lookup failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup short link").into_response()
+        }
+    }
+}
+
+/// GET /gate/admin/links - Every short link with owner and hit count (admin only)
+pub async fn links_admin_page(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    if !is_admin(&state, &headers, &jar).await {
+        warn!(client = %addr, "Access denied - administration requires admin rank");
+        return forbidden();
+    }
+
+    match load_links(&state).await {
+        Ok(links) => Html(LINKS_HTML.replace("{links}", &render_links(&links))).into_response(),
+        Err(e) => {
+            warn!(client = %addr, error = %e, "Failed to load short links");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load short links").into_response()
+        }
+    }
+}
+
+/// POST /gate/admin/links/:uuid/revoke - Delete a short link
+pub async fn link_revoke_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(uuid_str): Path<String>,
+) -> Response {
+    let link = match admin_target(&state, &addr, &headers, &jar, &uuid_str).await {
+        Ok(uuid) => uuid,
+        Err(e) => return e.into_response(),
+    };
+
+    match delete_link(&state, &link).await {
+        Ok(()) => {
+            info!(client = %addr, target = %link, "Short link revoked");
+            Redirect::to(LINKS_ADMIN_PATH).into_response()
+        }
+        Err(e) => {
+            warn!(client = %addr, target = %link, error = %e, "Failed to revoke short link");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to revoke short link").into_response()
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt};
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+use crate::admin::{forbidden, format_time, is_admin};
+use crate::query_cache::query_params;
+use crate::query_log::{redact, Redaction};
+use crate::templates::{escape_html, SLOW_QUERIES_HTML};
+use crate::AppState;
+
+/// Slow queries kept for the admin page
+const RECENT_SLOW_QUERIES: usize = 200;
+
+/// A proxied query that took longer than the threshold
+#[derive(Clone)]
+pub struct SlowQuery {
+    pub time: DateTime<Utc>,
+    pub duration: Duration,
+    pub client: SocketAddr,
+    pub credential: String,
+    /// Upstream status, or why there was none
+    pub status: String,
+    /// Query text with whitespace collapsed, after redaction
+    pub query: String,
+}
+
+/// Log of proxied `/query` requests whose upstream round trip - from
+/// sending the request until the last byte of the result - exceeds
+/// `SLOW_QUERY_MS`. Entries go to the `slow_query` log target and a short
+/// in-memory tail for `/gate/admin/slow-queries`. Results served from the
+/// query cache never reach Oxigraph and are not timed.
+pub struct SlowQueryLog {
+    threshold: Option<Duration>,
+    redaction: Redaction,
+    recent: Mutex<VecDeque<SlowQuery>>,
+}
+
+impl SlowQueryLog {
+    /// A zero threshold disables the log
+    pub fn new(threshold: Duration, redaction: Redaction) -> Self {
+        Self {
+            threshold: (!threshold.is_zero()).then_some(threshold),
+            redaction,
+            recent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.threshold.is_some()
+    }
+
+    /// Slow queries, newest first
+    pub fn recent(&self) -> Vec<SlowQuery> {
+        self.recent.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    fn push(&self, entry: SlowQuery) {
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() >= RECENT_SLOW_QUERIES {
+            recent.pop_front();
+        }
+        recent.push_back(entry);
+    }
+}
+
+/// Times one proxied query and logs it when dropped, if it was slow. Kept
+/// alive by the response body, so streamed results are timed to the end.
+pub struct QueryTimer {
+    state: Arc<AppState>,
+    started: Instant,
+    client: SocketAddr,
+    credential: String,
+    query: String,
+    status: Option<StatusCode>,
+}
+
+impl QueryTimer {
+    /// Start timing a query request, given its (buffered) body
+    pub fn start(
+        state: &Arc<AppState>,
+        client: SocketAddr,
+        credential: &str,
+        headers: &HeaderMap,
+        query_string: Option<&str>,
+        body: &[u8],
+    ) -> Self {
+        let query = query_params(headers, query_string, body)
+            .and_then(|params| params.into_iter().find(|(name, _)| name == "query"))
+            .map(|(_, query)| redact(&normalize(&query), state.slow_queries.redaction))
+            .unwrap_or_default();
+        Self {
+            state: Arc::clone(state),
+            started: Instant::now(),
+            client,
+            credential: credential.to_string(),
+            query,
+            status: None,
+        }
+    }
+
+    pub fn set_status(&mut self, status: StatusCode) {
+        self.status = Some(status);
+    }
+}
+
+impl Drop for QueryTimer {
+    fn drop(&mut self) {
+        let log = &self.state.slow_queries;
+        let duration = self.started.elapsed();
+        if log.threshold.is_none_or(|threshold| duration < threshold) {
+            return;
+        }
+        let status = match self.status {
+            Some(status) => status.as_u16().to_string(),
+            None => "no response".to_string(),
+        };
+        warn!(
+            target: "slow_query",
+            client = %self.client,
+            credential = %self.credential,
+            duration_ms = duration.as_millis() as u64,
+            status = %status,
+            query = ?self.query,
+            "Slow query"
+        );
+        log.push(SlowQuery {
+            time: Utc::now(),
+            duration,
+            client: self.client,
+            credential: std::mem::take(&mut self.credential),
+            status,
+            query: std::mem::take(&mut self.query),
+        });
+    }
+}
+
+/// A response body stream that keeps `timer` running until it ends
+pub fn timed<S: Stream>(stream: S, timer: Option<QueryTimer>) -> impl Stream<Item = S::Item> {
+    stream.map(move |item| {
+        let _ = &timer;
+        item
+    })
+}
+
+/// Collapse whitespace so the same query from different clients reads alike
+fn normalize(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn render_slow_queries(queries: &[SlowQuery]) -> String {
+    if queries.is_empty() {
+        return r#"<tr><td class="empty" colspan="5">No slow queries logged</td></tr>"#.to_string();
+    }
+    queries
+        .iter()
+        .map(|q| {
+            format!(
+                r#"<tr><td>{}</td><td>{} ms</td><td>{}<br>{}</td><td>{}</td><td><pre>{}</pre></td></tr>"#,
+                format_time(q.time),
+                q.duration.as_millis(),
+                escape_html(&q.credential),
+                q.client,
+                escape_html(&q.status),
+                escape_html(&q.query),
+            )
+        })
+        .collect()
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// GET /gate/admin/slow-queries - Recent slow queries (admin only)
+pub async fn slow_queries_admin_page(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    if !is_admin(&state, &headers, &jar).await {
+        warn!(client = %addr, "Access denied - administration requires admin rank");
+        return forbidden();
+    }
+
+    let status = match state.slow_queries.threshold {
+        Some(threshold) => format!("Queries over {} ms, newest first; refreshes every 5 seconds.", threshold.as_millis()),
+        None => "Slow query logging is off. Set SLOW_QUERY_MS to record slow queries.".to_string(),
+    };
+
+    Html(
+        SLOW_QUERIES_HTML
+            .replace("{status}", &status)
+            .replace("{queries}", &render_slow_queries(&state.slow_queries.recent())),
+    )
+    .into_response()
+}
@@ -0,0 +1,106 @@
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName},
+    middleware::Next,
+    response::Response,
+};
+use futures_util::{Stream, StreamExt};
+use opentelemetry::{global, trace::TracerProvider as _};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    trace::{SdkTracerProvider, Tracer},
+    Resource,
+};
+use std::{env, sync::Arc};
+use tracing::{field, info_span, Instrument, Span};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::Registry;
+
+use crate::metrics::route_label;
+use crate::AppState;
+
+/// `service.name` reported unless `OTEL_SERVICE_NAME` is set
+const SERVICE_NAME: &str = "oxigraph-gate";
+
+/// W3C Trace Context headers, replaced by the gate's own when exporting
+const TRACE_HEADERS: [HeaderName; 2] = [
+    HeaderName::from_static("traceparent"),
+    HeaderName::from_static("tracestate"),
+];
+
+/// Whether spans are exported: set by the standard OTLP endpoint variables
+pub fn export_configured() -> bool {
+    ["OTEL_EXPORTER_OTLP_ENDPOINT", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"]
+        .iter()
+        .any(|name| env::var(name).is_ok_and(|v| !v.is_empty()))
+}
+
+/// A tracing layer exporting spans over OTLP/HTTP, configured by the
+/// standard `OTEL_*` variables, and the W3C propagator for `traceparent`
+pub fn otlp_layer() -> Result<OpenTelemetryLayer<Registry, Tracer>, String> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .build()
+        .map_err(|e| format!("Failed to build OTLP exporter: {}", e))?;
+    let mut resource = Resource::builder();
+    if env::var("OTEL_SERVICE_NAME").is_err() {
+        resource = resource.with_service_name(SERVICE_NAME);
+    }
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource.build())
+        .build();
+    let tracer = provider.tracer(SERVICE_NAME);
+    global::set_tracer_provider(provider);
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Headers the gate sets itself when exporting, instead of forwarding them
+pub fn is_trace_header(name: &HeaderName) -> bool {
+    TRACE_HEADERS.contains(name)
+}
+
+/// `traceparent` (and `tracestate`) for a request made within the current
+/// span; empty when spans are not exported
+pub fn trace_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let context = Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    headers
+}
+
+/// A response body stream that keeps `span` open until it ends
+pub fn traced<S: Stream>(stream: S, span: Span) -> impl Stream<Item = S::Item> {
+    stream.map(move |item| {
+        let _ = &span;
+        item
+    })
+}
+
+/// Middleware running each request in a server span, continuing the
+/// caller's trace when it sent a `traceparent`
+pub async fn trace_middleware(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    if !state.trace_export {
+        return next.run(req).await;
+    }
+    let route = route_label(&req);
+    let span = info_span!(
+        "request",
+        otel.name = %format!("{} {}", req.method(), route),
+        otel.kind = "server",
+        http.request.method = %req.method(),
+        http.route = %route,
+        url.path = %req.uri().path(),
+        http.response.status_code = field::Empty,
+    );
+    let parent = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(req.headers())));
+    let _ = span.set_parent(parent);
+    let response = next.run(req).instrument(span.clone()).await;
+    span.record("http.response.status_code", response.status().as_u16());
+    response
+}
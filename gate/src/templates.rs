@@ -36,7 +36,7 @@ pub const LOGIN_HTML: &str = r#"<!DOCTYPE html>
             margin: 0 0 2rem 0;
             color: #aaa;
         }
-        input[type="text"] {
+        input[type="text"], input[type="password"] {
             width: 100%;
             padding: 0.875rem;
             font-size: 1rem;
@@ -48,7 +48,7 @@ pub const LOGIN_HTML: &str = r#"<!DOCTYPE html>
             text-align: center;
             margin-bottom: 1rem;
         }
-        input[type="text"]:focus {
+        input[type="text"]:focus, input[type="password"]:focus {
             outline: none;
             border-color: #e94560;
         }
@@ -77,11 +77,27 @@ pub const LOGIN_HTML: &str = r#"<!DOCTYPE html>
             <input type="text" name="token" placeholder="Access Token" autocomplete="off" required>
             <button type="submit">Authenticate</button>
         </form>
+        <!-- PASSWORD_FORM -->
+        <!-- OAUTH_LOGIN -->
     </div>
 </body>
 </html>
 "#;
 
+/// Username/password form inserted into the login pages when a password
+/// backend (e.g. LDAP) is configured
+pub const PASSWORD_FORM_HTML: &str = r#"<p style="margin: 1.5rem 0 1rem 0;">or sign in with your directory account</p>
+        <form method="POST" action="/gate/login">
+            <input type="text" name="username" placeholder="Username" autocomplete="username" required>
+            <input type="password" name="password" placeholder="Password" autocomplete="current-password" required>
+            <button type="submit">Sign in</button>
+        </form>"#;
+
+pub const GITHUB_LOGIN_HTML: &str = r#"<p style="margin: 1.5rem 0 1rem 0;">or</p>
+        <form method="GET" action="/gate/oauth/github">
+            <button type="submit">Sign in with GitHub</button>
+        </form>"#;
+
 pub const LOGIN_ERROR_HTML: &str = r#"<!DOCTYPE html>
 <html lang="en">
 <head>
@@ -120,7 +136,7 @@ pub const LOGIN_ERROR_HTML: &str = r#"<!DOCTYPE html>
             margin: 0 0 2rem 0;
             color: #ff6b6b;
         }
-        input[type="text"] {
+        input[type="text"], input[type="password"] {
             width: 100%;
             padding: 0.875rem;
             font-size: 1rem;
@@ -132,7 +148,7 @@ pub const LOGIN_ERROR_HTML: &str = r#"<!DOCTYPE html>
             text-align: center;
             margin-bottom: 1rem;
         }
-        input[type="text"]:focus {
+        input[type="text"]:focus, input[type="password"]:focus {
             outline: none;
             border-color: #e94560;
         }
@@ -156,13 +172,1509 @@ pub const LOGIN_ERROR_HTML: &str = r#"<!DOCTYPE html>
 <body>
     <div class="container">
         <h1>Oxigraph Gate</h1>
-        <p>Invalid token. Please try again.</p>
+        <p>Invalid credentials. Please try again.</p>
         <form method="POST" action="/gate/login">
             <input type="text" name="token" placeholder="Access Token" autocomplete="off" required>
             <button type="submit">Authenticate</button>
         </form>
+        <!-- PASSWORD_FORM -->
+        <!-- OAUTH_LOGIN -->
+    </div>
+</body>
+</html>
+"#;
+
+
+pub const UPLOAD_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Oxigraph Gate - Upload</title>
+    <style>
+        * {
+            box-sizing: border-box;
+        }
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            background: #1a1a2e;
+            color: #eee;
+            min-height: 100vh;
+            margin: 0;
+            display: flex;
+            align-items: center;
+            justify-content: center;
+        }
+        .container {
+            text-align: center;
+            background: #16213e;
+            padding: 3rem;
+            border-radius: 12px;
+            box-shadow: 0 4px 20px rgba(0, 0, 0, 0.3);
+            max-width: 560px;
+            width: 90%;
+        }
+        h1 {
+            margin: 0 0 0.5rem 0;
+            color: #e94560;
+            font-size: 1.8rem;
+        }
+        p {
+            margin: 0 0 2rem 0;
+            color: #aaa;
+        }
+        #usage {
+            margin: -1.5rem 0 1.5rem 0;
+            font-size: 0.9rem;
+        }
+        #usage:empty {
+            display: none;
+        }
+        #usage.full {
+            color: #ff6b6b;
+        }
+        #drop {
+            border: 2px dashed #0f3460;
+            border-radius: 6px;
+            padding: 2.5rem 1rem;
+            margin-bottom: 1rem;
+            color: #aaa;
+            cursor: pointer;
+        }
+        #drop.active {
+            border-color: #e94560;
+            color: #eee;
+        }
+        #results {
+            list-style: none;
+            padding: 0;
+            margin: 0;
+            text-align: left;
+        }
+        #results li {
+            padding: 0.5rem 0;
+            border-bottom: 1px solid #0f3460;
+            word-break: break-all;
+        }
+        #results a {
+            color: #ff6b6b;
+        }
+        #results .error {
+            color: #ff6b6b;
+        }
+        #results img {
+            display: block;
+            max-width: 100%;
+            max-height: 160px;
+            margin-top: 0.5rem;
+            border-radius: 4px;
+        }
+        input[type="file"] {
+            display: none;
+        }
+        label {
+            display: block;
+            margin-bottom: 1rem;
+            color: #aaa;
+            text-align: left;
+        }
+        #dir {
+            width: 100%;
+            margin-top: 0.25rem;
+            padding: 0.5rem;
+            border: 1px solid #0f3460;
+            border-radius: 6px;
+            background: #1a1a2e;
+            color: #eee;
+        }
+    </style>
+</head>
+<body>
+    <div class="container">
+        <h1>Upload</h1>
+        <p>Drop files here, click to choose, or paste an image from the clipboard</p>
+        <p id="usage"></p>
+        <label>Folder (empty for none)
+            <input type="text" id="dir" placeholder="/reports/2024/">
+        </label>
+        <div id="drop">Drop or paste here</div>
+        <input type="file" id="picker" multiple>
+        <ul id="results"></ul>
     </div>
+    <script>
+        const drop = document.getElementById('drop');
+        const picker = document.getElementById('picker');
+        const results = document.getElementById('results');
+        const dir = document.getElementById('dir');
+        const usage = document.getElementById('usage');
+
+        // "Upload here" in a directory listing opens the page as /upload?dir=...
+        dir.value = new URLSearchParams(location.search).get('dir') || '';
+
+        // Clipboard images arrive as "image.png" (or with no name at all),
+        // so give them a timestamped name that stays unique in listings.
+        function pastedName(file) {
+            const ext = (file.type.split('/')[1] || 'bin').replace('jpeg', 'jpg').replace(/\+.*$/, '');
+            const stamp = new Date().toISOString().replace(/[-:]/g, '').replace('T', '-').slice(0, 15);
+            return 'paste-' + stamp + '-' + Math.random().toString(36).slice(2, 6) + '.' + ext;
+        }
+
+        function addResult(file, html) {
+            const li = document.createElement('li');
+            li.innerHTML = html;
+            if (file.type.startsWith('image/')) {
+                const img = document.createElement('img');
+                img.src = URL.createObjectURL(file);
+                li.appendChild(img);
+            }
+            results.prepend(li);
+            return li;
+        }
+
+        function formatBytes(bytes) {
+            const units = ['bytes', 'KiB', 'MiB', 'GiB', 'TiB'];
+            let i = 0;
+            while (bytes >= 1024 && i < units.length - 1) {
+                bytes /= 1024;
+                i++;
+            }
+            return (i === 0 ? bytes : bytes.toFixed(1)) + ' ' + units[i];
+        }
+
+        // Storage usage of the signed-in identity; nothing is shown to
+        // anonymous visitors
+        async function showUsage() {
+            try {
+                const response = await fetch('/gate/quota', { credentials: 'same-origin' });
+                if (!response.ok) return;
+                const json = await response.json();
+                if (json.quota === null) {
+                    usage.textContent = formatBytes(json.used) + ' stored';
+                } else {
+                    usage.textContent = formatBytes(json.used) + ' of ' + formatBytes(json.quota) + ' used';
+                    usage.classList.toggle('full', json.available === 0);
+                }
+            } catch (e) {}
+        }
+
+        function escapeHtml(s) {
+            return s.replace(/[&<>"']/g, c => ({ '&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;', "'": '&#39;' }[c]));
+        }
+
+        async function upload(file, name) {
+            const li = addResult(file, 'Uploading ' + escapeHtml(name) + '&hellip;');
+            const form = new FormData();
+            form.append('files', file, name);
+            try {
+                const target = dir.value.trim() ? '?dir=' + encodeURIComponent(dir.value.trim()) : '';
+                const response = await fetch('/res' + target, { method: 'POST', body: form, credentials: 'same-origin' });
+                if (!response.ok) {
+                    throw new Error(response.status + ' ' + (await response.text()));
+                }
+                const json = await response.json();
+                const uuid = json.files[0].uuid;
+                const url = location.origin + '/res/' + uuid;
+                li.firstChild.remove();
+                li.insertAdjacentHTML('afterbegin', escapeHtml(name) + '<br><a href="' + url + '" target="_blank">' + url + '</a>');
+                if (navigator.clipboard) {
+                    navigator.clipboard.writeText(url).catch(() => {});
+                }
+                showUsage();
+            } catch (e) {
+                li.firstChild.remove();
+                li.insertAdjacentHTML('afterbegin', '<span class="error">' + escapeHtml(name) + ': ' + escapeHtml(e.message) + '</span>');
+            }
+        }
+
+        function uploadAll(files, fromClipboard) {
+            for (const file of files) {
+                const generic = !file.name || file.name === 'image.png';
+                upload(file, fromClipboard && generic ? pastedName(file) : file.name);
+            }
+        }
+
+        showUsage();
+
+        drop.addEventListener('click', () => picker.click());
+        picker.addEventListener('change', () => {
+            uploadAll(picker.files, false);
+            picker.value = '';
+        });
+
+        drop.addEventListener('dragover', e => {
+            e.preventDefault();
+            drop.classList.add('active');
+        });
+        drop.addEventListener('dragleave', () => drop.classList.remove('active'));
+        drop.addEventListener('drop', e => {
+            e.preventDefault();
+            drop.classList.remove('active');
+            // Screenshots dragged from OS previews are often unnamed images
+            uploadAll(e.dataTransfer.files, true);
+        });
+
+        document.addEventListener('paste', e => {
+            const files = [];
+            for (const item of e.clipboardData.items) {
+                if (item.kind === 'file') {
+                    const file = item.getAsFile();
+                    if (file) files.push(file);
+                }
+            }
+            if (files.length > 0) {
+                e.preventDefault();
+                uploadAll(files, true);
+            }
+        });
+    </script>
+</body>
+</html>
+"#;
+
+/// Directory compare page; reads `a` and `b` from the query string and
+/// renders the result of `/api/diff`
+pub const DIFF_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Oxigraph Gate - Compare</title>
+    <style>
+        * {
+            box-sizing: border-box;
+        }
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            background: #1a1a2e;
+            color: #eee;
+            margin: 0;
+            padding: 2rem;
+        }
+        h1 {
+            margin: 0 0 1.5rem 0;
+            color: #e94560;
+            font-size: 1.4rem;
+        }
+        h2 {
+            margin: 2rem 0 0.75rem 0;
+            font-size: 1.1rem;
+            color: #aaa;
+        }
+        form {
+            display: flex;
+            gap: 0.5rem;
+            max-width: 900px;
+        }
+        input[type="text"] {
+            flex: 1;
+            padding: 0.75rem;
+            font-size: 1rem;
+            border: 2px solid #0f3460;
+            border-radius: 6px;
+            background: #16213e;
+            color: #eee;
+        }
+        input[type="text"]:focus {
+            outline: none;
+            border-color: #e94560;
+        }
+        button {
+            padding: 0.75rem 1.5rem;
+            font-size: 1rem;
+            font-weight: 600;
+            background: #e94560;
+            color: white;
+            border: none;
+            border-radius: 6px;
+            cursor: pointer;
+        }
+        button:hover {
+            background: #ff6b6b;
+        }
+        table {
+            border-collapse: collapse;
+            width: 100%;
+            max-width: 900px;
+            background: #16213e;
+            border-radius: 8px;
+        }
+        td {
+            padding: 0.5rem 1rem;
+            border-bottom: 1px solid #0f3460;
+            word-break: break-all;
+        }
+        td.size {
+            text-align: right;
+            color: #aaa;
+            font-family: monospace;
+            white-space: nowrap;
+        }
+        .empty, #status {
+            color: #aaa;
+        }
+        .error {
+            color: #ff6b6b;
+        }
+    </style>
+</head>
+<body>
+    <h1>Compare directories</h1>
+    <form id="form">
+        <input type="text" name="a" placeholder="/path/to/a" required>
+        <input type="text" name="b" placeholder="/path/to/b" required>
+        <button type="submit">Compare</button>
+    </form>
+    <p id="status"></p>
+    <div id="result"></div>
+    <script>
+        const form = document.getElementById('form');
+        const status = document.getElementById('status');
+        const result = document.getElementById('result');
+
+        function escapeHtml(s) {
+            return String(s).replace(/[&<>"']/g, c => ({ '&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;', "'": '&#39;' }[c]));
+        }
+
+        function size(item) {
+            return item.dir ? '' : (item.size ?? '?') + ' B';
+        }
+
+        function section(title, rows) {
+            let html = '<h2>' + escapeHtml(title) + ' (' + rows.length + ')</h2>';
+            if (rows.length === 0) {
+                return html + '<p class="empty">None</p>';
+            }
+            return html + '<table>' + rows.join('') + '</table>';
+        }
+
+        function row(path, ...cells) {
+            return '<tr><td>' + escapeHtml(path) + '</td>' + cells.map(c => '<td class="size">' + escapeHtml(c) + '</td>').join('') + '</tr>';
+        }
+
+        async function compare(a, b) {
+            status.textContent = 'Comparing…';
+            result.innerHTML = '';
+            try {
+                const query = new URLSearchParams({ a, b });
+                const response = await fetch('/api/diff?' + query, { credentials: 'same-origin' });
+                if (!response.ok) {
+                    throw new Error(response.status + ' ' + (await response.text()));
+                }
+                const diff = await response.json();
+                status.textContent = '';
+                result.innerHTML =
+                    section('Only in ' + diff.a, diff.only_in_a.map(i => row(i.path, size(i)))) +
+                    section('Only in ' + diff.b, diff.only_in_b.map(i => row(i.path, size(i)))) +
+                    section('Different content', diff.differing.map(d => row(d.path, size(d.a), size(d.b))));
+            } catch (e) {
+                status.innerHTML = '<span class="error">' + escapeHtml(e.message) + '</span>';
+            }
+        }
+
+        form.addEventListener('submit', e => {
+            e.preventDefault();
+            const a = form.a.value, b = form.b.value;
+            history.replaceState(null, '', '?' + new URLSearchParams({ a, b }));
+            compare(a, b);
+        });
+
+        const params = new URLSearchParams(location.search);
+        if (params.get('a') && params.get('b')) {
+            form.a.value = params.get('a');
+            form.b.value = params.get('b');
+            compare(form.a.value, form.b.value);
+        }
+    </script>
 </body>
 </html>
 "#;
 
+/// Directory listing page. `{title}`, `{sidebar}`, `{entries}` and
+/// `{readme}` are substituted by the `/file` handler; all must already be
+/// HTML-escaped (the README as sanitized HTML).
+pub const LISTING_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Index of {title}</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            background: #1a1a2e;
+            color: #eee;
+            margin: 0;
+            padding: 2rem;
+        }
+        .layout {
+            display: flex;
+            gap: 2rem;
+            align-items: flex-start;
+        }
+        main {
+            flex: 1;
+            min-width: 0;
+        }
+        .sidebar {
+            width: 220px;
+            flex-shrink: 0;
+            background: #16213e;
+            border-radius: 8px;
+            padding: 1rem;
+        }
+        .sidebar h2 {
+            margin: 0 0 0.75rem 0;
+            font-size: 0.9rem;
+            color: #aaa;
+            text-transform: uppercase;
+            letter-spacing: 0.05em;
+        }
+        .sidebar ul {
+            list-style: none;
+            margin: 0;
+            padding: 0;
+        }
+        .sidebar li {
+            padding: 0.3rem 0;
+            word-break: break-word;
+        }
+        .sidebar a.current {
+            color: #e94560;
+        }
+        h1 {
+            margin: 0 0 1.5rem 0;
+            color: #e94560;
+            font-size: 1.4rem;
+            word-break: break-all;
+        }
+        table {
+            border-collapse: collapse;
+            width: 100%;
+            max-width: 900px;
+            background: #16213e;
+            border-radius: 8px;
+        }
+        td {
+            padding: 0.5rem 1rem;
+            border-bottom: 1px solid #0f3460;
+        }
+        td.size {
+            text-align: right;
+            color: #aaa;
+            font-family: monospace;
+        }
+        td.hash {
+            color: #aaa;
+            font-family: monospace;
+        }
+        td.empty {
+            color: #aaa;
+        }
+        a {
+            color: #eee;
+            text-decoration: none;
+        }
+        a:hover {
+            color: #ff6b6b;
+        }
+        td.select {
+            width: 1rem;
+            padding-right: 0;
+        }
+        td.icon {
+            width: 3rem;
+            padding-right: 0;
+            text-align: center;
+        }
+        img.thumb {
+            display: block;
+            max-width: 3rem;
+            max-height: 3rem;
+            margin: 0 auto;
+            border-radius: 4px;
+        }
+        .actions {
+            display: flex;
+            gap: 0.75rem;
+            align-items: center;
+            margin-top: 1rem;
+            color: #aaa;
+        }
+        select {
+            padding: 0.3rem;
+            background: #1a1a2e;
+            color: #eee;
+            border: 1px solid #0f3460;
+            border-radius: 4px;
+        }
+        button {
+            padding: 0.4rem 0.9rem;
+            font-weight: 600;
+            background: #e94560;
+            color: white;
+            border: none;
+            border-radius: 6px;
+            cursor: pointer;
+        }
+        button:hover {
+            background: #ff6b6b;
+        }
+        button.secondary {
+            background: #0f3460;
+            color: #aaa;
+        }
+        button.secondary:hover {
+            background: #16213e;
+            color: #eee;
+        }
+        form.toggle {
+            margin-top: 1rem;
+        }
+        .readme {
+            max-width: 900px;
+            margin-top: 2rem;
+            padding: 0 1.5rem 1rem;
+            background: #16213e;
+            border-radius: 8px;
+            line-height: 1.5;
+        }
+        .readme-header {
+            display: flex;
+            justify-content: space-between;
+            align-items: center;
+            padding: 0.5rem 0;
+            border-bottom: 1px solid #0f3460;
+            color: #aaa;
+            font-family: monospace;
+        }
+        .readme-header form.toggle {
+            margin: 0;
+        }
+        .readme a {
+            color: #e94560;
+        }
+        .readme pre, .readme code {
+            background: #1a1a2e;
+            border-radius: 4px;
+            font-family: monospace;
+        }
+        .readme code {
+            padding: 0.1rem 0.3rem;
+        }
+        .readme pre {
+            padding: 0.75rem;
+            overflow-x: auto;
+        }
+        .readme pre code {
+            padding: 0;
+        }
+        .readme blockquote {
+            margin: 0;
+            padding-left: 1rem;
+            border-left: 3px solid #0f3460;
+            color: #aaa;
+        }
+        .readme img {
+            max-width: 100%;
+        }
+    </style>
+</head>
+<body>
+    <div class="layout">
+{sidebar}
+    <main>
+    <h1>Index of {title}</h1>
+    <form method="POST" action="/res/manifest">
+    <table>
+{entries}
+    </table>
+    <div class="actions">
+        <span>Selected files:</span>
+        <select name="format">
+            <option value="json">JSON manifest</option>
+            <option value="urls">URL list</option>
+        </select>
+        <button type="submit">Get download links</button>
+        <button type="button" class="secondary" id="move">Rename / move</button>
+        <button type="button" class="secondary" id="mkdir">New folder</button>
+        <button type="button" class="secondary" id="upload">Upload here</button>
+        <button type="button" class="secondary" id="zip">Download as zip</button>
+    </div>
+    </form>
+    {readme}
+    </main>
+    </div>
+    <script>
+        // Rename or move the one selected file: the new path is split into
+        // the target directory and the new name
+        document.getElementById('move').addEventListener('click', async () => {
+            const selected = document.querySelectorAll('input[name="file"]:checked');
+            if (selected.length !== 1) {
+                alert('Select one file to rename or move.');
+                return;
+            }
+            const dir = decodeURIComponent(location.pathname.slice('/file'.length));
+            const name = selected[0].closest('tr').querySelector('a').textContent;
+            const target = prompt('New path of ' + name, dir + name);
+            if (!target || target === dir + name) {
+                return;
+            }
+            const cut = target.lastIndexOf('/') + 1;
+            const folder = cut ? target.slice(0, cut) : dir;
+            const form = new URLSearchParams({ dir: folder, name: target.slice(cut) });
+            const response = await fetch('/res/' + selected[0].value, { method: 'PATCH', body: form, credentials: 'same-origin' });
+            if (!response.ok) {
+                alert(await response.text());
+                return;
+            }
+            location.href = '/file' + folder.split('/').map(encodeURIComponent).join('/');
+        });
+
+        document.getElementById('mkdir').addEventListener('click', async () => {
+            const name = prompt('Name of the new folder');
+            if (!name) {
+                return;
+            }
+            const response = await fetch(location.pathname + encodeURIComponent(name) + '/?mkdir', { method: 'POST', credentials: 'same-origin' });
+            if (!response.ok) {
+                alert(await response.text());
+                return;
+            }
+            location.href = (await response.json()).path;
+        });
+
+        document.getElementById('upload').addEventListener('click', () => {
+            const dir = decodeURIComponent(location.pathname.slice('/file'.length));
+            location.href = '/upload?dir=' + encodeURIComponent(dir);
+        });
+
+        document.getElementById('zip').addEventListener('click', () => {
+            location.href = './?format=zip';
+        });
+    </script>
+</body>
+</html>
+"#;
+
+/// Virtual collection page, laid out like a directory listing. `{title}`,
+/// `{sidebar}` and `{entries}` are substituted (already escaped) by the
+/// `/collections/:uuid` handler.
+pub const COLLECTION_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>{title}</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            background: #1a1a2e;
+            color: #eee;
+            margin: 0;
+            padding: 2rem;
+        }
+        .layout {
+            display: flex;
+            gap: 2rem;
+            align-items: flex-start;
+        }
+        main {
+            flex: 1;
+            min-width: 0;
+        }
+        .sidebar {
+            width: 220px;
+            flex-shrink: 0;
+            background: #16213e;
+            border-radius: 8px;
+            padding: 1rem;
+        }
+        .sidebar h2 {
+            margin: 0 0 0.75rem 0;
+            font-size: 0.9rem;
+            color: #aaa;
+            text-transform: uppercase;
+            letter-spacing: 0.05em;
+        }
+        .sidebar ul {
+            list-style: none;
+            margin: 0;
+            padding: 0;
+        }
+        .sidebar li {
+            padding: 0.3rem 0;
+            word-break: break-word;
+        }
+        .sidebar a.current {
+            color: #e94560;
+        }
+        h1 {
+            margin: 0 0 1.5rem 0;
+            color: #e94560;
+            font-size: 1.4rem;
+            word-break: break-all;
+        }
+        table {
+            border-collapse: collapse;
+            width: 100%;
+            max-width: 900px;
+            background: #16213e;
+            border-radius: 8px;
+        }
+        td {
+            padding: 0.5rem 1rem;
+            border-bottom: 1px solid #0f3460;
+        }
+        td.size {
+            text-align: right;
+            color: #aaa;
+            font-family: monospace;
+        }
+        td.hash {
+            color: #aaa;
+            font-family: monospace;
+        }
+        td.empty {
+            color: #aaa;
+        }
+        a {
+            color: #eee;
+            text-decoration: none;
+        }
+        a:hover {
+            color: #ff6b6b;
+        }
+        td.select {
+            width: 1rem;
+            padding-right: 0;
+        }
+        td.icon {
+            width: 3rem;
+            padding-right: 0;
+            text-align: center;
+        }
+        img.thumb {
+            display: block;
+            max-width: 3rem;
+            max-height: 3rem;
+            margin: 0 auto;
+            border-radius: 4px;
+        }
+        .actions {
+            display: flex;
+            gap: 0.75rem;
+            align-items: center;
+            margin-top: 1rem;
+            color: #aaa;
+        }
+        select {
+            padding: 0.3rem;
+            background: #1a1a2e;
+            color: #eee;
+            border: 1px solid #0f3460;
+            border-radius: 4px;
+        }
+        button {
+            padding: 0.4rem 0.9rem;
+            font-weight: 600;
+            background: #e94560;
+            color: white;
+            border: none;
+            border-radius: 6px;
+            cursor: pointer;
+        }
+        button:hover {
+            background: #ff6b6b;
+        }
+    </style>
+</head>
+<body>
+    <div class="layout">
+{sidebar}
+    <main>
+    <h1>{title}</h1>
+    <form method="POST" action="/res/manifest">
+    <table>
+{entries}
+    </table>
+    <div class="actions">
+        <span>Selected files:</span>
+        <select name="format">
+            <option value="json">JSON manifest</option>
+            <option value="urls">URL list</option>
+        </select>
+        <button type="submit">Get download links</button>
+    </div>
+    </form>
+    </main>
+    </div>
+</body>
+</html>
+"#;
+
+/// Administration page. `{failures}` is the failed-login chart and
+/// `{sessions}`, `{tokens}` and `{users}` are table rows, all rendered (and
+/// escaped) by the admin handler.
+pub const ADMIN_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Oxigraph Gate - Admin</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            background: #1a1a2e;
+            color: #eee;
+            margin: 0;
+            padding: 2rem;
+        }
+        h1 {
+            margin: 0 0 1.5rem 0;
+            color: #e94560;
+            font-size: 1.4rem;
+        }
+        h2 {
+            margin: 2rem 0 0.75rem 0;
+            font-size: 1.1rem;
+            color: #aaa;
+        }
+        table {
+            border-collapse: collapse;
+            width: 100%;
+            max-width: 1100px;
+            background: #16213e;
+            border-radius: 8px;
+        }
+        th, td {
+            padding: 0.5rem 1rem;
+            border-bottom: 1px solid #0f3460;
+            text-align: left;
+            word-break: break-all;
+        }
+        th {
+            color: #aaa;
+            font-weight: 600;
+        }
+        td.empty {
+            color: #aaa;
+        }
+        .grant {
+            display: flex;
+            gap: 1rem;
+            justify-content: space-between;
+            align-items: center;
+            padding: 0.25rem 0;
+        }
+        form {
+            margin: 0;
+        }
+        button {
+            padding: 0.4rem 0.9rem;
+            font-weight: 600;
+            background: #e94560;
+            color: white;
+            border: none;
+            border-radius: 6px;
+            cursor: pointer;
+        }
+        button:hover {
+            background: #ff6b6b;
+        }
+        .chart {
+            display: flex;
+            align-items: flex-end;
+            gap: 2px;
+            height: 120px;
+            max-width: 1100px;
+            padding: 0.5rem;
+            background: #16213e;
+            border-radius: 8px;
+        }
+        .chart div {
+            flex: 1;
+            min-height: 1px;
+            background: #e94560;
+        }
+        .chart div.zero {
+            background: #0f3460;
+        }
+        .summary {
+            color: #aaa;
+            margin: 0 0 0.75rem 0;
+        }
+        select {
+            padding: 0.3rem;
+            background: #1a1a2e;
+            color: #eee;
+            border: 1px solid #0f3460;
+            border-radius: 4px;
+        }
+    </style>
+</head>
+<body>
+    <h1>Administration</h1>
+    <p class="summary"><a href="/gate/admin/links">Short links</a> &middot; <a href="/gate/admin/queries">Generated queries</a> &middot; <a href="/gate/admin/slow-queries">Slow queries</a></p>
+
+    <h2>Maintenance</h2>
+{maintenance}
+
+    <h2>Failed logins</h2>
+{failures}
+
+    <h2>Active sessions</h2>
+    <table>
+        <tr><th>Identity</th><th>Backend</th><th>Rank</th><th>Created</th><th>Last seen</th><th></th></tr>
+{sessions}
+    </table>
+
+    <h2>Access tokens</h2>
+    <table>
+        <tr><th>Token</th><th>Created</th><th>Grants</th><th></th></tr>
+{tokens}
+    </table>
+
+    <h2>Directory users</h2>
+    <table>
+        <tr><th>DN</th><th>Rank</th><th></th></tr>
+{users}
+    </table>
+</body>
+</html>
+"#;
+
+pub const LINKS_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Oxigraph Gate - Short links</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            background: #1a1a2e;
+            color: #eee;
+            margin: 0;
+            padding: 2rem;
+        }
+        h1 {
+            margin: 0 0 1.5rem 0;
+            color: #e94560;
+            font-size: 1.4rem;
+        }
+        a {
+            color: #eee;
+        }
+        table {
+            border-collapse: collapse;
+            width: 100%;
+            max-width: 1100px;
+            background: #16213e;
+            border-radius: 8px;
+        }
+        th, td {
+            padding: 0.5rem 1rem;
+            border-bottom: 1px solid #0f3460;
+            text-align: left;
+            word-break: break-all;
+        }
+        th {
+            color: #aaa;
+            font-weight: 600;
+        }
+        td.empty {
+            color: #aaa;
+        }
+        form {
+            margin: 0;
+        }
+        button {
+            padding: 0.4rem 0.9rem;
+            font-weight: 600;
+            background: #e94560;
+            color: white;
+            border: none;
+            border-radius: 6px;
+            cursor: pointer;
+        }
+        button:hover {
+            background: #ff6b6b;
+        }
+        .back {
+            display: inline-block;
+            margin-bottom: 1rem;
+            color: #aaa;
+        }
+    </style>
+</head>
+<body>
+    <a class="back" href="/gate/admin">&larr; Administration</a>
+    <h1>Short links</h1>
+
+    <table>
+        <tr><th>Link</th><th>Target</th><th>Owner</th><th>Hits</th><th>Created</th><th></th></tr>
+{links}
+    </table>
+</body>
+</html>
+"#;
+
+/// Tail of generated SPARQL. `{status}` is plain text and `{queries}` are
+/// table rows rendered (and escaped) by the handler.
+pub const QUERIES_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <meta http-equiv="refresh" content="5">
+    <title>Oxigraph Gate - Generated queries</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            background: #1a1a2e;
+            color: #eee;
+            margin: 0;
+            padding: 2rem;
+        }
+        h1 {
+            margin: 0 0 1.5rem 0;
+            color: #e94560;
+            font-size: 1.4rem;
+        }
+        a {
+            color: #eee;
+        }
+        table {
+            border-collapse: collapse;
+            width: 100%;
+            background: #16213e;
+            border-radius: 8px;
+        }
+        th, td {
+            padding: 0.5rem 1rem;
+            border-bottom: 1px solid #0f3460;
+            text-align: left;
+            vertical-align: top;
+        }
+        th {
+            color: #aaa;
+            font-weight: 600;
+        }
+        td.empty {
+            color: #aaa;
+        }
+        pre {
+            margin: 0;
+            font-size: 0.85rem;
+            white-space: pre-wrap;
+            word-break: break-all;
+        }
+        .summary {
+            color: #aaa;
+            margin: 0 0 0.75rem 0;
+        }
+        .back {
+            display: inline-block;
+            margin-bottom: 1rem;
+            color: #aaa;
+        }
+    </style>
+</head>
+<body>
+    <a class="back" href="/gate/admin">&larr; Administration</a>
+    <h1>Generated queries</h1>
+    <p class="summary">{status}</p>
+
+    <table>
+        <tr><th>Time</th><th>Request</th><th>Kind</th><th>Query</th></tr>
+{queries}
+    </table>
+</body>
+</html>
+"#;
+
+/// Tail of slow proxied queries, with the same placeholders as
+/// [`QUERIES_HTML`]
+pub const SLOW_QUERIES_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <meta http-equiv="refresh" content="5">
+    <title>Oxigraph Gate - Slow queries</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            background: #1a1a2e;
+            color: #eee;
+            margin: 0;
+            padding: 2rem;
+        }
+        h1 {
+            margin: 0 0 1.5rem 0;
+            color: #e94560;
+            font-size: 1.4rem;
+        }
+        a {
+            color: #eee;
+        }
+        table {
+            border-collapse: collapse;
+            width: 100%;
+            background: #16213e;
+            border-radius: 8px;
+        }
+        th, td {
+            padding: 0.5rem 1rem;
+            border-bottom: 1px solid #0f3460;
+            text-align: left;
+            vertical-align: top;
+        }
+        th {
+            color: #aaa;
+            font-weight: 600;
+        }
+        td.empty {
+            color: #aaa;
+        }
+        pre {
+            margin: 0;
+            font-size: 0.85rem;
+            white-space: pre-wrap;
+            word-break: break-all;
+        }
+        .summary {
+            color: #aaa;
+            margin: 0 0 0.75rem 0;
+        }
+        .back {
+            display: inline-block;
+            margin-bottom: 1rem;
+            color: #aaa;
+        }
+    </style>
+</head>
+<body>
+    <a class="back" href="/gate/admin">&larr; Administration</a>
+    <h1>Slow queries</h1>
+    <p class="summary">{status}</p>
+
+    <table>
+        <tr><th>Time</th><th>Duration</th><th>Credential</th><th>Status</th><th>Query</th></tr>
+{queries}
+    </table>
+</body>
+</html>
+"#;
+
+/// Graph Store read rendered for a browser. `{title}` is the HTML-escaped
+/// graph IRI, `{summary}` a line about the content and `{turtle}` the
+/// HTML-escaped Turtle.
+pub const STORE_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Oxigraph Gate - {title}</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            background: #1a1a2e;
+            color: #eee;
+            margin: 0;
+            padding: 2rem;
+        }
+        h1 {
+            margin: 0 0 1.5rem 0;
+            color: #e94560;
+            font-size: 1.4rem;
+            word-break: break-all;
+        }
+        .summary {
+            color: #aaa;
+            margin: 0 0 0.75rem 0;
+        }
+        pre {
+            margin: 0;
+            padding: 1rem;
+            font-size: 0.85rem;
+            white-space: pre-wrap;
+            word-break: break-all;
+            background: #16213e;
+            border-radius: 8px;
+        }
+    </style>
+</head>
+<body>
+    <h1>{title}</h1>
+    <p class="summary">{summary}</p>
+    <pre>{turtle}</pre>
+</body>
+</html>
+"#;
+
+/// Escape text for inclusion in HTML element content or attribute values
+pub fn escape_html(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Substitute `{name}` placeholders of a template in a single pass, so
+/// values containing placeholders are inserted as they are
+pub fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let placeholder = values.iter().find_map(|(name, value)| {
+            let tail = rest[1..].strip_prefix(name)?.strip_prefix('}')?;
+            Some((*value, tail))
+        });
+        match placeholder {
+            Some((value, tail)) => {
+                result.push_str(value);
+                rest = tail;
+            }
+            None => {
+                result.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Shared layout for small gate pages (invites, notices). `{title}` and
+/// `{body}` are substituted by the handler; `{title}` must be HTML-escaped.
+pub const PAGE_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Oxigraph Gate - {title}</title>
+    <style>
+        * {
+            box-sizing: border-box;
+        }
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            background: #1a1a2e;
+            color: #eee;
+            min-height: 100vh;
+            margin: 0;
+            display: flex;
+            align-items: center;
+            justify-content: center;
+        }
+        .container {
+            text-align: center;
+            background: #16213e;
+            padding: 3rem;
+            border-radius: 12px;
+            box-shadow: 0 4px 20px rgba(0, 0, 0, 0.3);
+            max-width: 480px;
+            width: 90%;
+        }
+        h1 {
+            margin: 0 0 0.5rem 0;
+            color: #e94560;
+            font-size: 1.8rem;
+        }
+        p {
+            margin: 0 0 2rem 0;
+            color: #aaa;
+        }
+        code {
+            display: block;
+            padding: 0.875rem;
+            margin-bottom: 2rem;
+            font-size: 0.9rem;
+            border: 2px solid #0f3460;
+            border-radius: 6px;
+            background: #1a1a2e;
+            word-break: break-all;
+        }
+        button, a.button {
+            display: inline-block;
+            width: 100%;
+            padding: 0.875rem;
+            font-size: 1rem;
+            font-weight: 600;
+            background: #e94560;
+            color: white;
+            border: none;
+            border-radius: 6px;
+            cursor: pointer;
+            text-decoration: none;
+            transition: background 0.2s;
+        }
+        button:hover, a.button:hover {
+            background: #ff6b6b;
+        }
+    </style>
+</head>
+<body>
+    <div class="container">
+        <h1>{title}</h1>
+        {body}
+    </div>
+</body>
+</html>
+"#;
+
+/// Landing page at `/`. `{identity}` is replaced with the sign-in status and
+/// `{links}` with the buttons the requester may use; a `HOME_TEMPLATE` file
+/// uses the same placeholders.
+pub const HOME_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Oxigraph Gate</title>
+    <style>
+        * {
+            box-sizing: border-box;
+        }
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            background: #1a1a2e;
+            color: #eee;
+            min-height: 100vh;
+            margin: 0;
+            display: flex;
+            align-items: center;
+            justify-content: center;
+        }
+        .container {
+            text-align: center;
+            background: #16213e;
+            padding: 3rem;
+            border-radius: 12px;
+            box-shadow: 0 4px 20px rgba(0, 0, 0, 0.3);
+            max-width: 400px;
+            width: 90%;
+        }
+        h1 {
+            margin: 0 0 0.5rem 0;
+            color: #e94560;
+            font-size: 1.8rem;
+        }
+        p {
+            margin: 0 0 2rem 0;
+            color: #aaa;
+        }
+        a.button {
+            display: block;
+            padding: 0.875rem;
+            margin-bottom: 0.75rem;
+            font-size: 1rem;
+            font-weight: 600;
+            background: #e94560;
+            color: white;
+            border-radius: 6px;
+            text-decoration: none;
+            transition: background 0.2s;
+        }
+        a.button:hover {
+            background: #ff6b6b;
+        }
+    </style>
+</head>
+<body>
+    <div class="container">
+        <h1>Oxigraph Gate</h1>
+        <p>{identity}</p>
+        {links}
+    </div>
+</body>
+</html>
+"#;
+
+/// Render a page using the shared layout. `body` is inserted as raw HTML.
+pub fn render_page(title: &str, body: &str) -> String {
+    PAGE_HTML
+        .replace("{title}", &escape_html(title))
+        .replace("{body}", body)
+}
+
+/// Preview page of a media file. `{title}` is the HTML-escaped file name,
+/// `{media}` the element showing the file and `{download}` the
+/// HTML-escaped URL downloading it.
+pub const PREVIEW_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>{title}</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            background: #1a1a2e;
+            color: #eee;
+            margin: 0;
+            padding: 2rem;
+        }
+        header {
+            display: flex;
+            gap: 1rem;
+            align-items: center;
+            justify-content: space-between;
+            margin: 0 0 1.5rem 0;
+        }
+        h1 {
+            margin: 0;
+            color: #e94560;
+            font-size: 1.4rem;
+            word-break: break-all;
+        }
+        a.button {
+            padding: 0.4rem 0.9rem;
+            font-weight: 600;
+            color: #eee;
+            text-decoration: none;
+            background: #0f3460;
+            border-radius: 4px;
+            white-space: nowrap;
+        }
+        a.button:hover {
+            color: #ff6b6b;
+        }
+        .media {
+            display: flex;
+            justify-content: center;
+            padding: 1rem;
+            background: #16213e;
+            border-radius: 8px;
+        }
+        .media img, .media video {
+            max-width: 100%;
+            max-height: 80vh;
+        }
+        .media audio {
+            width: 100%;
+        }
+    </style>
+</head>
+<body>
+    <header>
+        <h1>{title}</h1>
+        <a class="button" href="{download}">Download</a>
+    </header>
+    <div class="media">
+        {media}
+    </div>
+</body>
+</html>
+"#;
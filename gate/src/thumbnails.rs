@@ -0,0 +1,241 @@
+//! Thumbnails of image files.
+//!
+//! A thumbnail is the image scaled down to fit `THUMB_MAX_EDGE` pixels and
+//! encoded as PNG. Thumbnails are cached in `FILES_DIR/.thumbs/` under the
+//! SHA-256 of the content, so files of identical content share one and new
+//! content of a file gets a new one. They are made in the background when
+//! an image is uploaded, or on first request. An empty cache file marks
+//! content that does not decode, so it is not tried again. The cache can be
+//! deleted at any time; thumbnails are made again as they are requested.
+
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use image::{ImageFormat, ImageReader, Limits};
+use std::{
+    net::SocketAddr,
+    path::{Path as FsPath, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::Semaphore;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::auth::AuthedUser;
+use crate::etag;
+use crate::files::{binding_value, parse_sha256, sparql_select, FILESYSTEM_GRAPH};
+use crate::integrity::hash_file;
+use crate::AppState;
+
+/// Cache directory under `FILES_DIR`
+const THUMBS_DIR: &str = ".thumbs";
+
+/// Longest edge of a thumbnail in pixels
+const THUMB_MAX_EDGE: u32 = 160;
+
+/// Thumbnails made at once; decoding a large image takes a lot of memory
+const MAX_CONCURRENT: usize = 2;
+
+/// Memory a decoder may allocate for one image
+const MAX_DECODE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// How long clients may reuse a thumbnail without asking again
+const CACHE_CONTROL: &str = "private, max-age=86400";
+
+/// Whether a file name is of an image type thumbnails are made for
+pub fn is_image(name: &str) -> bool {
+    matches!(
+        mime_guess::from_path(name).first_raw(),
+        Some("image/png" | "image/jpeg" | "image/gif" | "image/webp")
+    )
+}
+
+/// Thumbnail cache on disk: `{sha256}.png` per content
+#[derive(Clone)]
+pub struct Thumbnails {
+    dir: PathBuf,
+    files_dir: PathBuf,
+    permits: Arc<Semaphore>,
+}
+
+impl Thumbnails {
+    pub fn new(files_dir: &str) -> Self {
+        Self {
+            dir: PathBuf::from(files_dir).join(THUMBS_DIR),
+            files_dir: PathBuf::from(files_dir),
+            permits: Arc::new(Semaphore::new(MAX_CONCURRENT)),
+        }
+    }
+
+    fn path(&self, sha256: &str) -> PathBuf {
+        self.dir.join(format!("{}.png", sha256))
+    }
+
+    /// The cached thumbnail, if made already: `Some(None)` for content
+    /// that does not decode
+    async fn cached(&self, sha256: &str) -> Result<Option<Option<PathBuf>>, String> {
+        let path = self.path(sha256);
+        match tokio::fs::metadata(&path).await {
+            Ok(meta) => Ok(Some((meta.len() > 0).then_some(path))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// The thumbnail of a stored file with content of this SHA-256, made
+    /// when not cached yet; `None` when the content does not decode
+    pub async fn thumbnail(&self, stored_as: &str, sha256: &str) -> Result<Option<PathBuf>, String> {
+        if let Some(cached) = self.cached(sha256).await? {
+            return Ok(cached);
+        }
+        let _permit = self.permits.acquire().await.map_err(|e| e.to_string())?;
+        // Another request may have made it while this one waited
+        if let Some(cached) = self.cached(sha256).await? {
+            return Ok(cached);
+        }
+
+        tokio::fs::create_dir_all(&self.dir).await.map_err(|e| e.to_string())?;
+        let path = self.path(sha256);
+        let source = self.files_dir.join(stored_as);
+        let target = path.clone();
+        let made = tokio::task::spawn_blocking(move || make_thumbnail(&source, &target))
+            .await
+            .map_err(|e| e.to_string())??;
+        if made {
+            info!(stored_as = %stored_as, sha256 = %sha256, "Thumbnail generated");
+            Ok(Some(path))
+        } else {
+            tokio::fs::write(&path, b"").await.map_err(|e| e.to_string())?;
+            info!(stored_as = %stored_as, sha256 = %sha256, "Thumbnail skipped - content does not decode");
+            Ok(None)
+        }
+    }
+
+    /// Make the thumbnail of newly stored content in the background, when
+    /// it is an image
+    pub fn prepare(&self, stored_as: &str, sha256: &str) {
+        if !is_image(stored_as) {
+            return;
+        }
+        let thumbnails = self.clone();
+        let stored_as = stored_as.to_string();
+        let sha256 = sha256.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = thumbnails.thumbnail(&stored_as, &sha256).await {
+                warn!(stored_as = %stored_as, error = %e, "Thumbnail generation failed");
+            }
+        });
+    }
+}
+
+/// Decode an image and write its thumbnail. `Ok(false)` when the content
+/// is not an image that decodes within the limits.
+fn make_thumbnail(source: &FsPath, target: &FsPath) -> Result<bool, String> {
+    let mut reader = ImageReader::open(source)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| e.to_string())?;
+    let mut limits = Limits::default();
+    limits.max_alloc = Some(MAX_DECODE_BYTES);
+    reader.limits(limits);
+    let Ok(image) = reader.decode() else {
+        return Ok(false);
+    };
+
+    let thumbnail = if image.width() > THUMB_MAX_EDGE || image.height() > THUMB_MAX_EDGE {
+        image.thumbnail(THUMB_MAX_EDGE, THUMB_MAX_EDGE)
+    } else {
+        image
+    };
+    // Written aside and renamed, so a thumbnail is never seen half-written
+    let staged = target.with_extension("tmp");
+    thumbnail
+        .save_with_format(&staged, ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    std::fs::rename(&staged, target).map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// The stored file of a file and the SHA-256 recorded for it, which names
+/// its thumbnail, so anything but 64 hex digits is ignored
+async fn stored_file(state: &AppState, uuid: &Uuid) -> Result<Option<(String, Option<String>)>, String> {
+    let query = format!(
+        r#"PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?storedAs ?sha256 FROM <{graph}> WHERE {{
+    <urn:uuid:{uuid}> liqk:storedAs ?storedAs .
+    OPTIONAL {{ <urn:uuid:{uuid}> liqk:sha256 ?sha256 }}
+}}
+LIMIT 1"#,
+        graph = FILESYSTEM_GRAPH,
+        uuid = uuid,
+    );
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+    Ok(bindings.first().and_then(|b| {
+        let stored_as = binding_value(b, "storedAs")?.to_string();
+        Some((stored_as, binding_value(b, "sha256").and_then(parse_sha256)))
+    }))
+}
+
+/// GET /res/{uuid}/thumb - PNG thumbnail of an image file
+pub async fn thumb_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    AuthedUser { fingerprint, .. }: AuthedUser,
+    headers: HeaderMap,
+    Path(uuid): Path<Uuid>,
+) -> Response {
+    let (stored_as, sha256) = match stored_file(&state, &uuid).await {
+        Ok(Some(stored)) => stored,
+        Ok(None) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, "File not found");
+            return (StatusCode::NOT_FOUND, "File not found").into_response();
+        }
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "SPARQL lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup file").into_response();
+        }
+    };
+    // Stored names are single file names; anything else is not read
+    if !is_image(&stored_as) || stored_as.contains(['/', '\\']) || stored_as.starts_with('.') {
+        return (StatusCode::NOT_FOUND, "No thumbnail for this file").into_response();
+    }
+
+    // Files stored before checksums were recorded are hashed on the spot
+    let sha256 = match sha256 {
+        Some(sha256) => sha256,
+        None => match hash_file(PathBuf::from(&state.files_dir).join(&stored_as)).await {
+            Ok(sha256) => sha256,
+            Err(e) => {
+                warn!(client = %addr, credential = %fingerprint, uuid = %uuid, stored_as = %stored_as, error = %e, "Failed to read file from disk");
+                return (StatusCode::NOT_FOUND, "File not found on disk").into_response();
+            }
+        },
+    };
+
+    let path = match state.thumbnails.thumbnail(&stored_as, &sha256).await {
+        Ok(Some(path)) => path,
+        Ok(None) => return (StatusCode::NOT_FOUND, "No thumbnail for this file").into_response(),
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, stored_as = %stored_as, error = %e, "Thumbnail generation failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to make thumbnail").into_response();
+        }
+    };
+    let body = match tokio::fs::read(&path).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "Failed to read thumbnail");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read thumbnail").into_response();
+        }
+    };
+
+    debug!(client = %addr, credential = %fingerprint, uuid = %uuid, bytes = body.len(), "Thumbnail served");
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("image/png"));
+    response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(CACHE_CONTROL));
+    if let Ok(tag) = HeaderValue::from_str(&format!("\"{}\"", sha256)) {
+        response_headers.insert(header::ETAG, tag);
+    }
+    etag::respond(&headers, StatusCode::OK, response_headers, body.into())
+}
@@ -0,0 +1,159 @@
+use axum::{
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    Router,
+};
+use futures_util::StreamExt;
+use rustls_acme::{acme::ACME_TLS_ALPN_NAME, caches::DirCache, AcmeConfig};
+use std::{fs::File, io::BufReader, sync::Arc, time::Duration};
+use tokio::net::TcpListener;
+use tokio_rustls::{rustls::ServerConfig, TlsAcceptor};
+use tracing::{debug, info, warn};
+
+use crate::listen::serve_connection;
+
+/// Default address HTTPS is served on; ACME validation needs port 443
+pub const DEFAULT_TLS_BIND_ADDR: &str = "0.0.0.0:443";
+/// Default directory ACME accounts and certificates are kept in
+pub const DEFAULT_ACME_CACHE_DIR: &str = "acme";
+
+/// Time a client has to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Built-in HTTPS, so the gate needs no TLS-terminating proxy in front
+pub struct TlsConfig {
+    /// Address HTTPS is served on (`TLS_BIND_ADDR`)
+    pub bind_addr: String,
+    pub source: CertSource,
+}
+
+/// Where the certificate comes from
+pub enum CertSource {
+    /// PEM files, read once at startup (`TLS_CERT_FILE`, `TLS_KEY_FILE`)
+    Files { cert_file: String, key_file: String },
+    /// Obtained and renewed from Let's Encrypt (`ACME_*`)
+    Acme {
+        domains: Vec<String>,
+        /// Contact email addresses for the ACME account
+        contact: Vec<String>,
+        cache_dir: String,
+        /// Use the Let's Encrypt staging environment
+        staging: bool,
+    },
+}
+
+impl TlsConfig {
+    /// The rustls configuration to serve with. For ACME this starts the
+    /// task that obtains the certificate and renews it before it expires;
+    /// until the first one is issued, handshakes fail.
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>, String> {
+        let mut config = match &self.source {
+            CertSource::Files { cert_file, key_file } => {
+                let certs = rustls_pemfile::certs(&mut BufReader::new(open(cert_file)?))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("Failed to read {}: {}", cert_file, e))?;
+                if certs.is_empty() {
+                    return Err(format!("No certificate in {}", cert_file));
+                }
+                let key = rustls_pemfile::private_key(&mut BufReader::new(open(key_file)?))
+                    .map_err(|e| format!("Failed to read {}: {}", key_file, e))?
+                    .ok_or_else(|| format!("No private key in {}", key_file))?;
+                ServerConfig::builder()
+                    .with_no_client_auth()
+                    .with_single_cert(certs, key)
+                    .map_err(|e| format!("Invalid certificate or key: {}", e))?
+            }
+            CertSource::Acme {
+                domains,
+                contact,
+                cache_dir,
+                staging,
+            } => {
+                let mut state = AcmeConfig::new(domains)
+                    .contact(contact.iter().map(|email| format!("mailto:{}", email)))
+                    .cache(DirCache::new(cache_dir.clone()))
+                    .directory_lets_encrypt(!staging)
+                    .state();
+                let resolver = state.resolver();
+                tokio::spawn(async move {
+                    while let Some(event) = state.next().await {
+                        match event {
+                            Ok(event) => info!(event = ?event, "ACME certificate event"),
+                            Err(e) => warn!(error = %e, "ACME certificate error"),
+                        }
+                    }
+                });
+                let mut config = ServerConfig::builder().with_no_client_auth().with_cert_resolver(resolver);
+                // TLS-ALPN-01 validation connections are answered by the resolver
+                config.alpn_protocols.push(ACME_TLS_ALPN_NAME.to_vec());
+                config
+            }
+        };
+        config.alpn_protocols.extend([b"h2".to_vec(), b"http/1.1".to_vec()]);
+        Ok(Arc::new(config))
+    }
+
+    /// Port clients are redirected to from plain HTTP
+    fn port(&self) -> Option<u16> {
+        self.bind_addr.rsplit_once(':').and_then(|(_, port)| port.parse().ok())
+    }
+}
+
+fn open(path: &str) -> Result<File, String> {
+    File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))
+}
+
+/// Serve `app` over TLS. Connections carry `ConnectInfo<SocketAddr>`
+/// like those of `axum::serve`.
+pub async fn serve(listener: TcpListener, config: Arc<ServerConfig>, app: Router) {
+    let acceptor = TlsAcceptor::from(config);
+    loop {
+        let (tcp, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!(error = %e, "Failed to accept connection");
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    debug!(client = %addr, error = %e, "TLS handshake failed");
+                    return;
+                }
+                Err(_) => {
+                    debug!(client = %addr, "TLS handshake timed out");
+                    return;
+                }
+            };
+            if stream.get_ref().1.alpn_protocol() == Some(ACME_TLS_ALPN_NAME) {
+                return;
+            }
+            serve_connection(stream, addr, app).await;
+        });
+    }
+}
+
+/// App for the plain HTTP listener while HTTPS is on: everything is
+/// redirected to the same path over HTTPS
+pub fn redirect_app(tls: &TlsConfig) -> Router {
+    let port = tls.port().filter(|&port| port != 443);
+    Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move { redirect_to_https(&headers, &uri, port) })
+}
+
+fn redirect_to_https(headers: &HeaderMap, uri: &Uri, port: Option<u16>) -> Response {
+    let Some(host) = headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .map(|host| host.rsplit_once(':').filter(|(_, p)| p.parse::<u16>().is_ok()).map_or(host, |(name, _)| name))
+        .filter(|host| !host.is_empty())
+    else {
+        return (StatusCode::BAD_REQUEST, "HTTPS required").into_response();
+    };
+    let port = port.map(|port| format!(":{}", port)).unwrap_or_default();
+    let path = uri.path_and_query().map_or("/", |pq| pq.as_str());
+    Redirect::permanent(&format!("https://{}{}{}", host, port, path)).into_response()
+}
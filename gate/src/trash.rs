@@ -0,0 +1,561 @@
+use axum::{
+    extract::{ConnectInfo, Path, Query, State},
+    http::{HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{Duration as ChronoDuration, Utc};
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    io::ErrorKind,
+    net::SocketAddr,
+    path::{Path as FsPath, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::admin::is_admin;
+use crate::blobs::referenced_blobs;
+use crate::browse::{
+    build_subtree_delete, label_taken, parse_urn_uuid, resolve_target_dir, walk_subtree, Subtree, TargetDir,
+    RANK_CHECK_CONCURRENCY,
+};
+use crate::files::{
+    binding_value, credential, escape_sparql_string, get_access_rank, send_sparql_update_once, sparql_select,
+    FILESYSTEM_GRAPH,
+};
+use crate::AppState;
+
+/// Directory below `FILES_DIR` holding the stored files of trashed files
+const TRASH_DIR: &str = ".trash";
+
+/// The `liqk:Trash` directory deleted files and directories are moved
+/// into. No directory includes it, so nothing in the trash has a path.
+pub const TRASH_NODE: Uuid = Uuid::from_u128(0x7f3c1d2e_9a4b_4c5d_8e6f_0a1b2c3d4e5f);
+
+/// Days a trashed item is kept before the cleanup purges it
+pub const DEFAULT_TRASH_RETENTION_DAYS: u64 = 30;
+
+/// How often the trash is checked for expired items
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// A file or directory in the trash
+pub struct TrashItem {
+    pub uuid: Uuid,
+    pub label: Option<String>,
+    pub is_dir: bool,
+    pub size: Option<u64>,
+    pub trashed_at: String,
+    /// The directory it was deleted from
+    pub from: Option<Uuid>,
+    /// Its `/file` path when it was deleted
+    pub path: Option<String>,
+}
+
+/// Where a stored file lies: in the files directory, or in the trash
+/// folder while its file is trashed
+fn stored_path(files_dir: &str, stored: &str, trashed: bool) -> PathBuf {
+    let dir = PathBuf::from(files_dir);
+    if trashed {
+        dir.join(TRASH_DIR).join(stored)
+    } else {
+        dir.join(stored)
+    }
+}
+
+/// Stored names are single file names; anything else is not touched
+fn is_file_name(stored: &str) -> bool {
+    FsPath::new(stored).file_name().and_then(|name| name.to_str()) == Some(stored)
+}
+
+/// The stored files going with a subtree: its own files' and their
+/// earlier versions'
+fn stored_names(subtree: &Subtree) -> Vec<String> {
+    subtree
+        .stored
+        .iter()
+        .cloned()
+        .chain(subtree.versions.iter().map(|(_, stored)| stored.clone()))
+        .collect()
+}
+
+/// The file and version nodes of a subtree that go with it
+fn subtree_nodes(subtree: &Subtree) -> Vec<Uuid> {
+    subtree
+        .deleted
+        .iter()
+        .copied()
+        .chain(subtree.versions.iter().map(|(version, _)| *version))
+        .collect()
+}
+
+/// Move stored files into the trash folder, or back out of it. A file
+/// already gone from where it is moved from counts as moved. Returns the
+/// names that could not be moved; they stay where they were.
+async fn move_stored(files_dir: &str, names: &[String], to_trash: bool) -> Vec<String> {
+    if to_trash {
+        if let Err(e) = tokio::fs::create_dir_all(PathBuf::from(files_dir).join(TRASH_DIR)).await {
+            warn!(error = %e, "Failed to create trash directory");
+            return names.to_vec();
+        }
+    }
+    let mut failed = Vec::new();
+    for stored in names {
+        if !is_file_name(stored) {
+            failed.push(stored.clone());
+            continue;
+        }
+        let from = stored_path(files_dir, stored, !to_trash);
+        match tokio::fs::rename(&from, stored_path(files_dir, stored, to_trash)).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                warn!(stored_as = %stored, error = %e, "Failed to move stored file");
+                failed.push(stored.clone());
+            }
+        }
+    }
+    failed
+}
+
+/// Remove stored files for good, from the trash folder or, for files that
+/// could not be moved there, the files directory. Returns how many went
+/// and the names that could not be removed.
+async fn remove_stored(files_dir: &str, names: &[String]) -> (usize, Vec<String>) {
+    let mut removed = 0;
+    let mut failed = Vec::new();
+    for stored in names {
+        if !is_file_name(stored) {
+            failed.push(stored.clone());
+            continue;
+        }
+        let mut result = tokio::fs::remove_file(stored_path(files_dir, stored, true)).await;
+        if matches!(&result, Err(e) if e.kind() == ErrorKind::NotFound) {
+            result = tokio::fs::remove_file(stored_path(files_dir, stored, false)).await;
+        }
+        match result {
+            Ok(()) => removed += 1,
+            Err(e) if e.kind() == ErrorKind::NotFound => removed += 1,
+            Err(e) => {
+                warn!(stored_as = %stored, error = %e, "Failed to remove stored file");
+                failed.push(stored.clone());
+            }
+        }
+    }
+    (removed, failed)
+}
+
+/// One SPARQL update unlinking a node from every directory and putting it
+/// into the trash, with when, from where and from which path it was deleted
+fn build_trash_update(node: &Uuid, parent: &Uuid, path: &str) -> String {
+    format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+DELETE {{ GRAPH <{graph}> {{ ?parent posix:includes <urn:uuid:{node}> }} }}
+WHERE {{ GRAPH <{graph}> {{ ?parent posix:includes <urn:uuid:{node}> }} }} ;
+INSERT DATA {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{trash}> a posix:Directory, liqk:Trash ;
+            rdfs:label "Trash" ;
+            posix:includes <urn:uuid:{node}> .
+        <urn:uuid:{node}> liqk:trashedAt "{now}"^^xsd:dateTime ;
+            liqk:trashedFrom <urn:uuid:{parent}> ;
+            liqk:trashedPath "{path}" .
+    }}
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        node = node,
+        trash = TRASH_NODE,
+        now = Utc::now().to_rfc3339(),
+        parent = parent,
+        path = escape_sparql_string(path),
+    )
+}
+
+/// One SPARQL update taking a node out of the trash into `dir`
+fn build_restore_update(node: &Uuid, dir: &Uuid) -> String {
+    format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+DELETE {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{trash}> posix:includes <urn:uuid:{node}> .
+        <urn:uuid:{node}> liqk:trashedAt ?at ;
+            liqk:trashedFrom ?from ;
+            liqk:trashedPath ?path .
+    }}
+}}
+INSERT {{ GRAPH <{graph}> {{ <urn:uuid:{dir}> posix:includes <urn:uuid:{node}> }} }}
+WHERE {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{trash}> posix:includes <urn:uuid:{node}> .
+        OPTIONAL {{ <urn:uuid:{node}> liqk:trashedAt ?at }}
+        OPTIONAL {{ <urn:uuid:{node}> liqk:trashedFrom ?from }}
+        OPTIONAL {{ <urn:uuid:{node}> liqk:trashedPath ?path }}
+    }}
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        trash = TRASH_NODE,
+        node = node,
+        dir = dir,
+    )
+}
+
+/// Move a file or directory into the trash: unlink it from every
+/// directory including it and move the stored files only reachable
+/// through it, with their versions, into the trash folder. Stored files
+/// that nodes outside it share stay in place. Returns the stored files
+/// that could not be moved.
+pub async fn move_to_trash(
+    state: &AppState,
+    node: &Uuid,
+    parent: &Uuid,
+    path: &str,
+    subtree: &Subtree,
+) -> Result<Vec<String>, String> {
+    let names = stored_names(subtree);
+    let shared = referenced_blobs(state, &names, &subtree_nodes(subtree)).await?;
+    send_sparql_update_once(state, "trash", &build_trash_update(node, parent, path)).await?;
+    state.path_cache.invalidate_all();
+    state.rank_cache.invalidate_all();
+    let names: Vec<String> = names.into_iter().filter(|name| !shared.contains(name)).collect();
+    Ok(move_stored(&state.files_dir, &names, true).await)
+}
+
+/// The items in the trash, newest first: all of them, only `item`, or
+/// only those trashed before `before` (RFC 3339)
+async fn trash_items(state: &AppState, item: Option<&Uuid>, before: Option<&str>) -> Result<Vec<TrashItem>, String> {
+    let values = item.map(|item| format!("    VALUES ?item {{ <urn:uuid:{}> }}\n", item)).unwrap_or_default();
+    let filter = before
+        .map(|before| format!("    FILTER(?trashedAt < \"{}\"^^xsd:dateTime)\n", escape_sparql_string(before)))
+        .unwrap_or_default();
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?item ?label ?storedAs ?size ?trashedAt ?from ?path FROM <{graph}> WHERE {{
+{values}    <urn:uuid:{trash}> posix:includes ?item .
+    ?item liqk:trashedAt ?trashedAt .
+{filter}    OPTIONAL {{ ?item rdfs:label ?label }}
+    OPTIONAL {{ ?item liqk:storedAs ?storedAs }}
+    OPTIONAL {{ ?item posix:size ?size }}
+    OPTIONAL {{ ?item liqk:trashedFrom ?from }}
+    OPTIONAL {{ ?item liqk:trashedPath ?path }}
+}}
+ORDER BY DESC(?trashedAt)"#,
+        graph = FILESYSTEM_GRAPH,
+        values = values,
+        trash = TRASH_NODE,
+        filter = filter,
+    );
+
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+    Ok(bindings
+        .iter()
+        .filter_map(|b| {
+            Some(TrashItem {
+                uuid: binding_value(b, "item").and_then(parse_urn_uuid)?,
+                label: binding_value(b, "label").map(str::to_string),
+                is_dir: binding_value(b, "storedAs").is_none(),
+                size: binding_value(b, "size").and_then(|s| s.parse().ok()),
+                trashed_at: binding_value(b, "trashedAt")?.to_string(),
+                from: binding_value(b, "from").and_then(parse_urn_uuid),
+                path: binding_value(b, "path").map(str::to_string),
+            })
+        })
+        .collect())
+}
+
+fn item_json(item: &TrashItem) -> serde_json::Value {
+    json!({
+        "uuid": item.uuid,
+        "name": item.label,
+        "is_dir": item.is_dir,
+        "size": item.size,
+        "trashed_at": item.trashed_at,
+        "from": item.from,
+        "path": item.path,
+    })
+}
+
+/// Delete a trashed item for good: it and everything only it includes,
+/// with the earlier versions of its files, then their stored files that
+/// no remaining node references. Returns the purged subtree and the
+/// stored files that could not be removed.
+async fn purge(state: &AppState, item: &Uuid) -> Result<(Subtree, Vec<String>), String> {
+    let subtree = walk_subtree(state, item).await?;
+    send_sparql_update_once(state, "purge", &build_subtree_delete(&subtree_nodes(&subtree))).await?;
+    state.path_cache.invalidate_all();
+    state.rank_cache.invalidate_all();
+
+    let names = stored_names(&subtree);
+    let names: Vec<String> = match referenced_blobs(state, &names, &[]).await {
+        Ok(shared) => names.into_iter().filter(|name| !shared.contains(name)).collect(),
+        Err(e) => {
+            // Without knowing which are shared, none is removed
+            warn!(uuid = %item, error = %e, "Stored file reference lookup failed");
+            return Ok((subtree, names));
+        }
+    };
+    let (_, failed) = remove_stored(&state.files_dir, &names).await;
+    Ok((subtree, failed))
+}
+
+/// Purge the items trashed more than `TRASH_RETENTION_DAYS` ago every
+/// hour; 0 keeps trashed items until they are purged by hand. Nothing is
+/// purged while the gate is read-only.
+pub fn spawn_trash_cleanup(state: Arc<AppState>) {
+    if state.trash_retention_days == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            if state.maintenance.is_read_only() || state.maintenance.is_upgrading() {
+                continue;
+            }
+            let before = (Utc::now() - ChronoDuration::days(state.trash_retention_days as i64)).to_rfc3339();
+            let expired = match trash_items(&state, None, Some(&before)).await {
+                Ok(expired) => expired,
+                Err(e) => {
+                    warn!(error = %e, "Trash lookup failed");
+                    continue;
+                }
+            };
+            let mut purged = 0;
+            for item in &expired {
+                match purge(&state, &item.uuid).await {
+                    Ok(_) => purged += 1,
+                    Err(e) => warn!(uuid = %item.uuid, error = %e, "Failed to purge trashed item"),
+                }
+            }
+            if purged > 0 {
+                info!(purged = purged, "Expired trash purged");
+            }
+        }
+    });
+}
+
+/// The rank required for a trash action, checked against the directory
+/// the item was deleted from. Items without one are left to admins.
+async fn item_rank(state: &AppState, item: &TrashItem, headers: &HeaderMap, jar: &CookieJar) -> i32 {
+    match &item.from {
+        Some(from) => get_access_rank(state, from, headers, jar).await,
+        None if is_admin(state, headers, jar).await => i32::MAX,
+        None => i32::MIN,
+    }
+}
+
+/// Look up one trashed item, or the response refusing the request
+async fn find_item(state: &AppState, addr: &SocketAddr, fingerprint: &str, uuid: &Uuid) -> Result<TrashItem, Response> {
+    match trash_items(state, Some(uuid), None).await {
+        Ok(items) => items
+            .into_iter()
+            .next()
+            .ok_or_else(|| (StatusCode::NOT_FOUND, "Not in the trash").into_response()),
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "Trash lookup failed");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to list trash").into_response())
+        }
+    }
+}
+
+/// GET /trash - The trashed items the requester could still reach where
+/// they were deleted from: the rule's rank (view by default) is required
+/// on that directory
+pub async fn trash_list_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    let fingerprint = credential(&state, &headers, &jar).fingerprint();
+    let required = match state.rules.required_rank(&state.roles, &Method::GET, "/trash") {
+        Ok(required) => required,
+        Err(status) => return (status, "Access denied").into_response(),
+    };
+    let items = match trash_items(&state, None, None).await {
+        Ok(items) => items,
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, error = %e, "Trash lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list trash").into_response();
+        }
+    };
+
+    let visible: Vec<serde_json::Value> = stream::iter(items)
+        .map(|item| {
+            let (state, headers, jar) = (&state, &headers, &jar);
+            async move { (item_rank(state, &item, headers, jar).await >= required).then(|| item_json(&item)) }
+        })
+        .buffered(RANK_CHECK_CONCURRENCY)
+        .filter_map(|item| async move { item })
+        .collect()
+        .await;
+    Json(json!({ "trash": visible })).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct RestoreParams {
+    /// Directory to restore into (path under `/file`) instead of the one
+    /// the item was deleted from
+    dir: Option<String>,
+    /// Directory to restore into, by UUID
+    parent: Option<Uuid>,
+}
+
+/// POST /trash/{uuid}/restore - Put a trashed item back into the directory
+/// it was deleted from, or into `?dir=`/`?parent=`, with its stored files.
+/// The rule's rank (edit by default) is required on the directory it was
+/// deleted from and on the one it goes into, and its name must be free there.
+pub async fn trash_restore_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(uuid): Path<Uuid>,
+    Query(params): Query<RestoreParams>,
+) -> Response {
+    let fingerprint = credential(&state, &headers, &jar).fingerprint();
+    let required = match state.rules.required_rank(&state.roles, &Method::POST, &format!("/trash/{}/restore", uuid)) {
+        Ok(required) => required,
+        Err(status) => return (status, "Access denied").into_response(),
+    };
+    let item = match find_item(&state, &addr, &fingerprint, &uuid).await {
+        Ok(item) => item,
+        Err(response) => return response,
+    };
+    let rank = item_rank(&state, &item, &headers, &jar).await;
+    if rank < required {
+        warn!(client = %addr, credential = %fingerprint, uuid = %uuid, rank = rank, required = required, "Access denied - insufficient rank for trashed item");
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+    let target = match TargetDir::from_params(params.dir.as_deref(), params.parent) {
+        Ok(Some(target)) => target,
+        Ok(None) => match item.from {
+            Some(from) => TargetDir::Uuid(from),
+            None => return (StatusCode::CONFLICT, "No original directory; give ?dir= or ?parent=").into_response(),
+        },
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let dir = match resolve_target_dir(&state, &addr, &fingerprint, &target, required, &headers, &jar).await {
+        Ok(dir) => dir,
+        Err(response) => return response,
+    };
+
+    let subtree = match walk_subtree(&state, &uuid).await {
+        Ok(subtree) => subtree,
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "Subtree lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list directory").into_response();
+        }
+    };
+    if dir == uuid || subtree.deleted.contains(&dir) || subtree.shared.contains(&dir) {
+        return (StatusCode::BAD_REQUEST, "A directory cannot be restored into itself").into_response();
+    }
+    if let Some(label) = &item.label {
+        match label_taken(&state, &[dir], label, &uuid).await {
+            Ok(false) => {}
+            Ok(true) => {
+                return (StatusCode::CONFLICT, format!("The directory already has an entry named {}", label))
+                    .into_response();
+            }
+            Err(e) => {
+                warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "Name lookup failed");
+                return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to check name").into_response();
+            }
+        }
+    }
+
+    if let Err(e) = send_sparql_update_once(&state, "trash-restore", &build_restore_update(&uuid, &dir)).await {
+        warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "Failed to restore trashed item");
+        return (StatusCode::BAD_GATEWAY, "Failed to restore").into_response();
+    }
+    state.path_cache.invalidate_all();
+    state.rank_cache.invalidate_all();
+    let failed = move_stored(&state.files_dir, &stored_names(&subtree), false).await;
+
+    info!(
+        client = %addr,
+        credential = %fingerprint,
+        uuid = %uuid,
+        dir = %dir,
+        directories = subtree.directories,
+        files = subtree.stored.len(),
+        failed = failed.len(),
+        "Trashed item restored"
+    );
+    Json(json!({
+        "uuid": uuid,
+        "dir": dir,
+        "directories": subtree.directories,
+        "files": subtree.stored.len(),
+        "not_restored_on_disk": failed,
+    }))
+    .into_response()
+}
+
+/// DELETE /trash/{uuid} - Purge a trashed item now. The rule's rank (edit
+/// by default) is required on the directory it was deleted from.
+pub async fn trash_purge_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path(uuid): Path<Uuid>,
+) -> Response {
+    let fingerprint = credential(&state, &headers, &jar).fingerprint();
+    let required = match state.rules.required_rank(&state.roles, &Method::DELETE, &format!("/trash/{}", uuid)) {
+        Ok(required) => required,
+        Err(status) => return (status, "Access denied").into_response(),
+    };
+    let item = match find_item(&state, &addr, &fingerprint, &uuid).await {
+        Ok(item) => item,
+        Err(response) => return response,
+    };
+    let rank = item_rank(&state, &item, &headers, &jar).await;
+    if rank < required {
+        warn!(client = %addr, credential = %fingerprint, uuid = %uuid, rank = rank, required = required, "Access denied - insufficient rank for trashed item");
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+
+    let (subtree, failed) = match purge(&state, &uuid).await {
+        Ok(purged) => purged,
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "Failed to purge trashed item");
+            return (StatusCode::BAD_GATEWAY, "Failed to purge").into_response();
+        }
+    };
+
+    info!(
+        client = %addr,
+        credential = %fingerprint,
+        uuid = %uuid,
+        directories = subtree.directories,
+        files = subtree.stored.len(),
+        versions = subtree.versions.len(),
+        shared = subtree.shared.len(),
+        failed = failed.len(),
+        "Trashed item purged"
+    );
+    Json(json!({
+        "uuid": uuid,
+        "directories": subtree.directories,
+        "files": subtree.stored.len(),
+        "versions": subtree.versions.len(),
+        "not_removed_from_disk": failed,
+        "kept_shared": subtree.shared,
+    }))
+    .into_response()
+}
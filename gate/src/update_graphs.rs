@@ -0,0 +1,669 @@
+use axum::http::Method;
+use percent_encoding::percent_decode_str;
+use std::{collections::HashMap, fmt};
+
+/// A graph written by a request
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphTarget {
+    Default,
+    Named(String),
+}
+
+impl fmt::Display for GraphTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphTarget::Default => write!(f, "the default graph"),
+            GraphTarget::Named(iri) => write!(f, "<{}>", iri),
+        }
+    }
+}
+
+/// Graphs written by a SPARQL update (`/update`) or Graph Store Protocol
+/// write (`/store`). Fails when the targets cannot be determined
+/// statically, e.g. `GRAPH ?g` in a template or `DROP ALL`.
+pub fn written_graphs(
+    path: &str,
+    query_string: Option<&str>,
+    content_type: Option<&str>,
+    body: &[u8],
+) -> Result<Vec<GraphTarget>, String> {
+    if path.to_lowercase().starts_with("/store") {
+        return match store_target(query_string) {
+            Some(target) => Ok(vec![target]),
+            None => Err("Graph Store writes must name a graph or the default graph".to_string()),
+        };
+    }
+
+    match update_text(query_string, content_type, body) {
+        Some(update) => Parser::new(&update).written_graphs(),
+        None => Err("Missing update".to_string()),
+    }
+}
+
+/// A `CLEAR` or `DROP` in an update, or a Graph Store `DELETE`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wipe {
+    /// The operation as written, e.g. `DROP ALL` or `CLEAR GRAPH <g>`
+    pub operation: String,
+    /// The named graph emptied; `None` for `ALL`, `NAMED` and graphs that
+    /// cannot be resolved
+    pub graph: Option<String>,
+}
+
+/// The graphs a SPARQL update (`/update`) or Graph Store request (`/store`)
+/// empties wholesale. `CLEAR DEFAULT` and `DROP DEFAULT` are left out.
+pub fn wiped_graphs(
+    method: &Method,
+    path: &str,
+    query_string: Option<&str>,
+    content_type: Option<&str>,
+    body: &[u8],
+) -> Vec<Wipe> {
+    if path.to_lowercase().starts_with("/store") {
+        return match store_target(query_string) {
+            Some(GraphTarget::Named(graph)) if *method == Method::DELETE => vec![Wipe {
+                operation: format!("DELETE GRAPH <{}>", graph),
+                graph: Some(graph),
+            }],
+            _ => Vec::new(),
+        };
+    }
+    match update_text(query_string, content_type, body) {
+        Some(update) => Parser::new(&update).wipes(),
+        None => Vec::new(),
+    }
+}
+
+/// The update of a `/update` request, sent as a form, in the URL or as an
+/// `application/sparql-update` body
+fn update_text(query_string: Option<&str>, content_type: Option<&str>, body: &[u8]) -> Option<String> {
+    let body = String::from_utf8_lossy(body);
+    let is_form = content_type.is_some_and(|ct| ct.starts_with("application/x-www-form-urlencoded"));
+    if is_form {
+        form_params(&body).into_iter().find(|(k, _)| k == "update").map(|(_, v)| v)
+    } else if body.trim().is_empty() {
+        form_params(query_string.unwrap_or("")).into_iter().find(|(k, _)| k == "update").map(|(_, v)| v)
+    } else {
+        Some(body.into_owned())
+    }
+}
+
+/// The graph a Graph Store Protocol request addresses (`graph=` or
+/// `default`), or `None` for the whole dataset
+pub fn store_target(query_string: Option<&str>) -> Option<GraphTarget> {
+    form_params(query_string.unwrap_or(""))
+        .into_iter()
+        .find(|(k, _)| k == "graph" || k == "default")
+        .map(|(k, graph)| if k == "default" { GraphTarget::Default } else { GraphTarget::Named(graph) })
+}
+
+/// Whether `iri` can be written between `<` and `>` in SPARQL
+pub fn is_valid_iri(iri: &str) -> bool {
+    !iri.is_empty()
+        && !iri
+            .chars()
+            .any(|c| c <= ' ' || matches!(c, '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\'))
+}
+
+/// Decode `application/x-www-form-urlencoded` pairs
+pub fn form_params(encoded: &str) -> Vec<(String, String)> {
+    let decode = |s: &str| percent_decode_str(&s.replace('+', " ")).decode_utf8_lossy().into_owned();
+    encoded
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Iri(String),
+    /// Prefixed name, e.g. `ex:graph`
+    PName(String),
+    Var,
+    Word(String),
+    Open,
+    Close,
+    Semicolon,
+    /// `[`, which can start a triple with an anonymous subject
+    Bracket,
+    /// Literals and punctuation irrelevant to graph targets
+    Other,
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '{' => {
+                tokens.push(Token::Open);
+                i += 1;
+            }
+            '}' => {
+                tokens.push(Token::Close);
+                i += 1;
+            }
+            ';' => {
+                tokens.push(Token::Semicolon);
+                i += 1;
+            }
+            '<' => {
+                // An IRI, or a comparison operator inside a WHERE clause
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|c| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\'));
+                match end {
+                    Some(len) if chars[i + 1 + len] == '>' => {
+                        tokens.push(Token::Iri(chars[i + 1..i + 1 + len].iter().collect()));
+                        i += len + 2;
+                    }
+                    _ => {
+                        tokens.push(Token::Other);
+                        i += 1;
+                    }
+                }
+            }
+            '"' | '\'' => {
+                let long = chars[i..].starts_with(&[c, c, c]);
+                i += if long { 3 } else { 1 };
+                while i < chars.len() {
+                    if chars[i] == '\\' {
+                        i += 2;
+                    } else if long && chars[i..].starts_with(&[c, c, c]) {
+                        i += 3;
+                        break;
+                    } else if !long && chars[i] == c {
+                        i += 1;
+                        break;
+                    } else {
+                        i += 1;
+                    }
+                }
+                tokens.push(Token::Other);
+            }
+            '?' | '$' => {
+                i += 1;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Var);
+            }
+            '[' => {
+                tokens.push(Token::Bracket);
+                i += 1;
+            }
+            '(' | ')' | ']' | ',' | '.' => {
+                tokens.push(Token::Other);
+                i += 1;
+            }
+            _ => {
+                let start = i;
+                while i < chars.len()
+                    && !chars[i].is_whitespace()
+                    && !matches!(chars[i], '{' | '}' | '(' | ')' | '[' | ']' | '<' | ';' | ',' | '"' | '\'' | '#')
+                {
+                    i += 1;
+                }
+                // A trailing `.` ends the triple rather than the name
+                let mut end = i;
+                while end > start + 1 && chars[end - 1] == '.' {
+                    end -= 1;
+                }
+                let word: String = chars[start..end].iter().collect();
+                tokens.push(if word.contains(':') { Token::PName(word) } else { Token::Word(word) });
+                if end < i {
+                    tokens.push(Token::Other);
+                }
+            }
+        }
+    }
+    tokens
+}
+
+/// Finds the graphs an update writes to. Only the structure relevant to
+/// write targets is parsed: templates of INSERT/DELETE operations (with
+/// `WITH` and `GRAPH`) and graph management operations. WHERE clauses
+/// only read and are skipped.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    prefixes: HashMap<String, String>,
+    base: Option<String>,
+    targets: Vec<GraphTarget>,
+}
+
+impl Parser {
+    fn new(update: &str) -> Self {
+        Self {
+            tokens: tokenize(update),
+            pos: 0,
+            prefixes: HashMap::new(),
+            base: None,
+            targets: Vec::new(),
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek_keyword(&self) -> Option<String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Word(w)) => Some(w.to_uppercase()),
+            _ => None,
+        }
+    }
+
+    fn skip_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_keyword().as_deref() == Some(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.skip_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(format!("Expected {}", keyword))
+        }
+    }
+
+    fn add_target(&mut self, target: GraphTarget) {
+        if !self.targets.contains(&target) {
+            self.targets.push(target);
+        }
+    }
+
+    fn resolve_iri(&self, iri: &str) -> String {
+        match &self.base {
+            Some(base) if !iri.contains(':') => format!("{}{}", base, iri),
+            _ => iri.to_string(),
+        }
+    }
+
+    /// Read a graph IRI (IRI or prefixed name)
+    fn graph_iri(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Iri(iri)) => Ok(self.resolve_iri(&iri)),
+            Some(Token::PName(name)) => {
+                let (prefix, local) = name.split_once(':').unwrap_or((&name, ""));
+                self.prefixes
+                    .get(prefix)
+                    .map(|ns| format!("{}{}", ns, local))
+                    .ok_or_else(|| format!("Unknown prefix {}:", prefix))
+            }
+            Some(Token::Var) => Err("Graph variables cannot be checked".to_string()),
+            _ => Err("Expected a graph IRI".to_string()),
+        }
+    }
+
+    /// `GRAPH <g>`, `<g>` or `DEFAULT` (ADD, MOVE and COPY operands)
+    fn graph_or_default(&mut self) -> Result<GraphTarget, String> {
+        if self.skip_keyword("DEFAULT") {
+            return Ok(GraphTarget::Default);
+        }
+        self.skip_keyword("GRAPH");
+        Ok(GraphTarget::Named(self.graph_iri()?))
+    }
+
+    fn written_graphs(mut self) -> Result<Vec<GraphTarget>, String> {
+        let mut with: Option<String> = None;
+
+        while let Some(token) = self.next() {
+            match token {
+                Token::Word(word) => match word.to_uppercase().as_str() {
+                    keyword @ ("PREFIX" | "BASE") => self.declaration(keyword)?,
+                    "WITH" => with = Some(self.graph_iri()?),
+                    "INSERT" | "DELETE" => {
+                        let is_data = self.skip_keyword("DATA");
+                        if !is_data {
+                            self.skip_keyword("WHERE");
+                        }
+                        if self.next() != Some(Token::Open) {
+                            return Err(format!("Expected {{ after {}", word.to_uppercase()));
+                        }
+                        let target = match (&with, is_data) {
+                            (Some(graph), false) => GraphTarget::Named(graph.clone()),
+                            _ => GraphTarget::Default,
+                        };
+                        self.template(target)?;
+                    }
+                    "LOAD" => {
+                        self.skip_keyword("SILENT");
+                        self.graph_iri()?;
+                        if self.skip_keyword("INTO") {
+                            self.expect_keyword("GRAPH")?;
+                            let graph = self.graph_iri()?;
+                            self.add_target(GraphTarget::Named(graph));
+                        } else {
+                            self.add_target(GraphTarget::Default);
+                        }
+                    }
+                    "CLEAR" | "DROP" | "CREATE" => {
+                        self.skip_keyword("SILENT");
+                        match self.peek_keyword().as_deref() {
+                            Some("NAMED") | Some("ALL") => {
+                                return Err(format!("{} NAMED/ALL affects every graph", word.to_uppercase()));
+                            }
+                            _ => {
+                                let target = self.graph_or_default()?;
+                                self.add_target(target);
+                            }
+                        }
+                    }
+                    "ADD" | "MOVE" | "COPY" => {
+                        self.skip_keyword("SILENT");
+                        let source = self.graph_or_default()?;
+                        self.expect_keyword("TO")?;
+                        let destination = self.graph_or_default()?;
+                        // MOVE also empties the source
+                        if word.eq_ignore_ascii_case("MOVE") {
+                            self.add_target(source);
+                        }
+                        self.add_target(destination);
+                    }
+                    _ => {}
+                },
+                // WHERE clauses only read
+                Token::Open => self.skip_block()?,
+                Token::Semicolon => with = None,
+                _ => {}
+            }
+        }
+
+        Ok(self.targets)
+    }
+
+    /// The `CLEAR` and `DROP` operations of the update. Unlike
+    /// [`Parser::written_graphs`], `ALL` and `NAMED` are reported rather
+    /// than refused, and a graph that cannot be resolved counts as any graph.
+    fn wipes(mut self) -> Vec<Wipe> {
+        let mut wipes = Vec::new();
+        while let Some(token) = self.next() {
+            match token {
+                Token::Word(word) => match word.to_uppercase().as_str() {
+                    keyword @ ("PREFIX" | "BASE") => {
+                        // A broken prologue leaves the names unresolved
+                        let _ = self.declaration(keyword);
+                    }
+                    operation @ ("CLEAR" | "DROP") => {
+                        let silent = if self.skip_keyword("SILENT") { " SILENT" } else { "" };
+                        let operation = format!("{}{}", operation, silent);
+                        match self.peek_keyword().as_deref() {
+                            Some(scope @ ("ALL" | "NAMED")) => {
+                                wipes.push(Wipe {
+                                    operation: format!("{} {}", operation, scope),
+                                    graph: None,
+                                });
+                                self.pos += 1;
+                            }
+                            Some("DEFAULT") => self.pos += 1,
+                            _ => {
+                                self.skip_keyword("GRAPH");
+                                let graph = self.graph_iri().ok();
+                                wipes.push(Wipe {
+                                    operation: match &graph {
+                                        Some(graph) => format!("{} GRAPH <{}>", operation, graph),
+                                        None => format!("{} GRAPH", operation),
+                                    },
+                                    graph,
+                                });
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+                Token::Open if self.skip_block().is_err() => break,
+                _ => {}
+            }
+        }
+        wipes
+    }
+
+    /// Read a `PREFIX` or `BASE` declaration, after its keyword
+    fn declaration(&mut self, keyword: &str) -> Result<(), String> {
+        if keyword == "BASE" {
+            let Some(Token::Iri(iri)) = self.next() else {
+                return Err("Invalid BASE declaration".to_string());
+            };
+            self.base = Some(iri);
+            return Ok(());
+        }
+        let Some(Token::PName(name)) = self.next() else {
+            return Err("Invalid PREFIX declaration".to_string());
+        };
+        let Some(Token::Iri(iri)) = self.next() else {
+            return Err("Invalid PREFIX declaration".to_string());
+        };
+        let iri = self.resolve_iri(&iri);
+        self.prefixes.insert(name.trim_end_matches(':').to_string(), iri);
+        Ok(())
+    }
+
+    /// Record the graphs written by a template, after its opening brace.
+    /// Triples outside `GRAPH` blocks go to `current`.
+    fn template(&mut self, current: GraphTarget) -> Result<(), String> {
+        loop {
+            match self.next() {
+                None => return Err("Unterminated block".to_string()),
+                Some(Token::Close) => return Ok(()),
+                Some(Token::Open) => self.template(current.clone())?,
+                Some(Token::Word(w)) if w.eq_ignore_ascii_case("GRAPH") => {
+                    let graph = self.graph_iri()?;
+                    if self.next() != Some(Token::Open) {
+                        return Err("Expected { after GRAPH".to_string());
+                    }
+                    self.template(GraphTarget::Named(graph))?;
+                }
+                Some(Token::Other) | Some(Token::Semicolon) => {}
+                Some(_) => self.add_target(current.clone()),
+            }
+        }
+    }
+
+    /// Skip a block, after its opening brace
+    fn skip_block(&mut self) -> Result<(), String> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                None => return Err("Unterminated block".to_string()),
+                Some(Token::Open) => depth += 1,
+                Some(Token::Close) => depth -= 1,
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATE: Option<&str> = Some("application/sparql-update");
+    const FORM: Option<&str> = Some("application/x-www-form-urlencoded");
+
+    fn written(update: &str) -> Result<Vec<GraphTarget>, String> {
+        written_graphs("/update", None, UPDATE, update.as_bytes())
+    }
+
+    fn wiped(update: &str) -> Vec<Wipe> {
+        wiped_graphs(&Method::POST, "/update", None, UPDATE, update.as_bytes())
+    }
+
+    fn named(iri: &str) -> GraphTarget {
+        GraphTarget::Named(iri.to_string())
+    }
+
+    #[test]
+    fn test_graph_in_insert_data() {
+        let update = r#"PREFIX ex: <http://ex.org/>
+            INSERT DATA {
+                <http://ex.org/s> <http://ex.org/p> "o" .
+                GRAPH ex:g { <http://ex.org/s> <http://ex.org/p> 1 }
+                GRAPH <http://ex.org/h> { [] <http://ex.org/p> <http://ex.org/o> }
+            }"#;
+        assert_eq!(
+            written(update),
+            Ok(vec![GraphTarget::Default, named("http://ex.org/g"), named("http://ex.org/h")])
+        );
+    }
+
+    #[test]
+    fn test_prefix_and_base_are_resolved() {
+        let update = r#"BASE <http://ex.org/>
+            PREFIX ex: <ns/>
+            INSERT DATA { GRAPH ex:g { <s> <p> <o> } GRAPH <rel> { <s> <p> <o> } }"#;
+        assert_eq!(written(update), Ok(vec![named("http://ex.org/ns/g"), named("http://ex.org/rel")]));
+        assert!(written("INSERT DATA { GRAPH ex:g { <s> <p> <o> } }").is_err());
+    }
+
+    #[test]
+    fn test_with_delete_insert() {
+        let update = r#"WITH <http://ex.org/g>
+            DELETE { ?s <http://ex.org/p> ?o }
+            INSERT { ?s <http://ex.org/p> 2 . GRAPH <http://ex.org/h> { ?s ?p ?o } }
+            WHERE { ?s <http://ex.org/p> ?o FILTER(?o < 2) }"#;
+        assert_eq!(written(update), Ok(vec![named("http://ex.org/g"), named("http://ex.org/h")]));
+
+        // WITH applies up to the end of its operation
+        let update = "WITH <http://ex.org/g> DELETE { ?s ?p ?o } WHERE { ?s ?p ?o } ;
+            INSERT { ?s ?p ?o } WHERE { ?s ?p ?o }";
+        assert_eq!(written(update), Ok(vec![named("http://ex.org/g"), GraphTarget::Default]));
+
+        // DELETE WHERE writes the graphs of its pattern
+        let update = "DELETE WHERE { GRAPH <http://ex.org/g> { ?s ?p ?o } }";
+        assert_eq!(written(update), Ok(vec![named("http://ex.org/g")]));
+    }
+
+    #[test]
+    fn test_using_only_reads() {
+        let update = r#"DELETE { ?s ?p ?o }
+            USING <http://ex.org/u>
+            USING NAMED <http://ex.org/n>
+            WHERE { GRAPH <http://ex.org/n> { ?s ?p ?o } }"#;
+        assert_eq!(written(update), Ok(vec![GraphTarget::Default]));
+
+        let update = "WITH <http://ex.org/g> INSERT { ?s ?p ?o } USING <http://ex.org/u> WHERE { ?s ?p ?o }";
+        assert_eq!(written(update), Ok(vec![named("http://ex.org/g")]));
+    }
+
+    #[test]
+    fn test_graph_variables_are_refused() {
+        assert!(written("INSERT { GRAPH ?g { ?s ?p ?o } } WHERE { GRAPH ?g { ?s ?p ?o } }").is_err());
+        assert!(written("WITH ?g DELETE { ?s ?p ?o } WHERE { ?s ?p ?o }").is_err());
+    }
+
+    #[test]
+    fn test_graph_management() {
+        let update = r#"CLEAR GRAPH <http://ex.org/a> ;
+            DROP SILENT DEFAULT ;
+            CREATE GRAPH <http://ex.org/b> ;
+            LOAD <http://ex.org/file.ttl> INTO GRAPH <http://ex.org/c> ;
+            MOVE <http://ex.org/d> TO DEFAULT"#;
+        assert_eq!(
+            written(update),
+            Ok(vec![
+                named("http://ex.org/a"),
+                GraphTarget::Default,
+                named("http://ex.org/b"),
+                named("http://ex.org/c"),
+                named("http://ex.org/d"),
+            ])
+        );
+        assert!(written("DROP ALL").is_err());
+        assert!(written("CLEAR SILENT NAMED").is_err());
+        assert!(written("drop all").is_err());
+    }
+
+    #[test]
+    fn test_wipes() {
+        let update = r#"PREFIX ex: <http://ex.org/>
+            DROP ALL ;
+            CLEAR DEFAULT ;
+            DROP DEFAULT ;
+            CLEAR SILENT GRAPH ex:a ;
+            DROP NAMED ;
+            INSERT DATA { <http://ex.org/s> <http://ex.org/p> <http://ex.org/o> }"#;
+        assert_eq!(
+            wiped(update),
+            vec![
+                Wipe { operation: "DROP ALL".to_string(), graph: None },
+                Wipe {
+                    operation: "CLEAR SILENT GRAPH <http://ex.org/a>".to_string(),
+                    graph: Some("http://ex.org/a".to_string()),
+                },
+                Wipe { operation: "DROP NAMED".to_string(), graph: None },
+            ]
+        );
+        // A graph that cannot be resolved counts as any graph
+        assert_eq!(wiped("DROP GRAPH ?g"), vec![Wipe { operation: "DROP GRAPH".to_string(), graph: None }]);
+
+        let store = wiped_graphs(&Method::DELETE, "/store", Some("graph=http%3A%2F%2Fex.org%2Fg"), None, b"");
+        assert_eq!(store[0].graph.as_deref(), Some("http://ex.org/g"));
+        assert!(wiped_graphs(&Method::PUT, "/store", Some("graph=http%3A%2F%2Fex.org%2Fg"), None, b"").is_empty());
+    }
+
+    #[test]
+    fn test_comments_and_strings_are_skipped() {
+        let update = r#"# GRAPH <http://ex.org/evil> { } DROP ALL
+            INSERT DATA {
+                <http://ex.org/s> <http://ex.org/p> "GRAPH <http://ex.org/evil> { <a> <b> <c> }" ;
+                    <http://ex.org/q> 'a \' GRAPH <http://ex.org/evil> {' ;
+                    <http://ex.org/r> """DROP ALL ; GRAPH <http://ex.org/evil> { "quoted" }""" .
+            } # CLEAR GRAPH <http://ex.org/evil>"#;
+        assert_eq!(written(update), Ok(vec![GraphTarget::Default]));
+        assert!(wiped(update).is_empty());
+    }
+
+    #[test]
+    fn test_update_sources() {
+        // Form bodies, with `update` after other parameters
+        let body = "using-graph-uri=http%3A%2F%2Fex.org%2Fu&update=INSERT+DATA+%7B+GRAPH+%3Chttp%3A%2F%2Fex.org%2Fg%3E+%7B+%3Cs%3E+%3Cp%3E+%3Co%3E+%7D+%7D";
+        assert_eq!(written_graphs("/update", None, FORM, body.as_bytes()), Ok(vec![named("http://ex.org/g")]));
+        let body = "using-graph-uri=x&update=DROP+ALL";
+        assert_eq!(wiped_graphs(&Method::POST, "/update", None, FORM, body.as_bytes()).len(), 1);
+
+        // The URL, when the body is empty
+        assert_eq!(written_graphs("/update", Some("update=CLEAR+DEFAULT"), None, b""), Ok(vec![GraphTarget::Default]));
+        assert!(written_graphs("/update", None, FORM, b"using-graph-uri=x").is_err());
+    }
+
+    #[test]
+    fn test_store_targets() {
+        let graph = Some("graph=http%3A%2F%2Fex.org%2Fg");
+        assert_eq!(written_graphs("/store", graph, None, b""), Ok(vec![named("http://ex.org/g")]));
+        assert_eq!(written_graphs("/store", Some("default"), None, b""), Ok(vec![GraphTarget::Default]));
+        assert!(written_graphs("/store", None, None, b"").is_err());
+    }
+
+    #[test]
+    fn test_form_params() {
+        assert_eq!(
+            form_params("a=1&&b=x+y%26z&c&d%3D=%3D"),
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "x y&z".to_string()),
+                ("c".to_string(), String::new()),
+                ("d=".to_string(), "=".to_string()),
+            ]
+        );
+    }
+}
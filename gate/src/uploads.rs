@@ -0,0 +1,671 @@
+use axum::{
+    extract::{ConnectInfo, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::HashSet,
+    io::SeekFrom,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::auth::random_secret;
+use crate::blobs::find_blob;
+use crate::browse::{place_upload, resolve_target_dir, TargetDir};
+use crate::files::{
+    build_upload_update, checksum_header, checksum_mismatch, credential, extract_extension, file_triples,
+    get_access_rank_iri, place_triples, safe_filename, send_sparql_update, UploadConflict, UPLOAD_ACTION_IRI,
+};
+use crate::integrity::hash_file;
+use crate::quotas::{check_quota, own_triples};
+use crate::AppState;
+
+/// Directory below `FILES_DIR` holding upload sessions
+const UPLOADS_DIR: &str = ".uploads";
+
+/// Upload sessions expire after this long without a new chunk (24 hours)
+const UPLOAD_EXPIRY_SECS: i64 = 24 * 3600;
+
+/// How often expired upload sessions are removed
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Largest chunk accepted by one PATCH request
+const MAX_CHUNK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Header carrying the byte offset of a chunk, and the session's offset in
+/// responses
+const OFFSET_HEADER: &str = "Upload-Offset";
+
+/// State of a resumable upload, persisted next to the partial content so
+/// uploads can continue after a gate restart
+#[derive(Serialize, Deserialize)]
+struct UploadSession {
+    id: String,
+    filename: String,
+    /// Total size announced when the upload was created
+    size: u64,
+    /// Bytes received so far
+    offset: u64,
+    /// Partial content, relative to the uploads directory
+    part_file: String,
+    /// Expected SHA-256 of the complete file
+    sha256: Option<String>,
+    /// Directory the file is put in once complete
+    #[serde(default)]
+    parent: Option<Uuid>,
+    /// How to handle a name taken in the directory, when not `UPLOAD_CONFLICT`
+    #[serde(default)]
+    conflict: Option<UploadConflict>,
+    /// Identity the file is charged to
+    #[serde(default)]
+    owner: Option<String>,
+    created: i64,
+    expires: i64,
+}
+
+#[derive(Deserialize)]
+pub struct CreateParams {
+    filename: String,
+    size: u64,
+    /// Directory to put the file in, as a path under `/file`
+    dir: Option<String>,
+    /// Directory to put the file in, by UUID
+    parent: Option<Uuid>,
+    /// How to handle a name already taken in the directory
+    conflict: Option<UploadConflict>,
+}
+
+/// Upload sessions on disk: `{id}.json` metadata and `{id}.part` content
+pub struct UploadStore {
+    dir: PathBuf,
+    /// Sessions with a request in progress
+    busy: Mutex<HashSet<String>>,
+}
+
+/// Marks a session busy until dropped
+struct BusyGuard<'a> {
+    store: &'a UploadStore,
+    id: String,
+}
+
+impl Drop for BusyGuard<'_> {
+    fn drop(&mut self) {
+        self.store.busy.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Session IDs are 64 hex characters; anything else is never a file name
+fn valid_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+impl UploadStore {
+    pub fn new(files_dir: &str) -> Self {
+        Self {
+            dir: PathBuf::from(files_dir).join(UPLOADS_DIR),
+            busy: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn meta_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    fn part_path(&self, session: &UploadSession) -> PathBuf {
+        self.dir.join(&session.part_file)
+    }
+
+    /// Claim a session for one request; `None` if another one holds it
+    fn lock(&self, id: &str) -> Option<BusyGuard<'_>> {
+        self.busy.lock().unwrap().insert(id.to_string()).then(|| BusyGuard {
+            store: self,
+            id: id.to_string(),
+        })
+    }
+
+    /// Load a live session. Expired sessions are treated as missing.
+    async fn load(&self, id: &str) -> Result<Option<UploadSession>, String> {
+        if !valid_id(id) {
+            return Ok(None);
+        }
+        let json = match tokio::fs::read(self.meta_path(id)).await {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read upload session: {}", e)),
+        };
+        let session: UploadSession =
+            serde_json::from_slice(&json).map_err(|e| format!("Invalid upload session: {}", e))?;
+        Ok((session.expires > Utc::now().timestamp()).then_some(session))
+    }
+
+    /// Persist a session atomically (temp file and rename)
+    async fn save(&self, session: &UploadSession) -> Result<(), String> {
+        let json = serde_json::to_vec(session).map_err(|e| e.to_string())?;
+        let path = self.meta_path(&session.id);
+        let tmp = path.with_extension("json.tmp");
+        let mut file = tokio::fs::File::create(&tmp)
+            .await
+            .map_err(|e| format!("Failed to write upload session: {}", e))?;
+        file.write_all(&json)
+            .await
+            .map_err(|e| format!("Failed to write upload session: {}", e))?;
+        file.sync_all()
+            .await
+            .map_err(|e| format!("Failed to write upload session: {}", e))?;
+        tokio::fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| format!("Failed to write upload session: {}", e))
+    }
+
+    /// Delete a session's metadata and partial content
+    async fn remove(&self, session: &UploadSession) {
+        let _ = tokio::fs::remove_file(self.part_path(session)).await;
+        let _ = tokio::fs::remove_file(self.meta_path(&session.id)).await;
+    }
+
+    /// Remove expired sessions and partial files left without metadata.
+    /// Returns the number of sessions removed.
+    pub async fn cleanup_expired(&self) -> usize {
+        let Ok(mut entries) = tokio::fs::read_dir(&self.dir).await else {
+            return 0;
+        };
+        let now = Utc::now().timestamp();
+        let mut removed = 0;
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some((id, extension)) = name.split_once('.') else {
+                continue;
+            };
+            if !valid_id(id) {
+                continue;
+            }
+            let Some(_guard) = self.lock(id) else {
+                continue;
+            };
+
+            match extension {
+                "json" => {
+                    let expired = match tokio::fs::read(entry.path()).await {
+                        Ok(json) => serde_json::from_slice::<UploadSession>(&json)
+                            .map(|s| s.expires <= now)
+                            .unwrap_or(true),
+                        Err(_) => false,
+                    };
+                    if expired {
+                        let _ = tokio::fs::remove_file(self.dir.join(format!("{}.part", id))).await;
+                        let _ = tokio::fs::remove_file(entry.path()).await;
+                        removed += 1;
+                    }
+                }
+                "part" if !self.meta_path(id).exists() => {
+                    let stale = entry
+                        .metadata()
+                        .await
+                        .ok()
+                        .and_then(|m| m.modified().ok())
+                        .and_then(|t| t.elapsed().ok())
+                        .is_some_and(|age| age.as_secs() as i64 > UPLOAD_EXPIRY_SECS);
+                    if stale {
+                        let _ = tokio::fs::remove_file(entry.path()).await;
+                    }
+                }
+                _ => {}
+            }
+        }
+        removed
+    }
+}
+
+/// Periodically remove abandoned upload sessions
+pub fn spawn_cleanup(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let removed = state.uploads.cleanup_expired().await;
+            if removed > 0 {
+                info!(removed = removed, "Expired upload sessions removed");
+            }
+        }
+    });
+}
+
+fn rfc3339(ts: i64) -> Option<String> {
+    Utc.timestamp_opt(ts, 0).single().map(|t| t.to_rfc3339())
+}
+
+fn session_json(session: &UploadSession) -> serde_json::Value {
+    json!({
+        "id": session.id,
+        "path": format!("/res/uploads/{}", session.id),
+        "filename": session.filename,
+        "size": session.size,
+        "offset": session.offset,
+        "expires": rfc3339(session.expires),
+    })
+}
+
+fn with_offset(mut response: Response, offset: u64) -> Response {
+    response
+        .headers_mut()
+        .insert(OFFSET_HEADER, HeaderValue::from(offset));
+    response
+}
+
+/// Uploads need the rank the rules require on the upload action. Returns
+/// the required rank.
+async fn check_upload_rank(
+    state: &AppState,
+    addr: &SocketAddr,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> Result<i32, Response> {
+    let required = state
+        .rules
+        .required_rank(&state.roles, method, uri.path())
+        .map_err(|status| (status, "Access denied").into_response())?;
+    let rank = get_access_rank_iri(state, UPLOAD_ACTION_IRI, headers, jar).await;
+    if rank < required {
+        warn!(client = %addr, rank = rank, required = required, "Access denied - insufficient rank for upload");
+        return Err((StatusCode::FORBIDDEN, "Access denied - upload requires edit access").into_response());
+    }
+    Ok(required)
+}
+
+/// Move a complete upload into file storage and index it
+async fn finalize(state: &AppState, addr: &SocketAddr, session: &UploadSession) -> Response {
+    let part_path = state.uploads.part_path(session);
+
+    let sha256 = match hash_file(part_path.clone()).await {
+        Ok(sha256) => sha256,
+        Err(e) => {
+            warn!(client = %addr, upload = %session.id, error = %e, "Failed to hash upload");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read upload").into_response();
+        }
+    };
+    if let Some(expected) = &session.sha256 {
+        if *expected != sha256 {
+            warn!(client = %addr, filename = %session.filename, expected = %expected, actual = %sha256, "Checksum mismatch");
+            state.uploads.remove(session).await;
+            return checksum_mismatch(&session.filename, expected, &sha256);
+        }
+    }
+
+    // The name is placed when the file is complete, against the directory
+    // as it is then
+    let conflict = session.conflict.unwrap_or(state.upload_conflict);
+    let (label, replaces) = match &session.parent {
+        Some(dir) => match place_upload(state, dir, &session.filename, conflict, &HashSet::new()).await {
+            Ok(Some(placement)) => (placement.label, placement.replaces),
+            Ok(None) => {
+                warn!(client = %addr, filename = %session.filename, conflict = %conflict.as_str(), "Upload refused - name taken");
+                state.uploads.remove(session).await;
+                return (StatusCode::CONFLICT, format!("The directory already has an entry named {}", session.filename))
+                    .into_response();
+            }
+            Err(e) => {
+                warn!(client = %addr, upload = %session.id, error = %e, "SPARQL lookup failed");
+                return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup directory").into_response();
+            }
+        },
+        None => (session.filename.clone(), None),
+    };
+
+    let file_uuid = Uuid::new_v4();
+    let extension = extract_extension(&session.filename).unwrap_or_else(|| "bin".to_string());
+    // Content already stored is shared rather than kept twice
+    let existing = match find_blob(state, &sha256, &extension, None).await {
+        Ok(existing) => existing,
+        Err(e) => {
+            warn!(client = %addr, upload = %session.id, error = %e, "Duplicate lookup failed");
+            None
+        }
+    };
+    let deduplicated = existing.is_some();
+    let stored_filename = match existing {
+        Some(existing) => {
+            let _ = tokio::fs::remove_file(&part_path).await;
+            existing
+        }
+        None => {
+            let stored_filename = format!("{}.{}", file_uuid, extension);
+            let file_path = PathBuf::from(&state.files_dir).join(&stored_filename);
+            if let Err(e) = tokio::fs::rename(&part_path, &file_path).await {
+                warn!(client = %addr, upload = %session.id, error = %e, "Failed to store upload");
+                return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to store file").into_response();
+            }
+            stored_filename
+        }
+    };
+    let _ = tokio::fs::remove_file(state.uploads.meta_path(&session.id)).await;
+
+    let mime_type = mime_guess::from_path(&session.filename)
+        .first_or_octet_stream()
+        .to_string();
+    let mut triples = file_triples(
+        &file_uuid,
+        &label,
+        &stored_filename,
+        session.size as usize,
+        &mime_type,
+        &Utc::now().to_rfc3339(),
+        &sha256,
+    );
+    place_triples(&mut triples, &file_uuid, session.parent.as_ref(), replaces.as_ref());
+    own_triples(&mut triples, &file_uuid, session.owner.as_deref());
+    let unlinked: Vec<(Uuid, Uuid)> = session.parent.zip(replaces).into_iter().collect();
+    let sparql_query = build_upload_update(&[triples], &unlinked);
+
+    match send_sparql_update(state, "upload", &sparql_query).await {
+        Ok(()) => {
+            state.metrics.upload(session.size);
+            info!(
+                client = %addr,
+                filename = %label,
+                stored_as = %stored_filename,
+                uuid = %file_uuid,
+                bytes = session.size,
+                replaces = %replaces.map_or_else(|| "-".to_string(), |old| old.to_string()),
+                "File uploaded"
+            );
+            state.thumbnails.prepare(&stored_filename, &sha256);
+        }
+        Err(e) => {
+            warn!(client = %addr, filename = %label, error = %e, "File uploaded but indexing failed");
+        }
+    }
+    // A replaced file's path now leads to its new version
+    if replaces.is_some() {
+        state.path_cache.invalidate_all();
+    }
+
+    let mut file = json!({
+        "filename": label,
+        "uuid": file_uuid.to_string(),
+        "sha256": sha256,
+    });
+    if let Some(old) = &replaces {
+        file["replaces"] = json!(old.to_string());
+    }
+    if deduplicated {
+        file["deduplicated"] = json!(true);
+    }
+    let json_response = json!({
+        "success": true,
+        "files": [file],
+    });
+
+    (
+        StatusCode::CREATED,
+        [(header::CONTENT_TYPE, "application/json")],
+        json_response.to_string(),
+    )
+        .into_response()
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// POST /res/uploads?filename=&size= - Start a resumable upload, with
+/// `&dir=` or `&parent=` for the directory to put the file in and
+/// `&conflict=` for what to do when its name is taken there
+pub async fn upload_create_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    uri: Uri,
+    Query(params): Query<CreateParams>,
+) -> Response {
+    let required = match check_upload_rank(&state, &addr, &Method::POST, &uri, &headers, &jar).await {
+        Ok(required) => required,
+        Err(response) => return response,
+    };
+
+    let Some(filename) = safe_filename(&params.filename) else {
+        warn!(client = %addr, filename = %params.filename, "Invalid filename");
+        return (StatusCode::BAD_REQUEST, "Invalid filename").into_response();
+    };
+    if params.size > state.body_limits.upload as u64 {
+        return state.body_limits.upload_too_large();
+    }
+    let sha256 = match checksum_header(&headers) {
+        Ok(expected) => expected,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
+    // The announced size is charged against the quota up front
+    let requester = credential(&state, &headers, &jar);
+    if let Err(response) = check_quota(&state, &addr, &requester, params.size).await {
+        return response;
+    }
+    let parent = match TargetDir::from_params(params.dir.as_deref(), params.parent) {
+        Ok(Some(target)) => {
+            let fingerprint = requester.fingerprint();
+            match resolve_target_dir(&state, &addr, &fingerprint, &target, required, &headers, &jar).await {
+                Ok(dir) => Some(dir),
+                Err(response) => return response,
+            }
+        }
+        Ok(None) => None,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
+    // Refuse a taken name before any content is sent; the name is placed
+    // again when the upload completes
+    if let Some(dir) = &parent {
+        let conflict = params.conflict.unwrap_or(state.upload_conflict);
+        match place_upload(&state, dir, &filename, conflict, &HashSet::new()).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                warn!(client = %addr, filename = %filename, conflict = %conflict.as_str(), "Upload refused - name taken");
+                return (StatusCode::CONFLICT, format!("The directory already has an entry named {}", filename)).into_response();
+            }
+            Err(e) => {
+                warn!(client = %addr, filename = %filename, error = %e, "SPARQL lookup failed");
+                return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup directory").into_response();
+            }
+        }
+    }
+
+    if let Err(e) = tokio::fs::create_dir_all(&state.uploads.dir).await {
+        warn!(client = %addr, error = %e, "Failed to create uploads directory");
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create uploads directory").into_response();
+    }
+
+    let id = random_secret();
+    let now = Utc::now().timestamp();
+    let session = UploadSession {
+        part_file: format!("{}.part", id),
+        id,
+        filename,
+        size: params.size,
+        offset: 0,
+        sha256,
+        parent,
+        conflict: params.conflict,
+        owner: requester.owner(),
+        created: now,
+        expires: now + UPLOAD_EXPIRY_SECS,
+    };
+
+    let created = match tokio::fs::File::create(state.uploads.part_path(&session)).await {
+        Ok(_) => state.uploads.save(&session).await,
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = created {
+        warn!(client = %addr, error = %e, "Failed to create upload session");
+        state.uploads.remove(&session).await;
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create upload").into_response();
+    }
+
+    // An empty file is complete right away
+    if session.size == 0 {
+        return finalize(&state, &addr, &session).await;
+    }
+
+    info!(client = %addr, filename = %session.filename, size = session.size, "Upload started");
+    let location = format!("/res/uploads/{}", session.id);
+    with_offset(
+        (
+            StatusCode::CREATED,
+            [(header::CONTENT_TYPE, "application/json".to_string()), (header::LOCATION, location)],
+            session_json(&session).to_string(),
+        )
+            .into_response(),
+        0,
+    )
+}
+
+/// GET /res/uploads/:id - Progress of a resumable upload
+pub async fn upload_status_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    uri: Uri,
+    Path(id): Path<String>,
+) -> Response {
+    if let Err(response) = check_upload_rank(&state, &addr, &Method::GET, &uri, &headers, &jar).await {
+        return response;
+    }
+
+    match state.uploads.load(&id).await {
+        Ok(Some(session)) => with_offset(
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "application/json")],
+                session_json(&session).to_string(),
+            )
+                .into_response(),
+            session.offset,
+        ),
+        Ok(None) => (StatusCode::NOT_FOUND, "Upload not found").into_response(),
+        Err(e) => {
+            warn!(client = %addr, error = %e, "Failed to load upload session");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load upload").into_response()
+        }
+    }
+}
+
+/// PATCH /res/uploads/:id - Append a chunk at `Upload-Offset`. Completes
+/// the upload once all bytes are received.
+pub async fn upload_append_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    Path(id): Path<String>,
+    req: Request,
+) -> Response {
+    let uri = req.uri().clone();
+    let headers = req.headers().clone();
+    if let Err(response) = check_upload_rank(&state, &addr, &Method::PATCH, &uri, &headers, &jar).await {
+        return response;
+    }
+
+    let Some(_guard) = state.uploads.lock(&id) else {
+        return (StatusCode::CONFLICT, "Another request is writing to this upload").into_response();
+    };
+    let mut session = match state.uploads.load(&id).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Upload not found").into_response(),
+        Err(e) => {
+            warn!(client = %addr, error = %e, "Failed to load upload session");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load upload").into_response();
+        }
+    };
+
+    let offset = headers
+        .get(OFFSET_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    match offset {
+        None => return (StatusCode::BAD_REQUEST, "Missing or invalid Upload-Offset header").into_response(),
+        Some(offset) if offset != session.offset => {
+            return with_offset(
+                (StatusCode::CONFLICT, format!("Upload is at offset {}", session.offset)).into_response(),
+                session.offset,
+            );
+        }
+        Some(_) => {}
+    }
+
+    let chunk = match axum::body::to_bytes(req.into_body(), MAX_CHUNK_SIZE).await {
+        Ok(chunk) => chunk,
+        Err(_) => return (StatusCode::PAYLOAD_TOO_LARGE, "Chunk too large (max 64 MiB)").into_response(),
+    };
+    if session.offset + chunk.len() as u64 > session.size {
+        return (StatusCode::BAD_REQUEST, "Chunk exceeds the announced upload size").into_response();
+    }
+
+    // Bytes past the recorded offset (a write interrupted by a restart)
+    // are discarded before appending
+    let part_path = state.uploads.part_path(&session);
+    let written = async {
+        let mut file = tokio::fs::OpenOptions::new().write(true).open(&part_path).await?;
+        file.set_len(session.offset).await?;
+        file.seek(SeekFrom::Start(session.offset)).await?;
+        file.write_all(&chunk).await?;
+        file.sync_data().await
+    }
+    .await;
+    if let Err(e) = written {
+        warn!(client = %addr, upload = %session.id, error = %e, "Failed to write upload chunk");
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to write chunk").into_response();
+    }
+
+    session.offset += chunk.len() as u64;
+    session.expires = Utc::now().timestamp() + UPLOAD_EXPIRY_SECS;
+
+    if session.offset == session.size {
+        return finalize(&state, &addr, &session).await;
+    }
+
+    if let Err(e) = state.uploads.save(&session).await {
+        warn!(client = %addr, upload = %session.id, error = %e, "Failed to save upload session");
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save upload").into_response();
+    }
+    with_offset(StatusCode::NO_CONTENT.into_response(), session.offset)
+}
+
+/// DELETE /res/uploads/:id - Abandon a resumable upload
+pub async fn upload_cancel_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    uri: Uri,
+    Path(id): Path<String>,
+) -> Response {
+    if let Err(response) = check_upload_rank(&state, &addr, &Method::DELETE, &uri, &headers, &jar).await {
+        return response;
+    }
+
+    let Some(_guard) = state.uploads.lock(&id) else {
+        return (StatusCode::CONFLICT, "Another request is writing to this upload").into_response();
+    };
+    match state.uploads.load(&id).await {
+        Ok(Some(session)) => {
+            state.uploads.remove(&session).await;
+            info!(client = %addr, filename = %session.filename, "Upload cancelled");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Upload not found").into_response(),
+        Err(e) => {
+            warn!(client = %addr, error = %e, "Failed to load upload session");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load upload").into_response()
+        }
+    }
+}
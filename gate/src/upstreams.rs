@@ -0,0 +1,73 @@
+/// Where requests under a routed prefix are sent
+#[derive(Clone, PartialEq, Debug)]
+pub enum Upstream {
+    /// The configured `OXIGRAPH_URL`, with the gate's SPARQL handling
+    Oxigraph,
+    /// Another HTTP service, proxied as is
+    Url(String),
+}
+
+struct Route {
+    prefix: String,
+    upstream: Upstream,
+}
+
+/// Prefix → upstream routing table for proxied requests. A route claims
+/// its prefix and every path below it; the prefix is stripped before the
+/// request is forwarded. Paths no route claims go to Oxigraph unchanged.
+#[derive(Default)]
+pub struct Upstreams {
+    routes: Vec<Route>,
+}
+
+impl Upstreams {
+    /// Parse `UPSTREAM_ROUTES`: comma-separated `prefix=url` pairs, where
+    /// the url `oxigraph` stands for `OXIGRAPH_URL`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut routes = Vec::new();
+        for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (prefix, url) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("route {} must be prefix=url", entry))?;
+            let prefix = prefix.trim().trim_end_matches('/');
+            if prefix.is_empty() {
+                return Err(format!("route {} would claim every path", entry));
+            }
+            if !prefix.starts_with('/') {
+                return Err(format!("prefix of route {} must start with /", entry));
+            }
+            let url = url.trim();
+            let upstream = if url.eq_ignore_ascii_case("oxigraph") {
+                Upstream::Oxigraph
+            } else if url.starts_with("http://") || url.starts_with("https://") {
+                Upstream::Url(url.trim_end_matches('/').to_string())
+            } else {
+                return Err(format!("upstream of route {} must be an http(s) URL or oxigraph", entry));
+            };
+            if routes.iter().any(|r: &Route| r.prefix.eq_ignore_ascii_case(prefix)) {
+                return Err(format!("prefix {} is routed twice", prefix));
+            }
+            routes.push(Route { prefix: prefix.to_string(), upstream });
+        }
+        routes.sort_by_key(|r| std::cmp::Reverse(r.prefix.len()));
+        Ok(Self { routes })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// The upstream claiming `path` and the path below its prefix, or
+    /// `None` when the request goes to Oxigraph unchanged
+    pub fn route(&self, path: &str) -> Option<(&Upstream, String)> {
+        self.routes.iter().find_map(|route| {
+            let head = path.get(..route.prefix.len())?;
+            let rest = &path[route.prefix.len()..];
+            if !head.eq_ignore_ascii_case(&route.prefix) || !(rest.is_empty() || rest.starts_with('/')) {
+                return None;
+            }
+            let rest = if rest.is_empty() { "/" } else { rest };
+            Some((&route.upstream, rest.to_string()))
+        })
+    }
+}
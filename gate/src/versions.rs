@@ -0,0 +1,482 @@
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use serde_json::json;
+use std::{io::ErrorKind, net::SocketAddr, path::PathBuf, sync::Arc};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::auth::AuthedUser;
+use crate::blobs::{find_blob, is_shared};
+use crate::files::{
+    binding_value, credential, escape_sparql_string, extract_extension, lookup_file_by_uuid, read_stored_file,
+    send_sparql_update_once, sparql_select, FILESYSTEM_GRAPH,
+};
+use crate::integrity::hash_file;
+use crate::quotas::{check_quota, own_triples};
+use crate::AppState;
+
+/// An earlier content of a file, from its `liqk:previousVersion` chain
+pub struct Version {
+    pub uuid: Uuid,
+    pub stored_as: String,
+    pub size: Option<u64>,
+    /// When the content was written
+    pub written: Option<String>,
+    pub sha256: Option<String>,
+}
+
+/// A fresh path in the files directory for content that is not in place
+/// yet; hidden, so it never collides with a stored file
+pub fn staging_path(files_dir: &str) -> PathBuf {
+    PathBuf::from(files_dir).join(format!(".{}.tmp", Uuid::new_v4()))
+}
+
+/// Rename a file's stored content to a new version's stored name,
+/// returned with the version UUID; `None` when the file had no content on
+/// disk to keep
+async fn keep_current(files_dir: &str, stored_filename: &str) -> std::io::Result<Option<(Uuid, String)>> {
+    let version = Uuid::new_v4();
+    let extension = extract_extension(stored_filename).unwrap_or_else(|| "bin".to_string());
+    let version_stored = format!("{}.{}", version, extension);
+    match tokio::fs::rename(
+        PathBuf::from(files_dir).join(stored_filename),
+        PathBuf::from(files_dir).join(&version_stored),
+    )
+    .await
+    {
+        Ok(()) => Ok(Some((version, version_stored))),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Make `content` the stored content of a file. The current content is
+/// kept under a new version UUID, returned with its stored name; `None`
+/// when the file had no content on disk to keep.
+async fn swap_in(
+    files_dir: &str,
+    stored_filename: &str,
+    content: &std::path::Path,
+) -> std::io::Result<Option<(Uuid, String)>> {
+    let kept = keep_current(files_dir, stored_filename).await?;
+    if let Err(e) = tokio::fs::rename(content, PathBuf::from(files_dir).join(stored_filename)).await {
+        if let Some((_, version_stored)) = &kept {
+            let _ = tokio::fs::rename(
+                PathBuf::from(files_dir).join(version_stored),
+                PathBuf::from(files_dir).join(stored_filename),
+            )
+            .await;
+        }
+        return Err(e);
+    }
+    Ok(kept)
+}
+
+/// New content put in place for a file
+pub struct Replaced {
+    /// The version keeping the content the file had, with its stored name
+    pub kept: Option<(Uuid, String)>,
+    /// The file's new `liqk:storedAs`, when its content is in another
+    /// stored file now
+    pub stored_as: Option<String>,
+    /// The file's `liqk:storedAs` before
+    previous: String,
+    /// Whether `stored_as` was written for this content rather than shared
+    fresh: bool,
+}
+
+impl Replaced {
+    /// Put the files directory back as it was before [`replace_content`],
+    /// for content that could not be recorded
+    async fn undo(&self, files_dir: &str) -> std::io::Result<()> {
+        let dir = PathBuf::from(files_dir);
+        match &self.kept {
+            // The old content was renamed to the version's stored name
+            Some((_, kept)) if *kept != self.previous => {
+                tokio::fs::rename(dir.join(kept), dir.join(&self.previous)).await?
+            }
+            // A shared stored file was left in place
+            Some(_) => {}
+            // There was no content on disk; the new content took its name
+            None if self.stored_as.is_none() => tokio::fs::remove_file(dir.join(&self.previous)).await?,
+            None => {}
+        }
+        match &self.stored_as {
+            Some(fresh) if self.fresh => tokio::fs::remove_file(dir.join(fresh)).await,
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Make the staged `content`, of SHA-256 `sha256`, the content of `file`.
+/// Content already stored is not written again: the file points at the
+/// stored file that has it. A stored file other nodes share is left as it
+/// is for the version to share too, and the new content gets a stored
+/// file of its own; otherwise [`swap_in`] applies.
+pub async fn replace_content(
+    state: &AppState,
+    file: &Uuid,
+    stored_filename: &str,
+    content: &std::path::Path,
+    sha256: &str,
+) -> Result<Replaced, String> {
+    let extension = extract_extension(stored_filename).unwrap_or_else(|| "bin".to_string());
+    let existing = find_blob(state, sha256, &extension, Some(stored_filename)).await?;
+    let shared = is_shared(state, stored_filename, file).await?;
+    if !shared && existing.is_none() {
+        let kept = swap_in(&state.files_dir, stored_filename, content).await.map_err(|e| e.to_string())?;
+        return Ok(Replaced { kept, stored_as: None, previous: stored_filename.to_string(), fresh: false });
+    }
+
+    let kept = if shared {
+        Some((Uuid::new_v4(), stored_filename.to_string()))
+    } else {
+        keep_current(&state.files_dir, stored_filename).await.map_err(|e| e.to_string())?
+    };
+    let fresh = existing.is_none();
+    let stored_as = match existing {
+        Some(existing) => {
+            let _ = tokio::fs::remove_file(content).await;
+            existing
+        }
+        None => {
+            let fresh = format!("{}.{}", Uuid::new_v4(), extension);
+            tokio::fs::rename(content, PathBuf::from(&state.files_dir).join(&fresh))
+                .await
+                .map_err(|e| e.to_string())?;
+            fresh
+        }
+    };
+    Ok(Replaced { kept, stored_as: Some(stored_as), previous: stored_filename.to_string(), fresh })
+}
+
+/// The update recording a file's new content: its size, checksum,
+/// modification time, owner and, when it moved, stored file, and the version
+/// node keeping the content it had, which takes over the old metadata and
+/// heads the `liqk:previousVersion` chain. Applying it twice changes
+/// nothing more.
+fn build_overwrite_update(
+    file: &Uuid,
+    version: Option<&(Uuid, String)>,
+    size: u64,
+    sha256: Option<&str>,
+    stored_as: Option<&str>,
+    owner: Option<&str>,
+) -> String {
+    let hash = sha256
+        .map(|h| format!("\n        <urn:uuid:{}> liqk:sha256 \"{}\" .", file, h))
+        .unwrap_or_default();
+    let mut own = String::new();
+    own_triples(&mut own, file, owner);
+    let (unstore, store) = match stored_as {
+        Some(stored_as) => (
+            format!("\n        <urn:uuid:{}> liqk:storedAs ?storedAs .", file),
+            format!("\n        <urn:uuid:{}> liqk:storedAs \"{}\" .", file, escape_sparql_string(stored_as)),
+        ),
+        None => (String::new(), String::new()),
+    };
+    let (unlink, version_triples, fresh) = match version {
+        Some((version, stored_as)) => (
+            format!("\n        <urn:uuid:{}> liqk:previousVersion ?previous .", file),
+            format!(
+                r#"
+        <urn:uuid:{version}> a liqk:FileVersion ;
+            liqk:storedAs "{stored_as}" ;
+            posix:size ?size ;
+            dc:format ?format ;
+            dc:created ?written ;
+            liqk:sha256 ?hash ;
+            liqk:owner ?owner ;
+            liqk:previousVersion ?previous .
+        <urn:uuid:{file}> liqk:previousVersion <urn:uuid:{version}> ."#,
+                version = version,
+                stored_as = stored_as,
+                file = file,
+            ),
+            format!("\n        FILTER NOT EXISTS {{ <urn:uuid:{}> a liqk:FileVersion }}", version),
+        ),
+        None => (String::new(), String::new(), String::new()),
+    };
+
+    format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX dc: <http://purl.org/dc/terms/>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+PREFIX liqk: <http://liqk.org/schema#>
+
+DELETE {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{file}> posix:size ?size .
+        <urn:uuid:{file}> liqk:sha256 ?hash .
+        <urn:uuid:{file}> dc:modified ?modified .
+        <urn:uuid:{file}> liqk:owner ?owner .{unlink}{unstore}
+    }}
+}}
+INSERT {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{file}> posix:size {new_size} ;
+            dc:modified "{now}"^^xsd:dateTime .{hash}{own}{store}{version_triples}
+    }}
+}}
+WHERE {{
+    GRAPH <{graph}> {{
+        <urn:uuid:{file}> liqk:storedAs ?storedAs .
+        OPTIONAL {{ <urn:uuid:{file}> posix:size ?size }}
+        OPTIONAL {{ <urn:uuid:{file}> liqk:sha256 ?hash }}
+        OPTIONAL {{ <urn:uuid:{file}> dc:format ?format }}
+        OPTIONAL {{ <urn:uuid:{file}> dc:created ?created }}
+        OPTIONAL {{ <urn:uuid:{file}> dc:modified ?modified }}
+        OPTIONAL {{ <urn:uuid:{file}> liqk:owner ?owner }}
+        OPTIONAL {{ <urn:uuid:{file}> liqk:previousVersion ?previous }}
+        BIND(COALESCE(?modified, ?created) AS ?written){fresh}
+    }}
+}}"#,
+        graph = FILESYSTEM_GRAPH,
+        file = file,
+        unlink = unlink,
+        unstore = unstore,
+        store = store,
+        new_size = size,
+        now = Utc::now().to_rfc3339(),
+        hash = hash,
+        own = own,
+        version_triples = version_triples,
+        fresh = fresh,
+    )
+}
+
+/// Record new content that [`replace_content`] put in place for `file`.
+/// Resolved paths cached before still lead to the stored file the file
+/// had, so the path cache is cleared once the content moved. When the
+/// update fails, the graph still names the old stored file, so the files
+/// directory is put back as it was and the new content dropped.
+pub async fn record_overwrite(
+    state: &AppState,
+    file: &Uuid,
+    replaced: &Replaced,
+    size: u64,
+    sha256: &str,
+    owner: Option<&str>,
+) -> Result<(), String> {
+    let query = build_overwrite_update(
+        file,
+        replaced.kept.as_ref(),
+        size,
+        Some(sha256),
+        replaced.stored_as.as_deref(),
+        owner,
+    );
+    if let Err(e) = send_sparql_update_once(state, "file-version", &query).await {
+        if let Err(undo) = replaced.undo(&state.files_dir).await {
+            warn!(uuid = %file, previous = %replaced.previous, error = %undo, "Failed to undo file content swap");
+        }
+        return Err(e);
+    }
+    if replaced.kept.is_some() || replaced.stored_as.is_some() {
+        state.path_cache.invalidate_all();
+    }
+    Ok(())
+}
+
+/// The earlier versions of a file, newest first; only `version` when given
+pub async fn file_versions(state: &AppState, file: &Uuid, version: Option<&Uuid>) -> Result<Vec<Version>, String> {
+    let only = version
+        .map(|version| format!("    VALUES ?version {{ <urn:uuid:{}> }}\n", version))
+        .unwrap_or_default();
+    let query = format!(
+        r#"PREFIX posix: <http://www.w3.org/ns/posix/stat#>
+PREFIX dc: <http://purl.org/dc/terms/>
+PREFIX liqk: <http://liqk.org/schema#>
+
+SELECT ?version ?storedAs ?size ?written ?sha256 FROM <{graph}> WHERE {{
+{only}    <urn:uuid:{file}> liqk:previousVersion+ ?version .
+    ?version liqk:storedAs ?storedAs .
+    OPTIONAL {{ ?version posix:size ?size }}
+    OPTIONAL {{ ?version dc:created ?created }}
+    OPTIONAL {{ ?version dc:modified ?modified }}
+    OPTIONAL {{ ?version liqk:sha256 ?sha256 }}
+    BIND(COALESCE(?modified, ?created) AS ?written)
+}}
+ORDER BY DESC(?written)"#,
+        graph = FILESYSTEM_GRAPH,
+        only = only,
+        file = file,
+    );
+
+    let bindings = sparql_select(&state.client, &state.oxigraph_url, &query).await?;
+    Ok(bindings
+        .iter()
+        .filter_map(|b| {
+            Some(Version {
+                uuid: binding_value(b, "version")?
+                    .strip_prefix("urn:uuid:")
+                    .and_then(|s| Uuid::parse_str(s).ok())?,
+                stored_as: binding_value(b, "storedAs")?.to_string(),
+                size: binding_value(b, "size").and_then(|s| s.parse().ok()),
+                written: binding_value(b, "written").map(str::to_string),
+                sha256: binding_value(b, "sha256").map(str::to_string),
+            })
+        })
+        .collect())
+}
+
+/// The version of `file` named `version`, answering 404 when it is not in
+/// the file's chain
+async fn find_version(state: &AppState, addr: &SocketAddr, fingerprint: &str, file: &Uuid, version: &Uuid) -> Result<Version, Response> {
+    match file_versions(state, file, Some(version)).await {
+        Ok(versions) => versions
+            .into_iter()
+            .next()
+            .ok_or_else(|| (StatusCode::NOT_FOUND, "Version not found").into_response()),
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %file, version = %version, error = %e, "SPARQL lookup failed");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup version").into_response())
+        }
+    }
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// GET /res/:uuid/versions - Earlier versions of a file, newest first
+pub async fn versions_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    AuthedUser { fingerprint, .. }: AuthedUser,
+    Path(uuid): Path<Uuid>,
+) -> Response {
+    match file_versions(&state, &uuid, None).await {
+        Ok(versions) => Json(json!({
+            "uuid": uuid,
+            "versions": versions.iter().map(|version| json!({
+                "version": version.uuid,
+                "size": version.size,
+                "written": version.written,
+                "sha256": version.sha256,
+            })).collect::<Vec<_>>(),
+        }))
+        .into_response(),
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "SPARQL lookup failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list versions").into_response()
+        }
+    }
+}
+
+/// GET /res/:uuid/versions/:version - Download an earlier version
+pub async fn version_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    AuthedUser { fingerprint, rank }: AuthedUser,
+    headers: HeaderMap,
+    Path((uuid, version)): Path<(Uuid, Uuid)>,
+) -> Response {
+    let version = match find_version(&state, &addr, &fingerprint, &uuid, &version).await {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+
+    match read_stored_file(&state.files_dir, &version.stored_as, &headers).await {
+        Ok((response, bytes)) => {
+            info!(client = %addr, credential = %fingerprint, uuid = %uuid, version = %version.uuid, rank = rank, stored_as = %version.stored_as, bytes = bytes, "File version served");
+            response
+        }
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, version = %version.uuid, stored_as = %version.stored_as, error = %e, "Failed to read file from disk");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read file").into_response()
+        }
+    }
+}
+
+/// POST /res/:uuid/versions/:version/restore - Make an earlier version the
+/// current content again. The content it replaces becomes a version in
+/// turn, so a restore can be undone. Needs the rank `PUT /res/{uuid}`
+/// needs.
+pub async fn restore_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    AuthedUser { fingerprint, rank }: AuthedUser,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Path((uuid, version)): Path<(Uuid, Uuid)>,
+) -> Response {
+    let required = match state.rules.required_rank(&state.roles, &Method::PUT, &format!("/res/{}", uuid)) {
+        Ok(required) => required,
+        Err(status) => return (status, "Access denied").into_response(),
+    };
+    if rank < required {
+        warn!(client = %addr, credential = %fingerprint, uuid = %uuid, rank = rank, required = required, "Access denied - insufficient rank for restore");
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+
+    let version = match find_version(&state, &addr, &fingerprint, &uuid, &version).await {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    let stored_filename = match lookup_file_by_uuid(&state.client, &state.oxigraph_url, &uuid.to_string()).await {
+        Ok(Some(name)) => name,
+        Ok(None) => return (StatusCode::NOT_FOUND, "File not found").into_response(),
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "SPARQL lookup failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lookup file").into_response();
+        }
+    };
+
+    // The restored version keeps its own content, so it is copied; the
+    // copy is dropped again when the version's stored file can be shared
+    let staged = staging_path(&state.files_dir);
+    let size = match tokio::fs::copy(PathBuf::from(&state.files_dir).join(&version.stored_as), &staged).await {
+        Ok(size) => size,
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, version = %version.uuid, error = %e, "Failed to copy file version");
+            let _ = tokio::fs::remove_file(&staged).await;
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read file version").into_response();
+        }
+    };
+    // The restored content is charged to the requester like new content
+    let requester = credential(&state, &headers, &jar);
+    if let Err(response) = check_quota(&state, &addr, &requester, size).await {
+        let _ = tokio::fs::remove_file(&staged).await;
+        return response;
+    }
+    // Versions kept before checksums were recorded for every file get one now
+    let sha256 = match version.sha256.clone() {
+        Some(sha256) => sha256,
+        None => match hash_file(staged.clone()).await {
+            Ok(sha256) => sha256,
+            Err(e) => {
+                warn!(client = %addr, credential = %fingerprint, uuid = %uuid, version = %version.uuid, error = %e, "Failed to hash file version");
+                let _ = tokio::fs::remove_file(&staged).await;
+                return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read file version").into_response();
+            }
+        },
+    };
+    let replaced = match replace_content(&state, &uuid, &stored_filename, &staged, &sha256).await {
+        Ok(replaced) => replaced,
+        Err(e) => {
+            warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "Failed to write file");
+            let _ = tokio::fs::remove_file(&staged).await;
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to write file").into_response();
+        }
+    };
+
+    if let Err(e) = record_overwrite(&state, &uuid, &replaced, size, &sha256, requester.owner().as_deref()).await {
+        warn!(client = %addr, credential = %fingerprint, uuid = %uuid, error = %e, "Failed to record file version in RDF");
+        return (StatusCode::BAD_GATEWAY, "Failed to record file version - the file is unchanged").into_response();
+    }
+
+    info!(client = %addr, credential = %fingerprint, uuid = %uuid, rank = rank, restored = %version.uuid, bytes = size, "File version restored");
+    Json(json!({
+        "uuid": uuid,
+        "restored": version.uuid,
+        "previous": replaced.kept.map(|(version, _)| version),
+    }))
+    .into_response()
+}
@@ -1,11 +1,40 @@
 #!/usr/bin/env python3
-"""Generate cryptographically random access tokens."""
+"""Generate cryptographically random access tokens.
 
+With --keyring the token is stored in the OS keyring (secret service,
+macOS keychain, Windows credential locker) through the `keyring` package
+under the service "liqk" and the label, and tokens.txt only records where
+it is. Without the package or a usable keyring it falls back to writing
+the token to tokens.txt.
+"""
+
+import argparse
 import hashlib
 import secrets
 from datetime import datetime
 
+KEYRING_SERVICE = "liqk"
+
+def store_in_keyring(label, token):
+    """Store the token in the OS keyring; False when no keyring is usable."""
+    try:
+        import keyring
+        from keyring.errors import KeyringError
+    except ImportError:
+        print("Warning: the keyring package is not installed, writing the token to tokens.txt")
+        return False
+    try:
+        keyring.set_password(KEYRING_SERVICE, label, token)
+    except KeyringError as e:
+        print(f"Warning: keyring unavailable ({e}), writing the token to tokens.txt")
+        return False
+    return True
+
 def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--keyring", action="store_true", help="store the token in the OS keyring")
+    args = parser.parse_args()
+
     label = input("Label: ").strip()
     if not label:
         print("Error: label cannot be empty")
@@ -15,11 +44,17 @@ def main():
     token_hash = hashlib.sha256(token.encode()).hexdigest()
     timestamp = datetime.now().isoformat()
 
+    stored = token
+    if args.keyring and store_in_keyring(label, token):
+        stored = f"keyring:{KEYRING_SERVICE}"
+
     with open("tokens.txt", "a") as f:
-        f.write(f"{timestamp} {label} {token} {token_hash}\n")
+        f.write(f"{timestamp} {label} {stored} {token_hash}\n")
 
     print(f"Token: {token}")
     print(f"Hash:  {token_hash}")
+    if stored != token:
+        print(f"Stored in the OS keyring as service \"{KEYRING_SERVICE}\", user \"{label}\"")
     return 0
 
 if __name__ == "__main__":
@@ -0,0 +1,29 @@
+//! Records build provenance for `liqk-crypto --version --json`. Every value
+//! is optional: builds from a source archive without git still succeed.
+//! Without `rerun-if` directives Cargo reruns this whenever a file in the
+//! package changes, which keeps the commit current for local builds.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    let git_commit = env::var("LIQK_CRYPTO_GIT_COMMIT").ok().or_else(|| {
+        let output = Command::new("git").args(["rev-parse", "HEAD"]).output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    });
+    let git_dirty = git_commit.is_some()
+        && Command::new("git")
+            .args(["status", "--porcelain", "--untracked-files=no", "."])
+            .output()
+            .is_ok_and(|o| o.status.success() && !o.stdout.is_empty());
+    let rustc = env::var("RUSTC").ok().and_then(|rustc| {
+        let output = Command::new(rustc).arg("--version").output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    });
+
+    println!("cargo:rustc-env=LIQK_CRYPTO_GIT_COMMIT={}", git_commit.unwrap_or_default());
+    println!("cargo:rustc-env=LIQK_CRYPTO_GIT_DIRTY={}", git_dirty);
+    println!("cargo:rustc-env=LIQK_CRYPTO_RUSTC={}", rustc.unwrap_or_default());
+    println!("cargo:rustc-env=LIQK_CRYPTO_TARGET={}", env::var("TARGET").unwrap_or_default());
+    println!("cargo:rustc-env=LIQK_CRYPTO_PROFILE={}", env::var("PROFILE").unwrap_or_default());
+}
@@ -0,0 +1,90 @@
+//! Encrypted configuration values.
+//!
+//! A value such as an access token or OAuth client secret is encrypted to
+//! a machine's public key and written as `enc:` followed by the base64 of
+//! an ordinary encrypted file, so it can sit in a `.env` file in place of
+//! the plaintext. The gate decrypts such values at startup by running
+//! `liqk-crypto conf decrypt` with the machine's secret key.
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use liqk_crypto::libcrux_kem::{PrivateKey, PublicKey};
+use liqk_crypto::{decrypt_bytes, encrypt_bytes_with, Kdf};
+use rand::CryptoRng;
+use std::io::{self, Read};
+
+/// Marks an encrypted configuration value
+pub const PREFIX: &str = "enc:";
+
+pub fn encrypt_value(
+    public_key: &PublicKey,
+    value: &str,
+    kdf: Kdf,
+    mut rng: &mut dyn CryptoRng,
+) -> Result<String> {
+    let encrypted = encrypt_bytes_with(public_key, value.as_bytes(), kdf, &mut rng)?;
+    Ok(format!("{}{}", PREFIX, STANDARD.encode(encrypted)))
+}
+
+pub fn decrypt_value(secret_key: &PrivateKey, value: &str) -> Result<String> {
+    let encoded = value
+        .trim()
+        .strip_prefix(PREFIX)
+        .ok_or_else(|| anyhow!("value does not start with '{}'", PREFIX))?;
+    let encrypted = STANDARD.decode(encoded).context("Invalid base64 in encrypted value")?;
+    let plaintext = decrypt_bytes(secret_key, &encrypted)?;
+    String::from_utf8(plaintext).context("Decrypted value is not UTF-8")
+}
+
+/// The value given on the command line, or read from stdin without its
+/// trailing newline, so secrets need not appear in shell history or `ps`
+pub fn value_arg(value: Option<String>) -> Result<String> {
+    if let Some(value) = value {
+        return Ok(value);
+    }
+    let mut value = String::new();
+    io::stdin().read_to_string(&mut value).context("Failed to read value from stdin")?;
+    if value.ends_with('\n') {
+        value.pop();
+        if value.ends_with('\r') {
+            value.pop();
+        }
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use liqk_crypto::generate_keypair;
+
+    #[test]
+    fn test_value_roundtrip() -> Result<()> {
+        let mut rng = rand::rng();
+        let (secret_key, public_key) = generate_keypair(&mut rng)?;
+
+        let encrypted = encrypt_value(&public_key, "s3cret=with spaces", Kdf::Sha256, &mut rng)?;
+        assert!(encrypted.starts_with(PREFIX));
+        assert!(!encrypted.contains('\n'));
+        assert_eq!(decrypt_value(&secret_key, &encrypted)?, "s3cret=with spaces");
+        // A trailing newline from a pasted value is tolerated
+        assert_eq!(decrypt_value(&secret_key, &format!("{}\n", encrypted))?, "s3cret=with spaces");
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_rejects_plaintext_and_tampering() -> Result<()> {
+        let mut rng = rand::rng();
+        let (secret_key, public_key) = generate_keypair(&mut rng)?;
+
+        assert!(decrypt_value(&secret_key, "plain").is_err());
+        assert!(decrypt_value(&secret_key, "enc:not base64!").is_err());
+
+        let encrypted = encrypt_value(&public_key, "value", Kdf::Sha256, &mut rng)?;
+        let mut bytes = STANDARD.decode(&encrypted[PREFIX.len()..])?;
+        *bytes.last_mut().unwrap() ^= 1;
+        let tampered = format!("{}{}", PREFIX, STANDARD.encode(bytes));
+        assert!(decrypt_value(&secret_key, &tampered).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,503 @@
+//! `daemon` subcommand: a small encrypted-backup agent.
+//!
+//! On every run each source is collected (files matching a glob, or the
+//! stdout of a command), encrypted to every recipient, written to the
+//! output directory, rotated, and optionally handed to a push command
+//! (e.g. `curl` to the gate's `/res` endpoint or `aws s3 cp`).
+
+use anyhow::{anyhow, bail, Context, Result};
+use liqk_crypto::encrypt_bytes;
+use liqk_crypto::keys::decode_public_key;
+use liqk_crypto::libcrux_kem::PublicKey;
+use rand::CryptoRng;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{shell_quote, write_atomic};
+
+/// Where a backup's plaintext comes from
+#[derive(Debug, PartialEq)]
+pub enum SourceKind {
+    /// Files in `dir` whose name matches `pattern` (`*` and `?` wildcards)
+    Glob { dir: PathBuf, pattern: String },
+    /// Standard output of a shell command
+    Command(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Source {
+    pub name: String,
+    pub kind: SourceKind,
+}
+
+/// Parsed daemon configuration
+#[derive(Debug)]
+pub struct Config {
+    pub interval: Duration,
+    pub output_dir: PathBuf,
+    /// Artifacts kept per source and recipient
+    pub keep: usize,
+    pub recipients: Vec<PathBuf>,
+    pub sources: Vec<Source>,
+    /// Shell command run for each new artifact; `{file}` is replaced with
+    /// its quoted path, which is also exported as `LIQK_ARTIFACT`
+    pub push: Option<String>,
+}
+
+/// Parse `interval` values such as `90`, `30s`, `15m`, `6h` or `1d`
+fn parse_interval(value: &str) -> Result<Duration> {
+    let (number, unit) = match value.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid interval: {}", value))?;
+    let unit_secs: u64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => bail!("Invalid interval unit in {} (use s, m, h or d)", value),
+    };
+    let secs = number.checked_mul(unit_secs).context("Interval too large")?;
+    if secs == 0 {
+        bail!("Interval must be positive");
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+fn parse_source(name: &str, value: &str) -> Result<Source> {
+    let kind = if let Some(pattern) = value.strip_prefix("glob:") {
+        let path = Path::new(pattern.trim());
+        let file_pattern = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow!("Invalid glob for source {}: {}", name, pattern))?;
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        if dir.to_string_lossy().contains(['*', '?']) {
+            bail!("Wildcards are only supported in the file name: {}", pattern);
+        }
+        SourceKind::Glob {
+            dir,
+            pattern: file_pattern.to_string(),
+        }
+    } else if let Some(command) = value.strip_prefix("command:") {
+        SourceKind::Command(command.trim().to_string())
+    } else {
+        bail!("Source {} must start with glob: or command:", name);
+    };
+
+    Ok(Source {
+        name: name.to_string(),
+        kind,
+    })
+}
+
+/// Parse the `key = value` configuration format. Blank lines and lines
+/// starting with `#` are ignored; `recipient` and `source.<name>` repeat.
+pub fn parse_config(text: &str) -> Result<Config> {
+    let mut interval = None;
+    let mut output_dir = None;
+    let mut keep = 7;
+    let mut recipients = Vec::new();
+    let mut sources = Vec::new();
+    let mut push = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .ok_or_else(|| anyhow!("Line {}: expected key = value", number + 1))?;
+
+        match key {
+            "interval" => interval = Some(parse_interval(value)?),
+            "output_dir" => output_dir = Some(PathBuf::from(value)),
+            "keep" => {
+                keep = value
+                    .parse()
+                    .with_context(|| format!("Line {}: invalid keep value", number + 1))?
+            }
+            "recipient" => recipients.push(PathBuf::from(value)),
+            "push" => push = Some(value.to_string()),
+            _ => match key.strip_prefix("source.") {
+                Some(name) if is_valid_name(name) => sources.push(parse_source(name, value)?),
+                Some(name) => bail!("Line {}: invalid source name {:?}", number + 1, name),
+                None => bail!("Line {}: unknown key {}", number + 1, key),
+            },
+        }
+    }
+
+    if recipients.is_empty() {
+        bail!("At least one recipient is required");
+    }
+    if sources.is_empty() {
+        bail!("At least one source is required");
+    }
+    if keep == 0 {
+        bail!("keep must be at least 1");
+    }
+
+    Ok(Config {
+        interval: interval.ok_or_else(|| anyhow!("interval is required"))?,
+        output_dir: output_dir.ok_or_else(|| anyhow!("output_dir is required"))?,
+        keep,
+        recipients,
+        sources,
+        push,
+    })
+}
+
+/// Source names become part of artifact file names
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Match a file name against a pattern with `*` and `?` wildcards
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the name index it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p + 1, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Collect `(artifact base name, plaintext)` pairs for a source
+fn collect(source: &Source) -> Result<Vec<(String, Vec<u8>)>> {
+    match &source.kind {
+        SourceKind::Glob { dir, pattern } => {
+            let mut items = Vec::new();
+            let entries = fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+            for entry in entries {
+                let entry = entry?;
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                if entry.file_type()?.is_file() && wildcard_match(pattern, &file_name) {
+                    let data = fs::read(entry.path())
+                        .with_context(|| format!("Failed to read {}", entry.path().display()))?;
+                    items.push((format!("{}.{}", source.name, file_name), data));
+                }
+            }
+            items.sort_by(|a, b| a.0.cmp(&b.0));
+            Ok(items)
+        }
+        SourceKind::Command(command) => {
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .output()
+                .with_context(|| format!("Failed to run command for source {}", source.name))?;
+            if !output.status.success() {
+                bail!(
+                    "Command for source {} failed ({}): {}",
+                    source.name,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            Ok(vec![(source.name.clone(), output.stdout)])
+        }
+    }
+}
+
+/// Label used in artifact names for a recipient key (its file stem)
+fn recipient_label(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().replace(['-', ' '], "_"))
+        .unwrap_or_else(|| "recipient".to_string())
+}
+
+fn load_recipients(paths: &[PathBuf]) -> Result<Vec<(String, PublicKey)>> {
+    paths
+        .iter()
+        .map(|path| {
+            let pem = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read recipient key {}", path.display()))?;
+            Ok((recipient_label(path), decode_public_key(&pem)?))
+        })
+        .collect()
+}
+
+/// Artifact path: `{base}-{unix seconds}.{recipient}.enc`
+fn artifact_path(dir: &Path, base: &str, timestamp: u64, label: &str) -> PathBuf {
+    dir.join(format!("{}-{:012}.{}.enc", base, timestamp, label))
+}
+
+/// Delete all but the newest `keep` artifacts of `base` for `label`
+pub fn rotate(dir: &Path, base: &str, label: &str, keep: usize) -> Result<Vec<PathBuf>> {
+    let prefix = format!("{}-", base);
+    let suffix = format!(".{}.enc", label);
+
+    let mut artifacts: Vec<(u64, PathBuf)> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let timestamp = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(&suffix))
+            .filter(|ts| !ts.is_empty() && ts.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|ts| ts.parse().ok());
+        if let Some(timestamp) = timestamp {
+            artifacts.push((timestamp, entry.path()));
+        }
+    }
+
+    artifacts.sort_by(|a, b| b.0.cmp(&a.0));
+    let mut removed = Vec::new();
+    for (_, path) in artifacts.into_iter().skip(keep) {
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        removed.push(path);
+    }
+    Ok(removed)
+}
+
+fn push(command: &str, artifact: &Path) -> Result<()> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command.replace("{file}", &shell_quote(artifact)))
+        .env("LIQK_ARTIFACT", artifact)
+        .status()
+        .context("Failed to run push command")?;
+    if !status.success() {
+        bail!("Push command failed for {} ({})", artifact.display(), status);
+    }
+    Ok(())
+}
+
+/// Run every source once. Returns the artifacts written and the number of
+/// failures; a failing source or push does not stop the others.
+pub fn run_once(config: &Config, mut rng: &mut dyn CryptoRng) -> Result<(Vec<PathBuf>, usize)> {
+    let recipients = load_recipients(&config.recipients)?;
+    fs::create_dir_all(&config.output_dir)
+        .with_context(|| format!("Failed to create {}", config.output_dir.display()))?;
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut written = Vec::new();
+    let mut failures = 0;
+
+    for source in &config.sources {
+        let items = match collect(source) {
+            Ok(items) => items,
+            Err(e) => {
+                eprintln!("Source {}: {:#}", source.name, e);
+                failures += 1;
+                continue;
+            }
+        };
+        if items.is_empty() {
+            eprintln!("Source {}: nothing matched", source.name);
+        }
+
+        for (base, plaintext) in &items {
+            for (label, public_key) in &recipients {
+                let path = artifact_path(&config.output_dir, base, timestamp, label);
+                let result = encrypt_bytes(public_key, plaintext, &mut rng)
+                    .and_then(|ciphertext| write_atomic(&path, &ciphertext));
+                if let Err(e) = result {
+                    eprintln!("Source {}: failed to write {}: {:#}", source.name, path.display(), e);
+                    failures += 1;
+                    continue;
+                }
+                println!("  Wrote {} ({} bytes plaintext)", path.display(), plaintext.len());
+
+                if let Some(command) = &config.push {
+                    if let Err(e) = push(command, &path) {
+                        eprintln!("Source {}: {:#}", source.name, e);
+                        failures += 1;
+                    }
+                }
+
+                match rotate(&config.output_dir, base, label, config.keep) {
+                    Ok(removed) => {
+                        for path in removed {
+                            println!("  Rotated out {}", path.display());
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Source {}: rotation failed: {:#}", source.name, e);
+                        failures += 1;
+                    }
+                }
+                written.push(path);
+            }
+        }
+    }
+
+    Ok((written, failures))
+}
+
+/// Run the daemon: back up immediately, then every `interval`. With
+/// `once`, run a single time and fail if anything failed.
+pub fn run(config_path: &Path, once: bool, rng: &mut dyn CryptoRng) -> Result<()> {
+    let text = fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read config {}", config_path.display()))?;
+    let config = parse_config(&text).with_context(|| format!("Invalid config {}", config_path.display()))?;
+
+    println!(
+        "Backup daemon started: {} source(s), {} recipient(s), every {}s",
+        config.sources.len(),
+        config.recipients.len(),
+        config.interval.as_secs()
+    );
+
+    loop {
+        println!("Backup run started");
+        let (written, failures) = run_once(&config, &mut *rng)?;
+        println!("Backup run finished: {} artifact(s), {} failure(s)", written.len(), failures);
+
+        if once {
+            if failures > 0 {
+                bail!("{} failure(s) during backup run", failures);
+            }
+            return Ok(());
+        }
+        thread::sleep(config.interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use liqk_crypto::keys::encode_public_key;
+    use liqk_crypto::{decrypt_bytes, generate_keypair};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_config() -> Result<()> {
+        let config = parse_config(
+            "# nightly dumps\n\
+             interval = 6h\n\
+             output_dir = /var/backups/liqk\n\
+             keep = 3\n\
+             recipient = /etc/liqk/backup.pk.pem\n\
+             source.oxigraph = glob:/data/dumps/*.nq\n\
+             source.files = command:tar -C /srv/files -cf - .\n",
+        )?;
+
+        assert_eq!(config.interval, Duration::from_secs(6 * 3600));
+        assert_eq!(config.keep, 3);
+        assert_eq!(config.recipients, vec![PathBuf::from("/etc/liqk/backup.pk.pem")]);
+        assert_eq!(
+            config.sources,
+            vec![
+                Source {
+                    name: "oxigraph".to_string(),
+                    kind: SourceKind::Glob {
+                        dir: PathBuf::from("/data/dumps"),
+                        pattern: "*.nq".to_string()
+                    },
+                },
+                Source {
+                    name: "files".to_string(),
+                    kind: SourceKind::Command("tar -C /srv/files -cf - .".to_string()),
+                },
+            ]
+        );
+        assert!(config.push.is_none());
+
+        assert!(parse_config("interval = 1h\noutput_dir = out\nsource.a = command:true\n").is_err());
+        assert!(parse_config("interval = 1x\noutput_dir = out\nrecipient = k\nsource.a = command:true\n").is_err());
+        assert!(parse_interval("300000000000000d").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.nq", "dump-2025.nq"));
+        assert!(wildcard_match("dump-????.nq", "dump-2025.nq"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("a*b*c", "aXXbYYc"));
+        assert!(!wildcard_match("*.nq", "dump.nq.tmp"));
+        assert!(!wildcard_match("dump-?.nq", "dump-10.nq"));
+    }
+
+    #[test]
+    fn test_rotate_keeps_newest() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        for ts in [100, 300, 200] {
+            fs::write(artifact_path(temp_dir.path(), "db", ts, "backup"), b"x")?;
+        }
+        // Other recipients and sources are rotated separately
+        fs::write(artifact_path(temp_dir.path(), "db", 50, "offsite"), b"x")?;
+        fs::write(artifact_path(temp_dir.path(), "db-old", 10, "backup"), b"x")?;
+
+        let removed = rotate(temp_dir.path(), "db", "backup", 2)?;
+
+        assert_eq!(removed, vec![artifact_path(temp_dir.path(), "db", 100, "backup")]);
+        assert!(artifact_path(temp_dir.path(), "db", 300, "backup").exists());
+        assert!(artifact_path(temp_dir.path(), "db", 200, "backup").exists());
+        assert!(artifact_path(temp_dir.path(), "db", 50, "offsite").exists());
+        assert!(artifact_path(temp_dir.path(), "db-old", 10, "backup").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_once_encrypts_sources() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let data_dir = temp_dir.path().join("data");
+        let output_dir = temp_dir.path().join("out");
+        fs::create_dir(&data_dir)?;
+        fs::write(data_dir.join("dump.nq"), b"<a> <b> <c> .")?;
+        fs::write(data_dir.join("notes.txt"), b"ignored")?;
+
+        let (secret_key, public_key) = generate_keypair(&mut rand::rng())?;
+        let pk_path = temp_dir.path().join("backup.pem");
+        fs::write(&pk_path, encode_public_key(&public_key))?;
+
+        let config = Config {
+            interval: Duration::from_secs(60),
+            output_dir: output_dir.clone(),
+            keep: 2,
+            recipients: vec![pk_path],
+            sources: vec![
+                parse_source("graph", &format!("glob:{}/*.nq", data_dir.display()))?,
+                parse_source("echo", "command:printf hello")?,
+            ],
+            push: None,
+        };
+
+        let (written, failures) = run_once(&config, &mut rand::rng())?;
+        assert_eq!(failures, 0);
+        assert_eq!(written.len(), 2);
+
+        let mut plaintexts: Vec<Vec<u8>> = written
+            .iter()
+            .map(|path| decrypt_bytes(&secret_key, &fs::read(path)?))
+            .collect::<Result<_>>()?;
+        plaintexts.sort();
+        assert_eq!(plaintexts, vec![b"<a> <b> <c> .".to_vec(), b"hello".to_vec()]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+//! PEM encoding and decoding of X-Wing keys.
+
+use anyhow::{anyhow, Context, Result};
+use libcrux_kem::{PrivateKey, PublicKey};
+use pem::Pem;
+
+use crate::ALGORITHM;
+
+pub const XWING_SK_LABEL: &str = "XWING SECRET KEY";
+pub const XWING_PK_LABEL: &str = "XWING PUBLIC KEY";
+
+pub fn encode_secret_key(secret_key: &PrivateKey) -> String {
+    pem::encode(&Pem::new(XWING_SK_LABEL, secret_key.encode()))
+}
+
+pub fn encode_public_key(public_key: &PublicKey) -> String {
+    pem::encode(&Pem::new(XWING_PK_LABEL, public_key.encode()))
+}
+
+pub fn decode_secret_key(pem_str: &str) -> Result<PrivateKey> {
+    let sk_pem = pem::parse(pem_str).context("Failed to parse secret key PEM")?;
+
+    if sk_pem.tag() != XWING_SK_LABEL {
+        return Err(anyhow!(
+            "Invalid secret key PEM label: expected '{}', got '{}'",
+            XWING_SK_LABEL,
+            sk_pem.tag()
+        ));
+    }
+
+    PrivateKey::decode(ALGORITHM, sk_pem.contents())
+        .map_err(|e| anyhow!("Failed to decode secret key: {:?}", e))
+}
+
+pub fn decode_public_key(pem_str: &str) -> Result<PublicKey> {
+    let pk_pem = pem::parse(pem_str).context("Failed to parse public key PEM")?;
+
+    if pk_pem.tag() != XWING_PK_LABEL {
+        return Err(anyhow!(
+            "Invalid public key PEM label: expected '{}', got '{}'",
+            XWING_PK_LABEL,
+            pk_pem.tag()
+        ));
+    }
+
+    PublicKey::decode(ALGORITHM, pk_pem.contents())
+        .map_err(|e| anyhow!("Failed to decode public key: {:?}", e))
+}
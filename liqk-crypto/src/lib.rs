@@ -0,0 +1,370 @@
+//! Core X-Wing KEM + ChaCha20Poly1305 encryption.
+//!
+//! This module only depends on the KEM, AEAD and KDF crates so it can be
+//! embedded with `default-features = false`. Key file (PEM) handling and the
+//! command-line interface live behind the `pem` and `cli` features.
+
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Nonce,
+};
+use hkdf::{Hkdf, SimpleHkdf};
+use libcrux_kem::{Algorithm, Ct, PrivateKey, PublicKey, Ss};
+use rand::{CryptoRng, RngCore};
+use sha2::{Sha256, Sha512};
+
+pub use libcrux_kem;
+
+pub const ALGORITHM: Algorithm = Algorithm::XWingKemDraft06;
+pub const NONCE_SIZE: usize = 12;
+/// HKDF info of the single key of format 1 files
+pub const HKDF_INFO: &[u8] = b"liqk-crypto-chacha20poly1305";
+
+/// HKDF info label of the format 2 content key
+pub const CONTENT_KEY_INFO: &[u8] = b"liqk-crypto v2 content encryption";
+
+/// Algorithm names as reported by `liqk-crypto --version --json`
+pub const KEM_NAME: &str = "X-Wing-draft-06";
+pub const AEAD_NAME: &str = "ChaCha20Poly1305";
+
+/// Version of the encrypted file format written by [`encrypt_bytes`].
+/// Format 1 is the unversioned `nonce || kem_ciphertext || ciphertext`
+/// layout; format 2 prefixes it with a header naming the format and KDF.
+pub const FORMAT_VERSION: u32 = 2;
+/// File format versions [`decrypt_bytes`] accepts
+pub const READABLE_FORMAT_VERSIONS: &[u32] = &[1, 2];
+
+/// Start of a format 2 file
+pub const MAGIC: &[u8; 4] = b"LQKC";
+/// Format 2 header: magic, format version byte, KDF id byte
+pub const HEADER_SIZE: usize = MAGIC.len() + 2;
+
+// X-Wing KEM ciphertext size: ML-KEM 768 (1088 bytes) + X25519 (32 bytes)
+pub const XWING_CT_SIZE: usize = 1120;
+pub const XWING_SEED_SIZE: usize = 32;
+
+/// Hash function of the HKDF key schedule, recorded in the file header
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Kdf {
+    #[default]
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl Kdf {
+    pub const ALL: [Kdf; 3] = [Kdf::Sha256, Kdf::Sha512, Kdf::Blake3];
+
+    /// Header byte identifying the KDF
+    pub fn id(self) -> u8 {
+        match self {
+            Kdf::Sha256 => 1,
+            Kdf::Sha512 => 2,
+            Kdf::Blake3 => 3,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|kdf| kdf.id() == id)
+    }
+
+    /// Name as reported by `liqk-crypto --version --json`
+    pub fn name(self) -> &'static str {
+        match self {
+            Kdf::Sha256 => "HKDF-SHA256",
+            Kdf::Sha512 => "HKDF-SHA512",
+            Kdf::Blake3 => "HKDF-BLAKE3",
+        }
+    }
+
+    /// Parse a command-line name: `sha256`, `sha512` or `blake3`
+    pub fn from_arg(arg: &str) -> Option<Self> {
+        match arg.to_ascii_lowercase().as_str() {
+            "sha256" => Some(Kdf::Sha256),
+            "sha512" => Some(Kdf::Sha512),
+            "blake3" => Some(Kdf::Blake3),
+            _ => None,
+        }
+    }
+
+    fn expand(self, salt: &[u8], ikm: &[u8], info: &[u8], out: &mut [u8]) -> Result<()> {
+        let result = match self {
+            Kdf::Sha256 => Hkdf::<Sha256>::new(Some(salt), ikm).expand(info, out),
+            Kdf::Sha512 => Hkdf::<Sha512>::new(Some(salt), ikm).expand(info, out),
+            // BLAKE3 has no block-level core for `Hmac`, so the generic one is used
+            Kdf::Blake3 => SimpleHkdf::<blake3::Hasher>::new(Some(salt), ikm).expand(info, out),
+        };
+        result.map_err(|e| anyhow!("HKDF expand failed: {:?}", e))
+    }
+}
+
+/// Keys derived from one KEM shared secret. The content key has its own
+/// info label, so a key added by a later format never matches it.
+pub struct KeySchedule {
+    /// Encrypts the file content
+    pub content_key: [u8; 32],
+}
+
+impl KeySchedule {
+    /// Derive the keys with `kdf`. The file header is the HKDF salt, so
+    /// keys derived under a different format or KDF never match.
+    pub fn derive(kdf: Kdf, shared_secret: &[u8], header: &[u8]) -> Result<Self> {
+        let mut schedule = Self { content_key: [0u8; 32] };
+        kdf.expand(header, shared_secret, CONTENT_KEY_INFO, &mut schedule.content_key)?;
+        Ok(schedule)
+    }
+}
+
+#[cfg(feature = "pem")]
+pub mod keys;
+
+/// Generate a new key pair from the given RNG
+pub fn generate_keypair(rng: &mut (impl RngCore + CryptoRng)) -> Result<(PrivateKey, PublicKey)> {
+    libcrux_kem::key_gen(ALGORITHM, rng).map_err(|e| anyhow!("Key generation failed: {:?}", e))
+}
+
+/// Deterministically derive a key pair from a 32-byte seed
+pub fn generate_keypair_from_seed(seed: &[u8; XWING_SEED_SIZE]) -> Result<(PrivateKey, PublicKey)> {
+    libcrux_kem::key_gen_derand(ALGORITHM, seed)
+        .map_err(|e| anyhow!("Key generation failed: {:?}", e))
+}
+
+/// Encrypt `plaintext` to `public_key` with the default KDF
+pub fn encrypt_bytes(
+    public_key: &PublicKey,
+    plaintext: &[u8],
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<Vec<u8>> {
+    encrypt_bytes_with(public_key, plaintext, Kdf::default(), rng)
+}
+
+/// Encrypt `plaintext` to `public_key`, deriving keys with `kdf`.
+///
+/// Output format: header || nonce || kem_ciphertext || symmetric_ciphertext,
+/// with the header also authenticated as associated data
+pub fn encrypt_bytes_with(
+    public_key: &PublicKey,
+    plaintext: &[u8],
+    kdf: Kdf,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<Vec<u8>> {
+    let (shared_secret, ciphertext_kem) = public_key
+        .encapsulate(rng)
+        .map_err(|e| anyhow!("Encapsulation failed: {:?}", e))?;
+
+    let header = header(kdf);
+    let keys = KeySchedule::derive(kdf, &shared_secret.encode(), &header)?;
+
+    let mut nonce_bytes = [0u8; NONCE_SIZE];
+    rng.fill_bytes(&mut nonce_bytes);
+    let nonce = Nonce::from_slice(&nonce_bytes);
+
+    let cipher = ChaCha20Poly1305::new_from_slice(&keys.content_key)
+        .map_err(|e| anyhow!("Failed to create cipher: {:?}", e))?;
+
+    let ciphertext = cipher
+        .encrypt(nonce, Payload { msg: plaintext, aad: &header })
+        .map_err(|e| anyhow!("Encryption failed: {:?}", e))?;
+
+    let kem_ct_bytes = ciphertext_kem.encode();
+    let mut output = Vec::with_capacity(HEADER_SIZE + NONCE_SIZE + kem_ct_bytes.len() + ciphertext.len());
+    output.extend_from_slice(&header);
+    output.extend_from_slice(&nonce_bytes);
+    output.extend_from_slice(&kem_ct_bytes);
+    output.extend_from_slice(&ciphertext);
+
+    Ok(output)
+}
+
+/// Decrypt data produced by [`encrypt_bytes`] (any readable format) with
+/// `secret_key`
+pub fn decrypt_bytes(secret_key: &PrivateKey, encrypted: &[u8]) -> Result<Vec<u8>> {
+    if !encrypted.starts_with(MAGIC) {
+        return decrypt_v1(secret_key, encrypted);
+    }
+    match decrypt_v2(secret_key, encrypted) {
+        Ok(plaintext) => Ok(plaintext),
+        // The random nonce of a format 1 file may begin with the magic
+        Err(e) => decrypt_v1(secret_key, encrypted).map_err(|_| e),
+    }
+}
+
+/// Format version and KDF recorded in an encrypted file's header; files
+/// without a header are format 1, which always used HKDF-SHA256
+pub fn file_format(encrypted: &[u8]) -> Result<(u32, Kdf)> {
+    if !encrypted.starts_with(MAGIC) || encrypted.len() < HEADER_SIZE {
+        return Ok((1, Kdf::Sha256));
+    }
+    let version = encrypted[MAGIC.len()] as u32;
+    if version != FORMAT_VERSION {
+        bail!("Unsupported file format version {}", version);
+    }
+    let id = encrypted[MAGIC.len() + 1];
+    let kdf = Kdf::from_id(id).ok_or_else(|| anyhow!("Unknown KDF id {} in file header", id))?;
+    Ok((version, kdf))
+}
+
+fn header(kdf: Kdf) -> [u8; HEADER_SIZE] {
+    let mut header = [0u8; HEADER_SIZE];
+    header[..MAGIC.len()].copy_from_slice(MAGIC);
+    header[MAGIC.len()] = FORMAT_VERSION as u8;
+    header[MAGIC.len() + 1] = kdf.id();
+    header
+}
+
+/// Split `nonce || kem_ciphertext || ciphertext` and decapsulate
+fn open_envelope<'a>(secret_key: &PrivateKey, body: &'a [u8]) -> Result<(&'a [u8], Ss, &'a [u8])> {
+    let min_size = NONCE_SIZE + XWING_CT_SIZE;
+
+    if body.len() < min_size {
+        return Err(anyhow!(
+            "Encrypted file too small: expected at least {} bytes, got {}",
+            min_size,
+            body.len()
+        ));
+    }
+
+    let nonce_bytes = &body[..NONCE_SIZE];
+    let kem_ct_bytes = &body[NONCE_SIZE..NONCE_SIZE + XWING_CT_SIZE];
+    let ciphertext = &body[NONCE_SIZE + XWING_CT_SIZE..];
+
+    let kem_ciphertext = Ct::decode(ALGORITHM, kem_ct_bytes)
+        .map_err(|e| anyhow!("Failed to decode KEM ciphertext: {:?}", e))?;
+
+    let shared_secret = kem_ciphertext
+        .decapsulate(secret_key)
+        .map_err(|e| anyhow!("Decapsulation failed: {:?}", e))?;
+
+    Ok((nonce_bytes, shared_secret, ciphertext))
+}
+
+fn decrypt_v2(secret_key: &PrivateKey, encrypted: &[u8]) -> Result<Vec<u8>> {
+    if encrypted.len() < HEADER_SIZE {
+        bail!("Encrypted file too small: truncated header");
+    }
+    let (_, kdf) = file_format(encrypted)?;
+    let (header, body) = encrypted.split_at(HEADER_SIZE);
+    let (nonce_bytes, shared_secret, ciphertext) = open_envelope(secret_key, body)?;
+
+    let keys = KeySchedule::derive(kdf, &shared_secret.encode(), header)?;
+
+    let nonce = Nonce::from_slice(nonce_bytes);
+    let cipher = ChaCha20Poly1305::new_from_slice(&keys.content_key)
+        .map_err(|e| anyhow!("Failed to create cipher: {:?}", e))?;
+
+    cipher
+        .decrypt(nonce, Payload { msg: ciphertext, aad: header })
+        .map_err(|e| anyhow!("Decryption failed: {:?}", e))
+}
+
+fn decrypt_v1(secret_key: &PrivateKey, encrypted: &[u8]) -> Result<Vec<u8>> {
+    let (nonce_bytes, shared_secret, ciphertext) = open_envelope(secret_key, encrypted)?;
+
+    let symmetric_key = derive_key_v1(&shared_secret)?;
+
+    let nonce = Nonce::from_slice(nonce_bytes);
+    let cipher = ChaCha20Poly1305::new_from_slice(&symmetric_key)
+        .map_err(|e| anyhow!("Failed to create cipher: {:?}", e))?;
+
+    cipher
+        .decrypt(nonce, ciphertext)
+        .map_err(|e| anyhow!("Decryption failed: {:?}", e))
+}
+
+/// The single format 1 key: HKDF-SHA256 without salt
+fn derive_key_v1(shared_secret: &Ss) -> Result<[u8; 32]> {
+    let ss_bytes: Vec<u8> = shared_secret.encode();
+    let hkdf = Hkdf::<Sha256>::new(None, &ss_bytes);
+    let mut key = [0u8; 32];
+    hkdf.expand(HKDF_INFO, &mut key)
+        .map_err(|e| anyhow!("HKDF expand failed: {:?}", e))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bytes_roundtrip() -> Result<()> {
+        let mut rng = rand::rng();
+        let (secret_key, public_key) = generate_keypair(&mut rng)?;
+
+        let plaintext = b"core roundtrip without key files";
+        let encrypted = encrypt_bytes(&public_key, plaintext, &mut rng)?;
+        let decrypted = decrypt_bytes(&secret_key, &encrypted)?;
+
+        assert_eq!(decrypted, plaintext);
+        Ok(())
+    }
+
+    #[test]
+    fn test_seeded_keypair_is_deterministic() -> Result<()> {
+        let seed = [7u8; XWING_SEED_SIZE];
+        let (_, pk1) = generate_keypair_from_seed(&seed)?;
+        let (_, pk2) = generate_keypair_from_seed(&seed)?;
+
+        assert_eq!(pk1.encode(), pk2.encode());
+        Ok(())
+    }
+
+    #[test]
+    fn test_roundtrip_with_every_kdf() -> Result<()> {
+        let mut rng = rand::rng();
+        let (secret_key, public_key) = generate_keypair(&mut rng)?;
+
+        for kdf in Kdf::ALL {
+            let encrypted = encrypt_bytes_with(&public_key, b"any kdf", kdf, &mut rng)?;
+            assert_eq!(file_format(&encrypted)?, (FORMAT_VERSION, kdf));
+            assert_eq!(decrypt_bytes(&secret_key, &encrypted)?, b"any kdf");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_depends_on_header() -> Result<()> {
+        let ss = [9u8; 32];
+        let keys = KeySchedule::derive(Kdf::Sha256, &ss, &header(Kdf::Sha256))?;
+
+        // The KDF recorded in the header is part of the derivation
+        let other = KeySchedule::derive(Kdf::Sha512, &ss, &header(Kdf::Sha512))?;
+        assert_ne!(keys.content_key, other.content_key);
+        Ok(())
+    }
+
+    #[test]
+    fn test_changed_kdf_id_is_rejected() -> Result<()> {
+        let mut rng = rand::rng();
+        let (secret_key, public_key) = generate_keypair(&mut rng)?;
+
+        let mut encrypted = encrypt_bytes_with(&public_key, b"pinned kdf", Kdf::Sha512, &mut rng)?;
+        encrypted[MAGIC.len() + 1] = Kdf::Sha256.id();
+        assert!(decrypt_bytes(&secret_key, &encrypted).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_1_still_decrypts() -> Result<()> {
+        let mut rng = rand::rng();
+        let (secret_key, public_key) = generate_keypair(&mut rng)?;
+
+        // Format 1 as written before the header existed
+        let (shared_secret, ciphertext_kem) = public_key
+            .encapsulate(&mut rng)
+            .map_err(|e| anyhow!("Encapsulation failed: {:?}", e))?;
+        let key = derive_key_v1(&shared_secret)?;
+        let nonce_bytes = [3u8; NONCE_SIZE];
+        let ciphertext = ChaCha20Poly1305::new_from_slice(&key)
+            .map_err(|e| anyhow!("{:?}", e))?
+            .encrypt(Nonce::from_slice(&nonce_bytes), &b"old file"[..])
+            .map_err(|e| anyhow!("{:?}", e))?;
+        let mut encrypted = nonce_bytes.to_vec();
+        encrypted.extend_from_slice(&ciphertext_kem.encode());
+        encrypted.extend_from_slice(&ciphertext);
+
+        assert_eq!(file_format(&encrypted)?, (1, Kdf::Sha256));
+        assert_eq!(decrypt_bytes(&secret_key, &encrypted)?, b"old file");
+        Ok(())
+    }
+}
@@ -1,33 +1,51 @@
-use anyhow::{anyhow, Context, Result};
-use chacha20poly1305::{
-    aead::{Aead, KeyInit},
-    ChaCha20Poly1305, Nonce,
-};
+use anyhow::{anyhow, bail, Context, Result};
 use clap::{Parser, Subcommand};
-use hkdf::Hkdf;
-use libcrux_kem::{Algorithm, Ct, PrivateKey, PublicKey, Ss};
-use pem::Pem;
-use rand::RngCore;
-use sha2::Sha256;
+use liqk_crypto::keys::{
+    decode_public_key, decode_secret_key, encode_public_key, encode_secret_key,
+};
+use liqk_crypto::{
+    decrypt_bytes, encrypt_bytes_with, file_format, generate_keypair, generate_keypair_from_seed, Kdf,
+    XWING_SEED_SIZE,
+};
+use rand::CryptoRng;
 use std::fs;
 use std::io::{self, BufRead, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tempfile::NamedTempFile;
 
-const XWING_SK_LABEL: &str = "XWING SECRET KEY";
-const XWING_PK_LABEL: &str = "XWING PUBLIC KEY";
-const NONCE_SIZE: usize = 12;
-const HKDF_INFO: &[u8] = b"liqk-crypto-chacha20poly1305";
+mod conf;
+mod daemon;
+mod remote;
+mod version;
 
-// X-Wing KEM ciphertext size: ML-KEM 768 (1088 bytes) + X25519 (32 bytes)
-const XWING_CT_SIZE: usize = 1120;
-const XWING_SEED_SIZE: usize = 32;
+use remote::Location;
 
 #[derive(Parser)]
 #[command(name = "liqk-crypto")]
 #[command(about = "File encryption using ChaCha20Poly1305 and X-Wing KEM")]
+#[command(disable_version_flag = true, arg_required_else_help = true)]
 struct Cli {
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
+    /// Print version information
+    #[arg(short = 'V', long)]
+    version: bool,
+    /// With --version: print a JSON report of supported algorithms, file
+    /// formats, compiled features and build provenance
+    #[arg(long, requires = "version")]
+    json: bool,
+    /// Fail instead of warning when an output file would be replaced or a
+    /// secret key file is readable by other users
+    #[arg(long, global = true, env = "LIQK_CRYPTO_STRICT")]
+    strict: bool,
+    /// TEST ONLY: derive all randomness (key generation, encapsulation,
+    /// nonces) from this seed so outputs are byte-for-byte reproducible.
+    /// Anything produced with it is insecure. Only compiled into debug
+    /// builds or with the `deterministic-rng` feature.
+    #[cfg(any(debug_assertions, feature = "deterministic-rng"))]
+    #[arg(long, global = true, hide = true, value_name = "SEED")]
+    deterministic_rng: Option<u64>,
 }
 
 #[derive(Subcommand)]
@@ -49,53 +67,161 @@ enum Commands {
         /// Path to the public key
         #[arg(long)]
         pk: PathBuf,
-        /// Path to the input file
-        #[arg(long)]
-        input: PathBuf,
-        /// Path to the output encrypted file
-        #[arg(long)]
-        output: PathBuf,
+        /// Input file: a path, an http(s):// URL or s3://bucket/key
+        #[arg(long, value_parser = Location::parse)]
+        input: Location,
+        /// Output encrypted file: a path, an http(s):// URL (PUT) or
+        /// s3://bucket/key
+        #[arg(long, value_parser = Location::parse)]
+        output: Location,
+        /// Hash of the key derivation: sha256, sha512 or blake3 (recorded
+        /// in the file header, so decryption needs no flag)
+        #[arg(long, default_value = "sha256", value_parser = parse_kdf)]
+        kdf: Kdf,
     },
     /// Decrypt a file with a secret key
     Decrypt {
         /// Path to the secret key
         #[arg(long)]
         sk: PathBuf,
-        /// Path to the input encrypted file
+        /// Input encrypted file: a path, an http(s):// URL or s3://bucket/key
+        #[arg(long, value_parser = Location::parse)]
+        input: Location,
+        /// Output decrypted file: a path, an http(s):// URL (PUT) or
+        /// s3://bucket/key; a path with --exec
+        #[arg(long, required_unless_present = "stdin", value_parser = Location::parse)]
+        output: Option<Location>,
+        /// Run this shell command after decrypting; `{file}` is replaced
+        /// with the quoted plaintext path (also in `$LIQK_PLAINTEXT`)
+        #[arg(long, value_name = "CMD")]
+        exec: Option<String>,
+        /// Pipe the plaintext to the command's stdin instead of writing a file
+        #[arg(long, requires = "exec", conflicts_with = "output")]
+        stdin: bool,
+        /// Overwrite and delete the plaintext file once the command exits
+        #[arg(long, requires = "exec", requires = "output")]
+        delete: bool,
+    },
+    /// Encrypt or decrypt single configuration values
+    Conf {
+        #[command(subcommand)]
+        command: ConfCommands,
+    },
+    /// Periodically encrypt backup sources to recipients
+    Daemon {
+        /// Path to the daemon configuration file
         #[arg(long)]
-        input: PathBuf,
-        /// Path to the output decrypted file
+        config: PathBuf,
+        /// Run a single backup and exit
         #[arg(long)]
-        output: PathBuf,
+        once: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfCommands {
+    /// Encrypt a value to a public key and print it as `enc:...`
+    Encrypt {
+        /// Path to the public key
+        #[arg(long)]
+        pk: PathBuf,
+        /// Hash of the key derivation: sha256, sha512 or blake3
+        #[arg(long, default_value = "sha256", value_parser = parse_kdf)]
+        kdf: Kdf,
+        /// The value; read from stdin when omitted
+        value: Option<String>,
+    },
+    /// Decrypt an `enc:...` value with a secret key and print it
+    Decrypt {
+        /// Path to the secret key
+        #[arg(long)]
+        sk: PathBuf,
+        /// The value; read from stdin when omitted
+        value: Option<String>,
     },
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
-    match cli.command {
-        Commands::Keygen { sk, pk, seed } => keygen(&sk, &pk, seed),
-        Commands::Encrypt { pk, input, output } => encrypt(&pk, &input, &output),
-        Commands::Decrypt { sk, input, output } => decrypt(&sk, &input, &output),
+    if cli.version {
+        println!("{}", if cli.json { version::json() } else { version::text() });
+        return Ok(());
+    }
+    let Some(command) = cli.command else {
+        bail!("no command given (see --help)");
+    };
+
+    #[cfg(any(debug_assertions, feature = "deterministic-rng"))]
+    let mut rng = cli_rng(cli.deterministic_rng);
+    #[cfg(not(any(debug_assertions, feature = "deterministic-rng")))]
+    let mut rng = cli_rng(None);
+
+    let strict = cli.strict;
+    match command {
+        Commands::Keygen { sk, pk, seed } => {
+            check_output_unused(&sk, strict)?;
+            check_output_unused(&pk, strict)?;
+            keygen(&sk, &pk, seed, &mut *rng)
+        }
+        Commands::Encrypt { pk, input, output, kdf } => {
+            if let Some(path) = output.local_path() {
+                check_output_unused(path, strict)?;
+            }
+            encrypt(&pk, &input, &output, kdf, &mut *rng)
+        }
+        Commands::Decrypt { sk, input, output, exec, stdin, delete } => {
+            check_secret_key_permissions(&sk, strict)?;
+            if let Some(path) = output.as_ref().and_then(Location::local_path) {
+                check_output_unused(path, strict)?;
+            }
+            match exec {
+                Some(command) => {
+                    let output_path = match &output {
+                        Some(output) => Some(output.local_path().context("--exec needs a local --output")?),
+                        None => None,
+                    };
+                    decrypt_exec(&sk, &input, output_path, &command, stdin, delete, strict)
+                }
+                None => decrypt(&sk, &input, &output.context("--output is required")?, strict),
+            }
+        }
+        Commands::Conf { command: ConfCommands::Encrypt { pk, kdf, value } } => {
+            let public_key = decode_public_key(&fs::read_to_string(&pk).context("Failed to read public key")?)?;
+            println!("{}", conf::encrypt_value(&public_key, &conf::value_arg(value)?, kdf, &mut *rng)?);
+            Ok(())
+        }
+        Commands::Conf { command: ConfCommands::Decrypt { sk, value } } => {
+            check_secret_key_permissions(&sk, strict)?;
+            let secret_key = decode_secret_key(&fs::read_to_string(&sk).context("Failed to read secret key")?)?;
+            println!("{}", conf::decrypt_value(&secret_key, &conf::value_arg(value)?)?);
+            Ok(())
+        }
+        Commands::Daemon { config, once } => daemon::run(&config, once, &mut *rng),
+    }
+}
+
+/// The system RNG, or a seeded ChaCha20 stream for `--deterministic-rng`
+fn cli_rng(deterministic_seed: Option<u64>) -> Box<dyn CryptoRng> {
+    match deterministic_seed {
+        Some(seed) => {
+            eprintln!("WARNING: --deterministic-rng is for testing only; outputs are NOT secure");
+            Box::new(<rand_chacha::ChaCha20Rng as rand::SeedableRng>::seed_from_u64(seed))
+        }
+        None => Box::new(rand::rng()),
     }
 }
 
-fn keygen(sk_path: &PathBuf, pk_path: &PathBuf, manual_seed: bool) -> Result<()> {
+fn keygen(sk_path: &PathBuf, pk_path: &PathBuf, manual_seed: bool, mut rng: &mut dyn CryptoRng) -> Result<()> {
     let (secret_key, public_key) = if manual_seed {
         let seed = read_seed_from_terminal()?;
-        libcrux_kem::key_gen_derand(Algorithm::XWingKemDraft06, &seed)
-            .map_err(|e| anyhow!("Key generation failed: {:?}", e))?
+        generate_keypair_from_seed(&seed)?
     } else {
-        let mut rng = rand::rng();
-        libcrux_kem::key_gen(Algorithm::XWingKemDraft06, &mut rng)
-            .map_err(|e| anyhow!("Key generation failed: {:?}", e))?
+        generate_keypair(&mut rng)?
     };
 
-    let sk_pem = Pem::new(XWING_SK_LABEL, secret_key.encode());
-    let pk_pem = Pem::new(XWING_PK_LABEL, public_key.encode());
-
-    fs::write(sk_path, pem::encode(&sk_pem)).context("Failed to write secret key")?;
-    fs::write(pk_path, pem::encode(&pk_pem)).context("Failed to write public key")?;
+    write_atomic(sk_path, encode_secret_key(&secret_key).as_bytes()).context("Failed to write secret key")?;
+    write_atomic(pk_path, encode_public_key(&public_key).as_bytes()).context("Failed to write public key")?;
 
     println!("Key pair generated successfully");
     println!("  Secret key: {}", sk_path.display());
@@ -104,6 +230,75 @@ fn keygen(sk_path: &PathBuf, pk_path: &PathBuf, manual_seed: bool) -> Result<()>
     Ok(())
 }
 
+/// Report a risky condition: a warning on stderr, or an error with `--strict`
+fn caution(strict: bool, message: String) -> Result<()> {
+    if strict {
+        bail!("{} (refusing because of --strict)", message);
+    }
+    eprintln!("WARNING: {}", message);
+    Ok(())
+}
+
+/// Outputs are replaced atomically, but replacing one is rarely intended
+/// (e.g. a second encryption over an earlier ciphertext or secret key)
+fn check_output_unused(path: &Path, strict: bool) -> Result<()> {
+    if path.exists() {
+        caution(strict, format!("{} already exists and will be replaced", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Secret keys are written with mode 0600; a key other users can read
+/// has probably been copied carelessly
+#[cfg(unix)]
+fn check_secret_key_permissions(path: &Path, strict: bool) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path).context("Failed to read secret key")?.permissions().mode();
+    if mode & 0o077 != 0 {
+        caution(
+            strict,
+            format!(
+                "secret key {} is accessible by other users (mode {:o}); run chmod 600 {}",
+                path.display(),
+                mode & 0o777,
+                path.display()
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_secret_key_permissions(_path: &Path, _strict: bool) -> Result<()> {
+    Ok(())
+}
+
+/// Write `data` to `path` so that the path either keeps its old content or
+/// holds the complete new content. Data goes to a temp file (mode 0600 on
+/// Unix) in the same directory, is fsynced, then renamed over `path`.
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let mut temp = NamedTempFile::new_in(dir)
+        .with_context(|| format!("Failed to create temporary file in {}", dir.display()))?;
+    temp.write_all(data).context("Failed to write temporary file")?;
+    temp.as_file().sync_all().context("Failed to sync temporary file")?;
+    temp.persist(path)
+        .map_err(|e| anyhow!("Failed to move output into place: {}", e.error))?;
+
+    // Persist the rename itself
+    #[cfg(unix)]
+    fs::File::open(dir)
+        .and_then(|d| d.sync_all())
+        .context("Failed to sync output directory")?;
+
+    Ok(())
+}
+
 fn read_seed_from_terminal() -> Result<[u8; XWING_SEED_SIZE]> {
     print!("Enter seed (64 hex characters): ");
     io::stdout().flush().context("Failed to flush stdout")?;
@@ -131,129 +326,151 @@ fn read_seed_from_terminal() -> Result<[u8; XWING_SEED_SIZE]> {
         .map_err(|_| anyhow!("Failed to convert seed to fixed-size array"))
 }
 
-fn encrypt(pk_path: &PathBuf, input_path: &PathBuf, output_path: &PathBuf) -> Result<()> {
-    let pk_pem_str = fs::read_to_string(pk_path).context("Failed to read public key")?;
-    let pk_pem = pem::parse(&pk_pem_str).context("Failed to parse public key PEM")?;
-
-    if pk_pem.tag() != XWING_PK_LABEL {
-        return Err(anyhow!(
-            "Invalid public key PEM label: expected '{}', got '{}'",
-            XWING_PK_LABEL,
-            pk_pem.tag()
-        ));
-    }
-
-    let public_key = PublicKey::decode(Algorithm::XWingKemDraft06, pk_pem.contents())
-        .map_err(|e| anyhow!("Failed to decode public key: {:?}", e))?;
-
-    let plaintext = fs::read(input_path).context("Failed to read input file")?;
-
-    let mut rng = rand::rng();
-
-    let (shared_secret, ciphertext_kem) = public_key
-        .encapsulate(&mut rng)
-        .map_err(|e| anyhow!("Encapsulation failed: {:?}", e))?;
-
-    let symmetric_key = derive_key(&shared_secret)?;
-
-    let mut nonce_bytes = [0u8; NONCE_SIZE];
-    rng.fill_bytes(&mut nonce_bytes);
-    let nonce = Nonce::from_slice(&nonce_bytes);
+fn parse_kdf(arg: &str) -> Result<Kdf, String> {
+    Kdf::from_arg(arg).ok_or_else(|| format!("unknown KDF '{}' (expected sha256, sha512 or blake3)", arg))
+}
 
-    let cipher = ChaCha20Poly1305::new_from_slice(&symmetric_key)
-        .map_err(|e| anyhow!("Failed to create cipher: {:?}", e))?;
+fn encrypt(
+    pk_path: &PathBuf,
+    input: &Location,
+    output: &Location,
+    kdf: Kdf,
+    mut rng: &mut dyn CryptoRng,
+) -> Result<()> {
+    let pk_pem_str = fs::read_to_string(pk_path).context("Failed to read public key")?;
+    let public_key = decode_public_key(&pk_pem_str)?;
 
-    let ciphertext = cipher
-        .encrypt(nonce, plaintext.as_ref())
-        .map_err(|e| anyhow!("Encryption failed: {:?}", e))?;
+    let plaintext = remote::read(input).context("Failed to read input file")?;
 
-    // Output format: nonce || kem_ciphertext || symmetric_ciphertext
-    let kem_ct_bytes = ciphertext_kem.encode();
-    let mut output = Vec::with_capacity(NONCE_SIZE + kem_ct_bytes.len() + ciphertext.len());
-    output.extend_from_slice(&nonce_bytes);
-    output.extend_from_slice(&kem_ct_bytes);
-    output.extend_from_slice(&ciphertext);
+    let encrypted = encrypt_bytes_with(&public_key, &plaintext, kdf, &mut rng)?;
 
-    fs::write(output_path, &output).context("Failed to write encrypted file")?;
+    remote::write(output, &encrypted).context("Failed to write encrypted file")?;
 
     println!("File encrypted successfully");
-    println!("  Input: {}", input_path.display());
-    println!("  Output: {}", output_path.display());
+    println!("  Input: {}", input);
+    println!("  Output: {}", output);
 
     Ok(())
 }
 
-fn decrypt(sk_path: &PathBuf, input_path: &PathBuf, output_path: &PathBuf) -> Result<()> {
+fn decrypt_input(sk_path: &PathBuf, input: &Location, strict: bool) -> Result<Vec<u8>> {
     let sk_pem_str = fs::read_to_string(sk_path).context("Failed to read secret key")?;
-    let sk_pem = pem::parse(&sk_pem_str).context("Failed to parse secret key PEM")?;
-
-    if sk_pem.tag() != XWING_SK_LABEL {
-        return Err(anyhow!(
-            "Invalid secret key PEM label: expected '{}', got '{}'",
-            XWING_SK_LABEL,
-            sk_pem.tag()
-        ));
-    }
-
-    let secret_key = PrivateKey::decode(Algorithm::XWingKemDraft06, sk_pem.contents())
-        .map_err(|e| anyhow!("Failed to decode secret key: {:?}", e))?;
-
-    let encrypted = fs::read(input_path).context("Failed to read encrypted file")?;
+    let secret_key = decode_secret_key(&sk_pem_str)?;
 
-    let min_size = NONCE_SIZE + XWING_CT_SIZE;
+    let encrypted = remote::read(input).context("Failed to read encrypted file")?;
 
-    if encrypted.len() < min_size {
-        return Err(anyhow!(
-            "Encrypted file too small: expected at least {} bytes, got {}",
-            min_size,
-            encrypted.len()
-        ));
+    // Format 1 has no header, so nothing binds its key to a KDF or version
+    if let Ok((1, _)) = file_format(&encrypted) {
+        caution(strict, format!("{} is in format 1, written by an older version; re-encrypt it", input))?;
     }
 
-    let nonce_bytes = &encrypted[..NONCE_SIZE];
-    let kem_ct_bytes = &encrypted[NONCE_SIZE..NONCE_SIZE + XWING_CT_SIZE];
-    let ciphertext = &encrypted[NONCE_SIZE + XWING_CT_SIZE..];
+    decrypt_bytes(&secret_key, &encrypted)
+}
+
+fn decrypt(sk_path: &PathBuf, input: &Location, output: &Location, strict: bool) -> Result<()> {
+    let plaintext = decrypt_input(sk_path, input, strict)?;
 
-    let kem_ciphertext = Ct::decode(Algorithm::XWingKemDraft06, kem_ct_bytes)
-        .map_err(|e| anyhow!("Failed to decode KEM ciphertext: {:?}", e))?;
+    remote::write(output, &plaintext).context("Failed to write decrypted file")?;
 
-    let shared_secret = kem_ciphertext
-        .decapsulate(&secret_key)
-        .map_err(|e| anyhow!("Decapsulation failed: {:?}", e))?;
+    println!("File decrypted successfully");
+    println!("  Input: {}", input);
+    println!("  Output: {}", output);
 
-    let symmetric_key = derive_key(&shared_secret)?;
+    Ok(())
+}
 
-    let nonce = Nonce::from_slice(nonce_bytes);
-    let cipher = ChaCha20Poly1305::new_from_slice(&symmetric_key)
-        .map_err(|e| anyhow!("Failed to create cipher: {:?}", e))?;
+/// Decrypt and hand the plaintext to `command`, either as a file at
+/// `output_path` or on its stdin. With `delete`, the plaintext file is
+/// overwritten and removed after the command exits, whether or not it
+/// succeeded.
+fn decrypt_exec(
+    sk_path: &PathBuf,
+    input: &Location,
+    output_path: Option<&Path>,
+    command: &str,
+    stdin: bool,
+    delete: bool,
+    strict: bool,
+) -> Result<()> {
+    let plaintext = decrypt_input(sk_path, input, strict)?;
+
+    let status = if stdin {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("Failed to run command")?;
+        let mut pipe = child.stdin.take().context("Failed to open command stdin")?;
+        // A command that stops reading early closes the pipe; that is its call
+        match pipe.write_all(&plaintext) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                let _ = child.kill();
+                return Err(e).context("Failed to write to command stdin");
+            }
+            _ => {}
+        }
+        drop(pipe);
+        child.wait().context("Failed to wait for command")?
+    } else {
+        let output_path = output_path.context("--output is required unless --stdin is used")?;
+        write_atomic(output_path, &plaintext).context("Failed to write decrypted file")?;
+
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(command.replace("{file}", &shell_quote(output_path)))
+            .env("LIQK_PLAINTEXT", output_path)
+            .status();
+
+        if delete {
+            destroy_file(output_path, plaintext.len())
+                .with_context(|| format!("Failed to delete plaintext {}", output_path.display()))?;
+        }
+        status.context("Failed to run command")?
+    };
 
-    let plaintext = cipher
-        .decrypt(nonce, ciphertext)
-        .map_err(|e| anyhow!("Decryption failed: {:?}", e))?;
+    if !status.success() {
+        bail!("Command failed ({})", status);
+    }
 
-    fs::write(output_path, &plaintext).context("Failed to write decrypted file")?;
+    println!("File decrypted and processed successfully");
+    println!("  Input: {}", input);
+    match output_path {
+        Some(path) if delete => println!("  Plaintext deleted: {}", path.display()),
+        Some(path) => println!("  Output: {}", path.display()),
+        None => println!("  Output: command stdin"),
+    }
 
-    println!("File decrypted successfully");
-    println!("  Input: {}", input_path.display());
-    println!("  Output: {}", output_path.display());
+    Ok(())
+}
 
+/// Overwrite a file with zeros, sync, then remove it. On copy-on-write or
+/// journaling filesystems and SSDs the old blocks may survive; this only
+/// keeps the plaintext from lingering as a readable file.
+fn destroy_file(path: &Path, len: usize) -> Result<()> {
+    let mut file = fs::OpenOptions::new().write(true).open(path)?;
+    file.write_all(&vec![0u8; len])?;
+    file.sync_all()?;
+    drop(file);
+    fs::remove_file(path)?;
     Ok(())
 }
 
-fn derive_key(shared_secret: &Ss) -> Result<[u8; 32]> {
-    let ss_bytes: Vec<u8> = shared_secret.encode();
-    let hkdf = Hkdf::<Sha256>::new(None, &ss_bytes);
-    let mut key = [0u8; 32];
-    hkdf.expand(HKDF_INFO, &mut key)
-        .map_err(|e| anyhow!("HKDF expand failed: {:?}", e))?;
-    Ok(key)
+/// Quote a path for a POSIX shell
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::RngCore;
     use tempfile::TempDir;
 
+    fn local(path: &Path) -> Location {
+        Location::Local(path.to_path_buf())
+    }
+
     #[test]
     fn test_roundtrip() -> Result<()> {
         let temp_dir = TempDir::new()?;
@@ -264,21 +481,21 @@ mod tests {
         let decrypted_path = temp_dir.path().join("decrypted.txt");
 
         // Generate keys
-        keygen(&sk_path, &pk_path, false)?;
+        keygen(&sk_path, &pk_path, false, &mut rand::rng())?;
 
         // Create test input
         let original_content = b"Hello, World! This is a test message for encryption.";
         fs::write(&input_path, original_content)?;
 
         // Encrypt
-        encrypt(&pk_path, &input_path, &encrypted_path)?;
+        encrypt(&pk_path, &local(&input_path), &local(&encrypted_path), Kdf::default(), &mut rand::rng())?;
 
         // Verify encrypted file is different from original
         let encrypted_content = fs::read(&encrypted_path)?;
         assert_ne!(encrypted_content.as_slice(), original_content);
 
         // Decrypt
-        decrypt(&sk_path, &encrypted_path, &decrypted_path)?;
+        decrypt(&sk_path, &local(&encrypted_path), &local(&decrypted_path))?;
 
         // Verify roundtrip
         let decrypted_content = fs::read(&decrypted_path)?;
@@ -296,13 +513,13 @@ mod tests {
         let encrypted_path = temp_dir.path().join("encrypted.bin");
         let decrypted_path = temp_dir.path().join("decrypted.txt");
 
-        keygen(&sk_path, &pk_path, false)?;
+        keygen(&sk_path, &pk_path, false, &mut rand::rng())?;
 
         let original_content = b"";
         fs::write(&input_path, original_content)?;
 
-        encrypt(&pk_path, &input_path, &encrypted_path)?;
-        decrypt(&sk_path, &encrypted_path, &decrypted_path)?;
+        encrypt(&pk_path, &local(&input_path), &local(&encrypted_path), Kdf::default(), &mut rand::rng())?;
+        decrypt(&sk_path, &local(&encrypted_path), &local(&decrypted_path))?;
 
         let decrypted_content = fs::read(&decrypted_path)?;
         assert_eq!(decrypted_content, original_content);
@@ -319,15 +536,15 @@ mod tests {
         let encrypted_path = temp_dir.path().join("encrypted.bin");
         let decrypted_path = temp_dir.path().join("decrypted.bin");
 
-        keygen(&sk_path, &pk_path, false)?;
+        keygen(&sk_path, &pk_path, false, &mut rand::rng())?;
 
         // Create 1MB of random data
         let mut original_content = vec![0u8; 1024 * 1024];
         rand::rng().fill_bytes(&mut original_content);
         fs::write(&input_path, &original_content)?;
 
-        encrypt(&pk_path, &input_path, &encrypted_path)?;
-        decrypt(&sk_path, &encrypted_path, &decrypted_path)?;
+        encrypt(&pk_path, &local(&input_path), &local(&encrypted_path), Kdf::default(), &mut rand::rng())?;
+        decrypt(&sk_path, &local(&encrypted_path), &local(&decrypted_path))?;
 
         let decrypted_content = fs::read(&decrypted_path)?;
         assert_eq!(decrypted_content, original_content);
@@ -347,17 +564,17 @@ mod tests {
         let decrypted_path = temp_dir.path().join("decrypted.txt");
 
         // Generate two different key pairs
-        keygen(&sk1_path, &pk1_path, false)?;
-        keygen(&sk2_path, &pk2_path, false)?;
+        keygen(&sk1_path, &pk1_path, false, &mut rand::rng())?;
+        keygen(&sk2_path, &pk2_path, false, &mut rand::rng())?;
 
         let original_content = b"Secret message";
         fs::write(&input_path, original_content)?;
 
         // Encrypt with key pair 1
-        encrypt(&pk1_path, &input_path, &encrypted_path)?;
+        encrypt(&pk1_path, &local(&input_path), &local(&encrypted_path), Kdf::default(), &mut rand::rng())?;
 
         // Try to decrypt with key pair 2 - should fail
-        let result = decrypt(&sk2_path, &encrypted_path, &decrypted_path);
+        let result = decrypt(&sk2_path, &local(&encrypted_path), &local(&decrypted_path));
         assert!(result.is_err());
 
         Ok(())
@@ -372,12 +589,12 @@ mod tests {
         let encrypted_path = temp_dir.path().join("encrypted.bin");
         let decrypted_path = temp_dir.path().join("decrypted.txt");
 
-        keygen(&sk_path, &pk_path, false)?;
+        keygen(&sk_path, &pk_path, false, &mut rand::rng())?;
 
         let original_content = b"Secret message";
         fs::write(&input_path, original_content)?;
 
-        encrypt(&pk_path, &input_path, &encrypted_path)?;
+        encrypt(&pk_path, &local(&input_path), &local(&encrypted_path), Kdf::default(), &mut rand::rng())?;
 
         // Tamper with the encrypted file
         let mut encrypted_content = fs::read(&encrypted_path)?;
@@ -387,9 +604,158 @@ mod tests {
         fs::write(&encrypted_path, &encrypted_content)?;
 
         // Decryption should fail due to authentication
-        let result = decrypt(&sk_path, &encrypted_path, &decrypted_path);
+        let result = decrypt(&sk_path, &local(&encrypted_path), &local(&decrypted_path));
         assert!(result.is_err());
 
+        // A failed run must not leave a (partial) output behind
+        assert!(!decrypted_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deterministic_rng_is_reproducible() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_path = temp_dir.path().join("input.txt");
+        fs::write(&input_path, b"Reproducible message")?;
+
+        let run = |name: &str| -> Result<(String, Vec<u8>)> {
+            let sk_path = temp_dir.path().join(format!("{}.sk.pem", name));
+            let pk_path = temp_dir.path().join(format!("{}.pk.pem", name));
+            let encrypted_path = temp_dir.path().join(format!("{}.bin", name));
+            let mut rng = cli_rng(Some(42));
+            keygen(&sk_path, &pk_path, false, &mut *rng)?;
+            encrypt(&pk_path, &local(&input_path), &local(&encrypted_path), Kdf::default(), &mut *rng)?;
+            Ok((fs::read_to_string(&pk_path)?, fs::read(&encrypted_path)?))
+        };
+
+        assert_eq!(run("first")?, run("second")?);
+
+        // A different seed must give different output
+        let mut rng = cli_rng(Some(43));
+        let pk_path = temp_dir.path().join("other.pk.pem");
+        keygen(&temp_dir.path().join("other.sk.pem"), &pk_path, false, &mut *rng)?;
+        assert_ne!(fs::read_to_string(&pk_path)?, run("third")?.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_and_cleans_up() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let output_path = temp_dir.path().join("output.bin");
+
+        fs::write(&output_path, b"old content")?;
+        write_atomic(&output_path, b"new content")?;
+
+        assert_eq!(fs::read(&output_path)?, b"new content");
+
+        // Only the output remains; the temp file was renamed into place
+        let entries = fs::read_dir(temp_dir.path())?.count();
+        assert_eq!(entries, 1);
+
+        Ok(())
+    }
+
+    /// Key pair and an encrypted file in `temp_dir`, returned as (sk, encrypted)
+    fn encrypted_fixture(temp_dir: &TempDir, content: &[u8]) -> Result<(PathBuf, PathBuf)> {
+        let sk_path = temp_dir.path().join("secret.pem");
+        let pk_path = temp_dir.path().join("public.pem");
+        let input_path = temp_dir.path().join("input.txt");
+        let encrypted_path = temp_dir.path().join("encrypted.bin");
+
+        keygen(&sk_path, &pk_path, false, &mut rand::rng())?;
+        fs::write(&input_path, content)?;
+        encrypt(&pk_path, &local(&input_path), &local(&encrypted_path), Kdf::default(), &mut rand::rng())?;
+
+        Ok((sk_path, encrypted_path))
+    }
+
+    #[test]
+    fn test_decrypt_exec_with_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content = b"processed by a hook";
+        let (sk_path, encrypted_path) = encrypted_fixture(&temp_dir, content)?;
+        let decrypted_path = temp_dir.path().join("it's decrypted.txt");
+        let copy_path = temp_dir.path().join("copy.txt");
+
+        let command = format!("cp {{file}} {}", shell_quote(&copy_path));
+        decrypt_exec(&sk_path, &local(&encrypted_path), Some(&decrypted_path), &command, false, false)?;
+
+        assert_eq!(fs::read(&decrypted_path)?, content);
+        assert_eq!(fs::read(&copy_path)?, content);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_exec_stdin() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content = b"piped to the hook";
+        let (sk_path, encrypted_path) = encrypted_fixture(&temp_dir, content)?;
+        let copy_path = temp_dir.path().join("copy.txt");
+
+        let command = format!("cat > {}", shell_quote(&copy_path));
+        decrypt_exec(&sk_path, &local(&encrypted_path), None, &command, true, false)?;
+
+        assert_eq!(fs::read(&copy_path)?, content);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_exec_delete() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content = b"short-lived plaintext";
+        let (sk_path, encrypted_path) = encrypted_fixture(&temp_dir, content)?;
+        let decrypted_path = temp_dir.path().join("decrypted.txt");
+        let copy_path = temp_dir.path().join("copy.txt");
+
+        let command = format!("cp \"$LIQK_PLAINTEXT\" {}", shell_quote(&copy_path));
+        decrypt_exec(&sk_path, &local(&encrypted_path), Some(&decrypted_path), &command, false, true)?;
+
+        assert_eq!(fs::read(&copy_path)?, content);
+        assert!(!decrypted_path.exists());
+
+        // The plaintext is removed even when the command fails
+        let result = decrypt_exec(&sk_path, &local(&encrypted_path), Some(&decrypted_path), "exit 3", false, true);
+        assert!(result.is_err());
+        assert!(!decrypted_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_checks() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let existing = temp_dir.path().join("existing.bin");
+        let missing = temp_dir.path().join("missing.bin");
+        fs::write(&existing, b"earlier output")?;
+
+        check_output_unused(&missing, true)?;
+        check_output_unused(&existing, false)?;
+        assert!(check_output_unused(&existing, true).is_err());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_secret_key_permissions() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new()?;
+        let sk_path = temp_dir.path().join("secret.pem");
+        let pk_path = temp_dir.path().join("public.pem");
+        keygen(&sk_path, &pk_path, false, &mut rand::rng())?;
+
+        // Keys are written 0600
+        check_secret_key_permissions(&sk_path, true)?;
+
+        fs::set_permissions(&sk_path, fs::Permissions::from_mode(0o644))?;
+        check_secret_key_permissions(&sk_path, false)?;
+        assert!(check_secret_key_permissions(&sk_path, true).is_err());
+
         Ok(())
     }
 }
@@ -0,0 +1,139 @@
+//! `--version` output, with a machine-readable capability report for
+//! scripts that must check the tool before relying on a format or algorithm.
+
+use liqk_crypto::keys::{XWING_PK_LABEL, XWING_SK_LABEL};
+use liqk_crypto::{Kdf, AEAD_NAME, FORMAT_VERSION, KEM_NAME, READABLE_FORMAT_VERSIONS};
+
+/// Version of the `--version --json` report itself; bumped when fields are
+/// removed or change meaning (new fields may be added without a bump)
+const REPORT_VERSION: u32 = 1;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Cargo features compiled into this binary
+fn features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "cli") {
+        features.push("cli");
+    }
+    if cfg!(feature = "pem") {
+        features.push("pem");
+    }
+    if cfg!(feature = "remote") {
+        features.push("remote");
+    }
+    if cfg!(feature = "deterministic-rng") {
+        features.push("deterministic-rng");
+    }
+    features
+}
+
+/// Whether the hidden `--deterministic-rng` flag exists in this build
+fn deterministic_rng_available() -> bool {
+    cfg!(any(debug_assertions, feature = "deterministic-rng"))
+}
+
+/// Build-time value recorded by `build.rs`; empty when unknown
+fn build_value(value: &'static str) -> Option<&'static str> {
+    (!value.is_empty()).then_some(value)
+}
+
+/// One-line version, for `--version`
+pub fn text() -> String {
+    match build_value(env!("LIQK_CRYPTO_GIT_COMMIT")) {
+        Some(commit) => format!("liqk-crypto {} ({})", VERSION, &commit[..commit.len().min(12)]),
+        None => format!("liqk-crypto {}", VERSION),
+    }
+}
+
+/// Capability report, for `--version --json`
+pub fn json() -> String {
+    let strings = |values: &[&str]| -> String {
+        let quoted: Vec<String> = values.iter().map(|v| json_string(v)).collect();
+        format!("[{}]", quoted.join(","))
+    };
+    let numbers = |values: &[u32]| -> String {
+        let values: Vec<String> = values.iter().map(u32::to_string).collect();
+        format!("[{}]", values.join(","))
+    };
+    let optional = |value: Option<&str>| value.map(json_string).unwrap_or_else(|| "null".to_string());
+
+    let fields = [
+        ("report_version", REPORT_VERSION.to_string()),
+        ("name", json_string("liqk-crypto")),
+        ("version", json_string(VERSION)),
+        ("kems", strings(&[KEM_NAME])),
+        ("aeads", strings(&[AEAD_NAME])),
+        ("kdfs", strings(&Kdf::ALL.map(Kdf::name))),
+        (
+            "formats",
+            format!(
+                "{{\"write\":{},\"read\":{}}}",
+                numbers(&[FORMAT_VERSION]),
+                numbers(READABLE_FORMAT_VERSIONS)
+            ),
+        ),
+        ("key_formats", strings(&[XWING_SK_LABEL, XWING_PK_LABEL])),
+        ("features", strings(&features())),
+        ("deterministic_rng", deterministic_rng_available().to_string()),
+        (
+            "build",
+            format!(
+                "{{\"git_commit\":{},\"git_dirty\":{},\"profile\":{},\"target\":{},\"rustc\":{}}}",
+                optional(build_value(env!("LIQK_CRYPTO_GIT_COMMIT"))),
+                env!("LIQK_CRYPTO_GIT_DIRTY") == "true",
+                optional(build_value(env!("LIQK_CRYPTO_PROFILE"))),
+                optional(build_value(env!("LIQK_CRYPTO_TARGET"))),
+                optional(build_value(env!("LIQK_CRYPTO_RUSTC"))),
+            ),
+        ),
+    ];
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("{}:{}", json_string(key), value))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Quote a string as a JSON string literal
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_string_escapes() {
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+    }
+
+    #[test]
+    fn test_json_report_lists_capabilities() {
+        let report = json();
+        assert!(report.starts_with("{\"report_version\":1,"));
+        assert!(report.contains("\"kems\":[\"X-Wing-draft-06\"]"));
+        assert!(report.contains("\"aeads\":[\"ChaCha20Poly1305\"]"));
+        assert!(report.contains("\"kdfs\":[\"HKDF-SHA256\",\"HKDF-SHA512\",\"HKDF-BLAKE3\"]"));
+        assert!(report.contains("\"formats\":{\"write\":[2],\"read\":[1,2]}"));
+        assert!(report.contains("\"cli\""));
+        assert!(report.contains("\"remote\""));
+        // Tests run as a debug build, which always has the hidden flag
+        assert!(report.contains("\"deterministic_rng\":true"));
+    }
+}